@@ -0,0 +1,70 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use zerodns::protocol::{AdditionalRR, Message};
+
+// a response which has 1 question, 4 authorities and 18 additionals
+const RESPONSE: &str = "a4e5808000010000000400120a68747470733a2f2f696d0864696e6774616c6b03636f6d0000010001c017000200010000001e000d036e73360674616f62616fc020c017000200010000001e0006036e7335c039c017000200010000001e0006036e7337c039c017000200010000001e0006036e7334c039c072000100010000001e0004aa211849c072000100010000001e0004aa21184bc072000100010000001e00042f584a21c072000100010000001e00042f584a23c072000100010000001e00042ff1cf0dc072000100010000001e00042ff1cf0fc04e000100010000001e00048ccd7a21c04e000100010000001e00048ccd7a22c035000100010000001e00048ccd7a24c035000100010000001e00048ccd7a23c060000100010000001e00046a0b2996c060000100010000001e00046a0b2319c060000100010000001e00046a0b231ac060000100010000001e00046a0b2995c072001c00010000001e00102401b180410000000000000000000004c04e001c00010000001e00102401b180410000000000000000000005c035001c00010000001e00102401b180410000000000000000000006c060001c00010000001e00102401b180410000000000000000000007";
+
+fn time_to_live(it: AdditionalRR<'_>) -> u32 {
+    match it {
+        AdditionalRR::RR(rr) => rr.time_to_live(),
+        AdditionalRR::PseudoRR(_) => 0,
+    }
+}
+
+fn bench_sections(c: &mut Criterion) {
+    let msg = Message::from(hex::decode(RESPONSE).unwrap());
+
+    let mut group = c.benchmark_group("sections");
+    group.throughput(Throughput::Bytes(msg.len() as u64));
+
+    group.bench_function("validate", |b| {
+        b.iter(|| black_box(&msg).validate().unwrap())
+    });
+
+    // every accessor walks the sections before it again
+    group.bench_function("iterators", |b| {
+        b.iter(|| {
+            let msg = black_box(&msg);
+            let mut n = 0u32;
+            n += msg.answers().map(|it| it.time_to_live()).sum::<u32>();
+            n += msg.authorities().map(|it| it.time_to_live()).sum::<u32>();
+            n += msg.additionals().map(time_to_live).sum::<u32>();
+            n += msg.additionals().count() as u32;
+            n
+        })
+    });
+
+    // the offsets are computed only once
+    group.bench_function("index", |b| {
+        b.iter(|| {
+            let index = black_box(&msg).index();
+            let mut n = 0u32;
+            n += index.answers().map(|it| it.time_to_live()).sum::<u32>();
+            n += index.authorities().map(|it| it.time_to_live()).sum::<u32>();
+            n += index.additionals().map(time_to_live).sum::<u32>();
+            n += index.additionals().len() as u32;
+            n
+        })
+    });
+
+    group.bench_function("random_access", |b| {
+        let index = msg.index();
+        b.iter(|| {
+            (0..18)
+                .rev()
+                .filter_map(|i| black_box(&index).additional(i))
+                .map(time_to_live)
+                .sum::<u32>()
+        })
+    });
+
+    group.bench_function("update_time_to_live", |b| {
+        let mut msg = Clone::clone(&msg);
+        b.iter(|| msg.update_time_to_live(black_box))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_sections);
+criterion_main!(benches);
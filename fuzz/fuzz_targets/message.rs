@@ -0,0 +1,35 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zerodns::protocol::{AdditionalRR, Message};
+
+// Accesses everything of the validated messages, which should never panic.
+fuzz_target!(|data: &[u8]| {
+    let msg = Message::from(data.to_vec());
+    if msg.validate().is_err() {
+        return;
+    }
+
+    let _ = msg.flags().opcode();
+    let _ = msg.flags().response_code();
+    for next in msg.questions() {
+        let _ = next.to_string();
+    }
+    for next in msg.answers().chain(msg.authorities()) {
+        let _ = next.to_string();
+        let _ = next.expanded_data(true);
+    }
+    for next in msg.additionals() {
+        match next {
+            AdditionalRR::PseudoRR(it) => {
+                let _ = it.to_string();
+            }
+            AdditionalRR::RR(it) => {
+                let _ = it.to_string();
+            }
+        }
+    }
+    for next in msg.edns_options() {
+        let _ = next.to_string();
+    }
+});
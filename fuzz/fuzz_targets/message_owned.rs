@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use zerodns::protocol::{Message, MessageOwned};
+
+// The messages which are rebuilt from the owned model must be valid.
+fuzz_target!(|data: &[u8]| {
+    let msg = Message::from(data.to_vec());
+    if msg.validate().is_err() {
+        return;
+    }
+
+    if let Ok(owned) = MessageOwned::try_from(&msg) {
+        if let Ok(rebuilt) = Message::try_from(&owned) {
+            rebuilt.validate().expect("rebuilt message should be valid");
+        }
+    }
+});
@@ -3,12 +3,12 @@ use std::sync::Arc;
 
 use socket2::{Domain, Protocol, Type};
 use tokio::net::{TcpListener, UdpSocket};
-use tokio::sync::Notify;
+use tokio::sync::{Notify, Semaphore};
 
 use crate::cache::MemoryLoadingCache;
 use crate::config::Config;
 use crate::handler::RuledHandler;
-use crate::server::{TcpServer, UdpServer};
+use crate::server::{Acl, RateLimiter, TcpServer, UdpServer};
 
 pub async fn run(c: Config, closer: Arc<Notify>) -> anyhow::Result<()> {
     let addr = c.server.listen.parse::<SocketAddr>()?;
@@ -25,6 +25,14 @@ pub async fn run(c: Config, closer: Arc<Notify>) -> anyhow::Result<()> {
             rb = rb.rule(next)?;
         }
 
+        for next in c.views.iter() {
+            rb = rb.view(next)?;
+        }
+
+        if let Some(path) = &c.global.hosts_file {
+            rb = rb.hosts_file(path)?;
+        }
+
         rb.build()
     };
 
@@ -35,6 +43,12 @@ pub async fn run(c: Config, closer: Arc<Notify>) -> anyhow::Result<()> {
         _ => None,
     };
 
+    let inflight = c
+        .server
+        .max_inflight
+        .filter(|it| *it > 0)
+        .map(|it| Arc::new(Semaphore::new(it)));
+
     let udp_server = {
         let socket = {
             let socket = socket2::Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
@@ -70,12 +84,24 @@ pub async fn run(c: Config, closer: Arc<Notify>) -> anyhow::Result<()> {
             UdpSocket::from_std(socket)?
         };
 
-        UdpServer::new(
+        let acl = Acl::try_from(&c.server.acl.merge(c.server.udp.as_ref()))?;
+
+        let mut server = UdpServer::new(
             socket,
             Clone::clone(&h),
             Clone::clone(&cs),
             Clone::clone(&closer),
         )
+        .acl(acl);
+
+        if let Some(rl) = &c.server.rate_limit {
+            server = server.limiter(RateLimiter::try_from(rl)?);
+        }
+        if let Some(inflight) = &inflight {
+            server = server.inflight(Clone::clone(inflight));
+        }
+
+        server
     };
 
     let tcp_server = {
@@ -103,13 +129,21 @@ pub async fn run(c: Config, closer: Arc<Notify>) -> anyhow::Result<()> {
             socket
         };
 
-        TcpServer::new(
+        let acl = Acl::try_from(&c.server.acl.merge(c.server.tcp.as_ref()))?;
+
+        let server = TcpServer::new(
             addr,
             TcpListener::from_std(socket.into())?,
             Clone::clone(&h),
             Clone::clone(&cs),
             Clone::clone(&closer),
         )
+        .acl(acl);
+
+        match &inflight {
+            Some(inflight) => server.inflight(Clone::clone(inflight)),
+            None => server,
+        }
     };
 
     let (_first, _second) = tokio::join!(udp_server.listen(), tcp_server.listen());
@@ -1,6 +1,8 @@
 use crate::filter::{
-    register, ChinaDNSFilterFactory, HostsFilterFactory, LuaFilterFactory, NoopFilterFactory,
-    Options, ProxyByFilterFactory,
+    register, DNSSECFilterFactory, EcsFilterFactory, GeoSplitFilterFactory, HostsFilterFactory,
+    IpBlockFilterFactory, LeasesFilterFactory, LuaFilterFactory, NoopFilterFactory, Options,
+    ProxyByFilterFactory, RebindingFilterFactory, RecursiveFilterFactory, RpzFilterFactory,
+    ZoneFilterFactory,
 };
 use crate::logger::{self, Config as LoggerConfig};
 
@@ -9,11 +11,33 @@ pub fn setup() {
     register("proxyby", |opts: &Options| {
         ProxyByFilterFactory::try_from(opts)
     });
+    register("geosplit", |opts: &Options| {
+        GeoSplitFilterFactory::try_from(opts)
+    });
+    // the legacy alias of geosplit, which splits by the geoip country 'CN'
     register("chinadns", |opts: &Options| {
-        ChinaDNSFilterFactory::try_from(opts)
+        GeoSplitFilterFactory::legacy(opts)
     });
     register("lua", |opts: &Options| LuaFilterFactory::try_from(opts));
     register("hosts", |opts: &Options| HostsFilterFactory::try_from(opts));
+    register("recursive", |opts: &Options| {
+        RecursiveFilterFactory::try_from(opts)
+    });
+    register("dnssec", |opts: &Options| {
+        DNSSECFilterFactory::try_from(opts)
+    });
+    register("ecs", |opts: &Options| EcsFilterFactory::try_from(opts));
+    register("zone", |opts: &Options| ZoneFilterFactory::try_from(opts));
+    register("leases", |opts: &Options| {
+        LeasesFilterFactory::try_from(opts)
+    });
+    register("rebinding", |opts: &Options| {
+        RebindingFilterFactory::try_from(opts)
+    });
+    register("ipblock", |opts: &Options| {
+        IpBlockFilterFactory::try_from(opts)
+    });
+    register("rpz", |opts: &Options| RpzFilterFactory::try_from(opts));
 }
 
 pub fn setup_logger(c: &LoggerConfig) -> crate::Result<()> {
@@ -63,6 +87,20 @@ mod tests {
             assert!(load("chinadns", &opts).is_ok());
         }
 
+        // geosplit
+        {
+            let opts: Options = toml::from_str(
+                r#"
+            trusted = ["8.8.8.8","8.8.4.4"]
+            mistrusted = ["223.5.5.5","223.6.6.6"]
+            cidrs = ["1.0.1.0/24", "240e::/20"]
+            mixed = "majority"
+            "#,
+            )
+            .unwrap();
+            assert!(load("geosplit", &opts).is_ok());
+        }
+
         // lua
         {
             let opts: Options = toml::from_str(
@@ -76,5 +114,84 @@ mod tests {
             .unwrap();
             assert!(load("lua", &opts).is_ok());
         }
+
+        // recursive
+        {
+            let opts: Options = toml::from_str(
+                r#"
+            root_hints = ["198.41.0.4", "199.9.14.201:53"]
+            timeout = "2s"
+            "#,
+            )
+            .unwrap();
+            assert!(load("recursive", &opts).is_ok());
+        }
+
+        // ecs
+        {
+            let opts: Options = toml::from_str(
+                r#"
+            mode = "fixed"
+            subnet = "1.2.3.0/24"
+            "#,
+            )
+            .unwrap();
+            assert!(load("ecs", &opts).is_ok());
+        }
+
+        // zone
+        {
+            let opts: Options = toml::from_str(
+                r#"
+            origin = "home.lan"
+            zone = """
+@       3600 SOA ns hostmaster 1 7200 900 604800 300
+router  3600 A   192.168.1.1
+"""
+            "#,
+            )
+            .unwrap();
+            assert!(load("zone", &opts).is_ok());
+        }
+
+        // rebinding
+        {
+            let opts: Options = toml::from_str(
+                r#"
+            allowlist = ["lan", "corp.internal"]
+            action = "block"
+            "#,
+            )
+            .unwrap();
+            assert!(load("rebinding", &opts).is_ok());
+        }
+
+        // ipblock
+        {
+            let opts: Options = toml::from_str(
+                r#"
+            cidrs = ["1.2.3.4", "240e::/20"]
+            action = "strip"
+            "#,
+            )
+            .unwrap();
+            assert!(load("ipblock", &opts).is_ok());
+        }
+
+        // rpz
+        {
+            let opts: Options = toml::from_str(
+                r#"
+            origin = "rpz.local"
+            zone = """
+$TTL 60
+@ SOA localhost. root.localhost. 1 3600 600 86400 60
+bad.example.com CNAME .
+"""
+            "#,
+            )
+            .unwrap();
+            assert!(load("rpz", &opts).is_ok());
+        }
     }
 }
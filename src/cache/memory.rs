@@ -1,8 +1,10 @@
-use crate::cache::{Loader, LoadingCache};
+use crate::cache::{Loaded, Loader, LoadingCache};
 use crate::protocol::Message;
 use crate::Result;
 use async_trait::async_trait;
 use moka::future::Cache;
+use parking_lot::Mutex;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 type Key = [u8; 32];
@@ -36,7 +38,10 @@ impl MemoryLoadingCacheBuilder {
     }
 }
 
-pub(crate) struct MemoryLoadingCache(Cache<Key, (Instant, Message)>);
+/// The cached response, None is the marker of private responses.
+type Value = (Instant, Option<Message>);
+
+pub(crate) struct MemoryLoadingCache(Cache<Key, Value>);
 
 impl Default for MemoryLoadingCache {
     fn default() -> Self {
@@ -47,6 +52,9 @@ impl Default for MemoryLoadingCache {
 impl MemoryLoadingCache {
     pub(crate) const DEFAULT_CAPACITY: usize = 1000;
 
+    /// The lifetime of the markers of private responses.
+    const PRIVATE_MARKER_TTL: Duration = Duration::from_secs(60);
+
     pub(crate) fn builder() -> MemoryLoadingCacheBuilder {
         MemoryLoadingCacheBuilder {
             capacity: Self::DEFAULT_CAPACITY,
@@ -55,10 +63,14 @@ impl MemoryLoadingCache {
     }
 
     #[inline(always)]
-    fn generate_key(req: &Message) -> Key {
+    fn generate_key(ns: &str, req: &Message) -> Key {
         use sha2::{Digest, Sha256};
 
         let mut h = Sha256::new();
+        if !ns.is_empty() {
+            h.update((ns.len() as u32).to_be_bytes());
+            h.update(ns.as_bytes());
+        }
         h.update(&req.0[2..]);
         h.finalize().into()
     }
@@ -66,28 +78,54 @@ impl MemoryLoadingCache {
 
 #[async_trait]
 impl LoadingCache for MemoryLoadingCache {
-    async fn load<L>(&self, req: Message, fut: L) -> Result<(Instant, Message)>
+    async fn load<L>(&self, ns: &str, req: Message, fut: L) -> Result<Option<(Instant, Message)>>
     where
         L: Loader,
     {
         let id = req.id();
-        let key = Self::generate_key(&req);
-        let (created_at, mut res) = self
-            .0
-            .try_get_with(key, async {
-                fut.load(req).await.map(|it| (Instant::now(), it))
-            })
-            .await
-            .map_err(|e| anyhow!("failed to loading result from cache: {:?}", e))?;
+        let key = Self::generate_key(ns, &req);
+
+        // the private response is handed to the loading caller only, never to the cache
+        let private = Arc::new(Mutex::new(None));
+        let (created_at, res) = {
+            let private = Clone::clone(&private);
+            self.0
+                .try_get_with(key, async move {
+                    let value = match fut.load(req).await? {
+                        Loaded::Shared(res) => Some(res),
+                        Loaded::Private(res) => {
+                            private.lock().replace(res);
+                            None
+                        }
+                    };
+                    Ok::<_, anyhow::Error>((Instant::now(), value))
+                })
+                .await
+                .map_err(|e| anyhow!("failed to loading result from cache: {:?}", e))?
+        };
+
+        let mut res = match res {
+            Some(res) => res,
+            None => {
+                if created_at.elapsed() > Self::PRIVATE_MARKER_TTL {
+                    self.0.invalidate(&key).await;
+                }
+                let res = private.lock().take();
+                match res {
+                    Some(res) => res,
+                    None => return Ok(None),
+                }
+            }
+        };
 
         // reset id
         res.set_id(id);
 
-        Ok((created_at, res))
+        Ok(Some((created_at, res)))
     }
 
-    async fn remove(&self, req: &Message) {
-        let key = Self::generate_key(req);
+    async fn remove(&self, ns: &str, req: &Message) {
+        let key = Self::generate_key(ns, req);
         self.0.invalidate(&key).await;
     }
 }
@@ -123,15 +161,16 @@ mod tests {
                     .response()
                     .rcode(RCode::NotImplemented)
                     .build();
-                Message::builder().flags(flags).build()
+                Message::builder().flags(flags).build().map(Loaded::Shared)
             }
         };
 
         // call twice
         for _ in 0..2 {
             let req = Clone::clone(&req);
-            let result = cache.load(req, fut()).await;
-            assert!(result.is_ok_and(|(created_at, msg)| {
+            let result = cache.load("", req, fut()).await;
+            assert!(result.is_ok_and(|it| {
+                let (created_at, msg) = it.unwrap();
                 RCode::NotImplemented == msg.flags().response_code() && id == msg.id()
             }));
         }
@@ -139,18 +178,63 @@ mod tests {
         // but calls only one time
         assert_eq!(1, calls.load(Ordering::SeqCst));
 
-        cache.remove(&req).await;
+        cache.remove("", &req).await;
 
         // call again
         {
             let req = Clone::clone(&req);
-            let result = cache.load(req, fut()).await;
-            assert!(result.is_ok_and(|(created_at, msg)| {
+            let result = cache.load("", req, fut()).await;
+            assert!(result.is_ok_and(|it| {
+                let (created_at, msg) = it.unwrap();
                 RCode::NotImplemented == msg.flags().response_code() && id == msg.id()
             }));
         }
 
         // should be twice because cache item has been removed already
         assert_eq!(2, calls.load(Ordering::SeqCst));
+
+        // the other namespace doesn't share the cache item
+        for _ in 0..2 {
+            let req = Clone::clone(&req);
+            assert!(cache.load("office", req, fut()).await.is_ok());
+        }
+        assert_eq!(3, calls.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_load_private() {
+        let req = Message::builder()
+            .flags(Flags::request())
+            .id(0x1234)
+            .question("www.youtube.com", Kind::A, Class::IN)
+            .build()
+            .unwrap();
+
+        let cache = MemoryLoadingCache::builder().build();
+
+        let calls: Arc<AtomicUsize> = Default::default();
+
+        let fut = || {
+            let calls = Clone::clone(&calls);
+            move |req: Message| async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                let flags = Flags::builder().response().rcode(RCode::Refused).build();
+                Message::builder()
+                    .flags(flags)
+                    .id(req.id())
+                    .build()
+                    .map(Loaded::Private)
+            }
+        };
+
+        // the loading caller gets the private response
+        let result = cache.load("", Clone::clone(&req), fut()).await.unwrap();
+        assert!(result.is_some_and(|(_, msg)| RCode::Refused == msg.flags().response_code()));
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+
+        // the others never see it, and the loader is not called again
+        let result = cache.load("", Clone::clone(&req), fut()).await.unwrap();
+        assert!(result.is_none());
+        assert_eq!(1, calls.load(Ordering::SeqCst));
     }
 }
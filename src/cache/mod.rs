@@ -2,41 +2,59 @@ use std::time::Instant;
 
 use crate::Result;
 use async_trait::async_trait;
-use byteorder::{BigEndian, ByteOrder};
 pub(crate) use memory::MemoryLoadingCache;
-use smallvec::SmallVec;
 use std::future::Future;
 
 use crate::protocol::Message;
 
 mod memory;
 
+/// The response of a loader.
+#[derive(Debug, Clone)]
+pub enum Loaded {
+    /// The response can be shared by the same requests.
+    Shared(Message),
+    /// The response belongs to the requester only, eg: it depends on the client address. It
+    /// will never be cached or shared, only a marker is cached, so that the same requests will
+    /// be resolved by themselves.
+    Private(Message),
+}
+
 pub trait Loader: Send {
-    fn load(self, req: Message) -> impl Future<Output = Result<Message>> + Send;
+    fn load(self, req: Message) -> impl Future<Output = Result<Loaded>> + Send;
 }
 
 impl<A, T> Loader for A
 where
     A: Send + FnOnce(Message) -> T,
-    T: Send + Future<Output = Result<Message>>,
+    T: Send + Future<Output = Result<Loaded>>,
 {
-    fn load(self, req: Message) -> impl Future<Output = Result<Message>> + Send {
+    fn load(self, req: Message) -> impl Future<Output = Result<Loaded>> + Send {
         self(req)
     }
 }
 
+/// The cache entries are isolated by the namespace, eg: the responses of different views.
 #[async_trait]
 pub trait LoadingCache: Send + Sync + 'static {
-    async fn load<L>(&self, req: Message, fut: L) -> Result<(Instant, Message)>
+    /// Loads the response from the cache, or by the loader. Returns None if the response of
+    /// the request is private and it's not loaded by this call, the caller should resolve the
+    /// request by itself.
+    async fn load<L>(&self, ns: &str, req: Message, fut: L) -> Result<Option<(Instant, Message)>>
     where
         L: Loader;
 
-    async fn remove(&self, req: &Message);
+    async fn remove(&self, ns: &str, req: &Message);
 }
 
 #[async_trait]
 pub(crate) trait LoadingCacheExt: Send + Sync + 'static {
-    async fn try_get_with_fixed<L>(&self, req: Message, fut: L) -> Result<Message>
+    async fn try_get_with_fixed<L>(
+        &self,
+        ns: &str,
+        req: Message,
+        fut: L,
+    ) -> Result<Option<Message>>
     where
         L: Loader;
 }
@@ -46,36 +64,34 @@ impl<A> LoadingCacheExt for A
 where
     A: LoadingCache,
 {
-    async fn try_get_with_fixed<L>(&self, req: Message, fut: L) -> Result<Message>
+    async fn try_get_with_fixed<L>(&self, ns: &str, req: Message, fut: L) -> Result<Option<Message>>
     where
         L: Loader,
     {
         // 1. compute the original cached value
-        let (created_at, mut value) = self.load(Clone::clone(&req), fut).await?;
+        let (created_at, mut value) = match self.load(ns, Clone::clone(&req), fut).await? {
+            Some(it) => it,
+            None => return Ok(None),
+        };
 
-        // 2. compute the newest list of time-to-live
-        let mut rewrites = SmallVec::<[(u16, u32); 4]>::new();
+        // 2. rewrite ttl
         let mut remove = false;
         let elapsed = Instant::now().duration_since(created_at).as_secs();
-        for next in value.answers() {
-            let mut ttl = (next.time_to_live() as i64) - (elapsed as i64);
+        value.update_time_to_live(|ttl| {
+            let ttl = (ttl as i64) - (elapsed as i64);
             if ttl <= 0 {
                 remove = true;
-                ttl = 1; // 1s at least
+                1 // 1s at least
+            } else {
+                ttl as u32
             }
-            rewrites.push((next.time_to_live_pos() as u16, ttl as u32));
-        }
+        });
 
         // 3. remove expired cache
         if remove {
-            self.remove(&req).await;
-        }
-
-        // 4. rewrite ttl
-        for (pos, ttl) in rewrites {
-            BigEndian::write_u32(&mut value.0[pos as usize..], ttl);
+            self.remove(ns, &req).await;
         }
 
-        Ok(value)
+        Ok(Some(value))
     }
 }
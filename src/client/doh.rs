@@ -1,17 +1,20 @@
-use super::Client;
+use super::{Client, RequestOptions};
 use crate::misc::http::{SimpleHttp1Codec, CRLF};
-use crate::protocol::{Message, DEFAULT_HTTP_PORT, DEFAULT_TLS_PORT};
+use crate::misc::{tcp, tls};
+use crate::protocol::{
+    ClientSubnet, DNSOptions, Message, Proxy, DEFAULT_HTTP_PORT, DEFAULT_TLS_PORT,
+};
 use futures::StreamExt;
 use once_cell::sync::Lazy;
 use smallvec::{smallvec, SmallVec};
 use std::fmt::{Display, Formatter};
 use std::io::{self, Write};
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::io::AsyncWriteExt;
-use tokio::net::TcpStream;
 use tokio_util::codec::FramedRead;
 
 use crate::Error::NetworkFailure;
@@ -22,6 +25,11 @@ pub struct DoHClientBuilder<'a> {
     host: Option<&'a str>,
     path: Option<&'a str>,
     timeout: Duration,
+    source: Option<SocketAddr>,
+    proxy: Option<Proxy>,
+    tls: tls::Config,
+    pool_size: usize,
+    opts: RequestOptions,
 }
 
 impl<'a> DoHClientBuilder<'a> {
@@ -45,6 +53,120 @@ impl<'a> DoHClientBuilder<'a> {
         self
     }
 
+    pub fn source(mut self, source: SocketAddr) -> Self {
+        self.source.replace(source);
+        self
+    }
+
+    /// Skip the verification of server certificates, DO NOT use it in production.
+    pub fn insecure(mut self, insecure: bool) -> Self {
+        self.tls.insecure = insecure;
+        self
+    }
+
+    /// Trust the CA certificates of the PEM bundle instead of the builtin webpki roots.
+    pub fn ca_file<P>(mut self, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.tls.ca_file.replace(path.into());
+        self
+    }
+
+    /// Present the client certificate for mutual TLS, both files should be in PEM format.
+    pub fn client_cert<P>(mut self, cert: P, key: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.tls.client_cert.replace((cert.into(), key.into()));
+        self
+    }
+
+    /// Pin the SHA-256 digest of SubjectPublicKeyInfo, can be called multiple times.
+    pub fn pin_sha256(mut self, pin: [u8; 32]) -> Self {
+        if !self.tls.pins.contains(&pin) {
+            self.tls.pins.push(pin);
+        }
+        self
+    }
+
+    pub fn alpn<A>(mut self, protocol: A) -> Self
+    where
+        A: Into<String>,
+    {
+        self.tls.alpn.push(protocol.into());
+        self
+    }
+
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy.replace(proxy);
+        self
+    }
+
+    pub fn pool_size(mut self, pool_size: usize) -> Self {
+        self.pool_size = pool_size;
+        self
+    }
+
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.opts.retries = retries;
+        self
+    }
+
+    pub fn edns_size(mut self, size: u16) -> Self {
+        self.opts.edns_size.replace(size);
+        self
+    }
+
+    pub fn ecs(mut self, ecs: ClientSubnet) -> Self {
+        self.opts.ecs.replace(ecs);
+        self
+    }
+
+    /// Applies the options of DNS url, the 'sni' option will be used as the host.
+    pub fn options(mut self, opts: &'a DNSOptions) -> Self {
+        if let Some(timeout) = opts.timeout {
+            self = self.timeout(timeout);
+        }
+        if let Some(sni) = &opts.sni {
+            self = self.host(sni.as_ref());
+        }
+        if let Some(retries) = opts.retries {
+            self = self.retries(retries);
+        }
+        if let Some(size) = opts.edns_size {
+            self = self.edns_size(size);
+        }
+        if let Some(ecs) = opts.ecs {
+            self = self.ecs(ecs);
+        }
+        if let Some(source) = opts.source {
+            self = self.source(source);
+        }
+        if let Some(pool_size) = opts.pool_size {
+            self = self.pool_size(pool_size);
+        }
+        if let Some(proxy) = &opts.proxy {
+            self = self.proxy(Clone::clone(proxy));
+        }
+        if let Some(path) = &opts.tls_ca {
+            self = self.ca_file(path);
+        }
+        if let (Some(cert), Some(key)) = (&opts.tls_cert, &opts.tls_key) {
+            self = self.client_cert(cert, key);
+        }
+        for pin in &opts.tls_pins {
+            self = self.pin_sha256(*pin);
+        }
+        for next in &opts.tls_alpn {
+            self = self.alpn(next);
+        }
+        if opts.tls_insecure {
+            self = self.insecure(true);
+        }
+        self
+    }
+
     pub fn build(self) -> DoHClient {
         let Self {
             https,
@@ -52,6 +174,11 @@ impl<'a> DoHClientBuilder<'a> {
             host,
             path,
             timeout,
+            source,
+            proxy,
+            tls,
+            pool_size,
+            opts,
         } = self;
         let host = host
             .map(|it| it.to_string())
@@ -63,6 +190,11 @@ impl<'a> DoHClientBuilder<'a> {
             host: Arc::new(host),
             path: path.map(|it| Arc::new(it.to_string())),
             timeout,
+            source,
+            proxy,
+            tls,
+            pool_size,
+            opts,
         }
     }
 }
@@ -74,6 +206,11 @@ pub struct DoHClient {
     host: Arc<String>,
     path: Option<Arc<String>>,
     timeout: Duration,
+    source: Option<SocketAddr>,
+    proxy: Option<Proxy>,
+    tls: tls::Config,
+    pool_size: usize,
+    opts: RequestOptions,
 }
 
 impl DoHClient {
@@ -87,6 +224,11 @@ impl DoHClient {
             host: None,
             path: None,
             timeout: Duration::from_secs(5),
+            source: None,
+            proxy: super::default_proxy(),
+            tls: Default::default(),
+            pool_size: tcp::DEFAULT_POOL_SIZE,
+            opts: Default::default(),
         }
     }
 
@@ -221,6 +363,7 @@ impl DoHClient {
         }
 
         let msg = Message::from(res.into_body());
+        msg.validate()?;
         Ok(msg)
     }
 }
@@ -250,18 +393,41 @@ impl Display for DoHClient {
 #[async_trait::async_trait]
 impl Client for DoHClient {
     async fn request(&self, req: &Message) -> crate::Result<Message> {
+        let req = self.opts.prepare(req);
+        self.opts.retry(|| self.request_once(&req)).await
+    }
+}
+
+impl DoHClient {
+    async fn request_once(&self, req: &Message) -> crate::Result<Message> {
         if self.https {
-            let key = (Clone::clone(&self.host), Clone::clone(&self.addr));
-            let pool = crate::misc::tls::get(key)?;
+            let key = tls::Key {
+                source: self.source,
+                proxy: Clone::clone(&self.proxy),
+                tls: Clone::clone(&self.tls),
+                pool_size: self.pool_size,
+                timeout: self.timeout,
+                ..tls::Key::new(Clone::clone(&self.host), self.addr)
+            };
+            let pool = tls::get(key)?;
 
             let mut obj = pool
                 .get()
                 .await
                 .map_err(|e| anyhow!("cannot get tcp stream: {:?}", e))?;
 
-            self.request_timeout(&mut obj.1, req).await
+            let res = self.request_timeout(&mut obj.1, req).await;
+
+            // mark the broken connection, it will be discarded by the pool
+            if res.is_err() {
+                obj.0 = 1;
+                let _ = obj.1.shutdown().await;
+            }
+
+            res
         } else {
-            let mut stream = TcpStream::connect(self.addr).await?;
+            let mut stream =
+                tcp::dial(self.addr, self.source, self.proxy.as_ref(), self.timeout).await?;
             self.request_timeout(&mut stream, req).await
         }
     }
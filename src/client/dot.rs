@@ -1,11 +1,12 @@
-use super::Client;
-use crate::misc::tls;
-use crate::protocol::{Codec, Message, DEFAULT_DOT_PORT};
+use super::{Client, RequestOptions};
+use crate::misc::{tcp, tls};
+use crate::protocol::{ClientSubnet, Codec, DNSOptions, Message, Proxy, DEFAULT_DOT_PORT};
 use crate::Result;
 
 use futures::{SinkExt, StreamExt};
 use once_cell::sync::Lazy;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::io::AsyncWriteExt;
@@ -40,6 +41,7 @@ dotv4!(aliyun, "dns.alidns.com", 223, 5, 5, 5);
 pub struct DoTClient {
     pool: tls::Pool,
     timeout: Duration,
+    opts: RequestOptions,
 }
 
 impl DoTClient {
@@ -50,6 +52,11 @@ impl DoTClient {
             sni: None,
             addr,
             timeout: Self::DEFAULT_TIMEOUT,
+            source: None,
+            proxy: super::default_proxy(),
+            tls: Default::default(),
+            pool_size: tcp::DEFAULT_POOL_SIZE,
+            opts: Default::default(),
         }
     }
 
@@ -77,7 +84,11 @@ impl DoTClient {
         w.flush().await?;
 
         match r.next().await {
-            Some(next) => next,
+            Some(next) => {
+                let res = next?;
+                res.validate()?;
+                Ok(res)
+            }
             None => bail!(crate::Error::ResolveNothing),
         }
     }
@@ -86,6 +97,13 @@ impl DoTClient {
 #[async_trait::async_trait]
 impl Client for DoTClient {
     async fn request(&self, req: &Message) -> Result<Message> {
+        let req = self.opts.prepare(req);
+        self.opts.retry(|| self.request_(&req)).await
+    }
+}
+
+impl DoTClient {
+    async fn request_(&self, req: &Message) -> Result<Message> {
         // TODO: implement multiplexing
         let mut obj = self
             .pool
@@ -108,6 +126,11 @@ pub struct DoTClientBuilder {
     sni: Option<String>,
     addr: SocketAddr,
     timeout: Duration,
+    source: Option<SocketAddr>,
+    proxy: Option<Proxy>,
+    tls: tls::Config,
+    pool_size: usize,
+    opts: RequestOptions,
 }
 
 impl DoTClientBuilder {
@@ -124,16 +147,152 @@ impl DoTClientBuilder {
         self
     }
 
+    pub fn source(mut self, source: SocketAddr) -> Self {
+        self.source.replace(source);
+        self
+    }
+
+    /// Skip the verification of server certificates, DO NOT use it in production.
+    pub fn insecure(mut self, insecure: bool) -> Self {
+        self.tls.insecure = insecure;
+        self
+    }
+
+    /// Trust the CA certificates of the PEM bundle instead of the builtin webpki roots.
+    pub fn ca_file<P>(mut self, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.tls.ca_file.replace(path.into());
+        self
+    }
+
+    /// Present the client certificate for mutual TLS, both files should be in PEM format.
+    pub fn client_cert<P>(mut self, cert: P, key: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.tls.client_cert.replace((cert.into(), key.into()));
+        self
+    }
+
+    /// Pin the SHA-256 digest of SubjectPublicKeyInfo, can be called multiple times.
+    pub fn pin_sha256(mut self, pin: [u8; 32]) -> Self {
+        if !self.tls.pins.contains(&pin) {
+            self.tls.pins.push(pin);
+        }
+        self
+    }
+
+    pub fn alpn<A>(mut self, protocol: A) -> Self
+    where
+        A: Into<String>,
+    {
+        self.tls.alpn.push(protocol.into());
+        self
+    }
+
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy.replace(proxy);
+        self
+    }
+
+    pub fn pool_size(mut self, pool_size: usize) -> Self {
+        self.pool_size = pool_size;
+        self
+    }
+
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.opts.retries = retries;
+        self
+    }
+
+    pub fn edns_size(mut self, size: u16) -> Self {
+        self.opts.edns_size.replace(size);
+        self
+    }
+
+    pub fn ecs(mut self, ecs: ClientSubnet) -> Self {
+        self.opts.ecs.replace(ecs);
+        self
+    }
+
+    /// Applies the options of DNS url.
+    pub fn options(mut self, opts: &DNSOptions) -> Self {
+        if let Some(timeout) = opts.timeout {
+            self = self.timeout(timeout);
+        }
+        if let Some(sni) = &opts.sni {
+            self = self.sni(sni.as_ref());
+        }
+        if let Some(retries) = opts.retries {
+            self = self.retries(retries);
+        }
+        if let Some(size) = opts.edns_size {
+            self = self.edns_size(size);
+        }
+        if let Some(ecs) = opts.ecs {
+            self = self.ecs(ecs);
+        }
+        if let Some(source) = opts.source {
+            self = self.source(source);
+        }
+        if let Some(pool_size) = opts.pool_size {
+            self = self.pool_size(pool_size);
+        }
+        if let Some(proxy) = &opts.proxy {
+            self = self.proxy(Clone::clone(proxy));
+        }
+        if let Some(path) = &opts.tls_ca {
+            self = self.ca_file(path);
+        }
+        if let (Some(cert), Some(key)) = (&opts.tls_cert, &opts.tls_key) {
+            self = self.client_cert(cert, key);
+        }
+        for pin in &opts.tls_pins {
+            self = self.pin_sha256(*pin);
+        }
+        for next in &opts.tls_alpn {
+            self = self.alpn(next);
+        }
+        if opts.tls_insecure {
+            self = self.insecure(true);
+        }
+        self
+    }
+
     pub fn build(self) -> Result<DoTClient> {
-        let Self { sni, addr, timeout } = self;
+        let Self {
+            sni,
+            addr,
+            timeout,
+            source,
+            proxy,
+            tls,
+            pool_size,
+            opts,
+        } = self;
+
+        let sni = match sni {
+            None => Arc::new(addr.ip().to_string()),
+            Some(sni) => Arc::new(sni),
+        };
 
-        let key = match sni {
-            None => (Arc::new(addr.ip().to_string()), addr),
-            Some(sni) => (Arc::new(sni), addr),
+        let key = tls::Key {
+            source,
+            proxy,
+            tls,
+            pool_size,
+            timeout,
+            ..tls::Key::new(sni, addr)
         };
 
         let pool = tls::get(key)?;
-        Ok(DoTClient { pool, timeout })
+        Ok(DoTClient {
+            pool,
+            timeout,
+            opts,
+        })
     }
 }
 
@@ -1,5 +1,5 @@
-use super::Client;
 use super::SYSTEM_CLIENT;
+use super::{Client, UdpClient};
 use crate::cachestr::Cachestr;
 use crate::protocol::{Class, Flags, Kind, Message, OpCode, RData};
 use crate::{Error, Result};
@@ -8,7 +8,7 @@ use moka::future::Cache;
 use once_cell::sync::Lazy;
 use rand::Rng;
 use smallvec::SmallVec;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, SocketAddr};
 use std::time::Duration;
 
 pub(super) type LookupIpv4Addrs = SmallVec<[Ipv4Addr; 2]>;
@@ -35,22 +35,30 @@ static PRESENT_HOSTS_V4: Lazy<HashMap<Cachestr, LookupIpv4Addrs>> = Lazy::new(||
     all
 });
 
-impl From<Cache<Cachestr, LookupIpv4Addrs>> for LookupCache {
-    fn from(value: Cache<Cachestr, LookupIpv4Addrs>) -> Self {
+type LookupKey = (Cachestr, Option<SocketAddr>);
+
+impl From<Cache<LookupKey, LookupIpv4Addrs>> for LookupCache {
+    fn from(value: Cache<LookupKey, LookupIpv4Addrs>) -> Self {
         Self(value)
     }
 }
 
-pub(super) struct LookupCache(Cache<Cachestr, LookupIpv4Addrs>);
+pub(super) struct LookupCache(Cache<LookupKey, LookupIpv4Addrs>);
 
 impl LookupCache {
-    pub(super) async fn lookup(&self, host: &str, timeout: Duration) -> Result<Ipv4Addr> {
+    /// Lookup the ipv4 address of host, use the bootstrap dns server if it's present.
+    pub(super) async fn lookup(
+        &self,
+        host: &str,
+        bootstrap: Option<SocketAddr>,
+        timeout: Duration,
+    ) -> Result<Ipv4Addr> {
         let key = Cachestr::from(host);
 
         let res = match PRESENT_HOSTS_V4.get(&key) {
             None => self
                 .0
-                .try_get_with(key, Self::lookup_(host, timeout))
+                .try_get_with((key, bootstrap), Self::lookup_(host, bootstrap, timeout))
                 .await
                 .map_err(|e| anyhow!("lookup failed: {}", e))?,
             Some(it) => Clone::clone(it),
@@ -64,7 +72,11 @@ impl LookupCache {
     }
 
     #[inline]
-    async fn lookup_(host: &str, timeout: Duration) -> Result<SmallVec<[Ipv4Addr; 2]>> {
+    async fn lookup_(
+        host: &str,
+        bootstrap: Option<SocketAddr>,
+        timeout: Duration,
+    ) -> Result<SmallVec<[Ipv4Addr; 2]>> {
         let flags = Flags::builder()
             .request()
             .recursive_query(true)
@@ -81,8 +93,13 @@ impl LookupCache {
 
         let mut ret = LookupIpv4Addrs::new();
 
-        let sys = SYSTEM_CLIENT.load();
-        let v = sys.request(&req0).await?;
+        let v = match bootstrap {
+            Some(addr) => {
+                let c = UdpClient::builder(addr).timeout(timeout).build();
+                c.request(&req0).await?
+            }
+            None => SYSTEM_CLIENT.load().request(&req0).await?,
+        };
         for next in v.answers() {
             if let Ok(RData::A(a)) = next.rdata() {
                 ret.push(a.ipaddr());
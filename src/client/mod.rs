@@ -1,13 +1,15 @@
 use crate::protocol::*;
 use crate::Result;
-use arc_swap::ArcSwap;
-pub use doh::DoHClient;
-pub use dot::DoTClient;
+use arc_swap::{ArcSwap, ArcSwapOption};
+pub use doh::{DoHClient, DoHClientBuilder};
+pub use dot::{DoTClient, DoTClientBuilder};
 use once_cell::sync::Lazy;
+use std::borrow::Cow;
+use std::future::Future;
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::time::Duration;
-pub use system::SystemClient;
+pub use system::{SystemClient, SystemClientBuilder};
 pub use tcp::{TcpClient, TcpClientBuilder};
 pub use tokio::sync::OnceCell;
 pub use udp::{UdpClient, UdpClientBuilder};
@@ -31,61 +33,142 @@ static DEFAULT_LOOKUPS: Lazy<lookup::LookupCache> = Lazy::new(|| {
     lookup::LookupCache::from(cache)
 });
 
+static DEFAULT_PROXY: Lazy<ArcSwapOption<Proxy>> = Lazy::new(Default::default);
+
 pub fn set_default_resolver(client: SystemClient) {
     info!("customize resolver from {}", &client);
     SYSTEM_CLIENT.store(Arc::new(client));
 }
 
+/// Sets the global proxy of TCP-based upstream connections (TCP/DoT/DoH), the 'proxy' option
+/// of DNS url takes precedence. UDP upstreams are proxied only if the option is present.
+pub fn set_default_proxy(proxy: Option<Proxy>) {
+    if let Some(proxy) = &proxy {
+        info!("use proxy {} for upstream connections", proxy);
+    }
+    DEFAULT_PROXY.store(proxy.map(Arc::new));
+}
+
+#[inline]
+pub(crate) fn default_proxy() -> Option<Proxy> {
+    DEFAULT_PROXY.load().as_deref().cloned()
+}
+
 #[async_trait::async_trait]
 pub trait Client: Sync + Send + 'static {
     async fn request(&self, request: &Message) -> Result<Message>;
 }
 
+/// The settings of requests which are shared by all kinds of clients.
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct RequestOptions {
+    pub(crate) retries: u8,
+    pub(crate) edns_size: Option<u16>,
+    pub(crate) ecs: Option<ClientSubnet>,
+}
+
+impl RequestOptions {
+    /// Rewrites the EDNS of request if necessary.
+    pub(crate) fn prepare<'a>(&self, req: &'a Message) -> Cow<'a, Message> {
+        if self.edns_size.is_none() && self.ecs.is_none() {
+            return Cow::Borrowed(req);
+        }
+
+        let mut req = Clone::clone(req);
+        if let Some(size) = self.edns_size {
+            req.set_udp_payload_size(size);
+        }
+        if let Some(ecs) = &self.ecs {
+            req.put_edns_option(&EdnsOption::ClientSubnet {
+                subnet: *ecs,
+                scope_prefix: 0,
+            });
+        }
+        Cow::Owned(req)
+    }
+
+    /// Calls the request function, and retry it after failures.
+    pub(crate) async fn retry<F, Fut>(&self, mut f: F) -> Result<Message>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<Message>>,
+    {
+        let mut retries = self.retries;
+        loop {
+            match f().await {
+                Ok(res) => return Ok(res),
+                Err(e) if retries > 0 => {
+                    debug!("request failed, retry again: {:?}", e);
+                    retries -= 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Sends the request to the DNS server, the timeout option of DNS url will be used first.
 pub async fn request(dns: &DNS, request: &Message, timeout: Duration) -> Result<Message> {
+    let opts = dns.options();
+    let timeout = opts.timeout.unwrap_or(timeout);
+
     match dns {
-        DNS::UDP(addr) => {
-            let c = UdpClient::builder(*addr).timeout(timeout).build();
+        DNS::UDP(addr, opts) => {
+            let c = UdpClient::builder(*addr)
+                .timeout(timeout)
+                .options(opts)
+                .build();
             c.request(request).await
         }
-        DNS::TCP(addr) => {
-            let c = TcpClient::builder(*addr).timeout(timeout).build()?;
+        DNS::TCP(addr, opts) => {
+            let c = TcpClient::builder(*addr)
+                .timeout(timeout)
+                .options(opts)
+                .build()?;
             c.request(request).await
         }
-        DNS::DoT(addr) => match addr {
+        DNS::DoT(addr, opts) => match addr {
             Address::SocketAddr(addr) => {
-                let c = DoTClient::builder(*addr).timeout(timeout).build()?;
+                let c = DoTClient::builder(*addr)
+                    .timeout(timeout)
+                    .options(opts)
+                    .build()?;
                 c.request(request).await
             }
             Address::HostAddr(host_addr) => {
                 let domain = &host_addr.host;
-                let ip = DEFAULT_LOOKUPS.lookup(domain, timeout).await?;
+                let ip = DEFAULT_LOOKUPS
+                    .lookup(domain, opts.bootstrap, timeout)
+                    .await?;
                 let addr = SocketAddr::new(IpAddr::V4(ip), host_addr.port);
                 let c = DoTClient::builder(addr)
                     .sni(domain.as_ref())
                     .timeout(timeout)
+                    .options(opts)
                     .build()?;
                 c.request(request).await
             }
         },
-        DNS::DoH(doh_addr) => {
+        DNS::DoH(doh_addr, opts) => {
             let dc = match &doh_addr.addr {
                 Address::SocketAddr(addr) => DoHClient::builder(*addr).https(doh_addr.https),
                 Address::HostAddr(addr) => {
                     let domain = &addr.host;
-                    let ip = DEFAULT_LOOKUPS.lookup(domain, timeout).await?;
-                    let mut bu = DoHClient::builder(SocketAddr::new(IpAddr::V4(ip), addr.port))
+                    let ip = DEFAULT_LOOKUPS
+                        .lookup(domain, opts.bootstrap, timeout)
+                        .await?;
+                    DoHClient::builder(SocketAddr::new(IpAddr::V4(ip), addr.port))
                         .host(domain)
-                        .https(doh_addr.https);
-
-                    if let Some(path) = &doh_addr.path {
-                        bu = bu.path(path);
-                    }
-                    bu
+                        .https(doh_addr.https)
                 }
+            };
+
+            let mut dc = dc.timeout(timeout).options(opts);
+            if let Some(path) = &doh_addr.path {
+                dc = dc.path(path);
             }
-            .build();
 
-            dc.request(request).await
+            dc.build().request(request).await
         }
     }
 }
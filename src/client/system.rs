@@ -1,5 +1,5 @@
 use super::{Client, TcpClient, UdpClient};
-use crate::protocol::Message;
+use crate::protocol::{DNSOptions, Message};
 use crate::Result;
 use resolv_conf::{Config, ScopedIp};
 use std::fmt::{Display, Formatter};
@@ -111,6 +111,7 @@ impl Client for SystemClient {
 pub struct SystemClientBuilder {
     timeout: Option<Duration>,
     nameservers: Vec<(SocketAddr, /* is_tcp */ bool)>,
+    opts: DNSOptions,
 }
 
 impl SystemClientBuilder {
@@ -127,10 +128,17 @@ impl SystemClientBuilder {
         self
     }
 
+    /// Sets the options which will be applied to all nameservers.
+    pub fn options(mut self, opts: DNSOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+
     pub fn build(self) -> Result<SystemClient> {
         let Self {
             timeout,
             nameservers,
+            opts,
         } = self;
 
         let mut clients = Vec::with_capacity(nameservers.len());
@@ -141,13 +149,13 @@ impl SystemClientBuilder {
                 if let Some(timeout) = timeout {
                     bu = bu.timeout(timeout);
                 }
-                clients.push(InnerClient::Tcp(bu.build()?));
+                clients.push(InnerClient::Tcp(bu.options(&opts).build()?));
             } else {
                 let mut bu = UdpClient::builder(addr);
                 if let Some(timeout) = timeout {
                     bu = bu.timeout(timeout);
                 }
-                clients.push(InnerClient::Udp(bu.build()));
+                clients.push(InnerClient::Udp(bu.options(&opts).build()));
             }
         }
 
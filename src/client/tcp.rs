@@ -10,10 +10,10 @@ use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 use tokio_util::codec::{FramedRead, FramedWrite};
 
-use crate::protocol::{Codec, Message};
+use crate::protocol::{ClientSubnet, Codec, DNSOptions, Message, Proxy};
 use crate::Result;
 
-use super::Client;
+use super::{Client, RequestOptions};
 
 macro_rules! tcpv4 {
     ($name:ident,$a:expr,$b:expr,$c:expr,$d:expr) => {
@@ -51,6 +51,7 @@ tcpv4!(cloudflare, 1, 1, 1, 1);
 pub struct TcpClient {
     pool: tcp::Pool,
     timeout: Duration,
+    opts: RequestOptions,
 }
 
 impl TcpClient {
@@ -59,6 +60,9 @@ impl TcpClient {
             addr,
             timeout: Duration::from_secs(5),
             source: None,
+            proxy: super::default_proxy(),
+            pool_size: tcp::DEFAULT_POOL_SIZE,
+            opts: Default::default(),
         }
     }
 
@@ -76,7 +80,11 @@ impl TcpClient {
         w.flush().await?;
 
         match r.next().await {
-            Some(next) => next,
+            Some(next) => {
+                let res = next?;
+                res.validate()?;
+                Ok(res)
+            }
             None => bail!(crate::Error::ResolveNothing),
         }
     }
@@ -84,7 +92,7 @@ impl TcpClient {
 
 impl Display for TcpClient {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let addr = self.pool.manager().key().0;
+        let addr = self.pool.manager().key().addr;
         if addr.port() == crate::DEFAULT_UDP_PORT {
             write!(f, "tcp://{}", addr.ip())?;
         } else {
@@ -97,6 +105,13 @@ impl Display for TcpClient {
 #[async_trait]
 impl Client for TcpClient {
     async fn request(&self, req: &Message) -> Result<Message> {
+        let req = self.opts.prepare(req);
+        self.opts.retry(|| self.request_(&req)).await
+    }
+}
+
+impl TcpClient {
+    async fn request_(&self, req: &Message) -> Result<Message> {
         // TODO: implement multiplexing
         let mut obj = self
             .pool
@@ -119,6 +134,9 @@ pub struct TcpClientBuilder {
     addr: SocketAddr,
     timeout: Duration,
     source: Option<SocketAddr>,
+    proxy: Option<Proxy>,
+    pool_size: usize,
+    opts: RequestOptions,
 }
 
 impl TcpClientBuilder {
@@ -132,15 +150,79 @@ impl TcpClientBuilder {
         self
     }
 
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy.replace(proxy);
+        self
+    }
+
+    pub fn pool_size(mut self, pool_size: usize) -> Self {
+        self.pool_size = pool_size;
+        self
+    }
+
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.opts.retries = retries;
+        self
+    }
+
+    pub fn edns_size(mut self, size: u16) -> Self {
+        self.opts.edns_size.replace(size);
+        self
+    }
+
+    pub fn ecs(mut self, ecs: ClientSubnet) -> Self {
+        self.opts.ecs.replace(ecs);
+        self
+    }
+
+    /// Applies the options of DNS url.
+    pub fn options(mut self, opts: &DNSOptions) -> Self {
+        if let Some(timeout) = opts.timeout {
+            self = self.timeout(timeout);
+        }
+        if let Some(retries) = opts.retries {
+            self = self.retries(retries);
+        }
+        if let Some(size) = opts.edns_size {
+            self = self.edns_size(size);
+        }
+        if let Some(ecs) = opts.ecs {
+            self = self.ecs(ecs);
+        }
+        if let Some(source) = opts.source {
+            self = self.source(source);
+        }
+        if let Some(pool_size) = opts.pool_size {
+            self = self.pool_size(pool_size);
+        }
+        if let Some(proxy) = &opts.proxy {
+            self = self.proxy(Clone::clone(proxy));
+        }
+        self
+    }
+
     pub fn build(self) -> Result<TcpClient> {
         let Self {
             addr,
             timeout,
             source,
+            proxy,
+            pool_size,
+            opts,
         } = self;
-        let pool = tcp::get((addr, source))?;
+        let pool = tcp::get(tcp::Key {
+            source,
+            proxy,
+            pool_size,
+            timeout,
+            ..tcp::Key::new(addr)
+        })?;
 
-        Ok(TcpClient { pool, timeout })
+        Ok(TcpClient {
+            pool,
+            timeout,
+            opts,
+        })
     }
 }
 
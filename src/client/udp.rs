@@ -12,10 +12,11 @@ use tokio::sync::{mpsc, oneshot, Mutex, RwLock};
 use tokio_util::codec::BytesCodec;
 use tokio_util::udp::UdpFramed;
 
-use crate::protocol::Message;
+use crate::misc::proxy::Socks5UdpRelay;
+use crate::protocol::{ClientSubnet, DNSOptions, Message, Proxy};
 use crate::{Error as ZeroError, Result};
 
-use super::Client;
+use super::{Client, RequestOptions};
 
 macro_rules! udpv4 {
     ($name:ident,$ip:expr) => {
@@ -60,6 +61,9 @@ udpv4!(quad9, "9.9.9.9", "149.112.112.112");
 pub struct UdpClient {
     addr: SocketAddr,
     timeout: Duration,
+    source: Option<SocketAddr>,
+    proxy: Option<Proxy>,
+    opts: RequestOptions,
 }
 
 impl Display for UdpClient {
@@ -80,31 +84,37 @@ impl UdpClient {
             inner: Self {
                 addr,
                 timeout: Duration::from_secs(15),
+                source: None,
+                proxy: None,
+                opts: Default::default(),
             },
         }
     }
 }
 
-static DEFAULT_MULTIPLEX_UDP_CLIENTS: Lazy<RwLock<HashMap<SocketAddr, MultiplexUdpClient>>> =
+/// (nameserver, source)
+type RequesterKey = (SocketAddr, Option<SocketAddr>);
+
+static DEFAULT_MULTIPLEX_UDP_CLIENTS: Lazy<RwLock<HashMap<RequesterKey, MultiplexUdpClient>>> =
     Lazy::new(Default::default);
 
 #[inline]
-async fn requester(addr: SocketAddr) -> Result<MultiplexUdpClient> {
+async fn requester(key: RequesterKey) -> Result<MultiplexUdpClient> {
     {
         let r = DEFAULT_MULTIPLEX_UDP_CLIENTS.read().await;
-        if let Some(v) = r.get(&addr) {
+        if let Some(v) = r.get(&key) {
             return Ok(Clone::clone(v));
         }
     }
 
     let mut w = DEFAULT_MULTIPLEX_UDP_CLIENTS.write().await;
 
-    if let Some(v) = w.get(&addr) {
+    if let Some(v) = w.get(&key) {
         return Ok(Clone::clone(v));
     }
 
-    let c = MultiplexUdpClient::new(addr).await?;
-    w.insert(addr, Clone::clone(&c));
+    let c = MultiplexUdpClient::new(key.0, key.1).await?;
+    w.insert(key, Clone::clone(&c));
 
     Ok(c)
 }
@@ -119,9 +129,9 @@ struct MultiplexUdpClient {
 }
 
 impl MultiplexUdpClient {
-    async fn new(nameserver: SocketAddr) -> Result<MultiplexUdpClient> {
+    async fn new(nameserver: SocketAddr, source: Option<SocketAddr>) -> Result<MultiplexUdpClient> {
         let socket = {
-            let (socket, source) = match &nameserver {
+            let (socket, unspecified) = match &nameserver {
                 SocketAddr::V4(_) => (
                     socket2::Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?,
                     SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)),
@@ -132,6 +142,7 @@ impl MultiplexUdpClient {
                 ),
             };
 
+            let source = source.unwrap_or(unspecified);
             let addr = socket2::SockAddr::from(source);
 
             socket
@@ -165,6 +176,10 @@ impl MultiplexUdpClient {
                 while let Some(next) = stream.next().await {
                     if let Ok((b, remote)) = next {
                         let msg = Message::from(b);
+                        if let Err(e) = msg.validate() {
+                            debug!("drop malformed dns response from {}: {}", remote, e);
+                            continue;
+                        }
                         let id = msg.id();
                         let handler = {
                             let mut w = handlers.lock().await;
@@ -270,9 +285,43 @@ impl MultiplexUdpClient {
 #[async_trait::async_trait]
 impl Client for UdpClient {
     async fn request(&self, req: &Message) -> Result<Message> {
-        let w = requester(self.addr).await?;
-        let res = w.request(req, self.timeout).await?;
-        Ok(res)
+        let req = self.opts.prepare(req);
+        match &self.proxy {
+            None => {
+                let w = requester((self.addr, self.source)).await?;
+                self.opts.retry(|| w.request(&req, self.timeout)).await
+            }
+            Some(proxy) => {
+                self.opts
+                    .retry(|| async {
+                        tokio::time::timeout(self.timeout, self.request_by_proxy(proxy, &req))
+                            .await?
+                    })
+                    .await
+            }
+        }
+    }
+}
+
+impl UdpClient {
+    /// Sends the request by the UDP ASSOCIATE of SOCKS5 proxy.
+    async fn request_by_proxy(&self, proxy: &Proxy, req: &Message) -> Result<Message> {
+        let relay = Socks5UdpRelay::associate(proxy, self.source).await?;
+        relay.send_to(req.as_ref(), self.addr).await?;
+        loop {
+            let (b, from) = relay.recv_from().await?;
+            if from != self.addr {
+                continue;
+            }
+            let res = Message::from(b);
+            if let Err(e) = res.validate() {
+                debug!("drop malformed dns response from {}: {}", from, e);
+                continue;
+            }
+            if res.id() == req.id() {
+                return Ok(res);
+            }
+        }
     }
 }
 
@@ -286,6 +335,55 @@ impl UdpClientBuilder {
         self
     }
 
+    pub fn source(mut self, source: SocketAddr) -> Self {
+        self.inner.source.replace(source);
+        self
+    }
+
+    /// Sends requests through the proxy, only SOCKS5 proxy is supported.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.inner.proxy.replace(proxy);
+        self
+    }
+
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.inner.opts.retries = retries;
+        self
+    }
+
+    pub fn edns_size(mut self, size: u16) -> Self {
+        self.inner.opts.edns_size.replace(size);
+        self
+    }
+
+    pub fn ecs(mut self, ecs: ClientSubnet) -> Self {
+        self.inner.opts.ecs.replace(ecs);
+        self
+    }
+
+    /// Applies the options of DNS url.
+    pub fn options(mut self, opts: &DNSOptions) -> Self {
+        if let Some(timeout) = opts.timeout {
+            self = self.timeout(timeout);
+        }
+        if let Some(retries) = opts.retries {
+            self = self.retries(retries);
+        }
+        if let Some(size) = opts.edns_size {
+            self = self.edns_size(size);
+        }
+        if let Some(ecs) = opts.ecs {
+            self = self.ecs(ecs);
+        }
+        if let Some(source) = opts.source {
+            self = self.source(source);
+        }
+        if let Some(proxy) = &opts.proxy {
+            self = self.proxy(Clone::clone(proxy));
+        }
+        self
+    }
+
     pub fn build(self) -> UdpClient {
         self.inner
     }
@@ -4,7 +4,7 @@ use clap::ArgMatches;
 use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
 use zerodns::client::request;
-use zerodns::protocol::{AdditionalRR, Class, Flags, Kind, Message, DNS};
+use zerodns::protocol::{AdditionalRR, Class, Flags, Kind, Message, RecordOwned, DNS, RR};
 
 pub(crate) async fn execute(sm: &ArgMatches) -> Result<()> {
     // --timeout 5
@@ -52,7 +52,10 @@ pub(crate) async fn execute(sm: &ArgMatches) -> Result<()> {
                     ScopedIp::V4(v4) => IpAddr::V4(*v4),
                     ScopedIp::V6(v6, _) => IpAddr::V6(*v6),
                 };
-                DNS::UDP(SocketAddr::new(ipaddr, zerodns::DEFAULT_UDP_PORT))
+                DNS::UDP(
+                    SocketAddr::new(ipaddr, zerodns::DEFAULT_UDP_PORT),
+                    Default::default(),
+                )
             }
             Some(s) => s.parse::<DNS>()?,
         }
@@ -144,7 +147,9 @@ fn print_resolve_result(
                 pseude.extended_rcode(),
                 pseude.udp_payload_size()
             );
-            // TODO: print pseude data pairs
+            for option in pseude.options() {
+                println!("; {}", option);
+            }
         }
     }
 
@@ -163,14 +168,7 @@ fn print_resolve_result(
     println!(";; ANSWER SECTION:");
 
     for answer in res.answers() {
-        println!(
-            "{}.\t{}\t{}\t{}\t{}",
-            answer.name(),
-            answer.time_to_live(),
-            answer.class(),
-            answer.kind(),
-            answer.rdata()?
-        );
+        print_record(&answer)?;
     }
 
     println!();
@@ -180,14 +178,7 @@ fn print_resolve_result(
         .filter(|it| matches!(it, AdditionalRR::RR(_)))
     {
         if let AdditionalRR::RR(rr) = next {
-            println!(
-                "{}.\t{}\t{}\t{}\t{}",
-                rr.name(),
-                rr.time_to_live(),
-                rr.class(),
-                rr.kind(),
-                rr.rdata()?
-            );
+            print_record(&rr)?;
         }
     }
 
@@ -199,3 +190,20 @@ fn print_resolve_result(
 
     Ok(())
 }
+
+/// Prints the record in zone file format, which can be consumed by other tools.
+#[inline]
+fn print_record(rr: &RR<'_>) -> Result<()> {
+    match RecordOwned::try_from(rr) {
+        Ok(record) => println!("{}", record),
+        Err(_) => println!(
+            "{}.\t{}\t{}\t{}\t{}",
+            rr.name(),
+            rr.time_to_live(),
+            rr.class(),
+            rr.kind(),
+            rr.rdata()?
+        ),
+    }
+    Ok(())
+}
@@ -53,6 +53,11 @@ pub(crate) async fn execute(sm: &ArgMatches) -> Result<()> {
         }
     }
 
+    // set global proxy of upstreams
+    if let Some(proxy) = &c.global.proxy {
+        zerodns::client::set_default_proxy(Some(proxy.parse()?));
+    }
+
     // initialize built-in modules
     zerodns::setup();
 
@@ -13,6 +13,8 @@ pub struct Config {
     pub server: ServerConfig,
     pub filters: HashMap<String, Filter>,
     pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub views: Vec<View>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,11 +30,58 @@ pub struct GlobalConfig {
     pub resolv_file: Option<String>,
     pub hosts_file: Option<String>,
     pub cache_size: Option<usize>,
+    pub proxy: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub listen: String,
+    #[serde(flatten)]
+    pub acl: AclConfig,
+    /// The acl overrides of udp listener.
+    pub udp: Option<AclConfig>,
+    /// The acl overrides of tcp listener.
+    pub tcp: Option<AclConfig>,
+    /// The rate limit of udp clients.
+    pub rate_limit: Option<RateLimitConfig>,
+    /// The max count of in-flight requests, the new requests wait until some are finished.
+    pub max_inflight: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    /// The queries per second of each client address.
+    pub client_rate: Option<u32>,
+    pub client_burst: Option<u32>,
+    /// The queries per second of each client network.
+    pub prefix_rate: Option<u32>,
+    pub prefix_burst: Option<u32>,
+    pub ipv4_prefix: Option<u8>,
+    pub ipv6_prefix: Option<u8>,
+    /// Answers every 'slip' over-limit query with TC=1, 0 means dropping all of them.
+    pub slip: Option<u32>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct AclConfig {
+    pub allow: Option<Vec<String>>,
+    pub deny: Option<Vec<String>>,
+    /// The action of rejected clients: 'refused' or 'drop'.
+    pub reject: Option<String>,
+}
+
+impl AclConfig {
+    /// Merges the overrides of a listener, the fields given by the listener win.
+    pub fn merge(&self, overrides: Option<&AclConfig>) -> AclConfig {
+        match overrides {
+            Some(o) => AclConfig {
+                allow: o.allow.as_ref().or(self.allow.as_ref()).cloned(),
+                deny: o.deny.as_ref().or(self.deny.as_ref()).cloned(),
+                reject: o.reject.as_ref().or(self.reject.as_ref()).cloned(),
+            },
+            None => Clone::clone(self),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,6 +97,14 @@ pub struct Rule {
     pub filters: Vec<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct View {
+    pub name: String,
+    #[serde(default)]
+    pub clients: Vec<String>,
+    pub rules: Vec<Rule>,
+}
+
 pub fn read_from_toml(pt: &PathBuf) -> anyhow::Result<Config> {
     let b = std::fs::read(pt)?;
     let s = String::from_utf8(b)?;
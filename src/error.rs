@@ -10,6 +10,9 @@ pub(crate) enum Error {
     #[error("invalid request format '{0}'")]
     InvalidRequestFormat(Cow<'static, str>),
 
+    #[error("malformed message at offset {0}: {1}")]
+    MalformedMessage(usize, Cow<'static, str>),
+
     #[error("invalid configuration '{0}'")]
     InvalidConfig(Cow<'static, str>),
 
@@ -28,6 +31,9 @@ pub(crate) enum Error {
     #[error("resolve returns nothing")]
     ResolveNothing,
 
+    #[error("request is dropped")]
+    Dropped,
+
     #[error(transparent)]
     Other(#[from] anyhow::Error), // source and Display delegate to anyhow::Error
 }
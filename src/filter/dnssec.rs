@@ -0,0 +1,2023 @@
+use crate::cachestr::Cachestr;
+use crate::protocol::{
+    base32hex, Class, EdnsOption, Flags, Kind, Message, MessageOwned, RCode, RData, Section,
+    DNSKEY, RR,
+};
+use crate::Result;
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use hashbrown::HashMap;
+use moka::future::Cache;
+use moka::Expiry;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::{handle_next, Context, Filter, FilterFactory, Options};
+
+/// The DS records of root KSK-2017 and KSK-2024, see https://data.iana.org/root-anchors/root-anchors.xml.
+const DEFAULT_TRUST_ANCHORS: [&str; 2] = [
+    ". 20326 8 2 E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D",
+    ". 38696 8 2 683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16",
+];
+
+/// The max iterations of NSEC3 hash, the responses with more iterations are treated as insecure,
+/// see RFC 9276 3.2.
+const MAX_NSEC3_ITERATIONS: u16 = 150;
+
+/// The info codes of Extended DNS Errors, see RFC 8914 4.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ExtendedError {
+    DnssecBogus = 6,
+    SignatureExpired = 7,
+    SignatureNotYetValid = 8,
+    DnskeyMissing = 9,
+    RrsigsMissing = 10,
+    NsecMissing = 12,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("{reason}")]
+struct Bogus {
+    code: ExtendedError,
+    reason: String,
+}
+
+impl Bogus {
+    fn error<S: Into<String>>(code: ExtendedError, reason: S) -> anyhow::Error {
+        anyhow::Error::new(Self {
+            code,
+            reason: reason.into(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TrustAnchor {
+    zone: String,
+    key_tag: u16,
+    algorithm: u8,
+    digest_type: u8,
+    digest: Vec<u8>,
+}
+
+impl std::str::FromStr for TrustAnchor {
+    type Err = anyhow::Error;
+
+    /// Parses the DS record in presentation format, eg: '. 20326 8 2 E06D44B8...'.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let fields = s
+            .split_whitespace()
+            .filter(|it| !it.eq_ignore_ascii_case("IN") && !it.eq_ignore_ascii_case("DS"))
+            .collect::<Vec<_>>();
+        if fields.len() < 5 {
+            bail!("invalid trust anchor '{}'", s);
+        }
+        Ok(Self {
+            zone: normalize(fields[0]),
+            key_tag: fields[1].parse()?,
+            algorithm: fields[2].parse()?,
+            digest_type: fields[3].parse()?,
+            digest: hex::decode(fields[4..].concat())?,
+        })
+    }
+}
+
+/// Converts the name to lowercase without the trailing dot, the root is an empty string.
+#[inline]
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[inline]
+fn labels(name: &str) -> Vec<&str> {
+    if name.is_empty() {
+        vec![]
+    } else {
+        name.split('.').collect()
+    }
+}
+
+/// Checks if the name equals to or is a subdomain of the zone.
+#[inline]
+fn is_subdomain(name: &str, zone: &str) -> bool {
+    zone.is_empty()
+        || name == zone
+        || (name.len() > zone.len()
+            && name.ends_with(zone)
+            && name.as_bytes()[name.len() - zone.len() - 1] == b'.')
+}
+
+/// Returns the ancestor of name which has the given count of labels.
+#[inline]
+fn ancestor(name: &str, n: usize) -> String {
+    let labels = labels(name);
+    labels[labels.len().saturating_sub(n)..].join(".")
+}
+
+/// Returns the name in canonical wire format, see RFC 4034 6.2.
+fn to_wire(name: &str) -> Vec<u8> {
+    let mut b = Vec::with_capacity(name.len() + 2);
+    for label in labels(name) {
+        b.push(label.len() as u8);
+        b.extend(label.bytes().map(|it| it.to_ascii_lowercase()));
+    }
+    b.push(0);
+    b
+}
+
+/// Compares names in canonical order, see RFC 4034 6.1.
+fn canonical_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    labels(a)
+        .into_iter()
+        .rev()
+        .map(str::as_bytes)
+        .cmp(labels(b).into_iter().rev().map(str::as_bytes))
+}
+
+/// Checks if the name is covered by the interval (owner, next) of NSEC/NSEC3.
+fn is_covered<T, F>(owner: T, next: T, name: T, cmp: F) -> bool
+where
+    F: Fn(&T, &T) -> std::cmp::Ordering,
+{
+    use std::cmp::Ordering::*;
+    if cmp(&owner, &next) == Less {
+        cmp(&owner, &name) == Less && cmp(&name, &next) == Less
+    } else {
+        // the last one of the chain
+        cmp(&owner, &name) == Less || cmp(&name, &next) == Less
+    }
+}
+
+#[inline]
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|it| it.as_secs() as u32)
+        .unwrap_or_default()
+}
+
+fn digest(digest_type: u8, data: &[u8]) -> Option<Vec<u8>> {
+    use ring::digest;
+    let alg = match digest_type {
+        1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        2 => &digest::SHA256,
+        4 => &digest::SHA384,
+        _ => return None,
+    };
+    Some(digest::digest(alg, data).as_ref().to_vec())
+}
+
+#[inline]
+fn is_supported_algorithm(algorithm: u8) -> bool {
+    matches!(algorithm, 5 | 7 | 8 | 10 | 13 | 14 | 15)
+}
+
+/// Verifies the signature by the public key of DNSKEY, see RFC 3110, RFC 6605 and RFC 8080.
+fn verify_signature(algorithm: u8, public_key: &[u8], message: &[u8], sig: &[u8]) -> bool {
+    use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+
+    match algorithm {
+        5 | 7 | 8 | 10 => {
+            let (e, n) = match public_key.split_first() {
+                Some((0, rest)) if rest.len() > 2 => {
+                    let size = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+                    let rest = &rest[2..];
+                    if rest.len() <= size {
+                        return false;
+                    }
+                    rest.split_at(size)
+                }
+                Some((&size, rest)) if rest.len() > size as usize => rest.split_at(size as usize),
+                _ => return false,
+            };
+            let params = match algorithm {
+                8 => &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+                10 => &signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY,
+                _ => &signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY,
+            };
+            RsaPublicKeyComponents { n, e }
+                .verify(params, message, sig)
+                .is_ok()
+        }
+        13 | 14 => {
+            let mut key = Vec::with_capacity(public_key.len() + 1);
+            key.push(0x04);
+            key.extend_from_slice(public_key);
+            let alg = if algorithm == 13 {
+                &signature::ECDSA_P256_SHA256_FIXED
+            } else {
+                &signature::ECDSA_P384_SHA384_FIXED
+            };
+            UnparsedPublicKey::new(alg, key)
+                .verify(message, sig)
+                .is_ok()
+        }
+        15 => UnparsedPublicKey::new(&signature::ED25519, public_key)
+            .verify(message, sig)
+            .is_ok(),
+        _ => false,
+    }
+}
+
+/// Computes the hashed owner name of NSEC3, see RFC 5155 5.
+fn nsec3_hash(name: &str, salt: &[u8], iterations: u16) -> String {
+    use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+
+    let mut b = to_wire(name);
+    b.extend_from_slice(salt);
+    let mut h = digest(&SHA1_FOR_LEGACY_USE_ONLY, &b);
+    for _ in 0..iterations {
+        let mut b = h.as_ref().to_vec();
+        b.extend_from_slice(salt);
+        h = digest(&SHA1_FOR_LEGACY_USE_ONLY, &b);
+    }
+    base32hex(h.as_ref()).to_ascii_lowercase()
+}
+
+#[derive(Debug, Clone)]
+struct Signature {
+    algorithm: u8,
+    labels: u8,
+    original_ttl: u32,
+    expiration: u32,
+    inception: u32,
+    key_tag: u16,
+    signer: String,
+    /// the RRSIG RDATA without the signature field, whose signer name is canonical.
+    prefix: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct RRset {
+    name: String,
+    kind: u16,
+    class: u16,
+    /// the minimum TTL of records
+    ttl: u32,
+    rdatas: Vec<Vec<u8>>,
+    signatures: Vec<Signature>,
+}
+
+impl RRset {
+    /// Groups the records into RRsets, and attaches the RRSIGs to the covered RRsets.
+    fn collect<'a, I>(records: I) -> Result<Vec<RRset>>
+    where
+        I: Iterator<Item = RR<'a>>,
+    {
+        let mut sets = HashMap::<(String, u16), RRset>::new();
+        for rr in records {
+            let name = normalize(&rr.name().to_string());
+            match rr.rdata()? {
+                RData::RRSIG(sig) => {
+                    let signer = normalize(&sig.signer_name().to_string());
+                    let mut prefix = rr.data()[..18].to_vec();
+                    prefix.extend(to_wire(&signer));
+                    let ent = sets
+                        .entry((Clone::clone(&name), sig.type_covered()))
+                        .or_insert_with(|| RRset {
+                            name,
+                            kind: sig.type_covered(),
+                            class: rr.class_code(),
+                            ..Default::default()
+                        });
+                    ent.signatures.push(Signature {
+                        algorithm: sig.algorithm(),
+                        labels: sig.labels(),
+                        original_ttl: sig.original_ttl(),
+                        expiration: sig.expiration(),
+                        inception: sig.inception(),
+                        key_tag: sig.key_tag(),
+                        signer,
+                        prefix,
+                        signature: sig.signature().to_vec(),
+                    });
+                }
+                _ => {
+                    let kind = rr.kind_code();
+                    let data = rr.expanded_data(true)?.into_owned();
+                    let ent = sets
+                        .entry((Clone::clone(&name), kind))
+                        .or_insert_with(|| RRset {
+                            name,
+                            kind,
+                            class: rr.class_code(),
+                            ..Default::default()
+                        });
+                    ent.ttl = if ent.rdatas.is_empty() {
+                        rr.time_to_live()
+                    } else {
+                        ent.ttl.min(rr.time_to_live())
+                    };
+                    if !ent.rdatas.contains(&data) {
+                        ent.rdatas.push(data);
+                    }
+                }
+            }
+        }
+
+        Ok(sets
+            .into_values()
+            .filter(|it| !it.rdatas.is_empty())
+            .map(|mut it| {
+                it.rdatas.sort();
+                it
+            })
+            .collect())
+    }
+
+    fn is_wildcard_expanded(&self) -> Option<&Signature> {
+        let n = labels(&self.name).len();
+        self.signatures.iter().find(|it| (it.labels as usize) < n)
+    }
+}
+
+/// Returns the data to be signed, see RFC 4034 3.1.8.1.
+fn signed_data(sig: &Signature, rrset: &RRset) -> Vec<u8> {
+    let owner = {
+        let labels = labels(&rrset.name);
+        if (sig.labels as usize) < labels.len() {
+            // wildcard expansion, see RFC 4035 5.3.2
+            let mut owner = String::from("*");
+            for label in &labels[labels.len() - sig.labels as usize..] {
+                owner.push('.');
+                owner.push_str(label);
+            }
+            to_wire(&owner)
+        } else {
+            to_wire(&rrset.name)
+        }
+    };
+
+    let mut b = Clone::clone(&sig.prefix);
+    for rdata in &rrset.rdatas {
+        b.extend_from_slice(&owner);
+        b.extend_from_slice(&rrset.kind.to_be_bytes());
+        b.extend_from_slice(&rrset.class.to_be_bytes());
+        b.extend_from_slice(&sig.original_ttl.to_be_bytes());
+        b.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        b.extend_from_slice(rdata);
+    }
+    b
+}
+
+/// Verifies the RRset by the DNSKEYs of zone.
+/// Verifies the RRset by the keys of zone, returns how long the validated RRset can be trusted,
+/// which is limited by both the TTL and the expiration of RRSIG.
+fn verify_rrset(rrset: &RRset, zone: &str, keys: &[Vec<u8>]) -> Result<Duration> {
+    let now = now();
+    let mut err = Bogus::error(
+        ExtendedError::RrsigsMissing,
+        format!(
+            "no RRSIG of zone '{}.' for '{}.' type {}",
+            zone, rrset.name, rrset.kind
+        ),
+    );
+
+    for sig in rrset.signatures.iter().filter(|it| it.signer == zone) {
+        if sig.labels as usize > labels(&rrset.name).len() {
+            continue;
+        }
+        // serial number arithmetic, see RFC 4034 3.1.5
+        if (sig.expiration.wrapping_sub(now) as i32) < 0 {
+            err = Bogus::error(
+                ExtendedError::SignatureExpired,
+                format!("RRSIG of '{}.' is expired", rrset.name),
+            );
+            continue;
+        }
+        if (now.wrapping_sub(sig.inception) as i32) < 0 {
+            err = Bogus::error(
+                ExtendedError::SignatureNotYetValid,
+                format!("RRSIG of '{}.' is not yet valid", rrset.name),
+            );
+            continue;
+        }
+
+        let data = signed_data(sig, rrset);
+        let mut found = false;
+        for key in keys.iter().map(|it| DNSKEY::new(it)) {
+            if key.algorithm() != sig.algorithm || key.key_tag() != sig.key_tag {
+                continue;
+            }
+            found = true;
+            if verify_signature(sig.algorithm, key.public_key(), &data, &sig.signature) {
+                let ttl = rrset
+                    .ttl
+                    .min(sig.original_ttl)
+                    .min(sig.expiration.wrapping_sub(now));
+                return Ok(Duration::from_secs(ttl as u64));
+            }
+        }
+
+        err = if found {
+            Bogus::error(
+                ExtendedError::DnssecBogus,
+                format!("invalid RRSIG of '{}.'", rrset.name),
+            )
+        } else {
+            Bogus::error(
+                ExtendedError::DnskeyMissing,
+                format!("no DNSKEY of zone '{}.' with tag {}", zone, sig.key_tag),
+            )
+        };
+    }
+
+    Err(err)
+}
+
+/// The validated state of a name, which is cached by the validator.
+#[derive(Debug, Clone)]
+enum Cut {
+    /// the name is a signed zone, with its validated DNSKEYs.
+    Secure(Arc<Vec<Vec<u8>>>),
+    /// the name is not a zone cut.
+    None,
+    /// the name is an unsigned delegation.
+    Insecure,
+}
+
+/// The cached cut, which expires after the lifetime of its proofs.
+#[derive(Debug, Clone)]
+struct Cached {
+    cut: Cut,
+    ttl: Duration,
+}
+
+struct CutExpiry;
+
+impl Expiry<Cachestr, Cached> for CutExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &Cachestr,
+        value: &Cached,
+        _created_at: Instant,
+    ) -> Option<Duration> {
+        Some(value.ttl)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    Secure {
+        zone: String,
+        keys: Arc<Vec<Vec<u8>>>,
+    },
+    Insecure,
+}
+
+/// Checks if the types are of a delegation point, which has NS but no SOA.
+fn is_delegation(types: &[u16]) -> bool {
+    types.contains(&(Kind::NS as u16)) && !types.contains(&(Kind::SOA as u16))
+}
+
+/// Denial of existence, see RFC 4035 5.4 and RFC 5155 8.
+struct Denial<'a> {
+    msg: &'a Message,
+}
+
+impl Denial<'_> {
+    /// Proves the name or the type doesn't exist, the 'kind' is None for NXDOMAIN.
+    /// Returns false if the proof is insecure because of NSEC3 opt-out or too many iterations.
+    fn prove(&self, qname: &str, kind: Option<Kind>) -> Result<bool> {
+        let mut nsec = vec![];
+        let mut nsec3 = vec![];
+        for rr in self.msg.authorities() {
+            match rr.rdata()? {
+                RData::NSEC(it) => nsec.push((
+                    normalize(&rr.name().to_string()),
+                    normalize(&it.next_domain_name().to_string()),
+                    it.type_bit_maps().iter().collect::<Vec<_>>(),
+                )),
+                RData::NSEC3(it) => {
+                    if it.hash_algorithm() != 1 || it.iterations() > MAX_NSEC3_ITERATIONS {
+                        return Ok(false);
+                    }
+                    let owner = normalize(&rr.name().to_string());
+                    let hash = owner.split('.').next().unwrap_or_default().to_string();
+                    nsec3.push((
+                        hash,
+                        base32hex(it.next_hashed_owner_name()).to_ascii_lowercase(),
+                        it.type_bit_maps().iter().collect::<Vec<_>>(),
+                        it.is_opt_out(),
+                        it.salt().to_vec(),
+                        it.iterations(),
+                    ));
+                }
+                _ => (),
+            }
+        }
+
+        let missing = || {
+            Bogus::error(
+                ExtendedError::NsecMissing,
+                format!("no denial of existence for '{}.'", qname),
+            )
+        };
+
+        // the NSEC of a delegation is signed by the parent, it proves nothing at or below the
+        // delegation except the DS at the cut, see RFC 4035 5.4 and RFC 6840 4.1.
+        let is_ds = kind == Some(Kind::DS);
+        let usable = |owner: &str, types: &[u16], name: &str| {
+            !is_delegation(types) || !is_subdomain(name, owner) || (is_ds && name == owner)
+        };
+
+        if !nsec.is_empty() {
+            let covered = |name: &str| {
+                nsec.iter().any(|(owner, next, types)| {
+                    usable(owner, types, name)
+                        && is_covered(&owner[..], &next[..], name, |a, b| canonical_cmp(a, b))
+                })
+            };
+            let no_type = |name: &str, kind: Kind| {
+                nsec.iter().any(|(owner, _, types)| {
+                    owner == name
+                        && usable(owner, types, name)
+                        && !types.contains(&(kind as u16))
+                        && !types.contains(&(Kind::CNAME as u16))
+                })
+            };
+            // the source of synthesis, the closest encloser is the longest common ancestor
+            // with the covering NSEC
+            let wildcard = || {
+                let ce = nsec
+                    .iter()
+                    .filter(|(owner, next, types)| {
+                        usable(owner, types, qname)
+                            && is_covered(&owner[..], &next[..], qname, |a, b| canonical_cmp(a, b))
+                    })
+                    .flat_map(|(owner, next, _)| [owner, next])
+                    .map(|it| common_ancestor(qname, it))
+                    .max_by_key(|it| labels(it).len())
+                    .unwrap_or_default();
+                if ce.is_empty() {
+                    "*".to_string()
+                } else {
+                    format!("*.{}", ce)
+                }
+            };
+            return match kind {
+                Some(kind) => {
+                    if nsec.iter().any(|(owner, _, _)| owner == qname) {
+                        return if no_type(qname, kind) {
+                            Ok(true)
+                        } else {
+                            Err(missing())
+                        };
+                    }
+                    // wildcard NODATA, see RFC 4035 3.1.3.4: the qname doesn't exist, and the
+                    // matching wildcard doesn't have the type.
+                    if covered(qname) && no_type(&wildcard(), kind) {
+                        Ok(true)
+                    } else {
+                        Err(missing())
+                    }
+                }
+                None => {
+                    if covered(qname) && covered(&wildcard()) {
+                        Ok(true)
+                    } else {
+                        Err(missing())
+                    }
+                }
+            };
+        }
+
+        if let Some((_, _, _, _, salt, iterations)) = nsec3.first() {
+            let hash = |name: &str| nsec3_hash(name, salt, *iterations);
+            let matched = |h: &str| nsec3.iter().find(|it| it.0 == h);
+            let covering = |h: &str| {
+                nsec3
+                    .iter()
+                    .find(|it| is_covered(&it.0[..], &it.1[..], h, |a, b| a.cmp(b)))
+            };
+
+            if let Some(kind) = kind {
+                if let Some((_, _, types, _, _, _)) = matched(&hash(qname)) {
+                    return if (!is_delegation(types) || is_ds)
+                        && !types.contains(&(kind as u16))
+                        && !types.contains(&(Kind::CNAME as u16))
+                    {
+                        Ok(true)
+                    } else {
+                        Err(missing())
+                    };
+                }
+            }
+
+            // closest encloser proof, see RFC 5155 8.3
+            let n = labels(qname).len();
+            for i in (0..n).rev() {
+                let ce = ancestor(qname, i);
+                match matched(&hash(&ce)) {
+                    None => continue,
+                    // the closest encloser of a delegation, the name is in the child zone
+                    Some((_, _, types, _, _, _)) if is_delegation(types) => return Err(missing()),
+                    Some(_) => (),
+                }
+                let next_closer = ancestor(qname, i + 1);
+                return match covering(&hash(&next_closer)) {
+                    Some(it) if it.3 => Ok(false),
+                    Some(_) => {
+                        let wildcard = if ce.is_empty() {
+                            "*".to_string()
+                        } else {
+                            format!("*.{}", ce)
+                        };
+                        let proved = match kind {
+                            // wildcard NODATA, see RFC 5155 8.7
+                            Some(kind) => matches!(
+                                matched(&hash(&wildcard)),
+                                Some((_, _, types, _, _, _))
+                                    if !types.contains(&(kind as u16))
+                                        && !types.contains(&(Kind::CNAME as u16))
+                            ),
+                            None => covering(&hash(&wildcard)).is_some(),
+                        };
+                        if proved {
+                            Ok(true)
+                        } else {
+                            Err(missing())
+                        }
+                    }
+                    None => Err(missing()),
+                };
+            }
+        }
+
+        Err(missing())
+    }
+
+    /// Proves there's no closer match for the wildcard expanded answer, see RFC 4035 5.3.4.
+    fn prove_wildcard(&self, qname: &str, labels_of_sig: u8) -> Result<bool> {
+        let next_closer = ancestor(qname, labels_of_sig as usize + 1);
+        for rr in self.msg.authorities() {
+            match rr.rdata()? {
+                RData::NSEC(it) => {
+                    let owner = normalize(&rr.name().to_string());
+                    let next = normalize(&it.next_domain_name().to_string());
+                    if is_covered(&owner[..], &next[..], qname, |a, b| canonical_cmp(a, b)) {
+                        return Ok(true);
+                    }
+                }
+                RData::NSEC3(it) => {
+                    if it.hash_algorithm() != 1 || it.iterations() > MAX_NSEC3_ITERATIONS {
+                        return Ok(false);
+                    }
+                    let owner = normalize(&rr.name().to_string());
+                    let owner = owner.split('.').next().unwrap_or_default();
+                    let next = base32hex(it.next_hashed_owner_name()).to_ascii_lowercase();
+                    let h = nsec3_hash(&next_closer, it.salt(), it.iterations());
+                    if is_covered(owner, &next[..], &h[..], |a, b| a.cmp(b)) {
+                        return Ok(!it.is_opt_out());
+                    }
+                }
+                _ => (),
+            }
+        }
+        Err(Bogus::error(
+            ExtendedError::NsecMissing,
+            format!("no proof of wildcard expansion for '{}.'", qname),
+        ))
+    }
+}
+
+fn common_ancestor(a: &str, b: &str) -> String {
+    let (la, lb) = (labels(a), labels(b));
+    let n = la
+        .iter()
+        .rev()
+        .zip(lb.iter().rev())
+        .take_while(|(x, y)| x.eq_ignore_ascii_case(y))
+        .count();
+    ancestor(a, n)
+}
+
+/// Validates the responses by the chain of trust from the trust anchors.
+pub(crate) struct Validator {
+    anchors: Vec<TrustAnchor>,
+    cuts: Cache<Cachestr, Cached>,
+}
+
+impl Validator {
+    /// The max lifetime of cached cuts.
+    const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(300);
+
+    async fn cache(&self, k: Cachestr, cut: &Cut, ttl: Duration) {
+        let cached = Cached {
+            cut: Clone::clone(cut),
+            ttl: ttl.min(Self::DEFAULT_CACHE_TTL),
+        };
+        self.cuts.insert(k, cached).await;
+    }
+
+    /// Sends the query by the rest of filters, with DO and CD bits.
+    async fn query(
+        next: Option<&dyn Filter>,
+        peer: Option<SocketAddr>,
+        name: &str,
+        kind: Kind,
+    ) -> Result<Message> {
+        let flags = Flags::builder()
+            .request()
+            .recursive_query(true)
+            .checking_disabled(true)
+            .build();
+        let mut req = Message::builder()
+            .id(rand::random())
+            .flags(flags)
+            .question(if name.is_empty() { "." } else { name }, kind, Class::IN)
+            .build()?;
+        req.set_dnssec_ok(true);
+
+        let mut ctx = Context {
+            peer,
+            ..Default::default()
+        };
+        let mut res = None;
+        handle_next(next, &mut ctx, &mut req, &mut res).await?;
+        res.ok_or_else(|| anyhow!("no response of '{}.' type {}", name, kind))
+    }
+
+    /// Validates the DNSKEY RRset of zone by the DS records, returns the cut and its lifetime.
+    async fn dnskeys<'a, I>(
+        &self,
+        next: Option<&dyn Filter>,
+        peer: Option<SocketAddr>,
+        zone: &str,
+        ds: I,
+    ) -> Result<(Cut, Duration)>
+    where
+        I: Iterator<Item = (u16, u8, u8, &'a [u8])>,
+    {
+        let ds = ds
+            .filter(|(_, alg, dt, _)| is_supported_algorithm(*alg) && digest(*dt, &[]).is_some())
+            .collect::<Vec<_>>();
+        if ds.is_empty() {
+            // no supported algorithm, treat it as insecure, see RFC 4035 5.2
+            return Ok((Cut::Insecure, Self::DEFAULT_CACHE_TTL));
+        }
+
+        let res = Self::query(next, peer, zone, Kind::DNSKEY).await?;
+        let rrset = RRset::collect(res.answers())?
+            .into_iter()
+            .find(|it| it.name == zone && it.kind == Kind::DNSKEY as u16)
+            .ok_or_else(|| {
+                Bogus::error(
+                    ExtendedError::DnskeyMissing,
+                    format!("no DNSKEY of zone '{}.'", zone),
+                )
+            })?;
+
+        let owner = to_wire(zone);
+        let trusted = rrset
+            .rdatas
+            .iter()
+            .filter(|rdata| {
+                let key = DNSKEY::new(rdata);
+                key.is_zone_key()
+                    && ds.iter().any(|(tag, alg, dt, d)| {
+                        *tag == key.key_tag()
+                            && *alg == key.algorithm()
+                            && digest(*dt, &[&owner[..], &rdata[..]].concat()).as_deref() == Some(d)
+                    })
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if trusted.is_empty() {
+            bail!(Bogus::error(
+                ExtendedError::DnskeyMissing,
+                format!("no DNSKEY of zone '{}.' matches the DS records", zone),
+            ));
+        }
+
+        let ttl = verify_rrset(&rrset, zone, &trusted[..])?;
+
+        let keys = rrset
+            .rdatas
+            .into_iter()
+            .filter(|it| DNSKEY::new(it).is_zone_key())
+            .collect::<Vec<_>>();
+
+        Ok((Cut::Secure(Arc::new(keys)), ttl))
+    }
+
+    /// Checks if the name is a zone cut by the DS query, the parent zone should be secure.
+    async fn cut(
+        &self,
+        next: Option<&dyn Filter>,
+        peer: Option<SocketAddr>,
+        name: &str,
+        parent: &str,
+        keys: &[Vec<u8>],
+    ) -> Result<Cut> {
+        let k = Cachestr::from(name);
+        if let Some(cached) = self.cuts.get(&k).await {
+            return Ok(cached.cut);
+        }
+
+        let res = Self::query(next, peer, name, Kind::DS).await?;
+
+        let mut ttl = Self::DEFAULT_CACHE_TTL;
+        let cut = match RRset::collect(res.answers())?
+            .into_iter()
+            .find(|it| it.name == name && it.kind == Kind::DS as u16)
+        {
+            Some(ds) => {
+                ttl = verify_rrset(&ds, parent, keys)?;
+                let records = ds
+                    .rdatas
+                    .iter()
+                    .filter(|it| it.len() > 4)
+                    .map(|it| (u16::from_be_bytes([it[0], it[1]]), it[2], it[3], &it[4..]));
+                let (cut, dnskey_ttl) = self.dnskeys(next, peer, name, records).await?;
+                ttl = ttl.min(dnskey_ttl);
+                cut
+            }
+            None => {
+                for rrset in RRset::collect(res.authorities())?
+                    .iter()
+                    .filter(|it| it.kind == Kind::NSEC as u16 || it.kind == Kind::NSEC3 as u16)
+                {
+                    ttl = ttl.min(verify_rrset(rrset, parent, keys)?);
+                }
+
+                if res.flags().response_code() == RCode::NameError {
+                    Cut::None
+                } else {
+                    let denial = Denial { msg: &res };
+                    match denial.prove(name, Some(Kind::DS)) {
+                        Ok(true) => {
+                            if Self::has_type(&res, name, Kind::NS)? {
+                                Cut::Insecure
+                            } else {
+                                Cut::None
+                            }
+                        }
+                        Ok(false) => Cut::Insecure,
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        };
+
+        self.cache(k, &cut, ttl).await;
+
+        Ok(cut)
+    }
+
+    /// Checks if the type exists in the NSEC/NSEC3 records which match the name.
+    fn has_type(res: &Message, name: &str, kind: Kind) -> Result<bool> {
+        for rr in res.authorities() {
+            match rr.rdata()? {
+                RData::NSEC(it) if normalize(&rr.name().to_string()) == name => {
+                    return Ok(it.type_bit_maps().contains(kind));
+                }
+                RData::NSEC3(it) => {
+                    let owner = normalize(&rr.name().to_string());
+                    let h = nsec3_hash(name, it.salt(), it.iterations());
+                    if owner.split('.').next() == Some(&h[..]) {
+                        return Ok(it.type_bit_maps().contains(kind));
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(false)
+    }
+
+    /// Walks from the closest trust anchor down to the name, see RFC 4035 5.
+    fn state<'a>(
+        &'a self,
+        next: Option<&'a dyn Filter>,
+        peer: Option<SocketAddr>,
+        name: &'a str,
+    ) -> BoxFuture<'a, Result<State>> {
+        Box::pin(async move {
+            let anchor = match self
+                .anchors
+                .iter()
+                .filter(|it| is_subdomain(name, &it.zone))
+                .max_by_key(|it| labels(&it.zone).len())
+            {
+                Some(anchor) => anchor,
+                None => return Ok(State::Insecure),
+            };
+
+            let mut zone = Clone::clone(&anchor.zone);
+            let mut keys = {
+                let k = Cachestr::from(zone.as_str());
+                let cut = match self.cuts.get(&k).await {
+                    Some(cached) => cached.cut,
+                    None => {
+                        let ds =
+                            self.anchors.iter().filter(|it| it.zone == zone).map(|it| {
+                                (it.key_tag, it.algorithm, it.digest_type, &it.digest[..])
+                            });
+                        let (cut, ttl) = self.dnskeys(next, peer, &zone, ds).await?;
+                        self.cache(k, &cut, ttl).await;
+                        cut
+                    }
+                };
+                match cut {
+                    Cut::Secure(keys) => keys,
+                    _ => return Ok(State::Insecure),
+                }
+            };
+
+            for n in labels(&zone).len() + 1..=labels(name).len() {
+                let child = ancestor(name, n);
+                match self.cut(next, peer, &child, &zone, &keys[..]).await? {
+                    Cut::Secure(child_keys) => {
+                        zone = child;
+                        keys = child_keys;
+                    }
+                    Cut::None => (),
+                    Cut::Insecure => return Ok(State::Insecure),
+                }
+            }
+
+            Ok(State::Secure { zone, keys })
+        })
+    }
+
+    /// Follows the CNAME chain of the answers, returns the last target.
+    fn chase(res: &Message, qname: &str, kind: Kind) -> Result<String> {
+        let mut target = qname.to_string();
+        if matches!(kind, Kind::CNAME | Kind::ANY) {
+            return Ok(target);
+        }
+        for _ in 0..res.answer_count() {
+            let mut next = None;
+            for rr in res.answers() {
+                if let RData::CNAME(it) = rr.rdata()? {
+                    if normalize(&rr.name().to_string()) == target {
+                        next.replace(normalize(&it.cname().to_string()));
+                        break;
+                    }
+                }
+            }
+            match next {
+                Some(next) => target = next,
+                None => break,
+            }
+        }
+        Ok(target)
+    }
+
+    /// Validates the denial of existence of the name, returns true if it's secure.
+    async fn deny(
+        &self,
+        next: Option<&dyn Filter>,
+        peer: Option<SocketAddr>,
+        res: &Message,
+        name: &str,
+        kind: Option<Kind>,
+    ) -> Result<bool> {
+        let (zone, keys) = match self.state(next, peer, name).await? {
+            State::Insecure => return Ok(false),
+            State::Secure { zone, keys } => (zone, keys),
+        };
+
+        // the denial must be signed by the closest zone, except the DS at the cut which is
+        // signed by the parent
+        let (zone, keys) = if kind == Some(Kind::DS) && zone == name && !zone.is_empty() {
+            match self
+                .state(next, peer, &ancestor(name, labels(name).len() - 1))
+                .await?
+            {
+                State::Insecure => return Ok(false),
+                State::Secure { zone, keys } => (zone, keys),
+            }
+        } else {
+            (zone, keys)
+        };
+
+        for auth in RRset::collect(res.authorities())? {
+            if let Some(sig) = auth.signatures.iter().find(|it| it.signer != zone) {
+                bail!(Bogus::error(
+                    ExtendedError::DnssecBogus,
+                    format!(
+                        "the denial of '{}.' is signed by '{}.' instead of '{}.'",
+                        name, sig.signer, zone
+                    ),
+                ));
+            }
+            verify_rrset(&auth, &zone, &keys[..])?;
+        }
+
+        let denial = Denial { msg: res };
+        denial.prove(name, kind)
+    }
+
+    /// Validates the response, returns true if it's secure.
+    async fn validate(
+        &self,
+        next: Option<&dyn Filter>,
+        peer: Option<SocketAddr>,
+        req: &Message,
+        res: &Message,
+    ) -> Result<bool> {
+        let (qname, kind) = match req.questions().next() {
+            Some(question) if question.class() == Class::IN => {
+                (normalize(&question.name().to_string()), question.kind())
+            }
+            _ => return Ok(false),
+        };
+
+        let rcode = res.flags().response_code();
+        if !matches!(rcode, RCode::NoError | RCode::NameError) {
+            return Ok(false);
+        }
+
+        let mut secure = true;
+
+        let answers = RRset::collect(res.answers())?;
+        for rrset in &answers {
+            let signer = match rrset.signatures.first() {
+                Some(sig) if is_subdomain(&rrset.name, &sig.signer) => Clone::clone(&sig.signer),
+                _ => {
+                    // unsigned answers are acceptable only if they're insecure
+                    match self.state(next, peer, &rrset.name).await? {
+                        State::Insecure => {
+                            secure = false;
+                            continue;
+                        }
+                        State::Secure { zone, .. } => {
+                            bail!(Bogus::error(
+                                ExtendedError::RrsigsMissing,
+                                format!("no RRSIG of zone '{}.' for '{}.'", zone, rrset.name),
+                            ));
+                        }
+                    }
+                }
+            };
+
+            match self.state(next, peer, &signer).await? {
+                State::Insecure => secure = false,
+                State::Secure { zone, keys } => {
+                    if zone != signer {
+                        bail!(Bogus::error(
+                            ExtendedError::DnssecBogus,
+                            format!("'{}.' is not a zone", signer),
+                        ));
+                    }
+                    verify_rrset(rrset, &zone, &keys[..])?;
+                    if let Some(sig) = rrset.is_wildcard_expanded() {
+                        let labels = sig.labels;
+                        for auth in RRset::collect(res.authorities())? {
+                            verify_rrset(&auth, &zone, &keys[..])?;
+                        }
+                        let denial = Denial { msg: res };
+                        if !denial.prove_wildcard(&rrset.name, labels)? {
+                            secure = false;
+                        }
+                    }
+                }
+            }
+        }
+
+        // the denial of existence is for the last target of the CNAME chain
+        let target = Self::chase(res, &qname, kind)?;
+        let negative = rcode == RCode::NameError
+            || !answers
+                .iter()
+                .any(|it| it.name == target && (it.kind == kind as u16 || kind == Kind::ANY));
+
+        if negative {
+            let kind = if rcode == RCode::NameError {
+                None
+            } else {
+                Some(kind)
+            };
+            if !self.deny(next, peer, res, &target, kind).await? {
+                secure = false;
+            }
+        }
+
+        Ok(secure)
+    }
+
+    fn bogus(req: &Message, err: &anyhow::Error) -> Result<Message> {
+        let code = err
+            .downcast_ref::<Bogus>()
+            .map(|it| it.code)
+            .unwrap_or(ExtendedError::DnssecBogus);
+
+        let flags = Flags::builder()
+            .response()
+            .opcode(req.flags().opcode())
+            .recursive_query(req.flags().is_recursive_query())
+            .recursive_available(true)
+            .rcode(RCode::ServerFailure)
+            .build();
+
+        let mut bu = Message::builder().id(req.id()).flags(flags);
+        for next in req.questions() {
+            bu = bu.raw_question(next);
+        }
+        let mut msg = bu.build()?;
+        msg.put_edns_option(&EdnsOption::ExtendedError {
+            info_code: code as u16,
+            extra_text: err.to_string(),
+        });
+        Ok(msg)
+    }
+
+    /// Removes the DNSSEC records which are not asked by the client without the DO bit, see
+    /// RFC 4035 3.2.1.
+    fn strip(req: &Message, msg: &Message) -> Result<Message> {
+        let qtype = req.questions().next().map(|it| it.kind());
+        let mut owned = MessageOwned::try_from(msg)?;
+        for section in [Section::Answer, Section::Authority, Section::Additional] {
+            owned.retain(section, |it| {
+                !matches!(it.kind, Kind::RRSIG | Kind::NSEC | Kind::NSEC3) || Some(it.kind) == qtype
+            });
+        }
+        let mut msg = owned.build()?;
+        if msg.is_dnssec_ok() {
+            msg.set_dnssec_ok(false);
+        }
+        Ok(msg)
+    }
+}
+
+pub(crate) struct DNSSECFilter {
+    validator: Arc<Validator>,
+    next: Option<Box<dyn Filter>>,
+}
+
+#[async_trait]
+impl Filter for DNSSECFilter {
+    async fn handle(
+        &self,
+        ctx: &mut Context,
+        req: &mut Message,
+        res: &mut Option<Message>,
+    ) -> Result<()> {
+        // skip the answered ones, and respect the CD bit of clients
+        if res.is_some() || req.flags().is_checking_disabled() {
+            return handle_next(self.next.as_deref(), ctx, req, res).await;
+        }
+
+        // the DO bit is set on the upstream copy only, the request of client is untouched
+        let dnssec_ok = req.is_dnssec_ok();
+        let mut upstream = Clone::clone(req);
+        upstream.set_dnssec_ok(true);
+
+        handle_next(self.next.as_deref(), ctx, &mut upstream, res).await?;
+
+        if let Some(msg) = res.as_mut() {
+            let next = self.next.as_deref();
+            match self.validator.validate(next, ctx.peer, req, msg).await {
+                Ok(secure) => {
+                    let flags = msg.flags();
+                    if secure != flags.is_authentic_data() {
+                        msg.set_flags(flags.into_builder().authentic_data(secure).build());
+                    }
+                    if !dnssec_ok {
+                        *msg = Validator::strip(req, msg)?;
+                    }
+                }
+                Err(e) => {
+                    if let Some(question) = req.questions().next() {
+                        warn!("dnssec validation of '{}' failed: {}", question.name(), e);
+                    }
+                    res.replace(Validator::bogus(req, &e)?);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_next(&mut self, next: Box<dyn Filter>) {
+        self.next.replace(next);
+    }
+}
+
+pub(crate) struct DNSSECFilterFactory {
+    validator: Arc<Validator>,
+}
+
+impl TryFrom<&Options> for DNSSECFilterFactory {
+    type Error = anyhow::Error;
+
+    fn try_from(opts: &Options) -> std::result::Result<Self, Self::Error> {
+        const KEY_TRUST_ANCHORS: &str = "trust_anchors";
+        const KEY_CACHE_SIZE: &str = "cache_size";
+
+        let anchors = match opts.get(KEY_TRUST_ANCHORS) {
+            None => DEFAULT_TRUST_ANCHORS
+                .iter()
+                .map(|it| it.parse::<TrustAnchor>())
+                .collect::<Result<Vec<_>>>()?,
+            Some(v) => {
+                let arr = v
+                    .as_array()
+                    .ok_or_else(|| anyhow!("invalid property '{}'", KEY_TRUST_ANCHORS))?;
+                let mut anchors = vec![];
+                for next in arr {
+                    let s = next
+                        .as_str()
+                        .ok_or_else(|| anyhow!("invalid property '{}'", KEY_TRUST_ANCHORS))?;
+                    anchors.push(s.parse::<TrustAnchor>()?);
+                }
+                if anchors.is_empty() {
+                    bail!(
+                        "invalid property '{}': empty trust anchors",
+                        KEY_TRUST_ANCHORS
+                    );
+                }
+                anchors
+            }
+        };
+
+        let capacity = match opts.get(KEY_CACHE_SIZE) {
+            None => 4096,
+            Some(v) => v
+                .as_integer()
+                .and_then(|it| u64::try_from(it).ok())
+                .ok_or_else(|| anyhow!("invalid property '{}'", KEY_CACHE_SIZE))?,
+        };
+
+        let cuts = Cache::builder()
+            .max_capacity(capacity)
+            .expire_after(CutExpiry)
+            .build();
+
+        Ok(Self {
+            validator: Arc::new(Validator { anchors, cuts }),
+        })
+    }
+}
+
+impl FilterFactory for DNSSECFilterFactory {
+    type Item = DNSSECFilter;
+
+    fn get(&self) -> Result<Self::Item> {
+        Ok(DNSSECFilter {
+            validator: Clone::clone(&self.validator),
+            next: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::rand::SystemRandom;
+    use ring::signature::{
+        EcdsaKeyPair, Ed25519KeyPair, KeyPair, RsaKeyPair, RsaPublicKeyComponents,
+        ECDSA_P256_SHA256_FIXED_SIGNING, RSA_PKCS1_SHA256,
+    };
+    use std::collections::BTreeMap;
+
+    // openssl genpkey -algorithm RSA -pkeyopt rsa_keygen_bits:2048 | openssl pkcs8 -topk8 -nocrypt -outform DER | base64
+    const RSA_PKCS8: &str = "MIIEvgIBADANBgkqhkiG9w0BAQEFAASCBKgwggSkAgEAAoIBAQDqu7Tj9qqeYrJujPBv4474G9v2r2sePE5Xt8A3YZpJ5AiChLKh\
+64EnHw+kleDhj37FvuF8Cb8xEg3E2Zp9fin/b/KZKmWyQ7heiMUEwftMbo3WTeduTMsaU+IbCR81dhnAeaAU4+DN5bf6uRqa6+wg\
++/TejQbvO4Bs6ISB1b0e1w9F9/s/oCbbnBfGf1lZd10wKzKX5dRkFpTXdb8F/bHxdcqTIhMaekfY+uX716KrmzV/HFE+dFgJGHFS\
+m92kD46TneMVbdsKjbmGH6XqhYOOrtL6a7jTkYhgbnfmZtqTdUcs/i5wH2RGB52xKmosDxFG6dJU+awkUE7KKFEiY1qDAgMBAAEC\
+ggEAAbRzT+SOwK++1Aa6CFYCx9KL+nKzuHh/qStwffZ7hGXfmfMUyRBI09vp5SwhggHkzdLhGGGJvfV/nBpogNjVvUE25d3k9zJ+\
+uFEN/UbbaGa1KQVP1QFByGMrlT6vt1ewQDGh9ATjsRsmBvApPU7RXDv4P1WO41XvTNszey3uPbcWmbV07SfpQ7dOE0kxwo1SW49p\
+HWT4DKI/SuYRhOqdQBjQroonC7b0RRpufNrOwwqn2aijUC6CUMkUKTeEJCK8t2kg84RzUlizDdEFLUuP2wKj68hvsSGSCM0YjJ6G\
+338sAuuZIM8JeJtV7vKI7twOL3gCgrcsz0Sw4AquSFlBoQKBgQD1oaQ0/rJs+qpmFBc0WqtM1nt15l5bwVHXA1i1G2xiU1P4hhC0\
+GaueoPmjcg5KffZEUAOeUqdXRfXF72AZnRsSauJQdoERP6AzyHyuE5tII9ZaNHMPFMipIZfHhoA+3fy0k0WSAxN4cYVtHwEcU3gd\
+yAGHNgX+MIHsUYMWnxj+hwKBgQD0pEvnRMfaWRvBg3jKx1u1hel1RNhxUe/ObB73GdLVsGHwhAOcYrCNOLQI1okDSNkrGVJCnWAY\
+zjesTeT8Ja0I/LcTWVSsUnIlu3IHzemc/fC120qxgd7VXWyNCDIs6ABd2FFTEHQbWXPqgLMMjupJzVLKuxZa4/C6R2e+QwAnJQKB\
+gQDfLWDfbdN8RTzxxqtWvFMMW4yNkSSGxd2wOLAuzZ3qsL+I/XbLzM8sMCLiKM1nTY+mffTrszmsiDpI/7gXTbG9SDQc269llqPe\
+qPjvyYy7PSSTjThFqaKwWX9ooIKBnHzxLSZ3Bih4tNyASz1dGVH37TPGQIWtcTF7IIy0zxWMnQKBgCWw3mTeKvBQBD+3MrgDKPT5\
+kbNYjQRAfHLQI6EsYFg92YkFvcytQ8guMUafTvKrmZHTT+IlfvWzZ85rHdlfMJ6O2OHpRGtcNGvtyi67ob9nPFu4UnSwrpUu1JeR\
+6LEUeWH7/uOOSGUiTgKWG3/W0O/X62aClnogXilE8Q3WIsLlAoGBAPH9MrJDJBDBDWsu5hrEBKE5EkQ/ZbqsIvzQ+MMznfgYx/c5\
+LeGCYhr7WjzuF4BaTZKVMLqOC202ltfmL1MmNiR9CWe55Myf2gvUcCxMC0Z295/e0MDJkV1Y3LICgWvUMWAxDo2OPnvENcyfT+hN\
+G9QzAnQu8zoBOwV/HUgYAse3";
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    enum SigningKey {
+        Rsa(RsaKeyPair),
+        Ecdsa(EcdsaKeyPair),
+        Ed25519(Ed25519KeyPair),
+    }
+
+    impl SigningKey {
+        fn algorithm(&self) -> u8 {
+            match self {
+                SigningKey::Rsa(_) => 8,
+                SigningKey::Ecdsa(_) => 13,
+                SigningKey::Ed25519(_) => 15,
+            }
+        }
+
+        fn dnskey(&self) -> Vec<u8> {
+            let mut b = vec![0x01, 0x01, 3, self.algorithm()];
+            match self {
+                SigningKey::Rsa(k) => {
+                    let c: RsaPublicKeyComponents<Vec<u8>> = k.public().into();
+                    b.push(c.e.len() as u8);
+                    b.extend_from_slice(&c.e);
+                    b.extend_from_slice(&c.n);
+                }
+                SigningKey::Ecdsa(k) => b.extend_from_slice(&k.public_key().as_ref()[1..]),
+                SigningKey::Ed25519(k) => b.extend_from_slice(k.public_key().as_ref()),
+            }
+            b
+        }
+
+        fn sign(&self, data: &[u8]) -> Vec<u8> {
+            let rng = SystemRandom::new();
+            match self {
+                SigningKey::Rsa(k) => {
+                    let mut sig = vec![0u8; k.public().modulus_len()];
+                    k.sign(&RSA_PKCS1_SHA256, &rng, data, &mut sig).unwrap();
+                    sig
+                }
+                SigningKey::Ecdsa(k) => k.sign(&rng, data).unwrap().as_ref().to_vec(),
+                SigningKey::Ed25519(k) => k.sign(data).as_ref().to_vec(),
+            }
+        }
+    }
+
+    fn rsa() -> SigningKey {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        let der = STANDARD.decode(RSA_PKCS8).unwrap();
+        SigningKey::Rsa(RsaKeyPair::from_pkcs8(&der).unwrap())
+    }
+
+    fn ecdsa() -> SigningKey {
+        let rng = SystemRandom::new();
+        let doc = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let k = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, doc.as_ref(), &rng);
+        SigningKey::Ecdsa(k.unwrap())
+    }
+
+    fn ed25519() -> SigningKey {
+        SigningKey::Ed25519(Ed25519KeyPair::from_seed_unchecked(&[7u8; 32]).unwrap())
+    }
+
+    fn bitmap(mut types: Vec<u16>) -> Vec<u8> {
+        types.sort();
+        types.dedup();
+        let mut b = vec![];
+        let mut windows = BTreeMap::<u8, [u8; 32]>::new();
+        for t in types {
+            let w = windows.entry((t >> 8) as u8).or_insert([0u8; 32]);
+            w[((t & 0xff) >> 3) as usize] |= 0x80 >> (t & 0x07);
+        }
+        for (window, bits) in windows {
+            let n = bits.iter().rposition(|it| *it != 0).unwrap() + 1;
+            b.push(window);
+            b.push(n as u8);
+            b.extend_from_slice(&bits[..n]);
+        }
+        b
+    }
+
+    #[derive(Debug, Clone)]
+    struct Record {
+        zone: String,
+        name: String,
+        kind: Kind,
+        covered: Option<u16>,
+        data: Vec<u8>,
+    }
+
+    struct Zone {
+        apex: String,
+        key: Option<SigningKey>,
+        nsec3: bool,
+        rrsets: BTreeMap<(String, u16), Vec<Vec<u8>>>,
+    }
+
+    impl Zone {
+        fn new(apex: &str, key: Option<SigningKey>, nsec3: bool) -> Self {
+            let mut zone = Self {
+                apex: apex.to_string(),
+                key,
+                nsec3,
+                rrsets: Default::default(),
+            };
+            if let Some(dnskey) = zone.key.as_ref().map(|it| it.dnskey()) {
+                zone.add(apex, Kind::DNSKEY, dnskey);
+            }
+            zone
+        }
+
+        fn add(&mut self, name: &str, kind: Kind, data: Vec<u8>) {
+            self.rrsets
+                .entry((name.to_string(), kind as u16))
+                .or_default()
+                .push(data);
+        }
+
+        /// Adds the delegation, the child is unsigned if key is absent.
+        fn delegate(&mut self, child: &Zone) {
+            self.add(
+                &child.apex,
+                Kind::NS,
+                to_wire(&format!("ns.{}", child.apex)),
+            );
+            if let Some(key) = &child.key {
+                let dnskey = key.dnskey();
+                let mut ds = DNSKEY::new(&dnskey).key_tag().to_be_bytes().to_vec();
+                ds.extend([key.algorithm(), 2]);
+                ds.extend(digest(2, &[&to_wire(&child.apex)[..], &dnskey[..]].concat()).unwrap());
+                self.add(&child.apex, Kind::DS, ds);
+            }
+        }
+
+        fn sign(&self, name: &str, kind: u16, rdatas: &[Vec<u8>]) -> Vec<u8> {
+            let key = self.key.as_ref().unwrap();
+            let now = now();
+            let mut prefix = kind.to_be_bytes().to_vec();
+            prefix.push(key.algorithm());
+            prefix.push(labels(name).len() as u8);
+            prefix.extend(300u32.to_be_bytes());
+            prefix.extend((now + 3600).to_be_bytes());
+            prefix.extend((now - 3600).to_be_bytes());
+            prefix.extend(DNSKEY::new(&key.dnskey()).key_tag().to_be_bytes());
+            prefix.extend(to_wire(&self.apex));
+
+            let mut rdatas = rdatas.to_vec();
+            rdatas.sort();
+            let sig = Signature {
+                algorithm: key.algorithm(),
+                labels: labels(name).len() as u8,
+                original_ttl: 300,
+                expiration: now + 3600,
+                inception: now - 3600,
+                key_tag: 0,
+                signer: Clone::clone(&self.apex),
+                prefix: Clone::clone(&prefix),
+                signature: vec![],
+            };
+            let rrset = RRset {
+                name: name.to_string(),
+                kind,
+                class: Class::IN as u16,
+                ttl: 300,
+                rdatas,
+                signatures: vec![],
+            };
+            let mut rdata = prefix;
+            rdata.extend(key.sign(&signed_data(&sig, &rrset)));
+            rdata
+        }
+
+        fn build(mut self) -> Vec<Record> {
+            if self.key.is_some() {
+                // build the chain of denial of existence
+                let mut names = BTreeMap::<String, Vec<u16>>::new();
+                for (name, kind) in self.rrsets.keys() {
+                    let types = names.entry(Clone::clone(name)).or_default();
+                    types.push(*kind);
+                    if *kind != Kind::NS as u16 || *name == self.apex {
+                        types.push(Kind::RRSIG as u16);
+                    }
+                }
+                if self.nsec3 {
+                    let mut hashes = names
+                        .into_iter()
+                        .map(|(name, types)| (nsec3_hash(&name, &[], 0), types))
+                        .collect::<Vec<_>>();
+                    hashes.sort();
+                    for (i, (h, types)) in hashes.iter().enumerate() {
+                        let next = &hashes[(i + 1) % hashes.len()].0;
+                        let next = base32hex_decode(next);
+                        let mut data = vec![1, 0, 0, 0, 0, next.len() as u8];
+                        data.extend(next);
+                        data.extend(bitmap(Clone::clone(types)));
+                        let owner = format!("{}.{}", h, self.apex);
+                        self.add(&owner, Kind::NSEC3, data);
+                    }
+                } else {
+                    let mut sorted = names.into_iter().collect::<Vec<_>>();
+                    sorted.sort_by(|a, b| canonical_cmp(&a.0, &b.0));
+                    for (i, (name, types)) in sorted.iter().enumerate() {
+                        let next = &sorted[(i + 1) % sorted.len()].0;
+                        let mut types = Clone::clone(types);
+                        types.push(Kind::NSEC as u16);
+                        if !types.contains(&(Kind::RRSIG as u16)) {
+                            types.push(Kind::RRSIG as u16);
+                        }
+                        let mut data = to_wire(next);
+                        data.extend(bitmap(types));
+                        self.add(name, Kind::NSEC, data);
+                    }
+                }
+            }
+
+            let mut records = vec![];
+            for ((name, kind), rdatas) in &self.rrsets {
+                for data in rdatas {
+                    records.push(Record {
+                        zone: Clone::clone(&self.apex),
+                        name: Clone::clone(name),
+                        kind: Kind::try_from(*kind).unwrap(),
+                        covered: None,
+                        data: Clone::clone(data),
+                    });
+                }
+                let delegation = *kind == Kind::NS as u16 && *name != self.apex;
+                if self.key.is_some() && !delegation {
+                    records.push(Record {
+                        zone: Clone::clone(&self.apex),
+                        name: Clone::clone(name),
+                        kind: Kind::RRSIG,
+                        covered: Some(*kind),
+                        data: self.sign(name, *kind, rdatas),
+                    });
+                }
+            }
+            records
+        }
+    }
+
+    fn from_wire(mut b: &[u8]) -> String {
+        let mut labels = vec![];
+        while let Some((&n, rest)) = b.split_first() {
+            if n == 0 {
+                break;
+            }
+            labels.push(String::from_utf8_lossy(&rest[..n as usize]).to_string());
+            b = &rest[n as usize..];
+        }
+        labels.join(".")
+    }
+
+    fn base32hex_decode(s: &str) -> Vec<u8> {
+        let mut b = vec![];
+        let mut buf = 0u32;
+        let mut bits = 0;
+        for c in s.to_ascii_uppercase().bytes() {
+            let v = match c {
+                b'0'..=b'9' => c - b'0',
+                _ => c - b'A' + 10,
+            };
+            buf = (buf << 5) | v as u32;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                b.push((buf >> bits) as u8);
+            }
+        }
+        b
+    }
+
+    /// A recursive upstream stand-in which answers from the signed zones.
+    struct Upstream {
+        apexes: Vec<String>,
+        records: Vec<Record>,
+    }
+
+    #[async_trait]
+    impl Filter for Upstream {
+        async fn handle(
+            &self,
+            _ctx: &mut Context,
+            req: &mut Message,
+            res: &mut Option<Message>,
+        ) -> Result<()> {
+            if !req.flags().is_checking_disabled() {
+                assert!(req.is_dnssec_ok(), "DO bit should be set");
+            }
+
+            let question = req.questions().next().unwrap();
+            let qname = normalize(&question.name().to_string());
+            let kind = question.kind();
+
+            let zone = |name: &str| {
+                self.apexes
+                    .iter()
+                    .filter(|it| {
+                        is_subdomain(name, it)
+                            && !(kind == Kind::DS && *it == name && !it.is_empty())
+                    })
+                    .max_by_key(|it| labels(it).len())
+                    .unwrap()
+            };
+
+            let records = self.records.iter().filter(|it| &it.zone == zone(&qname));
+            let matches = |name: &str, kind: Kind| {
+                records
+                    .clone()
+                    .filter(|it| {
+                        it.name == name && (it.kind == kind || it.covered == Some(kind as u16))
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            let mut target = Clone::clone(&qname);
+            let mut answers = matches(&qname, kind);
+            if answers.is_empty() {
+                answers = matches(&qname, Kind::CNAME);
+                // chase the CNAME
+                if let Some(cname) = answers.iter().find(|it| it.kind == Kind::CNAME) {
+                    target = from_wire(&cname.data);
+                    let chased = self
+                        .records
+                        .iter()
+                        .filter(|it| {
+                            it.name == target
+                                && (it.kind == kind || it.covered == Some(kind as u16))
+                        })
+                        .collect::<Vec<_>>();
+                    answers.extend(chased);
+                }
+            }
+            let mut authorities = vec![];
+            let mut rcode = RCode::NoError;
+            if !answers
+                .iter()
+                .any(|it| it.name == target && it.kind == kind)
+            {
+                // the denial of the last target
+                let records = self.records.iter().filter(|it| &it.zone == zone(&target));
+                if !records.clone().any(|it| it.name == target) {
+                    rcode = RCode::NameError;
+                }
+                authorities = records
+                    .clone()
+                    .filter(|it| {
+                        matches!(it.kind, Kind::NSEC | Kind::NSEC3)
+                            || matches!(it.covered, Some(t) if t == Kind::NSEC as u16 || t == Kind::NSEC3 as u16)
+                    })
+                    .collect();
+            }
+
+            let name = |s: &str| {
+                if s.is_empty() {
+                    ".".to_string()
+                } else {
+                    s.to_string()
+                }
+            };
+
+            let flags = Flags::builder()
+                .response()
+                .recursive_query(true)
+                .recursive_available(true)
+                .rcode(rcode)
+                .build();
+            let mut bu = Message::builder()
+                .id(req.id())
+                .flags(flags)
+                .raw_question(question);
+            for next in answers {
+                bu = bu.answer(name(&next.name), next.kind, Class::IN, 300, &next.data[..]);
+            }
+            for next in authorities {
+                bu = bu.authority(name(&next.name), next.kind, Class::IN, 300, &next.data[..]);
+            }
+            bu = bu.additional_pseudo(1232, 0, 0, 0, None::<&[u8]>);
+            res.replace(bu.build()?);
+
+            Ok(())
+        }
+
+        fn set_next(&mut self, _next: Box<dyn Filter>) {}
+    }
+
+    fn setup() -> (DNSSECFilter, Upstream) {
+        // . (RSA) -> example (ECDSA, NSEC3) -> ed.example (Ed25519)
+        //         -> insecure (unsigned)
+        let mut root = Zone::new("", Some(rsa()), false);
+        let mut example = Zone::new("example", Some(ecdsa()), true);
+        let mut ed = Zone::new("ed.example", Some(ed25519()), false);
+        let mut insecure = Zone::new("insecure", None, false);
+
+        ed.add("www.ed.example", Kind::A, vec![10, 0, 0, 2]);
+        example.add("www.example", Kind::A, vec![10, 0, 0, 1]);
+        example.add("alias.example", Kind::CNAME, to_wire("www.ed.example"));
+        example.add(
+            "dangling.example",
+            Kind::CNAME,
+            to_wire("missing.ed.example"),
+        );
+        example.add("bad.example", Kind::A, vec![10, 0, 0, 4]);
+        example.delegate(&ed);
+        insecure.add("host.insecure", Kind::A, vec![10, 0, 0, 3]);
+        root.delegate(&example);
+        root.delegate(&insecure);
+
+        let anchor = {
+            let dnskey = root.key.as_ref().unwrap().dnskey();
+            let key = DNSKEY::new(&dnskey);
+            let d = digest(2, &[&[0u8][..], &dnskey[..]].concat()).unwrap();
+            format!(". IN DS {} 8 2 {}", key.key_tag(), hex::encode_upper(d))
+        };
+
+        let apexes = [&root, &example, &ed, &insecure]
+            .iter()
+            .map(|it| Clone::clone(&it.apex))
+            .collect();
+
+        let mut records = vec![];
+        for zone in [root, example, ed, insecure] {
+            records.extend(zone.build());
+        }
+
+        // tamper the signed answer
+        for next in records.iter_mut() {
+            if next.name == "bad.example" && next.kind == Kind::A {
+                next.data = vec![10, 0, 0, 5];
+            }
+        }
+
+        let opts: Options = toml::from_str(&format!("trust_anchors = [\"{}\"]", anchor)).unwrap();
+        let factory = DNSSECFilterFactory::try_from(&opts).unwrap();
+
+        (factory.get().unwrap(), Upstream { apexes, records })
+    }
+
+    async fn resolve(f: &DNSSECFilter, name: &str, kind: Kind, cd: bool) -> Result<Message> {
+        let flags = Flags::builder()
+            .request()
+            .recursive_query(true)
+            .checking_disabled(cd)
+            .build();
+        let mut req = Message::builder()
+            .id(0x1234)
+            .flags(flags)
+            .question(name, kind, Class::IN)
+            .build()?;
+        let mut ctx = Context::default();
+        let mut res = None;
+        f.handle(&mut ctx, &mut req, &mut res).await?;
+        res.ok_or_else(|| anyhow!("no response"))
+    }
+
+    fn ede(msg: &Message) -> Option<u16> {
+        match msg.edns_option(EdnsOption::EXTENDED_ERROR)? {
+            EdnsOption::ExtendedError { info_code, .. } => Some(info_code),
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dnssec() -> anyhow::Result<()> {
+        init();
+
+        let (mut f, upstream) = setup();
+        f.set_next(Box::new(upstream));
+
+        // secure answers of RSA, ECDSA and Ed25519 zones
+        for (name, kind, rcode, answers) in [
+            ("www.example", Kind::A, RCode::NoError, 1),
+            ("www.ed.example", Kind::A, RCode::NoError, 1),
+            ("alias.example", Kind::A, RCode::NoError, 1),
+            ("dangling.example", Kind::A, RCode::NameError, 0),
+            ("www.example", Kind::AAAA, RCode::NoError, 0),
+            ("missing.example", Kind::A, RCode::NameError, 0),
+            ("missing.ed.example", Kind::A, RCode::NameError, 0),
+            ("nope", Kind::A, RCode::NameError, 0),
+        ] {
+            let res = resolve(&f, name, kind, false).await?;
+            assert_eq!(rcode, res.flags().response_code(), "{}", name);
+            let n = res.answers().filter(|it| it.kind() == kind).count();
+            assert_eq!(answers, n, "{}", name);
+            assert!(res.flags().is_authentic_data(), "{} should be secure", name);
+            // the client doesn't set the DO bit
+            let dnssec = res
+                .answers()
+                .chain(res.authorities())
+                .filter(|it| matches!(it.kind(), Kind::RRSIG | Kind::NSEC | Kind::NSEC3))
+                .count();
+            assert_eq!(0, dnssec, "{}", name);
+        }
+
+        // the DNSSEC records are kept for the clients with the DO bit
+        {
+            let mut req = Message::builder()
+                .id(0x1234)
+                .flags(Flags::builder().request().recursive_query(true).build())
+                .question("www.example", Kind::A, Class::IN)
+                .build()?;
+            req.set_dnssec_ok(true);
+            let mut ctx = Context::default();
+            let mut res = None;
+            f.handle(&mut ctx, &mut req, &mut res).await?;
+            let res = res.unwrap();
+            assert!(res.flags().is_authentic_data());
+            assert!(res.answers().any(|it| it.kind() == Kind::RRSIG));
+        }
+
+        // insecure delegation
+        let res = resolve(&f, "host.insecure", Kind::A, false).await?;
+        assert_eq!(RCode::NoError, res.flags().response_code());
+        assert_eq!(1, res.answer_count());
+        assert!(!res.flags().is_authentic_data());
+
+        // bogus
+        let res = resolve(&f, "bad.example", Kind::A, false).await?;
+        assert_eq!(RCode::ServerFailure, res.flags().response_code());
+        assert_eq!(0, res.answer_count());
+        assert_eq!(Some(ExtendedError::DnssecBogus as u16), ede(&res));
+
+        // checking disabled
+        let res = resolve(&f, "bad.example", Kind::A, true).await?;
+        assert_eq!(RCode::NoError, res.flags().response_code());
+        assert!(!res.flags().is_authentic_data());
+
+        Ok(())
+    }
+
+    /// Replays the denial records of the owner in a zone, except the DNSKEY and DS queries.
+    struct Replay {
+        upstream: Upstream,
+        zone: String,
+        owner: String,
+        rcode: RCode,
+    }
+
+    #[async_trait]
+    impl Filter for Replay {
+        async fn handle(
+            &self,
+            ctx: &mut Context,
+            req: &mut Message,
+            res: &mut Option<Message>,
+        ) -> Result<()> {
+            let question = req.questions().next().unwrap();
+            if matches!(question.kind(), Kind::DNSKEY | Kind::DS) {
+                return self.upstream.handle(ctx, req, res).await;
+            }
+
+            let flags = Flags::builder()
+                .response()
+                .recursive_query(true)
+                .recursive_available(true)
+                .rcode(self.rcode)
+                .build();
+            let mut bu = Message::builder()
+                .id(req.id())
+                .flags(flags)
+                .raw_question(question);
+            let denial = |t: u16| t == Kind::NSEC as u16 || t == Kind::NSEC3 as u16;
+            for next in self.upstream.records.iter().filter(|it| {
+                it.zone == self.zone
+                    && it.name == self.owner
+                    && (denial(it.kind as u16) || it.covered.is_some_and(denial))
+            }) {
+                bu = bu.authority(&next.name, next.kind, Class::IN, 300, &next.data[..]);
+            }
+            bu = bu.additional_pseudo(1232, 0, 0, 0, None::<&[u8]>);
+            res.replace(bu.build()?);
+
+            Ok(())
+        }
+
+        fn set_next(&mut self, _next: Box<dyn Filter>) {}
+    }
+
+    #[tokio::test]
+    async fn test_replayed_delegation() -> anyhow::Result<()> {
+        init();
+
+        // the delegation NSEC/NSEC3 of the parent proves nothing in the child zone
+        let hashed = format!("{}.example", nsec3_hash("ed.example", &[], 0));
+        for (zone, owner, rcode, name) in [
+            ("", "example", RCode::NameError, "foo.example"),
+            ("", "example", RCode::NoError, "example"),
+            ("example", hashed.as_str(), RCode::NoError, "ed.example"),
+        ] {
+            let (mut f, upstream) = setup();
+            f.set_next(Box::new(Replay {
+                upstream,
+                zone: zone.to_string(),
+                owner: owner.to_string(),
+                rcode,
+            }));
+            let res = resolve(&f, name, Kind::A, false).await?;
+            assert_eq!(
+                RCode::ServerFailure,
+                res.flags().response_code(),
+                "{}",
+                name
+            );
+            assert!(!res.flags().is_authentic_data(), "{}", name);
+        }
+
+        // the delegation NSEC is usable for the DS at the cut only
+        let mut data = to_wire("d.example");
+        data.extend(bitmap(vec![
+            Kind::NS as u16,
+            Kind::RRSIG as u16,
+            Kind::NSEC as u16,
+        ]));
+        let msg = Message::builder()
+            .id(1)
+            .flags(Flags::builder().response().build())
+            .question("child.example", Kind::DS, Class::IN)
+            .authority("child.example", Kind::NSEC, Class::IN, 300, data)
+            .build()?;
+        let denial = Denial { msg: &msg };
+        assert!(denial.prove("child.example", Some(Kind::DS))?);
+        assert!(denial.prove("child.example", Some(Kind::A)).is_err());
+        assert!(denial.prove("www.child.example", Some(Kind::A)).is_err());
+        assert!(denial.prove("www.child.example", None).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wildcard_nodata() -> anyhow::Result<()> {
+        init();
+
+        // '*.example' has A only, and 'b.example' is covered by 'a.example' -> 'd.example'
+        let nsec = |next: &str, types: Vec<u16>| {
+            let mut data = to_wire(next);
+            data.extend(bitmap(types));
+            data
+        };
+        let msg = Message::builder()
+            .id(1)
+            .flags(Flags::builder().response().build())
+            .question("b.example", Kind::TXT, Class::IN)
+            .authority(
+                "*.example",
+                Kind::NSEC,
+                Class::IN,
+                300,
+                nsec("a.example", vec![Kind::A as u16, Kind::NSEC as u16]),
+            )
+            .authority(
+                "a.example",
+                Kind::NSEC,
+                Class::IN,
+                300,
+                nsec("d.example", vec![Kind::A as u16, Kind::NSEC as u16]),
+            )
+            .build()?;
+
+        let denial = Denial { msg: &msg };
+        assert!(denial.prove("b.example", Some(Kind::TXT))?);
+        // the wildcard has the type
+        assert!(denial.prove("b.example", Some(Kind::A)).is_err());
+        // the name is not covered
+        assert!(denial.prove("e.example", Some(Kind::TXT)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rdata() {
+        init();
+
+        let (_, upstream) = setup();
+        let mut bu = Message::builder()
+            .id(1)
+            .flags(Flags::builder().response().build());
+        for next in &upstream.records {
+            let name = if next.name.is_empty() {
+                "."
+            } else {
+                &next.name[..]
+            };
+            bu = bu.answer(name, next.kind, Class::IN, 300, &next.data[..]);
+        }
+        let msg = bu.build().unwrap();
+
+        let mut kinds = vec![];
+        for rr in msg.answers() {
+            let rdata = rr.rdata().unwrap();
+            info!("{}.\t{}\t{}", rr.name(), rr.kind(), rdata);
+            match rdata {
+                RData::DNSKEY(it) => assert_eq!(257, it.flags()),
+                RData::DS(it) => assert_eq!(2, it.digest_type()),
+                RData::RRSIG(it) => assert!(it.signature().len() >= 64),
+                RData::NSEC(it) => assert!(it.type_bit_maps().contains(Kind::NSEC)),
+                RData::NSEC3(it) => {
+                    assert_eq!(20, it.next_hashed_owner_name().len());
+                    assert!(!it.type_bit_maps().contains(Kind::NSEC));
+                }
+                _ => continue,
+            }
+            kinds.push(rr.kind());
+        }
+        for kind in [Kind::DNSKEY, Kind::DS, Kind::RRSIG, Kind::NSEC, Kind::NSEC3] {
+            assert!(kinds.contains(&kind), "{} should be parsed", kind);
+        }
+    }
+
+    #[test]
+    fn test_trust_anchor() {
+        let anchor = DEFAULT_TRUST_ANCHORS[0].parse::<TrustAnchor>().unwrap();
+        assert_eq!("", anchor.zone);
+        assert_eq!(20326, anchor.key_tag);
+        assert_eq!(8, anchor.algorithm);
+        assert_eq!(32, anchor.digest.len());
+
+        let anchor = "example. IN DS 12345 13 2 AABB"
+            .parse::<TrustAnchor>()
+            .unwrap();
+        assert_eq!("example", anchor.zone);
+        assert_eq!(vec![0xaa, 0xbb], anchor.digest);
+
+        assert!("example. 12345 13".parse::<TrustAnchor>().is_err());
+    }
+}
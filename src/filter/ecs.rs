@@ -0,0 +1,585 @@
+use crate::protocol::{ClientSubnet, EdnsOption, Message, RCode};
+use crate::Result;
+use async_trait::async_trait;
+use moka::future::Cache;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{handle_next, Context, ContextFlags, Filter, FilterFactory, Options};
+
+/// The mode of attaching EDNS Client Subnet, see RFC 7871.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Mode {
+    /// Attach the subnet of the client address, the private ones are skipped.
+    Client { ipv4_prefix: u8, ipv6_prefix: u8 },
+    /// Attach a fixed subnet.
+    Fixed(ClientSubnet),
+    /// Strip the client subnet for privacy.
+    Strip,
+}
+
+impl Mode {
+    fn subnet_of(ip: IpAddr, ipv4_prefix: u8, ipv6_prefix: u8) -> Option<ClientSubnet> {
+        let ip = match ip {
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => IpAddr::V4(v4),
+                None => ip,
+            },
+            IpAddr::V4(_) => ip,
+        };
+
+        let (private, prefix) = match ip {
+            IpAddr::V4(v4) => (
+                v4.is_private()
+                    || v4.is_loopback()
+                    || v4.is_link_local()
+                    || v4.is_unspecified()
+                    || v4.is_broadcast(),
+                ipv4_prefix,
+            ),
+            IpAddr::V6(v6) => (
+                v6.is_loopback()
+                    || v6.is_unspecified()
+                    // unique local fc00::/7 and link local fe80::/10
+                    || v6.segments()[0] & 0xfe00 == 0xfc00
+                    || v6.segments()[0] & 0xffc0 == 0xfe80,
+                ipv6_prefix,
+            ),
+        };
+
+        if private {
+            return None;
+        }
+
+        Some(truncate(&ClientSubnet { addr: ip, prefix }, prefix))
+    }
+}
+
+/// Clears the bits of address which are out of the prefix.
+fn truncate(subnet: &ClientSubnet, prefix: u8) -> ClientSubnet {
+    match subnet.addr {
+        IpAddr::V4(v4) => {
+            let prefix = prefix.min(subnet.prefix).min(32);
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            ClientSubnet {
+                addr: IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask)),
+                prefix,
+            }
+        }
+        IpAddr::V6(v6) => {
+            let prefix = prefix.min(subnet.prefix).min(128);
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            ClientSubnet {
+                addr: IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask)),
+                prefix,
+            }
+        }
+    }
+}
+
+fn client_subnet(msg: &Message) -> Option<(ClientSubnet, u8)> {
+    match msg.edns_option(EdnsOption::CLIENT_SUBNET) {
+        Some(EdnsOption::ClientSubnet {
+            subnet,
+            scope_prefix,
+        }) => Some((subnet, scope_prefix)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Question {
+    name: String,
+    kind: u16,
+    class: u16,
+}
+
+impl Question {
+    fn of(req: &Message) -> Option<Self> {
+        let question = req.questions().next()?;
+        Some(Self {
+            name: question.name().to_string().to_ascii_lowercase(),
+            kind: question.kind() as u16,
+            class: question.class() as u16,
+        })
+    }
+}
+
+/// The cache of answers which are scoped by the client subnet, so the answers of different
+/// subnets will never be mixed.
+struct ScopedCache {
+    // the latest scope prefix of each question
+    scopes: Cache<Question, u8>,
+    answers: Cache<(Question, ClientSubnet), (Instant, Message)>,
+}
+
+impl ScopedCache {
+    const MAX_TTL: Duration = Duration::from_secs(3600);
+
+    fn new(capacity: u64) -> Self {
+        Self {
+            scopes: Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(Self::MAX_TTL)
+                .build(),
+            answers: Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(Self::MAX_TTL)
+                .build(),
+        }
+    }
+
+    async fn get(&self, question: &Question, subnet: &ClientSubnet) -> Option<Message> {
+        let scope = self.scopes.get(question).await?;
+        let key = (Clone::clone(question), truncate(subnet, scope));
+        let (created_at, mut msg) = self.answers.get(&key).await?;
+
+        let mut expired = false;
+        let elapsed = Instant::now().duration_since(created_at).as_secs();
+        msg.update_time_to_live(|ttl| {
+            if ttl as u64 <= elapsed {
+                expired = true;
+                0
+            } else {
+                ttl - elapsed as u32
+            }
+        });
+
+        if expired {
+            self.answers.invalidate(&key).await;
+            return None;
+        }
+
+        Some(msg)
+    }
+
+    async fn put(&self, question: Question, subnet: &ClientSubnet, msg: &Message) {
+        let flags = msg.flags();
+        if flags.is_message_truncated() {
+            return;
+        }
+        match flags.response_code() {
+            RCode::NoError | RCode::NameError => (),
+            _ => return,
+        }
+
+        // no client subnet in the response means the answer is suitable for all clients
+        let scope = match client_subnet(msg) {
+            Some((_, scope)) => scope.min(subnet.prefix),
+            None => 0,
+        };
+
+        self.scopes.insert(Clone::clone(&question), scope).await;
+        self.answers
+            .insert(
+                (question, truncate(subnet, scope)),
+                (Instant::now(), Clone::clone(msg)),
+            )
+            .await;
+    }
+}
+
+pub(crate) struct EcsFilter {
+    mode: Mode,
+    cache: Arc<ScopedCache>,
+    next: Option<Box<dyn Filter>>,
+}
+
+impl EcsFilter {
+    /// Makes the client subnet of response consistent with the original request.
+    fn restore(res: &mut Message, original: Option<ClientSubnet>) {
+        match original {
+            None => {
+                res.remove_edns_option(EdnsOption::CLIENT_SUBNET);
+            }
+            Some(subnet) => {
+                if let Some((_, scope)) = client_subnet(res) {
+                    res.put_edns_option(&EdnsOption::ClientSubnet {
+                        subnet,
+                        scope_prefix: scope.min(subnet.prefix),
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Filter for EcsFilter {
+    async fn handle(
+        &self,
+        ctx: &mut Context,
+        req: &mut Message,
+        res: &mut Option<Message>,
+    ) -> Result<()> {
+        if res.is_some() {
+            return handle_next(self.next.as_deref(), ctx, req, res).await;
+        }
+
+        let original = client_subnet(req).map(|(subnet, _)| subnet);
+
+        let subnet = match self.mode {
+            Mode::Strip => {
+                req.remove_edns_option(EdnsOption::CLIENT_SUBNET);
+                None
+            }
+            Mode::Fixed(subnet) => Some(subnet),
+            // respect the client subnet which is sent by the client, but no longer than the
+            // configured prefix for the privacy
+            Mode::Client {
+                ipv4_prefix,
+                ipv6_prefix,
+            } => match original {
+                Some(subnet) => {
+                    let prefix = match subnet.addr {
+                        IpAddr::V4(_) => ipv4_prefix,
+                        IpAddr::V6(_) => ipv6_prefix,
+                    };
+                    Some(truncate(&subnet, prefix))
+                }
+                None => ctx
+                    .peer
+                    .and_then(|peer| Mode::subnet_of(peer.ip(), ipv4_prefix, ipv6_prefix)),
+            },
+        };
+
+        if let Some(subnet) = subnet.as_ref() {
+            req.put_edns_option(&EdnsOption::ClientSubnet {
+                subnet: *subnet,
+                scope_prefix: 0,
+            });
+        }
+
+        // the answers depend on the client address, so use the scoped cache instead of the global one
+        let scoped = match (self.mode, subnet) {
+            (Mode::Client { .. }, Some(subnet)) => {
+                ctx.flags.insert(ContextFlags::NO_CACHE);
+                Question::of(req).map(|question| (question, subnet))
+            }
+            _ => None,
+        };
+
+        if let Some((question, subnet)) = scoped.as_ref() {
+            if let Some(mut msg) = self.cache.get(question, subnet).await {
+                debug!("hit ecs cache: {} {}", question.name, subnet);
+                msg.set_id(req.id());
+                Self::restore(&mut msg, original);
+                res.replace(msg);
+                return Ok(());
+            }
+        }
+
+        handle_next(self.next.as_deref(), ctx, req, res).await?;
+
+        if let Some(msg) = res.as_mut() {
+            if let Some((question, subnet)) = scoped {
+                self.cache.put(question, &subnet, msg).await;
+            }
+            Self::restore(msg, original);
+        }
+
+        Ok(())
+    }
+
+    fn set_next(&mut self, next: Box<dyn Filter>) {
+        self.next.replace(next);
+    }
+}
+
+pub(crate) struct EcsFilterFactory {
+    mode: Mode,
+    cache: Arc<ScopedCache>,
+}
+
+impl TryFrom<&Options> for EcsFilterFactory {
+    type Error = anyhow::Error;
+
+    fn try_from(opts: &Options) -> std::result::Result<Self, Self::Error> {
+        const KEY_MODE: &str = "mode";
+        const KEY_SUBNET: &str = "subnet";
+        const KEY_IPV4_PREFIX: &str = "ipv4_prefix";
+        const KEY_IPV6_PREFIX: &str = "ipv6_prefix";
+        const KEY_CACHE_SIZE: &str = "cache_size";
+
+        let prefix = |key: &str, default: u8, max: u8| -> Result<u8> {
+            match opts.get(key) {
+                None => Ok(default),
+                Some(v) => v
+                    .as_integer()
+                    .and_then(|it| u8::try_from(it).ok())
+                    .filter(|it| *it <= max)
+                    .ok_or_else(|| anyhow!("invalid property '{}'", key)),
+            }
+        };
+
+        let mode = match opts.get(KEY_MODE) {
+            None => "client",
+            Some(v) => v
+                .as_str()
+                .ok_or_else(|| anyhow!("invalid property '{}'", KEY_MODE))?,
+        };
+
+        let mode = match mode {
+            "client" => Mode::Client {
+                ipv4_prefix: prefix(KEY_IPV4_PREFIX, 24, 32)?,
+                ipv6_prefix: prefix(KEY_IPV6_PREFIX, 56, 128)?,
+            },
+            "fixed" => {
+                let subnet = opts
+                    .get(KEY_SUBNET)
+                    .and_then(|it| it.as_str())
+                    .ok_or_else(|| anyhow!("invalid property '{}'", KEY_SUBNET))?
+                    .parse::<ClientSubnet>()?;
+                Mode::Fixed(truncate(&subnet, subnet.prefix))
+            }
+            "strip" => Mode::Strip,
+            other => bail!("invalid property '{}': unknown mode '{}'", KEY_MODE, other),
+        };
+
+        let capacity = match opts.get(KEY_CACHE_SIZE) {
+            None => 4096,
+            Some(v) => v
+                .as_integer()
+                .and_then(|it| u64::try_from(it).ok())
+                .ok_or_else(|| anyhow!("invalid property '{}'", KEY_CACHE_SIZE))?,
+        };
+
+        Ok(Self {
+            mode,
+            cache: Arc::new(ScopedCache::new(capacity)),
+        })
+    }
+}
+
+impl FilterFactory for EcsFilterFactory {
+    type Item = EcsFilter;
+
+    fn get(&self) -> Result<Self::Item> {
+        Ok(EcsFilter {
+            mode: self.mode,
+            cache: Clone::clone(&self.cache),
+            next: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Class, Flags, Kind};
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    /// An upstream stand-in which answers the address of client subnet with scope /16.
+    struct Upstream {
+        cnt: Arc<AtomicU64>,
+    }
+
+    #[async_trait]
+    impl Filter for Upstream {
+        async fn handle(
+            &self,
+            _ctx: &mut Context,
+            req: &mut Message,
+            res: &mut Option<Message>,
+        ) -> Result<()> {
+            self.cnt.fetch_add(1, Ordering::SeqCst);
+
+            let question = req.questions().next().unwrap();
+            let subnet = client_subnet(req).map(|(subnet, _)| subnet);
+            let ip = match subnet.map(|it| it.addr) {
+                Some(IpAddr::V4(v4)) => v4,
+                _ => Ipv4Addr::LOCALHOST,
+            };
+
+            let mut msg = Message::builder()
+                .id(req.id())
+                .flags(Flags::builder().response().build())
+                .question(question.name().to_string(), Kind::A, Class::IN)
+                .answer(
+                    question.name().to_string(),
+                    Kind::A,
+                    Class::IN,
+                    300,
+                    &ip.octets()[..],
+                )
+                .build()?;
+
+            if let Some(subnet) = subnet {
+                msg.put_edns_option(&EdnsOption::ClientSubnet {
+                    subnet,
+                    scope_prefix: 16,
+                });
+            }
+
+            res.replace(msg);
+            Ok(())
+        }
+
+        fn set_next(&mut self, _next: Box<dyn Filter>) {}
+    }
+
+    fn filter(props: &str) -> (EcsFilter, Arc<AtomicU64>) {
+        let opts: Options = toml::from_str(props).unwrap();
+        let mut f = EcsFilterFactory::try_from(&opts).unwrap().get().unwrap();
+        let cnt = Arc::new(AtomicU64::new(0));
+        f.set_next(Box::new(Upstream {
+            cnt: Clone::clone(&cnt),
+        }));
+        (f, cnt)
+    }
+
+    async fn query(f: &EcsFilter, peer: &str, ecs: Option<&str>) -> (Context, Message, Message) {
+        let mut ctx = Context::default();
+        ctx.peer.replace(peer.parse::<SocketAddr>().unwrap());
+        let mut req = Message::builder()
+            .id(1234)
+            .question("www.example.com.", Kind::A, Class::IN)
+            .build()
+            .unwrap();
+        if let Some(ecs) = ecs {
+            req.put_edns_option(&EdnsOption::ClientSubnet {
+                subnet: ecs.parse().unwrap(),
+                scope_prefix: 0,
+            });
+        }
+        let mut res = None;
+        f.handle(&mut ctx, &mut req, &mut res).await.unwrap();
+        (ctx, req, res.unwrap())
+    }
+
+    fn address(msg: &Message) -> String {
+        msg.answers().next().unwrap().rdata().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_truncate() {
+        init();
+
+        for (expect, subnet, prefix) in [
+            ("1.2.3.0/24", "1.2.3.4/32", 24),
+            ("1.2.0.0/16", "1.2.3.4/24", 16),
+            ("1.2.3.0/24", "1.2.3.4/24", 28),
+            ("0.0.0.0/0", "1.2.3.4/24", 0),
+            ("2001:db8:0:ff00::/56", "2001:db8:0:ffff::1/128", 56),
+        ] {
+            let subnet = subnet.parse::<ClientSubnet>().unwrap();
+            assert_eq!(expect, truncate(&subnet, prefix).to_string());
+        }
+
+        assert!(Mode::subnet_of("192.168.1.1".parse().unwrap(), 24, 56).is_none());
+        assert!(Mode::subnet_of("fe80::1".parse().unwrap(), 24, 56).is_none());
+        assert_eq!(
+            Some("1.2.3.0/24".parse().unwrap()),
+            Mode::subnet_of("::ffff:1.2.3.4".parse().unwrap(), 24, 56)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ecs_client() {
+        init();
+
+        let (f, cnt) = filter(r#"mode = "client""#);
+
+        let (ctx, req, res) = query(&f, "1.2.3.4:5353", None).await;
+        assert!(ctx.flags.contains(ContextFlags::NO_CACHE));
+        assert_eq!(
+            Some(("1.2.3.0/24".parse().unwrap(), 0)),
+            client_subnet(&req)
+        );
+        assert_eq!("1.2.3.0", address(&res));
+        // the client didn't send ECS, so it should be stripped from the response
+        assert!(client_subnet(&res).is_none());
+        assert_eq!(1, cnt.load(Ordering::SeqCst));
+
+        // hit cache: in the scope 1.2.0.0/16
+        let (_, _, res) = query(&f, "1.2.200.1:5353", None).await;
+        assert_eq!("1.2.3.0", address(&res));
+        assert_eq!(1234, res.id());
+        assert_eq!(1, cnt.load(Ordering::SeqCst));
+
+        // miss cache: out of the scope
+        let (_, _, res) = query(&f, "5.6.7.8:5353", None).await;
+        assert_eq!("5.6.7.0", address(&res));
+        assert_eq!(2, cnt.load(Ordering::SeqCst));
+
+        // respect the client subnet sent by client, and echo it
+        let (_, req, res) = query(&f, "5.6.7.8:5353", Some("9.9.9.0/24")).await;
+        assert_eq!(
+            Some(("9.9.9.0/24".parse().unwrap(), 0)),
+            client_subnet(&req)
+        );
+        assert_eq!("9.9.9.0", address(&res));
+        assert_eq!(
+            Some(("9.9.9.0/24".parse().unwrap(), 16)),
+            client_subnet(&res)
+        );
+        assert_eq!(3, cnt.load(Ordering::SeqCst));
+
+        // the longer client subnet is truncated to the configured prefix, and hits the cache
+        let (_, req, res) = query(&f, "5.6.7.8:5353", Some("9.9.8.7/32")).await;
+        assert_eq!(
+            Some(("9.9.8.0/24".parse().unwrap(), 0)),
+            client_subnet(&req)
+        );
+        assert_eq!("9.9.9.0", address(&res));
+        assert_eq!(
+            Some(("9.9.8.7/32".parse().unwrap(), 16)),
+            client_subnet(&res)
+        );
+        assert_eq!(3, cnt.load(Ordering::SeqCst));
+
+        let (_, req, _) = query(&f, "5.6.7.8:5353", Some("2001:db8:0:ffff::1/128")).await;
+        assert_eq!(
+            Some(("2001:db8:0:ff00::/56".parse().unwrap(), 0)),
+            client_subnet(&req)
+        );
+
+        // skip private clients
+        let (ctx, req, res) = query(&f, "192.168.1.1:5353", None).await;
+        assert!(!ctx.flags.contains(ContextFlags::NO_CACHE));
+        assert!(client_subnet(&req).is_none());
+        assert_eq!("127.0.0.1", address(&res));
+    }
+
+    #[tokio::test]
+    async fn test_ecs_fixed_and_strip() {
+        init();
+
+        let (f, _) = filter(
+            r#"mode = "fixed"
+        subnet = "8.8.4.4/24""#,
+        );
+        let (ctx, req, res) = query(&f, "1.2.3.4:5353", Some("9.9.9.0/24")).await;
+        assert!(!ctx.flags.contains(ContextFlags::NO_CACHE));
+        assert_eq!(
+            Some(("8.8.4.0/24".parse().unwrap(), 0)),
+            client_subnet(&req)
+        );
+        assert_eq!("8.8.4.0", address(&res));
+        assert_eq!(
+            Some(("9.9.9.0/24".parse().unwrap(), 16)),
+            client_subnet(&res)
+        );
+
+        let (f, _) = filter(r#"mode = "strip""#);
+        let (_, req, res) = query(&f, "1.2.3.4:5353", Some("9.9.9.0/24")).await;
+        assert!(client_subnet(&req).is_none());
+        assert_eq!("127.0.0.1", address(&res));
+        assert!(client_subnet(&res).is_none());
+
+        for props in [
+            r#"mode = "unknown""#,
+            r#"mode = "fixed""#,
+            r#"ipv4_prefix = 33"#,
+        ] {
+            let opts: Options = toml::from_str(props).unwrap();
+            assert!(EcsFilterFactory::try_from(&opts).is_err());
+        }
+    }
+}
@@ -0,0 +1,625 @@
+use crate::client::request;
+use async_trait::async_trait;
+use maxminddb::Reader;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::filter::misc::OptionsReader;
+use crate::misc::ipset::IpSet;
+use crate::protocol::{Message, RData, DNS};
+use crate::Result;
+
+use super::{handle_next, Context, Filter, FilterFactory, Options};
+
+/// How to judge the answers of mistrusted upstreams, which have addresses both in and out of
+/// the region.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum MixedPolicy {
+    /// Drop the mistrusted answers, and wait for the trusted ones.
+    Trusted,
+    /// Accept the mistrusted answers.
+    Mistrusted,
+    /// Accept the mistrusted answers if most of the addresses are in the region.
+    Majority,
+}
+
+impl std::str::FromStr for MixedPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "trusted" => Ok(Self::Trusted),
+            "mistrusted" => Ok(Self::Mistrusted),
+            "majority" => Ok(Self::Majority),
+            other => bail!("invalid mixed policy '{}'", other),
+        }
+    }
+}
+
+/// The region which is served by the mistrusted upstreams, the addresses are matched by either
+/// the countries of geoip database or the CIDR lists.
+struct Region {
+    geoip: Option<(Reader<Vec<u8>>, Vec<String>)>,
+    cidrs: IpSet,
+}
+
+impl Region {
+    fn contains(&self, addr: IpAddr) -> bool {
+        if self.cidrs.contains(addr) {
+            return true;
+        }
+        if let Some((geoip, countries)) = &self.geoip {
+            if let Ok(country) = geoip.lookup::<maxminddb::geoip2::Country>(addr) {
+                if let Some(code) = country.country.and_then(|it| it.iso_code) {
+                    return countries.iter().any(|it| it.eq_ignore_ascii_case(code));
+                }
+            }
+        }
+        false
+    }
+
+    /// Checks if the answers of mistrusted upstreams should be accepted, both A and AAAA records
+    /// are evaluated, and the answers without any address are always accepted.
+    fn accept(&self, msg: &Message, policy: MixedPolicy) -> bool {
+        let (mut inside, mut total) = (0usize, 0usize);
+        for next in msg.answers() {
+            let addr = match next.rdata() {
+                Ok(RData::A(a)) => IpAddr::V4(a.ipaddr()),
+                Ok(RData::AAAA(a)) => IpAddr::V6(a.ipaddr()),
+                _ => continue,
+            };
+            total += 1;
+            let ok = self.contains(addr);
+            debug!("{:?}: in_region={}", addr, ok);
+            if ok {
+                inside += 1;
+            }
+        }
+
+        if inside == total {
+            return true;
+        }
+        if inside == 0 {
+            return false;
+        }
+
+        match policy {
+            MixedPolicy::Trusted => false,
+            MixedPolicy::Mistrusted => true,
+            MixedPolicy::Majority => inside * 2 > total,
+        }
+    }
+}
+
+struct Group {
+    servers: Vec<DNS>,
+    timeout: Duration,
+}
+
+pub(crate) struct GeoSplitFilter {
+    trusted: Arc<Group>,
+    mistrusted: Arc<Group>,
+    region: Arc<Region>,
+    policy: MixedPolicy,
+    trusted_wait: Duration,
+    next: Option<Box<dyn Filter>>,
+}
+
+impl GeoSplitFilter {
+    /// Requests the servers of group one by one, returns the first answer and its upstream.
+    ///
+    /// The answer will be dropped if it's rejected by the region.
+    async fn request(
+        req: &Message,
+        group: &Group,
+        region: Option<(&Region, MixedPolicy)>,
+    ) -> Option<(String, Message)> {
+        for server in group.servers.iter() {
+            match request(server, req, group.timeout).await {
+                Ok(r) => {
+                    debug!("query from {} ok", server);
+                    if let Some((region, policy)) = region {
+                        if !region.accept(&r, policy) {
+                            debug!("reject answers from {}", server);
+                            return None;
+                        }
+                    }
+                    return Some((server.to_string(), r));
+                }
+                Err(e) => {
+                    let name = req.questions().next().map(|it| it.name().to_string());
+                    warn!(
+                        "failed to query '{}' from {}: {}",
+                        name.unwrap_or_default(),
+                        server,
+                        e
+                    );
+                }
+            }
+        }
+        None
+    }
+}
+
+#[async_trait]
+impl Filter for GeoSplitFilter {
+    async fn handle(
+        &self,
+        ctx: &mut Context,
+        req: &mut Message,
+        res: &mut Option<Message>,
+    ) -> Result<()> {
+        if res.is_none() {
+            // the answers are tagged by whether they are from the trusted group
+            let (tx, mut rx) = mpsc::channel::<(bool, (String, Message))>(2);
+
+            // resolve from mistrusted dns
+            {
+                let msg = Clone::clone(req);
+                let group = Clone::clone(&self.mistrusted);
+                let region = Clone::clone(&self.region);
+                let policy = self.policy;
+                let tx = Clone::clone(&tx);
+                tokio::spawn(async move {
+                    if let Some(answer) = Self::request(&msg, &group, Some((&region, policy))).await
+                    {
+                        tx.send((false, answer)).await.ok();
+                    }
+                });
+            }
+
+            // resolve from trusted dns
+            {
+                let msg = Clone::clone(req);
+                let group = Clone::clone(&self.trusted);
+                tokio::spawn(async move {
+                    if let Some(answer) = Self::request(&msg, &group, None).await {
+                        tx.send((true, answer)).await.ok();
+                    }
+                });
+            }
+
+            // the first accepted answer wins, but the trusted one is held for a while, so that
+            // the names in the region are resolved by the mistrusted upstreams
+            let answer = match rx.recv().await {
+                Some((false, answer)) => Some(answer),
+                Some((true, answer)) => {
+                    match tokio::time::timeout(self.trusted_wait, rx.recv()).await {
+                        Ok(Some((_, mistrusted))) => Some(mistrusted),
+                        _ => Some(answer),
+                    }
+                }
+                None => None,
+            };
+
+            if let Some((upstream, msg)) = answer {
+                ctx.set_upstream(upstream);
+                res.replace(msg);
+            }
+        }
+
+        handle_next(self.next.as_deref(), ctx, req, res).await
+    }
+
+    fn set_next(&mut self, next: Box<dyn Filter>) {
+        self.next.replace(next);
+    }
+}
+
+pub(crate) struct GeoSplitFilterFactory {
+    trusted: Arc<Group>,
+    mistrusted: Arc<Group>,
+    region: Arc<Region>,
+    policy: MixedPolicy,
+    trusted_wait: Duration,
+}
+
+impl GeoSplitFilterFactory {
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+    const DEFAULT_TRUSTED_WAIT: Duration = Duration::from_millis(200);
+    const DEFAULT_COUNTRY: &'static str = "CN";
+
+    /// The timeout of the legacy 'chinadns' alias.
+    const LEGACY_TIMEOUT: Duration = Duration::from_secs(15);
+
+    /// Creates the factory of the legacy 'chinadns' alias, whose servers wait for 15s and the
+    /// first accepted answer wins without holding the trusted one.
+    pub(crate) fn legacy(opts: &Options) -> Result<Self> {
+        Self::new(opts, Self::LEGACY_TIMEOUT, Duration::ZERO)
+    }
+
+    fn new(opts: &Options, default_timeout: Duration, default_wait: Duration) -> Result<Self> {
+        const KEY_TRUSTED: &str = "trusted";
+        const KEY_MISTRUSTED: &str = "mistrusted";
+        const KEY_GEOIP_DATABASE: &str = "geoip_database";
+        const KEY_COUNTRIES: &str = "countries";
+        const KEY_CIDRS: &str = "cidrs";
+        const KEY_CIDR_FILES: &str = "cidr_files";
+        const KEY_MIXED: &str = "mixed";
+        const KEY_TRUSTED_TIMEOUT: &str = "trusted_timeout";
+        const KEY_MISTRUSTED_TIMEOUT: &str = "mistrusted_timeout";
+        const KEY_TRUSTED_WAIT: &str = "trusted_wait";
+
+        let r = OptionsReader::from(opts);
+
+        let trusted = {
+            r.get_addrs(KEY_TRUSTED)?
+                .ok_or(anyhow!("invalid property '{}'", KEY_TRUSTED))?
+        };
+        let mistrusted = {
+            r.get_addrs(KEY_MISTRUSTED)?
+                .ok_or(anyhow!("invalid property '{}'", KEY_MISTRUSTED))?
+        };
+
+        let strings =
+            |key: &str| -> Result<Vec<String>> { Ok(r.get_strings(key)?.unwrap_or_default()) };
+
+        let duration = |key: &str, default: Duration| -> Result<Duration> {
+            match opts.get(key) {
+                None => Ok(default),
+                Some(toml::Value::Integer(n)) if *n > 0 => Ok(Duration::from_secs(*n as u64)),
+                Some(toml::Value::String(s)) => crate::protocol::parse_duration(s),
+                Some(_) => bail!("invalid property '{}'", key),
+            }
+        };
+
+        let geoip = match opts.get(KEY_GEOIP_DATABASE) {
+            None => None,
+            Some(v) => {
+                let path = v
+                    .as_str()
+                    .ok_or(anyhow!("invalid property '{}'", KEY_GEOIP_DATABASE))?;
+                let mut countries = strings(KEY_COUNTRIES)?;
+                if countries.is_empty() {
+                    countries.push(Self::DEFAULT_COUNTRY.to_string());
+                }
+                Some((maxminddb::Reader::open_readfile(path)?, countries))
+            }
+        };
+
+        let cidrs = {
+            let mut bu = IpSet::builder();
+            for next in strings(KEY_CIDRS)? {
+                bu.insert(&next)?;
+            }
+            for next in strings(KEY_CIDR_FILES)? {
+                bu.read_file(&next)?;
+            }
+            bu.build()
+        };
+
+        if geoip.is_none() && cidrs.is_empty() {
+            bail!(
+                "no region, either '{}', '{}' or '{}' is required",
+                KEY_GEOIP_DATABASE,
+                KEY_CIDRS,
+                KEY_CIDR_FILES
+            );
+        }
+
+        let policy = match opts.get(KEY_MIXED) {
+            None => MixedPolicy::Trusted,
+            Some(v) => v
+                .as_str()
+                .ok_or(anyhow!("invalid property '{}'", KEY_MIXED))?
+                .parse()?,
+        };
+
+        Ok(Self {
+            trusted: Arc::new(Group {
+                servers: trusted,
+                timeout: duration(KEY_TRUSTED_TIMEOUT, default_timeout)?,
+            }),
+            mistrusted: Arc::new(Group {
+                servers: mistrusted,
+                timeout: duration(KEY_MISTRUSTED_TIMEOUT, default_timeout)?,
+            }),
+            region: Arc::new(Region { geoip, cidrs }),
+            policy,
+            trusted_wait: duration(KEY_TRUSTED_WAIT, default_wait)?,
+        })
+    }
+}
+
+impl TryFrom<&Options> for GeoSplitFilterFactory {
+    type Error = anyhow::Error;
+
+    fn try_from(opts: &Options) -> std::result::Result<Self, Self::Error> {
+        Self::new(opts, Self::DEFAULT_TIMEOUT, Self::DEFAULT_TRUSTED_WAIT)
+    }
+}
+
+impl FilterFactory for GeoSplitFilterFactory {
+    type Item = GeoSplitFilter;
+
+    fn get(&self) -> Result<Self::Item> {
+        Ok(GeoSplitFilter {
+            trusted: Clone::clone(&self.trusted),
+            mistrusted: Clone::clone(&self.mistrusted),
+            region: Clone::clone(&self.region),
+            policy: self.policy,
+            trusted_wait: self.trusted_wait,
+            next: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::protocol::{Class, Flags, Kind};
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    #[test]
+    fn test_region_accept() {
+        init();
+
+        let region = {
+            let mut bu = IpSet::builder();
+            bu.insert("1.0.1.0/24").unwrap();
+            bu.insert("240e::/20").unwrap();
+            Region {
+                geoip: None,
+                cidrs: bu.build(),
+            }
+        };
+
+        let answers = |addrs: &[&str]| {
+            let mut bu = Message::builder()
+                .id(1234)
+                .flags(Flags::builder().response().build())
+                .question("example.com", Kind::A, Class::IN)
+                .answer("example.com", Kind::CNAME, Class::IN, 300, &[0u8][..]);
+            for next in addrs {
+                bu = match next.parse::<IpAddr>().unwrap() {
+                    IpAddr::V4(v4) => {
+                        bu.answer("example.com", Kind::A, Class::IN, 300, v4.octets().to_vec())
+                    }
+                    IpAddr::V6(v6) => bu.answer(
+                        "example.com",
+                        Kind::AAAA,
+                        Class::IN,
+                        300,
+                        v6.octets().to_vec(),
+                    ),
+                };
+            }
+            bu.build().unwrap()
+        };
+
+        // (addresses, trusted, mistrusted, majority)
+        for (addrs, expects) in [
+            (vec![], [true, true, true]),
+            (vec!["1.0.1.1", "240e::1"], [true, true, true]),
+            (vec!["8.8.8.8", "2001:4860::8888"], [false, false, false]),
+            (vec!["1.0.1.1", "8.8.8.8"], [false, true, false]),
+            (vec!["1.0.1.1", "240e::1", "8.8.8.8"], [false, true, true]),
+        ] {
+            let msg = answers(&addrs);
+            for (policy, expect) in [
+                MixedPolicy::Trusted,
+                MixedPolicy::Mistrusted,
+                MixedPolicy::Majority,
+            ]
+            .into_iter()
+            .zip(expects)
+            {
+                assert_eq!(
+                    expect,
+                    region.accept(&msg, policy),
+                    "{:?} {:?}",
+                    addrs,
+                    policy
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_factory() {
+        init();
+
+        for (props, ok) in [
+            (
+                r#"
+            trusted = ["8.8.8.8"]
+            mistrusted = ["223.5.5.5"]
+            cidrs = ["1.0.1.0/24"]
+            mixed = "majority"
+            trusted_timeout = "3s"
+            mistrusted_timeout = 1
+            trusted_wait = "100ms"
+            "#,
+                true,
+            ),
+            // no region
+            (
+                r#"
+            trusted = ["8.8.8.8"]
+            mistrusted = ["223.5.5.5"]
+            "#,
+                false,
+            ),
+            (
+                r#"
+            trusted = ["8.8.8.8"]
+            mistrusted = ["223.5.5.5"]
+            cidrs = "1.0.1.0/24"
+            mixed = "unknown"
+            "#,
+                false,
+            ),
+            (
+                r#"
+            trusted = ["8.8.8.8"]
+            mistrusted = ["223.5.5.5"]
+            cidr_files = ["/nonexistent/china_ip_list.txt"]
+            "#,
+                false,
+            ),
+        ] {
+            let opts = toml::from_str::<Options>(props).unwrap();
+            assert_eq!(
+                ok,
+                GeoSplitFilterFactory::try_from(&opts).is_ok(),
+                "{}",
+                props
+            );
+        }
+
+        // the legacy 'chinadns' alias keeps the old defaults
+        let opts = toml::from_str::<Options>(
+            r#"
+            trusted = ["8.8.8.8"]
+            mistrusted = ["223.5.5.5"]
+            cidrs = ["1.0.1.0/24"]
+            "#,
+        )
+        .unwrap();
+        let legacy = GeoSplitFilterFactory::legacy(&opts).unwrap();
+        assert_eq!(Duration::from_secs(15), legacy.trusted.timeout);
+        assert_eq!(Duration::from_secs(15), legacy.mistrusted.timeout);
+        assert_eq!(Duration::ZERO, legacy.trusted_wait);
+    }
+
+    /// Serves the A queries with the address after a delay.
+    async fn stand_in(addr: [u8; 4], delay: Duration) -> Result<String> {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+        let local = socket.local_addr()?;
+        tokio::spawn(async move {
+            let mut b = [0u8; 512];
+            while let Ok((n, peer)) = socket.recv_from(&mut b).await {
+                let req = Message::from(b[..n].to_vec());
+                tokio::time::sleep(delay).await;
+                let res = Message::builder()
+                    .id(req.id())
+                    .flags(Flags::builder().response().build())
+                    .question("example.com", Kind::A, Class::IN)
+                    .answer("example.com", Kind::A, Class::IN, 300, &addr[..])
+                    .build()
+                    .unwrap();
+                socket.send_to(res.as_ref(), peer).await.ok();
+            }
+        });
+        Ok(local.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_trusted_wait() -> Result<()> {
+        init();
+
+        let trusted = stand_in([8, 8, 8, 8], Duration::ZERO).await?;
+        let mistrusted = stand_in([1, 0, 1, 1], Duration::from_millis(100)).await?;
+
+        let req = Message::builder()
+            .id(0x1234)
+            .flags(Flags::builder().request().recursive_query(true).build())
+            .question("example.com", Kind::A, Class::IN)
+            .build()?;
+
+        // the accepted mistrusted answer wins in the window, otherwise the trusted one is used
+        for (wait, upstream) in [("1s", &mistrusted), ("10ms", &trusted)] {
+            let opts = toml::from_str::<Options>(&format!(
+                r#"
+                trusted = ["{}"]
+                mistrusted = ["{}"]
+                cidrs = ["1.0.1.0/24"]
+                trusted_wait = "{}"
+                "#,
+                trusted, mistrusted, wait
+            ))?;
+            let f = GeoSplitFilterFactory::try_from(&opts)?.get()?;
+
+            let mut ctx = Context::default();
+            let mut resp = None;
+            f.handle(&mut ctx, &mut Clone::clone(&req), &mut resp)
+                .await?;
+            assert!(resp.is_some());
+            assert!(
+                ctx.upstream()
+                    .is_some_and(|it| it.contains(upstream.as_str())),
+                "{}: {:?}",
+                wait,
+                ctx.upstream()
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_geosplit() -> Result<()> {
+        init();
+
+        let opts = toml::from_str::<Options>(
+            r#"
+        trusted = ["tcp://8.8.8.8"]
+        mistrusted = ["223.5.5.5"]
+        geoip_database = "GeoLite2-Country.mmdb"
+        "#,
+        )?;
+
+        let factory = GeoSplitFilterFactory::try_from(&opts)?;
+        let mut ctx = Context::default();
+
+        let show = |msg: &Message| {
+            for next in msg.answers() {
+                info!(
+                    "answer: domain={}, rdata={}",
+                    next.name(),
+                    next.rdata().unwrap(),
+                );
+            }
+        };
+
+        {
+            let f = factory.get()?;
+            let mut req = {
+                // type=A domain=baidu.com
+                let baidu =
+                    "16060120000100000000000105626169647503636f6d00000100010000291000000000000000";
+                let raw = hex::decode(baidu)?;
+                Message::from(Bytes::from(raw))
+            };
+            let mut resp = None;
+
+            let res = f.handle(&mut ctx, &mut req, &mut resp).await;
+
+            assert!(res.is_ok());
+            assert!(ctx.upstream().is_some());
+            assert!(resp.is_some_and(|it| {
+                show(&it);
+                true
+            }));
+        }
+
+        {
+            let f = factory.get()?;
+
+            let mut req = {
+                // type=A domain=google.com
+                let google = "ca580120000100000000000106676f6f676c6503636f6d00000100010000291000000000000000";
+                let raw = hex::decode(google)?;
+                Message::from(Bytes::from(raw))
+            };
+            let mut resp = None;
+
+            let res = f.handle(&mut ctx, &mut req, &mut resp).await;
+
+            assert!(res.is_ok());
+            assert!(resp.is_some_and(|it| {
+                show(&it);
+                true
+            }));
+        }
+
+        Ok(())
+    }
+}
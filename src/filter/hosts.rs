@@ -1,34 +1,152 @@
+use super::watcher::FileWatcher;
 use super::{handle_next, Context, Filter, FilterFactory, Options};
+use crate::misc::is_valid_domain;
 use crate::{cachestr::Cachestr, protocol::*, Result};
+use arc_swap::ArcSwap;
 use hashbrown::HashMap;
 use once_cell::sync::Lazy;
 use smallvec::SmallVec;
 use std::io::{BufRead, BufReader};
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use toml::Value;
 
 type HostValue = SmallVec<[IpAddr; 1]>;
 type HostMap = HashMap<Cachestr, HostValue>;
 
-#[derive(Debug, Copy, Clone)]
-enum IpOctets {
-    V4([u8; 4]),
-    V6([u8; 16]),
+/// The default TTL of answers.
+const DEFAULT_TTL: u32 = 300;
+
+/// The max length of alias chains.
+const MAX_ALIAS_CHAIN: usize = 8;
+
+/// The hosts, all names are in lowercase and end with a dot.
+#[derive(Debug, Default)]
+struct Hosts {
+    names: HostMap,
+    // the wildcards like '*.dev.lan', which are keyed by the suffix 'dev.lan.'
+    wildcards: HostMap,
+    // alias -> target
+    aliases: HashMap<Cachestr, Cachestr>,
+    // the first name of each address, which is used by PTR answers
+    ptrs: HashMap<IpAddr, Cachestr>,
 }
 
-impl AsRef<[u8]> for IpOctets {
-    fn as_ref(&self) -> &[u8] {
-        match self {
-            IpOctets::V4(b) => &b[..],
-            IpOctets::V6(b) => &b[..],
+impl Hosts {
+    /// Returns the addresses of the name, the exact names take precedence over the wildcards,
+    /// and the longest wildcard wins.
+    fn addrs(&self, name: &str) -> Option<&HostValue> {
+        if let Some(v) = self.names.get(&Cachestr::from(name)) {
+            return Some(v);
         }
+        let mut rest = name;
+        while let Some(i) = rest.find('.') {
+            rest = &rest[i + 1..];
+            if rest.is_empty() {
+                break;
+            }
+            if let Some(v) = self.wildcards.get(&Cachestr::from(rest)) {
+                return Some(v);
+            }
+        }
+        None
+    }
+
+    fn is_known(&self, name: &str) -> bool {
+        self.aliases.contains_key(&Cachestr::from(name)) || self.addrs(name).is_some()
+    }
+
+    /// Returns the answers of the question in the form of (owner, kind, data).
+    ///
+    /// Returns None if the name is unknown, and the empty answers mean NODATA.
+    fn lookup(&self, question: &Question<'_>) -> Result<Option<Vec<(String, Kind, Vec<u8>)>>> {
+        let kind = question.kind();
+        let mut owner = question.name().to_string();
+        let mut key = format!("{}.", owner.to_ascii_lowercase());
+
+        if kind == Kind::PTR {
+            let name = match parse_reverse(&key).and_then(|ip| self.ptrs.get(&ip)) {
+                Some(name) => name,
+                None => return Ok(None),
+            };
+            let data = RDataOwned::PTR(Cachestr::from(name.trim_end_matches('.'))).to_bytes()?;
+            return Ok(Some(vec![(owner, Kind::PTR, data)]));
+        }
+
+        let mut answers = vec![];
+        for _ in 0..MAX_ALIAS_CHAIN {
+            if let Some(target) = self.aliases.get(&Cachestr::from(key.as_str())) {
+                let target = target.trim_end_matches('.');
+                let data = RDataOwned::CNAME(Cachestr::from(target)).to_bytes()?;
+                answers.push((owner, Kind::CNAME, data));
+                if kind == Kind::CNAME {
+                    break;
+                }
+                owner = target.to_string();
+                key = format!("{}.", target);
+                continue;
+            }
+
+            let addrs = match self.addrs(&key) {
+                Some(addrs) => addrs,
+                // the target of alias is left to the client
+                None if !answers.is_empty() => break,
+                None => return Ok(None),
+            };
+            for addr in addrs.iter() {
+                match (kind, addr) {
+                    (Kind::A, IpAddr::V4(v4)) => {
+                        answers.push((Clone::clone(&owner), kind, v4.octets().to_vec()))
+                    }
+                    (Kind::AAAA, IpAddr::V6(v6)) => {
+                        answers.push((Clone::clone(&owner), kind, v6.octets().to_vec()))
+                    }
+                    _ => (),
+                }
+            }
+            break;
+        }
+
+        Ok(Some(answers))
+    }
+}
+
+/// Parses the address from the reverse name like '4.3.2.1.in-addr.arpa.' or '...ip6.arpa.'.
+pub(super) fn parse_reverse(name: &str) -> Option<IpAddr> {
+    if let Some(rest) = name.strip_suffix(".in-addr.arpa.") {
+        let mut octets = [0u8; 4];
+        let mut labels = rest.split('.');
+        for i in (0..4).rev() {
+            octets[i] = labels.next()?.parse().ok()?;
+        }
+        if labels.next().is_some() {
+            return None;
+        }
+        return Some(IpAddr::V4(Ipv4Addr::from(octets)));
+    }
+
+    let rest = name.strip_suffix(".ip6.arpa.")?;
+    let mut octets = [0u8; 16];
+    let mut labels = rest.split('.');
+    for i in (0..32).rev() {
+        let label = labels.next()?;
+        if label.len() != 1 {
+            return None;
+        }
+        let nibble = u8::from_str_radix(label, 16).ok()?;
+        octets[i / 2] |= if i % 2 == 0 { nibble << 4 } else { nibble };
     }
+    if labels.next().is_some() {
+        return None;
+    }
+    Some(IpAddr::V6(Ipv6Addr::from(octets)))
 }
 
 pub(crate) struct HostsFilter {
-    hosts: Arc<HostMap>,
+    hosts: Arc<ArcSwap<Hosts>>,
+    ttl: u32,
     next: Option<Box<dyn Filter>>,
 }
 
@@ -43,53 +161,18 @@ impl Filter for HostsFilter {
         if res.is_none()
             && req.questions().all(|question| {
                 matches!(question.class(), Class::IN)
-                    && matches!(question.kind(), Kind::A | Kind::AAAA)
+                    && matches!(
+                        question.kind(),
+                        Kind::A | Kind::AAAA | Kind::CNAME | Kind::PTR
+                    )
             })
         {
-            let lookup = |question: &Question| {
-                let mut sb = SmallVec::<[u8; 128]>::new();
-                for name in question.name() {
-                    sb.extend_from_slice(name);
-                    sb.push(b'.');
-                }
-                let k = Cachestr::from(unsafe { std::str::from_utf8_unchecked(&sb[..]) });
-
-                let mut ips = SmallVec::<[IpOctets; 1]>::new();
-
-                if let Some(v) = self.hosts.get(&k) {
-                    for ip in v.iter() {
-                        match question.kind() {
-                            Kind::A => {
-                                if let IpAddr::V4(v4) = ip {
-                                    ips.push(IpOctets::V4(v4.octets()));
-                                }
-                            }
-                            Kind::AAAA => {
-                                if let IpAddr::V6(v6) = ip {
-                                    ips.push(IpOctets::V6(v6.octets()));
-                                }
-                            }
-                            _ => {
-                                return None;
-                            }
-                        }
-                    }
-                }
-
-                if ips.is_empty() {
-                    None
-                } else {
-                    Some((sb, ips))
-                }
-            };
-
-            let answers = req
-                .questions()
-                .map(|question| {
-                    let answer = lookup(&question);
-                    (question, answer)
-                })
-                .collect::<Vec<_>>();
+            let hosts = self.hosts.load();
+            let mut answers = vec![];
+            for question in req.questions() {
+                let answer = hosts.lookup(&question)?;
+                answers.push((question, answer));
+            }
 
             if !answers.is_empty() && answers.iter().any(|(_, answer)| answer.is_some()) {
                 let f = Flags::builder()
@@ -102,14 +185,14 @@ impl Filter for HostsFilter {
                 for (question, answer) in &answers {
                     let name = question.name().to_string();
                     bu = bu.question(name, question.kind(), question.class());
-                    if let Some((k, v)) = answer {
-                        for ip in v {
+                    if let Some(answer) = answer {
+                        for (owner, kind, data) in answer {
                             bu = bu.answer(
-                                unsafe { std::str::from_utf8_unchecked(&k[..]) },
-                                question.kind(),
+                                owner.as_str(),
+                                *kind,
                                 question.class(),
-                                300,
-                                ip.as_ref(),
+                                self.ttl,
+                                &data[..],
                             );
                         }
                     }
@@ -127,16 +210,60 @@ impl Filter for HostsFilter {
     }
 }
 
+/// The sources of hosts, which will be read again when any of the files is changed.
 #[derive(Debug, Clone, Default)]
-pub(crate) struct HostsFilterFactory(Arc<HostMap>);
+struct Sources {
+    hosts: Option<Value>,
+    files: Vec<PathBuf>,
+    aliases: Option<Value>,
+}
+
+impl Sources {
+    fn load(&self) -> Result<Hosts> {
+        let mut dst = Hosts::default();
+
+        // 1. read property of 'hosts'
+        if let Some(it) = &self.hosts {
+            HostsFilterFactory::read_hosts(it, &mut dst)?;
+        }
+
+        // 2. read the hosts files
+        for file in &self.files {
+            HostsFilterFactory::read_hosts_file(file, &mut dst)?;
+        }
+
+        // 3. read property of 'aliases', the targets must be known hosts
+        if let Some(it) = &self.aliases {
+            HostsFilterFactory::read_aliases(it, &mut dst)?;
+            for (alias, target) in dst.aliases.iter() {
+                if !dst.is_known(target) {
+                    bail!("the target of alias '{}' is not a host: {}", alias, target);
+                }
+            }
+        }
+
+        Ok(dst)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HostsFilterFactory {
+    hosts: Arc<ArcSwap<Hosts>>,
+    ttl: u32,
+}
 
 impl HostsFilterFactory {
-    fn read_hosts_file(path: &PathBuf, dst: &mut HostMap) -> Result<()> {
+    /// The interval of checking the hosts files.
+    const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+    /// Reads the hosts file, the malformed lines will be skipped with warnings.
+    fn read_hosts_file(path: &PathBuf, dst: &mut Hosts) -> Result<()> {
         let f = std::fs::File::open(path)?;
 
         let mut r = BufReader::new(f);
 
         let mut s = String::new();
+        let mut lineno = 0;
 
         loop {
             s.clear();
@@ -145,6 +272,7 @@ impl HostsFilterFactory {
             if n == 0 {
                 break;
             }
+            lineno += 1;
 
             let line = s.trim();
 
@@ -158,10 +286,28 @@ impl HostsFilterFactory {
             let mut sp = REGEX_SP.split(line);
 
             if let Some(first) = sp.next() {
-                let ip = first.parse::<IpAddr>()?;
+                let ip = match first.parse::<IpAddr>() {
+                    Ok(ip) => ip,
+                    Err(_) => {
+                        warn!(
+                            "skip malformed line {} of hosts file {:?}: invalid address '{}'",
+                            lineno, path, first
+                        );
+                        continue;
+                    }
+                };
 
                 for host in sp {
-                    Self::push_into(host, ip, dst);
+                    // the trailing comments
+                    if host.starts_with('#') {
+                        break;
+                    }
+                    if let Err(e) = Self::push_into(host, ip, dst) {
+                        warn!(
+                            "skip malformed host of line {} in hosts file {:?}: {}",
+                            lineno, path, e
+                        );
+                    }
                 }
             }
         }
@@ -169,34 +315,51 @@ impl HostsFilterFactory {
         Ok(())
     }
 
-    #[inline]
-    fn push_into(host: &str, ip: IpAddr, dst: &mut HostMap) {
-        let host = host.trim();
-        let host = if host.ends_with('.') {
-            Cachestr::from(host)
+    /// Normalizes the host into lowercase with the trailing dot.
+    fn normalize(host: &str) -> Result<String> {
+        let host = host.trim().to_ascii_lowercase();
+        if !is_valid_domain(&host) {
+            bail!("invalid host '{}'", host);
+        }
+        if host.ends_with('.') {
+            Ok(host)
         } else {
-            Cachestr::from(format!("{}.", host))
+            Ok(format!("{}.", host))
+        }
+    }
+
+    #[inline]
+    fn push_into(host: &str, ip: IpAddr, dst: &mut Hosts) -> Result<()> {
+        let (host, map) = match host.trim().strip_prefix("*.") {
+            Some(suffix) => (Self::normalize(suffix)?, &mut dst.wildcards),
+            None => {
+                let host = Cachestr::from(Self::normalize(host)?);
+                dst.ptrs.entry(ip).or_insert_with(|| Clone::clone(&host));
+                (host.to_string(), &mut dst.names)
+            }
         };
-        let ent = dst.entry(Clone::clone(&host)).or_default();
+        let host = Cachestr::from(host);
+        let ent = map.entry(Clone::clone(&host)).or_default();
         if !ent.contains(&ip) {
             ent.push(ip);
             debug!("detect new host: {}\t{}", ip, host);
         }
+        Ok(())
     }
 
     #[inline]
-    fn read_hosts(src: &Value, dst: &mut HostMap) -> Result<()> {
+    fn read_hosts(src: &Value, dst: &mut Hosts) -> Result<()> {
         if let Some(tbl) = src.as_table() {
             for (k, v) in tbl.iter() {
                 let ip = k.parse::<IpAddr>()?;
                 match v {
                     Value::String(host) => {
-                        Self::push_into(host, ip, dst);
+                        Self::push_into(host, ip, dst)?;
                     }
                     Value::Array(arr) => {
                         for next in arr {
                             let host = next.as_str().ok_or_else(|| anyhow!("invalid config"))?;
-                            Self::push_into(host, ip, dst);
+                            Self::push_into(host, ip, dst)?;
                         }
                     }
                     _ => bail!("invalid config"),
@@ -206,30 +369,49 @@ impl HostsFilterFactory {
 
         Ok(())
     }
+
+    #[inline]
+    fn read_aliases(src: &Value, dst: &mut Hosts) -> Result<()> {
+        let tbl = src.as_table().ok_or_else(|| anyhow!("invalid config"))?;
+        for (k, v) in tbl.iter() {
+            let target = v.as_str().ok_or_else(|| anyhow!("invalid config"))?;
+            let alias = Self::normalize(k)?;
+            let target = Self::normalize(target)?;
+            if alias == target {
+                bail!("invalid alias '{}' which points to itself", k);
+            }
+            debug!("detect new alias: {}\t{}", alias, target);
+            dst.aliases
+                .insert(Cachestr::from(alias), Cachestr::from(target));
+        }
+        Ok(())
+    }
 }
 
 impl TryFrom<&Options> for HostsFilterFactory {
     type Error = anyhow::Error;
 
     fn try_from(value: &Options) -> std::result::Result<Self, Self::Error> {
-        let mut dst = HostMap::new();
+        const KEY_TTL: &str = "ttl";
+        const KEY_ALIASES: &str = "aliases";
 
-        // 1. read property of 'hosts'
-        if let Some(it) = value.get("hosts") {
-            Self::read_hosts(it, &mut dst)?;
-        }
+        let mut sources = Sources {
+            hosts: value.get("hosts").cloned(),
+            aliases: value.get(KEY_ALIASES).cloned(),
+            ..Default::default()
+        };
 
-        // 2. read property of 'include/includes'
+        // read property of 'include/includes'
         for field in ["include", "includes"] {
             if let Some(files) = value.get(field) {
                 match files {
                     Value::String(file) => {
-                        Self::read_hosts_file(&PathBuf::from(file), &mut dst)?;
+                        sources.files.push(PathBuf::from(file));
                     }
                     Value::Array(arr) => {
                         for item in arr {
                             let file = item.as_str().ok_or_else(|| anyhow!("invalid config"))?;
-                            Self::read_hosts_file(&PathBuf::from(file), &mut dst)?;
+                            sources.files.push(PathBuf::from(file));
                         }
                     }
                     _ => bail!("invalid config"),
@@ -237,7 +419,45 @@ impl TryFrom<&Options> for HostsFilterFactory {
             }
         }
 
-        Ok(Self(Arc::new(dst)))
+        let ttl = match value.get(KEY_TTL) {
+            None => DEFAULT_TTL,
+            Some(v) => v
+                .as_integer()
+                .and_then(|it| u32::try_from(it).ok())
+                .ok_or_else(|| anyhow!("invalid property '{}'", KEY_TTL))?,
+        };
+
+        // the files are watched by default
+        let watcher = FileWatcher::from_options(
+            value,
+            Clone::clone(&sources.files),
+            Self::DEFAULT_WATCH_INTERVAL,
+        )?;
+
+        let hosts = Arc::new(ArcSwap::from_pointee(sources.load()?));
+
+        // replaces the hosts atomically if any of the files is changed, the old hosts will be
+        // kept if the files cannot be read.
+        if let Some(watcher) = watcher {
+            watcher.spawn(&hosts, move |hosts, changed| {
+                if !changed {
+                    return;
+                }
+                match sources.load() {
+                    Ok(next) => {
+                        info!(
+                            "reload hosts ok: files={:?}, names={}",
+                            &sources.files,
+                            next.names.len()
+                        );
+                        hosts.store(Arc::new(next));
+                    }
+                    Err(e) => warn!("failed to reload hosts {:?}: {:?}", &sources.files, e),
+                }
+            });
+        }
+
+        Ok(Self { hosts, ttl })
     }
 }
 
@@ -246,7 +466,8 @@ impl FilterFactory for HostsFilterFactory {
 
     fn get(&self) -> Result<Self::Item> {
         Ok(Self::Item {
-            hosts: Clone::clone(&self.0),
+            hosts: Clone::clone(&self.hosts),
+            ttl: self.ttl,
             next: None,
         })
     }
@@ -292,4 +513,191 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_hosts_answers() -> anyhow::Result<()> {
+        init();
+
+        let opts = toml::from_str::<Options>(
+            r#"
+        ttl = 60
+        hosts = { "192.168.1.1" = ["router.lan", "gateway.lan"], "fd00::1" = "router.lan", "192.168.1.100" = "*.dev.lan", "192.168.1.101" = "*.api.dev.lan", "192.168.1.2" = "nas.lan" }
+        aliases = { "www.lan" = "router.lan", "files.lan" = "storage.lan", "storage.lan" = "nas.lan" }
+        "#,
+        )?;
+        let f = HostsFilterFactory::try_from(&opts)?.get()?;
+
+        let query = |name: &'static str, kind: Kind| {
+            let f = &f;
+            async move {
+                let mut ctx = Context::default();
+                let mut req = Message::builder()
+                    .id(1234)
+                    .question(name, kind, Class::IN)
+                    .build()
+                    .unwrap();
+                let mut res = None;
+                f.handle(&mut ctx, &mut req, &mut res).await.unwrap();
+                res.map(|msg| {
+                    msg.answers()
+                        .map(|rr| {
+                            assert_eq!(60, rr.time_to_live());
+                            format!("{} {} {}", rr.name(), rr.kind(), rr.rdata().unwrap())
+                        })
+                        .collect::<Vec<_>>()
+                })
+            }
+        };
+
+        // exact names are case insensitive
+        assert_eq!(
+            Some(vec!["Router.LAN A 192.168.1.1".to_string()]),
+            query("Router.LAN", Kind::A).await
+        );
+        assert_eq!(
+            Some(vec!["router.lan AAAA fd00::1".to_string()]),
+            query("router.lan", Kind::AAAA).await
+        );
+
+        // NODATA if no address of the family
+        assert_eq!(Some(vec![]), query("nas.lan", Kind::AAAA).await);
+
+        // wildcards, the longest one wins
+        assert_eq!(
+            Some(vec!["a.b.dev.lan A 192.168.1.100".to_string()]),
+            query("a.b.dev.lan", Kind::A).await
+        );
+        assert_eq!(
+            Some(vec!["v1.api.dev.lan A 192.168.1.101".to_string()]),
+            query("v1.api.dev.lan", Kind::A).await
+        );
+        assert_eq!(None, query("dev.lan", Kind::A).await);
+
+        // aliases
+        assert_eq!(
+            Some(vec![
+                "files.lan CNAME storage.lan".to_string(),
+                "storage.lan CNAME nas.lan".to_string(),
+                "nas.lan A 192.168.1.2".to_string(),
+            ]),
+            query("files.lan", Kind::A).await
+        );
+        assert_eq!(
+            Some(vec!["www.lan CNAME router.lan".to_string()]),
+            query("www.lan", Kind::CNAME).await
+        );
+
+        // reverse lookups with the first name
+        assert_eq!(
+            Some(vec!["1.1.168.192.in-addr.arpa PTR router.lan".to_string()]),
+            query("1.1.168.192.in-addr.arpa", Kind::PTR).await
+        );
+        assert_eq!(
+            Some(vec![
+                "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.d.f.ip6.arpa PTR router.lan"
+                    .to_string()
+            ]),
+            query(
+                "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.d.f.ip6.arpa",
+                Kind::PTR
+            )
+            .await
+        );
+        assert_eq!(None, query("100.1.168.192.in-addr.arpa", Kind::PTR).await);
+
+        // unknown names are passed through
+        assert_eq!(None, query("google.com", Kind::A).await);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reload_hosts_file() -> anyhow::Result<()> {
+        init();
+
+        let path = std::env::temp_dir().join(format!("zerodns-hosts-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "# comments\n192.168.1.1 router.lan # trailing comments\nbad-ip nothing.lan\n192.168.1.2 bad_host! nas.lan\n",
+        )?;
+
+        let opts = toml::from_str::<Options>(&format!(
+            "include = {:?}\nwatch = \"50ms\"",
+            path.to_str().unwrap()
+        ))?;
+        let f = HostsFilterFactory::try_from(&opts)?.get()?;
+
+        let query = |name: &'static str| {
+            let f = &f;
+            async move {
+                let mut ctx = Context::default();
+                let mut req = Message::builder()
+                    .id(1234)
+                    .question(name, Kind::A, Class::IN)
+                    .build()
+                    .unwrap();
+                let mut res = None;
+                f.handle(&mut ctx, &mut req, &mut res).await.unwrap();
+                res.map(|msg| msg.answer_count())
+            }
+        };
+
+        // the malformed lines are skipped
+        assert_eq!(Some(1), query("router.lan").await);
+        assert_eq!(Some(1), query("nas.lan").await);
+        assert_eq!(None, query("nothing.lan").await);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        std::fs::write(&path, "192.168.1.3 printer.lan\n")?;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert_eq!(None, query("router.lan").await);
+        assert_eq!(Some(1), query("printer.lan").await);
+
+        // the old hosts are kept if the file is missing
+        std::fs::remove_file(&path)?;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(Some(1), query("printer.lan").await);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_reverse() {
+        init();
+
+        assert_eq!(
+            Some("1.2.3.4".parse::<IpAddr>().unwrap()),
+            parse_reverse("4.3.2.1.in-addr.arpa.")
+        );
+        assert_eq!(
+            Some("2001:db8::567:89ab".parse::<IpAddr>().unwrap()),
+            parse_reverse(
+                "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa."
+            )
+        );
+        for bad in [
+            "3.2.1.in-addr.arpa.",
+            "5.4.3.2.1.in-addr.arpa.",
+            "256.3.2.1.in-addr.arpa.",
+            "1.0.ip6.arpa.",
+            "www.example.com.",
+        ] {
+            assert_eq!(None, parse_reverse(bad), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_invalid_aliases() {
+        init();
+
+        for props in [
+            r#"aliases = { "www.lan" = "nothing.lan" }"#,
+            r#"aliases = { "www.lan" = "www.lan" }"#,
+            r#"ttl = -1"#,
+        ] {
+            let opts = toml::from_str::<Options>(props).unwrap();
+            assert!(HostsFilterFactory::try_from(&opts).is_err(), "{}", props);
+        }
+    }
 }
@@ -0,0 +1,335 @@
+use async_trait::async_trait;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::filter::misc::OptionsReader;
+use crate::misc::ipset::{IpSet, IpSetBuilder};
+use crate::protocol::{Message, MessageOwned, RCode, RData, RDataOwned, Section};
+use crate::Result;
+
+use super::{handle_next, Context, Filter, FilterFactory, Options};
+
+/// How to handle the responses which have blocked addresses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Action {
+    /// Rewrite the response to NXDOMAIN, like the 'bogus-nxdomain' of dnsmasq.
+    NXDomain,
+    /// Remove the records of blocked addresses.
+    Strip,
+}
+
+/// A blocked network and its hit counter.
+#[derive(Debug)]
+struct Entry {
+    network: String,
+    set: IpSet,
+    hits: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Blocklist {
+    /// The union of all entries, for the fast lookups.
+    all: IpSet,
+    entries: Vec<Entry>,
+    action: Action,
+}
+
+impl Blocklist {
+    /// Checks the address, and counts the hits of the matched entries.
+    fn hit(&self, addr: IpAddr, qname: &str) -> bool {
+        if !self.all.contains(addr) {
+            return false;
+        }
+        for next in self.entries.iter().filter(|it| it.set.contains(addr)) {
+            let hits = next.hits.fetch_add(1, Ordering::Relaxed) + 1;
+            info!(
+                "blocked address {} of {} matches {} ({} hits)",
+                addr, qname, &next.network, hits
+            );
+        }
+        true
+    }
+
+    /// Returns the blocked networks and their hit counts.
+    fn stats(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.entries
+            .iter()
+            .map(|it| (it.network.as_str(), it.hits.load(Ordering::Relaxed)))
+    }
+
+    /// Returns the hit count of the network.
+    #[cfg(test)]
+    fn hits(&self, network: &str) -> u64 {
+        self.stats()
+            .find(|(it, _)| *it == network)
+            .map(|(_, hits)| hits)
+            .unwrap_or_default()
+    }
+
+    /// Returns the rewritten response if it has blocked addresses.
+    fn check(&self, req: &Message, res: &Message) -> Result<Option<Message>> {
+        let qname = match req.questions().next() {
+            Some(question) => question.name().to_string(),
+            None => return Ok(None),
+        };
+
+        let mut blocked = false;
+        for next in res.answers() {
+            let addr = match next.rdata() {
+                Ok(RData::A(it)) => IpAddr::V4(it.ipaddr()),
+                Ok(RData::AAAA(it)) => IpAddr::V6(it.ipaddr()),
+                _ => continue,
+            };
+            // don't stop at the first one, so that all hits are counted
+            if self.hit(addr, &qname) {
+                blocked = true;
+            }
+        }
+        if !blocked {
+            return Ok(None);
+        }
+
+        let mut owned = MessageOwned::try_from(res)?;
+        match self.action {
+            Action::NXDomain => {
+                for section in [Section::Answer, Section::Authority, Section::Additional] {
+                    owned.records_mut(section).clear();
+                }
+                owned.set_rcode(RCode::NameError);
+            }
+            Action::Strip => {
+                owned.retain(Section::Answer, |it| match &it.data {
+                    RDataOwned::A(v4) => !self.all.contains(IpAddr::V4(*v4)),
+                    RDataOwned::AAAA(v6) => !self.all.contains(IpAddr::V6(*v6)),
+                    _ => true,
+                });
+            }
+        }
+
+        Ok(Some(owned.build()?))
+    }
+}
+
+impl Drop for Blocklist {
+    /// Shows the hits in the log when the server is stopped.
+    fn drop(&mut self) {
+        let hits = self
+            .stats()
+            .filter(|(_, hits)| *hits > 0)
+            .map(|(network, hits)| format!("{}={}", network, hits))
+            .collect::<Vec<_>>();
+        if !hits.is_empty() {
+            info!("the hits of blocked networks: {}", hits.join(", "));
+        }
+    }
+}
+
+/// Checks the answer addresses of the following filters against the blocked networks, the
+/// matched responses are rewritten to NXDOMAIN or stripped.
+pub(crate) struct IpBlockFilter {
+    blocklist: Arc<Blocklist>,
+    next: Option<Box<dyn Filter>>,
+}
+
+#[async_trait]
+impl Filter for IpBlockFilter {
+    async fn handle(
+        &self,
+        ctx: &mut Context,
+        req: &mut Message,
+        res: &mut Option<Message>,
+    ) -> Result<()> {
+        handle_next(self.next.as_deref(), ctx, req, res).await?;
+
+        if let Some(msg) = res.as_ref() {
+            if let Some(rewritten) = self.blocklist.check(req, msg)? {
+                res.replace(rewritten);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_next(&mut self, next: Box<dyn Filter>) {
+        self.next.replace(next);
+    }
+}
+
+pub(crate) struct IpBlockFilterFactory {
+    blocklist: Arc<Blocklist>,
+}
+
+impl TryFrom<&Options> for IpBlockFilterFactory {
+    type Error = anyhow::Error;
+
+    fn try_from(opts: &Options) -> std::result::Result<Self, Self::Error> {
+        const KEY_CIDRS: &str = "cidrs";
+        const KEY_CIDR_FILES: &str = "cidr_files";
+        const KEY_ACTION: &str = "action";
+
+        let r = OptionsReader::from(opts);
+
+        let mut all = IpSet::builder();
+        let mut entries = vec![];
+        let mut add = |network: &str| -> Result<()> {
+            let mut bu = IpSet::builder();
+            bu.insert(network)?;
+            all.insert(network)?;
+            entries.push(Entry {
+                network: network.trim().to_string(),
+                set: bu.build(),
+                hits: Default::default(),
+            });
+            Ok(())
+        };
+
+        for network in r.get_strings(KEY_CIDRS)?.unwrap_or_default() {
+            add(&network).map_err(|e| anyhow!("invalid blocked network '{}': {}", &network, e))?;
+        }
+        for path in r.get_strings(KEY_CIDR_FILES)?.unwrap_or_default() {
+            IpSetBuilder::read_networks(&path, &mut add)
+                .map_err(|e| anyhow!("failed to read '{}': {}", &path, e))?;
+        }
+
+        if entries.is_empty() {
+            bail!(
+                "no blocked network, either '{}' or '{}' is required",
+                KEY_CIDRS,
+                KEY_CIDR_FILES
+            );
+        }
+
+        let action = match opts.get(KEY_ACTION) {
+            None => Action::NXDomain,
+            Some(v) => match v.as_str() {
+                Some("nxdomain") => Action::NXDomain,
+                Some("strip") => Action::Strip,
+                _ => bail!("invalid property '{}'", KEY_ACTION),
+            },
+        };
+
+        Ok(Self {
+            blocklist: Arc::new(Blocklist {
+                all: all.build(),
+                entries,
+                action,
+            }),
+        })
+    }
+}
+
+impl FilterFactory for IpBlockFilterFactory {
+    type Item = IpBlockFilter;
+
+    fn get(&self) -> Result<Self::Item> {
+        Ok(IpBlockFilter {
+            blocklist: Clone::clone(&self.blocklist),
+            next: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Class, Flags, Kind};
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    #[test]
+    fn test_ipblock() -> anyhow::Result<()> {
+        init();
+
+        let req = Message::builder()
+            .id(1234)
+            .question("nonexist.example.com", Kind::A, Class::IN)
+            .build()?;
+        let hijacked = Message::builder()
+            .id(1234)
+            .flags(Flags::builder().response().build())
+            .question("nonexist.example.com", Kind::A, Class::IN)
+            .answer(
+                "nonexist.example.com",
+                Kind::A,
+                Class::IN,
+                60,
+                &[1, 2, 3, 4][..],
+            )
+            .answer(
+                "nonexist.example.com",
+                Kind::A,
+                Class::IN,
+                60,
+                &[5, 6, 7, 8][..],
+            )
+            .build()?;
+
+        let factory = {
+            let opts: Options = toml::from_str(r#"cidrs = ["1.2.3.0/24", "1.2.3.4", "fd00::/8"]"#)?;
+            IpBlockFilterFactory::try_from(&opts)?
+        };
+        let res = factory.blocklist.check(&req, &hijacked)?.unwrap();
+        assert_eq!(RCode::NameError, res.flags().response_code());
+        assert_eq!(0, res.answer_count());
+        assert_eq!(1, res.question_count());
+
+        let _ = factory.blocklist.check(&req, &hijacked)?;
+        assert_eq!(2, factory.blocklist.hits("1.2.3.0/24"));
+        assert_eq!(2, factory.blocklist.hits("1.2.3.4"));
+        assert_eq!(0, factory.blocklist.hits("fd00::/8"));
+
+        // strip
+        let opts: Options = toml::from_str(
+            r#"
+            cidrs = "1.2.3.4"
+            action = "strip"
+            "#,
+        )?;
+        let factory = IpBlockFilterFactory::try_from(&opts)?;
+        let res = factory.blocklist.check(&req, &hijacked)?.unwrap();
+        assert_eq!(RCode::NoError, res.flags().response_code());
+        assert_eq!(
+            vec!["5.6.7.8".to_string()],
+            res.answers()
+                .map(|it| it.rdata().unwrap().to_string())
+                .collect::<Vec<_>>()
+        );
+
+        // untouched
+        let opts: Options = toml::from_str(r#"cidrs = ["9.9.9.9"]"#)?;
+        let factory = IpBlockFilterFactory::try_from(&opts)?;
+        assert!(factory.blocklist.check(&req, &hijacked)?.is_none());
+
+        // read from the files
+        let path = std::env::temp_dir().join(format!("zerodns-ipblock-{}", std::process::id()));
+        std::fs::write(&path, "# sinkholes\n5.6.7.0/24 # malware\n")?;
+        let mut opts = Options::new();
+        opts.insert(
+            "cidr_files".to_string(),
+            toml::Value::String(path.to_str().unwrap().to_string()),
+        );
+        let factory = IpBlockFilterFactory::try_from(&opts)?;
+        assert!(factory.blocklist.check(&req, &hijacked)?.is_some());
+        assert_eq!(1, factory.blocklist.hits("5.6.7.0/24"));
+
+        // the invalid lines are reported like other list files
+        std::fs::write(&path, "5.6.7.0/24\nabc\n")?;
+        let err = IpBlockFilterFactory::try_from(&opts).err().unwrap();
+        std::fs::remove_file(&path)?;
+        assert!(err.to_string().contains("invalid line 2"), "{}", err);
+
+        for bad in [
+            "",
+            r#"cidrs = ["abc"]"#,
+            "cidrs = \"1.2.3.4\"\naction = \"drop\"",
+        ] {
+            let opts: Options = toml::from_str(bad)?;
+            assert!(IpBlockFilterFactory::try_from(&opts).is_err(), "{}", bad);
+        }
+
+        Ok(())
+    }
+}
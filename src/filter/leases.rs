@@ -0,0 +1,452 @@
+use super::hosts::parse_reverse;
+use super::watcher::FileWatcher;
+use super::{handle_next, Context, Filter, FilterFactory, Options};
+use crate::cachestr::Cachestr;
+use crate::misc::is_valid_domain;
+use crate::protocol::{Class, Flags, Kind, Message, RDataOwned};
+use crate::Result;
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+use hashbrown::HashMap;
+use smallvec::SmallVec;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A DHCP lease, the expiry is in unix seconds and zero means infinite.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Lease {
+    addr: IpAddr,
+    expiry: u64,
+}
+
+impl Lease {
+    #[inline]
+    fn is_expired(&self, now: u64) -> bool {
+        self.expiry != 0 && self.expiry <= now
+    }
+}
+
+/// The leased names, which are in lowercase and qualified by the local domain.
+#[derive(Debug, Default)]
+struct Leases {
+    names: HashMap<Cachestr, SmallVec<[Lease; 2]>>,
+    ptrs: HashMap<IpAddr, (Cachestr, u64)>,
+}
+
+impl Leases {
+    /// Parses the leases file of dnsmasq, each line of IPv4 leases is in the form of
+    /// '<expiry> <mac> <ip> <hostname> <client-id>', and the IPv6 leases follow the 'duid' line
+    /// in the form of '<expiry> <iaid> <ip> <hostname> <client-id>'.
+    ///
+    /// The leases without hostname ('*'), the malformed and the expired lines are skipped.
+    fn parse(s: &str, domain: &str, now: u64) -> Self {
+        let mut leases = Self::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("duid ") {
+                continue;
+            }
+
+            let fields = line
+                .split_ascii_whitespace()
+                .collect::<SmallVec<[&str; 5]>>();
+            if fields.len() < 4 {
+                warn!("skip malformed line {} of leases: {}", i + 1, line);
+                continue;
+            }
+
+            let (expiry, addr) = match (fields[0].parse::<u64>(), fields[2].parse::<IpAddr>()) {
+                (Ok(expiry), Ok(addr)) => (expiry, addr),
+                _ => {
+                    warn!("skip malformed line {} of leases: {}", i + 1, line);
+                    continue;
+                }
+            };
+            let lease = Lease { addr, expiry };
+
+            let host = fields[3];
+            if host == "*" || lease.is_expired(now) {
+                continue;
+            }
+            let name = format!("{}.{}", host.to_ascii_lowercase(), domain);
+            if !is_valid_domain(&name) {
+                warn!("skip invalid hostname of leases: {}", host);
+                continue;
+            }
+
+            let name = Cachestr::from(name);
+            leases
+                .ptrs
+                .insert(addr, (Clone::clone(&name), lease.expiry));
+            let ent = leases.names.entry(name).or_default();
+            // the renewed lease of same address replaces the old one
+            ent.retain(|it| it.addr != addr);
+            ent.push(lease);
+        }
+        leases
+    }
+
+    /// Returns the leases which are not expired.
+    fn prune(&self, now: u64) -> Self {
+        let mut leases = Self::default();
+        for (name, v) in self.names.iter() {
+            let v = v
+                .iter()
+                .filter(|it| !it.is_expired(now))
+                .copied()
+                .collect::<SmallVec<[Lease; 2]>>();
+            if !v.is_empty() {
+                leases.names.insert(Clone::clone(name), v);
+            }
+        }
+        for (addr, (name, expiry)) in self.ptrs.iter() {
+            let lease = Lease {
+                addr: *addr,
+                expiry: *expiry,
+            };
+            if !lease.is_expired(now) {
+                leases.ptrs.insert(*addr, (Clone::clone(name), *expiry));
+            }
+        }
+        leases
+    }
+
+    fn has_expired(&self, now: u64) -> bool {
+        self.names
+            .values()
+            .any(|v| v.iter().any(|it| it.is_expired(now)))
+    }
+}
+
+#[inline]
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|it| it.as_secs())
+        .unwrap_or_default()
+}
+
+fn read_leases(path: &Path, domain: &str) -> Result<Leases> {
+    let s = std::fs::read_to_string(path)?;
+    Ok(Leases::parse(&s, domain, now()))
+}
+
+pub(crate) struct LeasesFilter {
+    leases: Arc<ArcSwap<Leases>>,
+    ttl: u32,
+    next: Option<Box<dyn Filter>>,
+}
+
+impl LeasesFilter {
+    fn answer(&self, req: &Message) -> Result<Option<Message>> {
+        if req.question_count() != 1 {
+            return Ok(None);
+        }
+        let question = match req.questions().next() {
+            Some(question) => question,
+            None => return Ok(None),
+        };
+        if question.class() != Class::IN {
+            return Ok(None);
+        }
+
+        let kind = question.kind();
+        let owner = question.name().to_string();
+        let name = owner.to_ascii_lowercase();
+        let leases = self.leases.load();
+        let now = now();
+
+        let mut answers = SmallVec::<[(Kind, Vec<u8>); 2]>::new();
+        match kind {
+            Kind::PTR => {
+                let ptr = parse_reverse(&format!("{}.", name))
+                    .and_then(|addr| leases.ptrs.get(&addr))
+                    .filter(|(_, expiry)| *expiry == 0 || *expiry > now);
+                match ptr {
+                    Some((name, _)) => {
+                        answers.push((Kind::PTR, RDataOwned::PTR(Clone::clone(name)).to_bytes()?))
+                    }
+                    None => return Ok(None),
+                }
+            }
+            Kind::A | Kind::AAAA => {
+                let v = match leases.names.get(&Cachestr::from(name)) {
+                    Some(v) => v,
+                    None => return Ok(None),
+                };
+                let v = v.iter().filter(|it| !it.is_expired(now));
+                if v.clone().next().is_none() {
+                    return Ok(None);
+                }
+                for lease in v {
+                    match (kind, lease.addr) {
+                        (Kind::A, IpAddr::V4(v4)) => answers.push((kind, v4.octets().to_vec())),
+                        (Kind::AAAA, IpAddr::V6(v6)) => answers.push((kind, v6.octets().to_vec())),
+                        _ => (),
+                    }
+                }
+            }
+            _ => return Ok(None),
+        }
+
+        let flags = Flags::builder()
+            .response()
+            .authoritative(true)
+            .recursive_query(req.flags().is_recursive_query())
+            .recursive_available(true)
+            .build();
+        let mut bu = Message::builder()
+            .id(req.id())
+            .flags(flags)
+            .raw_question(question);
+        for (kind, data) in &answers {
+            bu = bu.answer(owner.as_str(), *kind, Class::IN, self.ttl, &data[..]);
+        }
+
+        Ok(Some(bu.build()?))
+    }
+}
+
+#[async_trait]
+impl Filter for LeasesFilter {
+    async fn handle(
+        &self,
+        ctx: &mut Context,
+        req: &mut Message,
+        res: &mut Option<Message>,
+    ) -> Result<()> {
+        if res.is_none() {
+            if let Some(msg) = self.answer(req)? {
+                res.replace(msg);
+            }
+        }
+        handle_next(self.next.as_deref(), ctx, req, res).await
+    }
+
+    fn set_next(&mut self, next: Box<dyn Filter>) {
+        self.next.replace(next);
+    }
+}
+
+pub(crate) struct LeasesFilterFactory {
+    leases: Arc<ArcSwap<Leases>>,
+    ttl: u32,
+}
+
+impl LeasesFilterFactory {
+    const DEFAULT_DOMAIN: &'static str = "lan";
+    const DEFAULT_TTL: u32 = 60;
+    const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(5);
+}
+
+impl TryFrom<&Options> for LeasesFilterFactory {
+    type Error = anyhow::Error;
+
+    fn try_from(opts: &Options) -> std::result::Result<Self, Self::Error> {
+        const KEY_FILE: &str = "file";
+        const KEY_DOMAIN: &str = "domain";
+        const KEY_TTL: &str = "ttl";
+
+        let path = match opts.get(KEY_FILE).and_then(|it| it.as_str()) {
+            Some(file) => PathBuf::from(file),
+            None => bail!("invalid property '{}'", KEY_FILE),
+        };
+
+        let domain = match opts.get(KEY_DOMAIN) {
+            None => Self::DEFAULT_DOMAIN.to_string(),
+            Some(v) => v
+                .as_str()
+                .map(|it| it.trim_matches('.').to_ascii_lowercase())
+                .filter(|it| !it.is_empty() && is_valid_domain(it))
+                .ok_or_else(|| anyhow!("invalid property '{}'", KEY_DOMAIN))?,
+        };
+
+        let ttl = match opts.get(KEY_TTL) {
+            None => Self::DEFAULT_TTL,
+            Some(v) => v
+                .as_integer()
+                .and_then(|it| u32::try_from(it).ok())
+                .ok_or_else(|| anyhow!("invalid property '{}'", KEY_TTL))?,
+        };
+
+        let watcher = FileWatcher::from_options(
+            opts,
+            vec![Clone::clone(&path)],
+            Self::DEFAULT_WATCH_INTERVAL,
+        )?;
+
+        // the leases file may be created later by the DHCP server
+        let initial = match read_leases(&path, &domain) {
+            Ok(leases) => leases,
+            Err(e) => {
+                warn!("failed to read leases {:?}: {:?}", &path, e);
+                Leases::default()
+            }
+        };
+        let leases = Arc::new(ArcSwap::from_pointee(initial));
+
+        // reloads the leases file if changed, and removes the expired leases
+        if let Some(watcher) = watcher {
+            watcher.spawn(&leases, move |leases, changed| {
+                if changed {
+                    match read_leases(&path, &domain) {
+                        Ok(next) => {
+                            info!(
+                                "reload leases ok: file={:?}, names={}",
+                                &path,
+                                next.names.len()
+                            );
+                            leases.store(Arc::new(next));
+                        }
+                        Err(e) => warn!("failed to reload leases {:?}: {:?}", &path, e),
+                    }
+                    return;
+                }
+
+                let now = now();
+                if leases.load().has_expired(now) {
+                    let next = leases.load().prune(now);
+                    leases.store(Arc::new(next));
+                }
+            });
+        }
+
+        Ok(Self { leases, ttl })
+    }
+}
+
+impl FilterFactory for LeasesFilterFactory {
+    type Item = LeasesFilter;
+
+    fn get(&self) -> Result<Self::Item> {
+        Ok(LeasesFilter {
+            leases: Clone::clone(&self.leases),
+            ttl: self.ttl,
+            next: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    const LEASES: &str = r#"
+1000 00:11:22:33:44:55 10.0.0.23 printer 01:00:11:22:33:44:55
+0 00:11:22:33:44:56 10.0.0.24 NAS *
+3000 00:11:22:33:44:57 10.0.0.25 * *
+500 00:11:22:33:44:58 10.0.0.26 phone *
+malformed line
+duid 00:01:00:01:2c:1f:00:00:00:11:22:33:44:55
+1000 12345 fd00::23 printer 00:01:00:01
+"#;
+
+    #[test]
+    fn test_parse_leases() {
+        init();
+
+        let leases = Leases::parse(LEASES, "lan", 900);
+        let addrs = |name: &str| {
+            leases
+                .names
+                .get(&Cachestr::from(name))
+                .map(|v| v.iter().map(|it| it.addr.to_string()).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            Some(vec!["10.0.0.23".to_string(), "fd00::23".to_string()]),
+            addrs("printer.lan")
+        );
+        assert_eq!(Some(vec!["10.0.0.24".to_string()]), addrs("nas.lan"));
+        // expired
+        assert_eq!(None, addrs("phone.lan"));
+        assert_eq!(2, leases.names.len());
+        assert_eq!(3, leases.ptrs.len());
+
+        assert!(!leases.has_expired(999));
+        assert!(leases.has_expired(1000));
+        let leases = leases.prune(1000);
+        assert_eq!(
+            vec![Cachestr::from("nas.lan")],
+            leases.names.keys().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(1, leases.ptrs.len());
+    }
+
+    #[tokio::test]
+    async fn test_leases_filter() -> anyhow::Result<()> {
+        init();
+
+        let path = std::env::temp_dir().join(format!("zerodns-leases-{}", std::process::id()));
+        let expiry = now() + 3600;
+        std::fs::write(
+            &path,
+            format!(
+                "{} 00:11:22:33:44:55 10.0.0.23 printer *\n{} 00:11:22:33:44:58 10.0.0.26 phone *\n",
+                expiry,
+                now() - 1
+            ),
+        )?;
+
+        let opts: Options = toml::from_str(&format!(
+            "file = {:?}\ndomain = \"lan.\"\nwatch = \"50ms\"",
+            path.to_str().unwrap()
+        ))?;
+        let f = LeasesFilterFactory::try_from(&opts)?.get()?;
+
+        let query = |name: &'static str, kind: Kind| {
+            let f = &f;
+            async move {
+                let mut ctx = Context::default();
+                let mut req = Message::builder()
+                    .id(1234)
+                    .question(name, kind, Class::IN)
+                    .build()
+                    .unwrap();
+                let mut res = None;
+                f.handle(&mut ctx, &mut req, &mut res).await.unwrap();
+                res.map(|msg| {
+                    assert!(msg.flags().is_authoritative());
+                    msg.answers()
+                        .map(|rr| format!("{} {} {}", rr.name(), rr.kind(), rr.rdata().unwrap()))
+                        .collect::<Vec<_>>()
+                })
+            }
+        };
+
+        assert_eq!(
+            Some(vec!["Printer.LAN A 10.0.0.23".to_string()]),
+            query("Printer.LAN", Kind::A).await
+        );
+        assert_eq!(Some(vec![]), query("printer.lan", Kind::AAAA).await);
+        assert_eq!(
+            Some(vec!["23.0.0.10.in-addr.arpa PTR printer.lan".to_string()]),
+            query("23.0.0.10.in-addr.arpa", Kind::PTR).await
+        );
+        assert_eq!(None, query("phone.lan", Kind::A).await);
+        assert_eq!(None, query("26.0.0.10.in-addr.arpa", Kind::PTR).await);
+        assert_eq!(None, query("printer.lan", Kind::MX).await);
+
+        // reload after the file is changed
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        std::fs::write(
+            &path,
+            format!("{} 00:11:22:33:44:56 10.0.0.24 nas *\n", expiry),
+        )?;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(None, query("printer.lan", Kind::A).await);
+        assert_eq!(
+            Some(vec!["nas.lan A 10.0.0.24".to_string()]),
+            query("nas.lan", Kind::A).await
+        );
+
+        std::fs::remove_file(&path)?;
+
+        Ok(())
+    }
+}
@@ -2,13 +2,15 @@ use super::proto::Filter;
 use crate::cachestr::Cachestr;
 use crate::client::request as resolve;
 use crate::filter::{handle_next, Context, ContextFlags, FilterFactory, Options};
-use crate::protocol::{Class, Flags, Kind, Message, OpCode, RCode, RDataOwned, DNS};
+use crate::protocol::{
+    Class, EdnsOption, Flags, Kind, Message, MessageOwned, OpCode, QuestionOwned, RCode,
+    RDataOwned, RecordOwned, Section, DNS,
+};
 use async_trait::async_trait;
 use mlua::prelude::*;
 use mlua::{Function, Lua, MetaMethod, UserData, Variadic};
 use once_cell::sync::Lazy;
 use smallvec::SmallVec;
-use std::borrow::Cow;
 use std::net::{Ipv4Addr, Ipv6Addr};
 use std::str::FromStr;
 use std::sync::Arc;
@@ -46,17 +48,37 @@ impl UserData for LuaLoggerModule {
     }
 }
 
+/// An editable message, which is created by 'Message(id [, opts])' or 'msg:edit()'.
 #[derive(Debug, Default)]
-struct LuaMessageBuilder {
-    id: u16,
-    flags: Flags,
-    questions: SmallVec<[(Cachestr, Class, Kind); 1]>,
-    answers: SmallVec<[(Cachestr, Class, Kind, u32, RDataOwned); 1]>,
+struct LuaMessageBuilder(MessageOwned);
+
+impl LuaMessageBuilder {
+    fn add_record(
+        &mut self,
+        section: Section,
+        (name, ttl, class, typ, data): (LuaString, u32, LuaValue, LuaValue, LuaValue),
+    ) -> LuaResult<()> {
+        let name = name.to_str()?;
+        let class = parse_class(class)?;
+        let kind = parse_kind(typ)?;
+        let data = parse_rdata(kind, data)?;
+        self.0.push(
+            section,
+            RecordOwned {
+                name: Cachestr::from(&*name),
+                kind,
+                class,
+                ttl,
+                data,
+            },
+        );
+        Ok(())
+    }
 }
 
 impl UserData for LuaMessageBuilder {
     fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
-        fields.add_field_method_get("id", |_lua, this| Ok(this.id));
+        fields.add_field_method_get("id", |_lua, this| Ok(this.0.id));
     }
 
     fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
@@ -67,93 +89,68 @@ impl UserData for LuaMessageBuilder {
                 let kind = parse_kind(typ)?;
 
                 let s = name.to_str()?;
-                this.questions.push((Cachestr::from(&*s), class, kind));
+                this.0.questions.push(QuestionOwned {
+                    name: Cachestr::from(&*s),
+                    kind,
+                    class,
+                });
                 Ok(())
             },
         );
 
-        methods.add_method_mut("answer", |_lua, this, (name, ttl, class, typ, data): (LuaString, u32, LuaValue, LuaValue, LuaValue)| {
-            let name = name.to_str()?;
-
-            let class = parse_class(class)?;
-            let typ = parse_kind(typ)?;
-
-            let to_str = || {
-                data.as_str().ok_or_else(|| LuaError::external(anyhow!("incorrect data type '{}', expect is 'string'",data.type_name())))
-            };
-            let to_table = || {
-                data.as_table().ok_or_else(|| LuaError::external(anyhow!("incorrect data type '{}', expect is 'table'",data.type_name())))
-            };
+        // msg:answer(name, ttl, class, type, data), same as authority/additional.
+        methods.add_method_mut("answer", |_lua, this, args| {
+            this.add_record(Section::Answer, args)
+        });
+        methods.add_method_mut("authority", |_lua, this, args| {
+            this.add_record(Section::Authority, args)
+        });
+        methods.add_method_mut("additional", |_lua, this, args| {
+            this.add_record(Section::Additional, args)
+        });
 
-            let rdata: LuaResult<RDataOwned> = match typ {
-                Kind::A => {
-                    let s = to_str()?;
-                    let v = s.parse::<Ipv4Addr>()?;
-                    Ok(RDataOwned::A(v))
-                }
-                Kind::AAAA => {
-                    let s = to_str()?;
-                    let v = s.parse::<Ipv6Addr>()?;
-                    Ok(RDataOwned::AAAA(v))
-                }
-                Kind::MX => {
-                    let tbl = to_table()?;
-                    let preference = tbl.get::<u16>("preference")?;
-                    let mail_exchange = tbl.get::<LuaString>("mail_exchange")?;
-                    let mail_exchange_str = mail_exchange.to_str()?;
-
-                    Ok(RDataOwned::MX {
-                        preference,
-                        mail_exchange: Cachestr::from(&*mail_exchange_str),
-                    })
-                }
-                Kind::CNAME => {
-                    let s = to_str()?;
-                    Ok(RDataOwned::CNAME(Cachestr::from(&*s)))
-                }
-                other => Err(LuaError::external(anyhow!("type '{}' is not supported yet", other))),
-            };
-            this.answers.push((Cachestr::from(&*name), class, typ, ttl, rdata?));
+        // msg:remove(section, type), returns the amount of removed records.
+        methods.add_method_mut(
+            "remove",
+            |_lua, this, (section, typ): (LuaString, LuaValue)| {
+                let section = parse_section(&section.to_str()?)?;
+                let kind = parse_kind(typ)?;
+                Ok(this.0.remove(section, kind))
+            },
+        );
 
+        methods.add_method_mut("set_rcode", |_lua, this, rcode: u16| {
+            let rcode = RCode::try_from(rcode)
+                .map_err(|_| LuaError::external(anyhow!("invalid rcode {}", rcode)))?;
+            this.0.set_rcode(rcode);
             Ok(())
         });
 
-        methods.add_method("build", |_lua, this, ()| {
-            let mut bu = Message::builder().id(this.id).flags(this.flags);
+        // msg:set_ttl(ttl), rewrites the time-to-live of all records.
+        methods.add_method_mut("set_ttl", |_lua, this, ttl: u32| {
+            this.0.records_all_mut().for_each(|it| it.ttl = ttl);
+            Ok(())
+        });
 
-            for (name, class, kind) in &this.questions {
-                bu = bu.question(Cow::from(name.as_ref()), *kind, *class);
-            }
+        methods.add_method_mut(
+            "set_edns_option",
+            |_lua, this, (code, data): (u16, LuaString)| {
+                this.0
+                    .edns_mut()
+                    .set_option(EdnsOption::parse(code, &data.as_bytes()));
+                Ok(())
+            },
+        );
 
-            for (name, class, typ, ttl, data) in &this.answers {
-                let name = Cow::from(name.as_ref());
-                match data {
-                    RDataOwned::A(ipv4) => {
-                        let octets = ipv4.octets().to_vec();
-                        bu = bu.answer(name, *typ, *class, *ttl, Cow::Owned(octets));
-                    }
-                    RDataOwned::AAAA(ipv6) => {
-                        let octets = ipv6.octets().to_vec();
-                        bu = bu.answer(name, *typ, *class, *ttl, Cow::Owned(octets));
-                    }
-                    RDataOwned::CNAME(cname) => {
-                        bu = bu.answer(name, *typ, *class, *ttl, cname.as_bytes());
-                    }
-                    RDataOwned::TXT(txt) => {
-                        let b = txt.as_bytes();
-                        let mut buf = Vec::with_capacity(b.len() + 1);
-                        buf.push(b.len() as u8);
-                        buf.extend_from_slice(b);
-                        bu = bu.answer(name, *typ, *class, *ttl, Cow::Owned(buf));
-                    }
-                    RDataOwned::UNKNOWN(b) => {
-                        bu = bu.answer(name, *typ, *class, *ttl, &b[..]);
-                    }
-                    _ => todo!(),
-                }
-            }
+        methods.add_method_mut("remove_edns_option", |_lua, this, code: u16| {
+            Ok(match this.0.edns.as_mut() {
+                Some(edns) => edns.remove_option(code),
+                None => false,
+            })
+        });
 
-            bu.build().map(LuaMessage).map_err(LuaError::external)
+        methods.add_method("build", |_lua, this, ()| {
+            this.0.build().map(LuaMessage).map_err(LuaError::external)
         });
     }
 }
@@ -273,6 +270,11 @@ impl UserData for LuaMessage {
             Ok(questions)
         });
 
+        methods.add_method("edit", |_lua, this, ()| {
+            let owned = MessageOwned::try_from(&this.0).map_err(LuaError::external)?;
+            Ok(LuaMessageBuilder(owned))
+        });
+
         methods.add_method("answers", |lua, this, ()| {
             let mut ret = vec![];
             for answer in this.0.answers() {
@@ -506,12 +508,11 @@ impl TryFrom<&Options> for LuaFilterFactory {
                         .truncated(truncated)
                         .build();
 
-                        Ok(LuaMessageBuilder {
+                        Ok(LuaMessageBuilder(MessageOwned {
                             id,
                             flags,
-                            questions: Default::default(),
-                            answers: Default::default(),
-                        })
+                            ..Default::default()
+                        }))
                     })?,
                 )?;
 
@@ -565,6 +566,70 @@ fn parse_class(v: LuaValue) -> LuaResult<Class> {
     Err(LuaError::external(anyhow!("invalid class: {:?}", v)))
 }
 
+fn parse_section(s: &str) -> LuaResult<Section> {
+    match s.to_ascii_lowercase().as_str() {
+        "answer" => Ok(Section::Answer),
+        "authority" => Ok(Section::Authority),
+        "additional" => Ok(Section::Additional),
+        _ => Err(LuaError::external(anyhow!("invalid section '{}'", s))),
+    }
+}
+
+fn parse_rdata(kind: Kind, data: LuaValue) -> LuaResult<RDataOwned> {
+    let to_str = || {
+        data.as_str().ok_or_else(|| {
+            LuaError::external(anyhow!(
+                "incorrect data type '{}', expect is 'string'",
+                data.type_name()
+            ))
+        })
+    };
+    let to_table = || {
+        data.as_table().ok_or_else(|| {
+            LuaError::external(anyhow!(
+                "incorrect data type '{}', expect is 'table'",
+                data.type_name()
+            ))
+        })
+    };
+
+    match kind {
+        Kind::A => {
+            let s = to_str()?;
+            let v = s.parse::<Ipv4Addr>()?;
+            Ok(RDataOwned::A(v))
+        }
+        Kind::AAAA => {
+            let s = to_str()?;
+            let v = s.parse::<Ipv6Addr>()?;
+            Ok(RDataOwned::AAAA(v))
+        }
+        Kind::MX => {
+            let tbl = to_table()?;
+            let preference = tbl.get::<u16>("preference")?;
+            let mail_exchange = tbl.get::<LuaString>("mail_exchange")?;
+            let mail_exchange_str = mail_exchange.to_str()?;
+
+            Ok(RDataOwned::MX {
+                preference,
+                mail_exchange: Cachestr::from(&*mail_exchange_str),
+            })
+        }
+        Kind::CNAME => {
+            let s = to_str()?;
+            Ok(RDataOwned::CNAME(Cachestr::from(&*s)))
+        }
+        Kind::TXT => {
+            let s = to_str()?;
+            Ok(RDataOwned::txt(&s))
+        }
+        other => Err(LuaError::external(anyhow!(
+            "type '{}' is not supported yet",
+            other
+        ))),
+    }
+}
+
 fn parse_kind(v: LuaValue) -> LuaResult<Kind> {
     if let Some(s) = v.as_str() {
         let kind = s.parse::<Kind>()?;
@@ -666,4 +731,66 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_lua_edit() -> anyhow::Result<()> {
+        init();
+
+        let script = r#"
+            function handle(ctx)
+              local msg = Message(ctx.request:id())
+              msg:question('example.com', 'IN', 'A')
+              msg:answer('example.com', 300, 'IN', 'A', '1.2.3.4')
+              msg:answer('example.com', 300, 'IN', 'AAAA', '::1')
+              msg:additional('example.com', 300, 'IN', 'TXT', 'hello')
+
+              local resp = msg:build():edit()
+              resp:remove('answer', 'AAAA')
+              resp:set_ttl(60)
+              resp:set_rcode(3)
+              resp:set_edns_option(3, 'zerodns')
+              ctx:answer(resp:build())
+            end
+            "#;
+
+        let factory = {
+            let mut opts = Options::default();
+            opts.insert("script".into(), script.into());
+            LuaFilterFactory::try_from(&opts)?
+        };
+
+        let f = factory.get()?;
+
+        let mut ctx = Context::default();
+        let mut req = Message::builder()
+            .id(0x1314)
+            .flags(Flags::request())
+            .question("example.com", Kind::A, Class::IN)
+            .build()?;
+
+        let mut resp = None;
+        f.handle(&mut ctx, &mut req, &mut resp).await?;
+
+        let resp = MessageOwned::try_from(&resp.unwrap())?;
+        assert_eq!(0x1314, resp.id);
+        assert_eq!(RCode::NameError, resp.flags.response_code());
+        assert_eq!(1, resp.answers.len());
+        assert_eq!(
+            RDataOwned::A(Ipv4Addr::new(1, 2, 3, 4)),
+            resp.answers[0].data
+        );
+        assert!(resp
+            .answers
+            .iter()
+            .chain(resp.additionals.iter())
+            .all(|it| it.ttl == 60));
+        assert_eq!(
+            Some(&EdnsOption::Nsid(b"zerodns".to_vec())),
+            resp.edns
+                .as_ref()
+                .and_then(|it| it.option(EdnsOption::NSID))
+        );
+
+        Ok(())
+    }
 }
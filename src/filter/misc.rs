@@ -16,14 +16,14 @@ impl OptionsReader<'_> {
             None => Ok(None),
             Some(v) => match v.as_array() {
                 None => {
-                    bail!("invalid dns urls: '{}'", v.to_string());
+                    bail!("invalid dns urls: '{}'", v);
                 }
                 Some(arr) => {
                     let mut servers = vec![];
                     for next in arr {
                         match next.as_str() {
                             None => {
-                                bail!("invalid dns urls: '{}'", v.to_string());
+                                bail!("invalid dns urls: '{}'", v);
                             }
                             Some(s) => {
                                 servers.push(DNS::from_str(s)?);
@@ -35,6 +35,28 @@ impl OptionsReader<'_> {
             },
         }
     }
+
+    /// Reads a string or an array of strings.
+    pub fn get_strings<A>(&self, k: A) -> Result<Option<Vec<String>>>
+    where
+        A: AsRef<str>,
+    {
+        let k = k.as_ref();
+        match self.0.get(k) {
+            None => Ok(None),
+            Some(toml::Value::String(s)) => Ok(Some(vec![Clone::clone(s)])),
+            Some(toml::Value::Array(arr)) => arr
+                .iter()
+                .map(|it| {
+                    it.as_str()
+                        .map(String::from)
+                        .ok_or_else(|| anyhow!("invalid property '{}'", k))
+                })
+                .collect::<Result<Vec<_>>>()
+                .map(Some),
+            Some(_) => bail!("invalid property '{}'", k),
+        }
+    }
 }
 
 impl<'a> From<&'a Options> for OptionsReader<'a> {
@@ -1,23 +1,40 @@
-pub(crate) use chinadns::ChinaDNSFilterFactory;
+pub(crate) use dnssec::DNSSECFilterFactory;
+pub(crate) use ecs::EcsFilterFactory;
+pub(crate) use geosplit::GeoSplitFilterFactory;
 pub(crate) use hosts::HostsFilterFactory;
+pub(crate) use ipblock::IpBlockFilterFactory;
+pub(crate) use leases::LeasesFilterFactory;
 pub(crate) use lua::LuaFilterFactory;
 #[cfg(test)]
 pub(crate) use noop::NoopFilter;
 pub(crate) use noop::NoopFilterFactory;
 pub use proto::{Context, ContextFlags, Filter};
 pub(crate) use proxyby::ProxyByFilterFactory;
+pub(crate) use rebinding::RebindingFilterFactory;
+pub(crate) use recursive::RecursiveFilterFactory;
 pub(crate) use registry::load;
 pub(crate) use registry::FilterFactoryExt;
 pub use registry::{register, FilterFactory, Options};
+pub(crate) use rpz::RpzFilterFactory;
 
 pub(crate) use proto::handle_next;
+pub(crate) use zone::ZoneFilterFactory;
 
-mod chinadns;
+mod dnssec;
+mod ecs;
+mod geosplit;
 mod hosts;
+mod ipblock;
+mod leases;
 mod lua;
 mod misc;
 mod noop;
 mod proto;
 mod proxyby;
+mod rebinding;
+mod recursive;
 mod registry;
+mod rpz;
 mod wasm;
+mod watcher;
+mod zone;
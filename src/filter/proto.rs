@@ -8,6 +8,8 @@ pub struct ContextFlags(u64);
 bitflags! {
     impl ContextFlags: u64 {
         const NO_CACHE = 1 << 0;
+        /// Drop the request silently, no response will be sent.
+        const DROP = 1 << 1;
     }
 }
 
@@ -15,12 +17,31 @@ bitflags! {
 pub struct Context {
     pub flags: ContextFlags,
     pub(crate) peer: Option<SocketAddr>,
+    pub(crate) upstream: Option<String>,
+    pub(crate) tcp: bool,
 }
 
 impl Context {
     pub fn client_addr(&self) -> SocketAddr {
         self.peer.unwrap()
     }
+
+    /// Returns true if the request is received over tcp.
+    pub fn is_tcp(&self) -> bool {
+        self.tcp
+    }
+
+    /// Returns the upstream which answered the request, it will be shown in the access log.
+    pub fn upstream(&self) -> Option<&str> {
+        self.upstream.as_deref()
+    }
+
+    pub fn set_upstream<S>(&mut self, upstream: S)
+    where
+        S: Into<String>,
+    {
+        self.upstream.replace(upstream.into());
+    }
 }
 
 #[async_trait::async_trait]
@@ -74,7 +95,7 @@ mod tests {
     #[tokio::test]
     async fn test_filter() {
         let mut ctx = Context::default();
-        let f = AlwaysNoneFilter::default();
+        let f = AlwaysNoneFilter;
         let mut req = Message::builder().build().unwrap();
         let mut res = None;
 
@@ -0,0 +1,402 @@
+use async_trait::async_trait;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+
+use crate::filter::misc::OptionsReader;
+use crate::misc::ipset::IpSet;
+use crate::protocol::{
+    AdditionalRR, Message, MessageOwned, RCode, RData, RDataOwned, RecordOwned, Section,
+    SvcParamKey,
+};
+use crate::Result;
+
+use super::{handle_next, Context, Filter, FilterFactory, Options};
+
+/// The private, loopback, link-local, CGNAT and unspecified networks.
+const DEFAULT_RANGES: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "::/128",
+    "::1/128",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+/// How to handle the responses which point to the internal networks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Action {
+    /// Remove the internal addresses, includes the SVCB/HTTPS address hints.
+    Remove,
+    /// Replace the whole response with REFUSED.
+    Block,
+}
+
+#[derive(Debug)]
+struct Guard {
+    ranges: IpSet,
+    /// The internal suffixes in lowercase, without the leading and trailing dots.
+    allowlist: Vec<String>,
+    action: Action,
+}
+
+impl Guard {
+    fn is_allowed(&self, qname: &str) -> bool {
+        let qname = qname.trim_end_matches('.').to_ascii_lowercase();
+        self.allowlist.iter().any(|suffix| {
+            qname == *suffix
+                || (qname.len() > suffix.len()
+                    && qname.ends_with(suffix.as_str())
+                    && qname.as_bytes()[qname.len() - suffix.len() - 1] == b'.')
+        })
+    }
+
+    fn is_internal_hint(&self, key: SvcParamKey, data: &[u8]) -> bool {
+        match key {
+            SvcParamKey::IPV4HINT => data.chunks_exact(4).any(|it| {
+                let b = <[u8; 4]>::try_from(it).unwrap();
+                self.ranges.contains(IpAddr::V4(Ipv4Addr::from(b)))
+            }),
+            SvcParamKey::IPV6HINT => data.chunks_exact(16).any(|it| {
+                let b = <[u8; 16]>::try_from(it).unwrap();
+                self.ranges.contains(IpAddr::V6(Ipv6Addr::from(b)))
+            }),
+            _ => false,
+        }
+    }
+
+    fn is_internal(&self, rdata: &RData<'_>) -> bool {
+        match rdata {
+            RData::A(it) => self.ranges.contains(IpAddr::V4(it.ipaddr())),
+            RData::AAAA(it) => self.ranges.contains(IpAddr::V6(it.ipaddr())),
+            RData::HTTPS(it) | RData::SVCB(it) => it
+                .params()
+                .any(|p| self.is_internal_hint(p.key(), p.data())),
+            _ => false,
+        }
+    }
+
+    /// Removes the internal addresses of the record, returns false if the whole record should be
+    /// removed.
+    fn strip(&self, record: &mut RecordOwned) -> bool {
+        match &mut record.data {
+            RDataOwned::A(v4) => !self.ranges.contains(IpAddr::V4(*v4)),
+            RDataOwned::AAAA(v6) => !self.ranges.contains(IpAddr::V6(*v6)),
+            RDataOwned::HTTPS { params, .. } | RDataOwned::SVCB { params, .. } => {
+                for (key, data) in params.iter_mut() {
+                    let size = match key {
+                        SvcParamKey::IPV4HINT => 4,
+                        SvcParamKey::IPV6HINT => 16,
+                        _ => continue,
+                    };
+                    let kept = data
+                        .chunks(size)
+                        .filter(|it| !self.is_internal_hint(*key, it))
+                        .flatten()
+                        .copied()
+                        .collect();
+                    *data = kept;
+                }
+                // an empty hint is invalid
+                params.retain(|(key, data)| {
+                    !(matches!(key, SvcParamKey::IPV4HINT | SvcParamKey::IPV6HINT)
+                        && data.is_empty())
+                });
+                true
+            }
+            _ => true,
+        }
+    }
+
+    /// Returns the replaced response if it points to the internal networks.
+    fn check(&self, req: &Message, res: &Message) -> Result<Option<Message>> {
+        let qname = match req.questions().next() {
+            Some(question) => question.name().to_string(),
+            None => return Ok(None),
+        };
+        if self.is_allowed(&qname) {
+            return Ok(None);
+        }
+
+        let mut internal = res.answers().any(|rr| match rr.rdata() {
+            Ok(rdata) => self.is_internal(&rdata),
+            Err(_) => false,
+        });
+        if !internal {
+            internal = res.additionals().any(|rr| match rr {
+                AdditionalRR::RR(rr) => match rr.rdata() {
+                    Ok(rdata) => self.is_internal(&rdata),
+                    Err(_) => false,
+                },
+                AdditionalRR::PseudoRR(_) => false,
+            });
+        }
+        if !internal {
+            return Ok(None);
+        }
+
+        warn!(
+            "possible dns rebinding of {} is detected: {:?}",
+            &qname, self.action
+        );
+
+        let mut owned = MessageOwned::try_from(res)?;
+        match self.action {
+            Action::Remove => {
+                owned.answers.retain_mut(|it| self.strip(it));
+                owned.additionals.retain_mut(|it| self.strip(it));
+            }
+            Action::Block => {
+                for section in [Section::Answer, Section::Authority, Section::Additional] {
+                    owned.records_mut(section).clear();
+                }
+                owned.set_rcode(RCode::Refused);
+            }
+        }
+
+        Ok(Some(owned.build()?))
+    }
+}
+
+/// Protects the internal services from DNS rebinding attacks, the responses of the following
+/// filters are checked after they run, the addresses in the internal networks are removed or
+/// blocked unless the query name is in the allowlist.
+pub(crate) struct RebindingFilter {
+    guard: Arc<Guard>,
+    next: Option<Box<dyn Filter>>,
+}
+
+#[async_trait]
+impl Filter for RebindingFilter {
+    async fn handle(
+        &self,
+        ctx: &mut Context,
+        req: &mut Message,
+        res: &mut Option<Message>,
+    ) -> Result<()> {
+        handle_next(self.next.as_deref(), ctx, req, res).await?;
+
+        if let Some(msg) = res.as_ref() {
+            if let Some(replaced) = self.guard.check(req, msg)? {
+                res.replace(replaced);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_next(&mut self, next: Box<dyn Filter>) {
+        self.next.replace(next);
+    }
+}
+
+pub(crate) struct RebindingFilterFactory {
+    guard: Arc<Guard>,
+}
+
+impl TryFrom<&Options> for RebindingFilterFactory {
+    type Error = anyhow::Error;
+
+    fn try_from(opts: &Options) -> std::result::Result<Self, Self::Error> {
+        const KEY_ALLOWLIST: &str = "allowlist";
+        const KEY_RANGES: &str = "ranges";
+        const KEY_ACTION: &str = "action";
+
+        let r = OptionsReader::from(opts);
+
+        let mut allowlist = vec![];
+        for next in r.get_strings(KEY_ALLOWLIST)?.unwrap_or_default() {
+            let suffix = next.trim_matches('.').to_ascii_lowercase();
+            if suffix.is_empty() {
+                bail!("invalid property '{}': empty suffix", KEY_ALLOWLIST);
+            }
+            allowlist.push(suffix);
+        }
+
+        let ranges = {
+            let mut bu = IpSet::builder();
+            for next in DEFAULT_RANGES {
+                bu.insert(next)?;
+            }
+            for next in r.get_strings(KEY_RANGES)?.unwrap_or_default() {
+                bu.insert(&next)?;
+            }
+            bu.build()
+        };
+
+        let action = match opts.get(KEY_ACTION) {
+            None => Action::Remove,
+            Some(v) => match v.as_str() {
+                Some("remove") => Action::Remove,
+                Some("block") => Action::Block,
+                _ => bail!("invalid property '{}'", KEY_ACTION),
+            },
+        };
+
+        Ok(Self {
+            guard: Arc::new(Guard {
+                ranges,
+                allowlist,
+                action,
+            }),
+        })
+    }
+}
+
+impl FilterFactory for RebindingFilterFactory {
+    type Item = RebindingFilter;
+
+    fn get(&self) -> Result<Self::Item> {
+        Ok(RebindingFilter {
+            guard: Clone::clone(&self.guard),
+            next: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Class, Flags, Kind};
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    /// Answers the fixed response like an upstream.
+    struct Fixed(Message);
+
+    #[async_trait]
+    impl Filter for Fixed {
+        async fn handle(
+            &self,
+            _ctx: &mut Context,
+            _req: &mut Message,
+            res: &mut Option<Message>,
+        ) -> Result<()> {
+            res.replace(Clone::clone(&self.0));
+            Ok(())
+        }
+
+        fn set_next(&mut self, _next: Box<dyn Filter>) {}
+    }
+
+    async fn resolve(opts: &str, qname: &str, upstream: &Message) -> anyhow::Result<Message> {
+        let opts: Options = toml::from_str(opts)?;
+        let mut f = RebindingFilterFactory::try_from(&opts)?.get()?;
+        f.set_next(Box::new(Fixed(Clone::clone(upstream))));
+
+        let mut req = Message::builder()
+            .id(1234)
+            .question(qname, Kind::A, Class::IN)
+            .build()?;
+        let mut ctx = Context::default();
+        let mut res = None;
+        f.handle(&mut ctx, &mut req, &mut res).await?;
+        res.ok_or_else(|| anyhow!("no response"))
+    }
+
+    fn rdata(msg: &Message) -> Vec<String> {
+        msg.answers()
+            .map(|it| it.rdata().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_rebinding() -> anyhow::Result<()> {
+        init();
+
+        let https = RDataOwned::HTTPS {
+            priority: 1,
+            target_name: ".".into(),
+            params: vec![
+                (SvcParamKey::ALPN, b"\x02h2".to_vec()),
+                (SvcParamKey::IPV4HINT, vec![192, 168, 1, 1, 8, 8, 8, 8]),
+                (
+                    SvcParamKey::IPV6HINT,
+                    "fe80::1".parse::<Ipv6Addr>()?.octets().to_vec(),
+                ),
+            ],
+        }
+        .to_bytes()?;
+
+        let upstream = Message::builder()
+            .id(1234)
+            .flags(Flags::builder().response().build())
+            .question("evil.example.com", Kind::A, Class::IN)
+            .answer(
+                "evil.example.com",
+                Kind::A,
+                Class::IN,
+                60,
+                &[10, 0, 0, 1][..],
+            )
+            .answer(
+                "evil.example.com",
+                Kind::A,
+                Class::IN,
+                60,
+                &[1, 2, 3, 4][..],
+            )
+            .answer(
+                "evil.example.com",
+                Kind::A,
+                Class::IN,
+                60,
+                &[100, 64, 0, 1][..],
+            )
+            .answer("evil.example.com", Kind::HTTPS, Class::IN, 60, &https[..])
+            .build()?;
+
+        // remove the internal addresses
+        let res = resolve("", "evil.example.com", &upstream).await?;
+        assert_eq!(
+            vec![
+                "1.2.3.4".to_string(),
+                "1 . alpn=\"h2\" ipv4hint=8.8.8.8".to_string()
+            ],
+            rdata(&res)
+        );
+
+        // the allowlist
+        let opts = r#"allowlist = ["example.com"]"#;
+        let res = resolve(opts, "Evil.Example.Com.", &upstream).await?;
+        assert_eq!(4, res.answer_count());
+        let res = resolve(opts, "notexample.com", &upstream).await?;
+        assert_eq!(2, res.answer_count());
+
+        // block
+        let res = resolve(r#"action = "block""#, "evil.example.com", &upstream).await?;
+        assert_eq!(RCode::Refused, res.flags().response_code());
+        assert_eq!(0, res.answer_count());
+
+        // the public addresses are untouched
+        let public = Message::builder()
+            .id(1234)
+            .flags(Flags::builder().response().build())
+            .question("example.com", Kind::A, Class::IN)
+            .answer("example.com", Kind::A, Class::IN, 60, &[1, 2, 3, 4][..])
+            .build()?;
+        let res = resolve("", "example.com", &public).await?;
+        assert_eq!(public, res);
+
+        // the extra ranges
+        let res = resolve(r#"ranges = "1.2.3.0/24""#, "example.com", &public).await?;
+        assert_eq!(0, res.answer_count());
+
+        for bad in [
+            r#"action = "drop""#,
+            r#"allowlist = ["."]"#,
+            r#"ranges = ["abc"]"#,
+        ] {
+            let opts: Options = toml::from_str(bad)?;
+            assert!(RebindingFilterFactory::try_from(&opts).is_err(), "{}", bad);
+        }
+
+        Ok(())
+    }
+}
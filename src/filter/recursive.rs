@@ -0,0 +1,1023 @@
+use crate::cachestr::Cachestr;
+use crate::protocol::{AdditionalRR, Class, Codec, Flags, Kind, Message, RCode, RR};
+use crate::Result;
+use async_trait::async_trait;
+use bytes::BytesMut;
+use futures::future::BoxFuture;
+use futures::{SinkExt, StreamExt};
+use moka::future::Cache;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+use super::{handle_next, Context, Filter, FilterFactory, Options};
+
+/// IPv4 addresses of the root servers, see https://www.iana.org/domains/root/servers.
+const DEFAULT_ROOT_HINTS: [Ipv4Addr; 13] = [
+    Ipv4Addr::new(198, 41, 0, 4),
+    Ipv4Addr::new(170, 247, 170, 2),
+    Ipv4Addr::new(192, 33, 4, 12),
+    Ipv4Addr::new(199, 7, 91, 13),
+    Ipv4Addr::new(192, 203, 230, 10),
+    Ipv4Addr::new(192, 5, 5, 241),
+    Ipv4Addr::new(192, 112, 36, 4),
+    Ipv4Addr::new(198, 97, 190, 53),
+    Ipv4Addr::new(192, 36, 148, 17),
+    Ipv4Addr::new(192, 58, 128, 30),
+    Ipv4Addr::new(193, 0, 14, 129),
+    Ipv4Addr::new(199, 7, 83, 42),
+    Ipv4Addr::new(202, 12, 27, 33),
+];
+
+/// The max depth of nested resolutions, which are caused by CNAMEs and glueless nameservers.
+const MAX_DEPTH: u8 = 8;
+
+/// The max count of queries sent for resolving a single name.
+const MAX_QUERIES: usize = 32;
+
+/// An owned resource record whose names in rdata are decompressed.
+#[derive(Debug, Clone)]
+struct Record {
+    name: String,
+    kind: Kind,
+    class: Class,
+    ttl: u32,
+    data: Vec<u8>,
+}
+
+impl Record {
+    fn new(rr: &RR<'_>) -> Result<Self> {
+        let data = rr.expanded_data(false)?.into_owned();
+
+        Ok(Self {
+            name: normalize(&rr.name().to_string()),
+            kind: rr.kind(),
+            class: rr.class(),
+            ttl: rr.time_to_live(),
+            data,
+        })
+    }
+
+    /// Returns the target of CNAME/NS records.
+    fn target(&self) -> String {
+        let mut labels = vec![];
+        let mut b = &self.data[..];
+        while let Some((&n, rest)) = b.split_first() {
+            let n = n as usize;
+            if n == 0 || n > rest.len() {
+                break;
+            }
+            labels.push(String::from_utf8_lossy(&rest[..n]).to_lowercase());
+            b = &rest[n..];
+        }
+        labels.join(".")
+    }
+
+    fn ipaddr(&self) -> Option<IpAddr> {
+        match self.kind {
+            Kind::A => <[u8; 4]>::try_from(&self.data[..]).ok().map(IpAddr::from),
+            Kind::AAAA => <[u8; 16]>::try_from(&self.data[..]).ok().map(IpAddr::from),
+            _ => None,
+        }
+    }
+}
+
+/// Converts the name to lowercase without the trailing dot, the root is an empty string.
+#[inline]
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[inline]
+fn label_count(name: &str) -> usize {
+    if name.is_empty() {
+        0
+    } else {
+        name.split('.').count()
+    }
+}
+
+/// Checks if the name equals to or is a subdomain of the zone.
+#[inline]
+fn is_subdomain(name: &str, zone: &str) -> bool {
+    zone.is_empty()
+        || name == zone
+        || (name.len() > zone.len()
+            && name.ends_with(zone)
+            && name.as_bytes()[name.len() - zone.len() - 1] == b'.')
+}
+
+/// Returns the ancestor of name which has the given count of labels.
+#[inline]
+fn ancestor(name: &str, n: usize) -> &str {
+    let total = label_count(name);
+    if n >= total {
+        return name;
+    }
+    match name.match_indices('.').nth(total - n - 1) {
+        Some((i, _)) => &name[i + 1..],
+        None => name,
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Delegation {
+    servers: Arc<Vec<SocketAddr>>,
+    expired_at: Instant,
+}
+
+/// The final result of an iterative resolution.
+#[derive(Debug, Default)]
+struct Resolution {
+    rcode: Option<RCode>,
+    answers: Vec<Record>,
+    authorities: Vec<Record>,
+}
+
+/// Resolves names iteratively from the root servers.
+pub(crate) struct Resolver {
+    roots: Arc<Vec<SocketAddr>>,
+    port: u16,
+    timeout: Duration,
+    minimise: bool,
+    /// zone -> nameservers, which are learned from referrals.
+    delegations: Cache<Cachestr, Delegation>,
+}
+
+impl Resolver {
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+    const DEFAULT_CACHE_CAPACITY: u64 = 4096;
+
+    /// Finds the closest enclosing zone of the name which has known nameservers.
+    async fn closest(&self, name: &str) -> (String, Arc<Vec<SocketAddr>>) {
+        let now = Instant::now();
+        let mut n = label_count(name);
+        while n > 0 {
+            let zone = ancestor(name, n);
+            if let Some(d) = self.delegations.get(&Cachestr::from(zone)).await {
+                if d.expired_at > now {
+                    return (zone.to_string(), d.servers);
+                }
+            }
+            n -= 1;
+        }
+        (String::new(), Clone::clone(&self.roots))
+    }
+
+    /// Sends a non-recursive query to the nameservers one by one until a usable response.
+    async fn query(&self, servers: &[SocketAddr], name: &str, kind: Kind) -> Result<Message> {
+        let qname = if name.is_empty() { "." } else { name };
+
+        let mut last = None;
+        for server in servers {
+            // a random id for each query, so that the responses are hard to be forged
+            let req = Message::builder()
+                .id(rand::random())
+                .flags(Flags::builder().request().build())
+                .question(qname, kind, Class::IN)
+                .build()?;
+
+            let res = match exchange_udp(*server, &req, self.timeout).await {
+                Ok(res) if res.flags().is_message_truncated() => {
+                    exchange_tcp(*server, &req, self.timeout).await
+                }
+                other => other,
+            };
+
+            match res {
+                Ok(res) => match res.flags().response_code() {
+                    RCode::NoError | RCode::NameError => return Ok(res),
+                    rcode => {
+                        debug!("nameserver {} replies {:?} for '{}'", server, rcode, name);
+                        last.replace(anyhow!("nameserver {} replies {:?}", server, rcode));
+                    }
+                },
+                Err(e) => {
+                    debug!(
+                        "failed to query '{}' from nameserver {}: {}",
+                        name, server, e
+                    );
+                    last.replace(e);
+                }
+            }
+        }
+
+        Err(last.unwrap_or_else(|| anyhow!("no available nameservers for '{}'", name)))
+    }
+
+    /// Resolves the addresses of nameservers from a referral, the glue records are preferred.
+    /// Collects the addresses of the delegated nameservers, the glue records out of the zone
+    /// of the referring servers are ignored since they could be forged.
+    async fn nameservers(
+        &self,
+        res: &Message,
+        zone: &str,
+        ns: &[Record],
+        depth: u8,
+    ) -> Vec<SocketAddr> {
+        let names = ns.iter().map(|it| it.target()).collect::<Vec<_>>();
+
+        let mut servers = vec![];
+        for next in res.additionals().filter_map(|it| match it {
+            AdditionalRR::RR(rr) => Record::new(&rr).ok(),
+            _ => None,
+        }) {
+            if !names.contains(&next.name) || !is_subdomain(&next.name, zone) {
+                continue;
+            }
+            if let Some(ip) = next.ipaddr() {
+                let addr = SocketAddr::new(ip, self.port);
+                if !servers.contains(&addr) {
+                    servers.push(addr);
+                }
+            }
+        }
+
+        if servers.is_empty() {
+            // glueless delegation, resolve the nameservers by ourselves:
+            for name in names {
+                match self.resolve(&name, Kind::A, depth + 1).await {
+                    Ok(r) => {
+                        for next in r.answers.iter().filter(|it| it.name == name) {
+                            if let Some(ip) = next.ipaddr() {
+                                servers.push(SocketAddr::new(ip, self.port));
+                            }
+                        }
+                    }
+                    Err(e) => debug!("failed to resolve nameserver '{}': {}", name, e),
+                }
+                if !servers.is_empty() {
+                    break;
+                }
+            }
+        }
+
+        servers
+    }
+
+    fn resolve<'a>(
+        &'a self,
+        qname: &'a str,
+        kind: Kind,
+        depth: u8,
+    ) -> BoxFuture<'a, Result<Resolution>> {
+        Box::pin(async move {
+            if depth > MAX_DEPTH {
+                bail!("too deep resolution for '{}'", qname);
+            }
+
+            let (mut zone, mut servers) = self.closest(qname).await;
+            let mut minimise = self.minimise;
+            // the count of labels to be queried, see RFC 9156
+            let mut n = label_count(&zone) + 1;
+
+            for _ in 0..MAX_QUERIES {
+                let (name, typ) = if minimise && n < label_count(qname) {
+                    (ancestor(qname, n), Kind::NS)
+                } else {
+                    (qname, kind)
+                };
+
+                let res = self.query(&servers[..], name, typ).await?;
+                let answers = res
+                    .answers()
+                    .map(|it| Record::new(&it))
+                    .collect::<Result<Vec<_>>>()?;
+                let authorities = res
+                    .authorities()
+                    .map(|it| Record::new(&it))
+                    .collect::<Result<Vec<_>>>()?;
+
+                let rcode = res.flags().response_code();
+
+                if name != qname {
+                    match rcode {
+                        RCode::NameError => {
+                            // some nameservers are not compatible with empty non-terminals,
+                            // ask for the full name instead.
+                            minimise = false;
+                            continue;
+                        }
+                        _ if answers.is_empty() => (),
+                        // the current nameservers are authoritative for the subdomain
+                        _ => {
+                            n += 1;
+                            continue;
+                        }
+                    }
+                }
+
+                let ns = authorities
+                    .iter()
+                    .filter(|it| {
+                        it.kind == Kind::NS
+                            && it.name.len() > zone.len()
+                            && is_subdomain(&it.name, &zone)
+                            && is_subdomain(qname, &it.name)
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                if answers.is_empty() && rcode == RCode::NoError && !ns.is_empty() {
+                    // referral
+                    let child = Clone::clone(&ns[0].name);
+                    let found = self.nameservers(&res, &zone, &ns[..], depth).await;
+                    if found.is_empty() {
+                        bail!("no available nameservers for zone '{}'", child);
+                    }
+
+                    let ttl = ns.iter().map(|it| it.ttl).min().unwrap_or_default();
+                    servers = Arc::new(found);
+                    self.delegations
+                        .insert(
+                            Cachestr::from(child.as_str()),
+                            Delegation {
+                                servers: Clone::clone(&servers),
+                                expired_at: Instant::now() + Duration::from_secs(ttl as u64),
+                            },
+                        )
+                        .await;
+
+                    debug!("zone '{}' is delegated to {:?}", child, &servers);
+
+                    n = label_count(&child) + 1;
+                    zone = child;
+                    continue;
+                }
+
+                if name != qname {
+                    // NODATA, it may be an empty non-terminal or a name without zone cut.
+                    n += 1;
+                    continue;
+                }
+
+                let r = Resolution {
+                    rcode: Some(rcode),
+                    answers,
+                    authorities,
+                };
+                return self.follow(&zone, qname, kind, r, depth).await;
+            }
+
+            bail!("too many queries for resolving '{}'", qname)
+        })
+    }
+
+    /// Chases the CNAME chain of answers. Only the records in the bailiwick of the answering
+    /// zone are trusted, the out-of-zone targets are resolved from the beginning.
+    async fn follow(
+        &self,
+        zone: &str,
+        qname: &str,
+        kind: Kind,
+        r: Resolution,
+        depth: u8,
+    ) -> Result<Resolution> {
+        let answers = r
+            .answers
+            .into_iter()
+            .filter(|it| is_subdomain(&it.name, zone))
+            .collect::<Vec<_>>();
+        let authorities = r
+            .authorities
+            .into_iter()
+            .filter(|it| is_subdomain(&it.name, zone))
+            .collect::<Vec<_>>();
+
+        let mut chain = vec![];
+        let mut current = qname.to_string();
+
+        loop {
+            let matched = answers
+                .iter()
+                .filter(|it| it.name == current && (it.kind == kind || kind == Kind::ANY))
+                .cloned()
+                .collect::<Vec<_>>();
+            if !matched.is_empty() || kind == Kind::CNAME {
+                chain.extend(matched);
+                break;
+            }
+
+            match answers
+                .iter()
+                .find(|it| it.name == current && it.kind == Kind::CNAME)
+            {
+                Some(cname) => {
+                    chain.push(Clone::clone(cname));
+                    current = cname.target();
+                    if chain.len() > MAX_QUERIES {
+                        bail!("too long CNAME chain for '{}'", qname);
+                    }
+                }
+                None => {
+                    if current == qname {
+                        break;
+                    }
+                    // the target of CNAME is out of zone, resolve it from the beginning.
+                    let mut next = self.resolve(&current, kind, depth + 1).await?;
+                    chain.append(&mut next.answers);
+                    return Ok(Resolution {
+                        rcode: next.rcode,
+                        answers: chain,
+                        authorities: next.authorities,
+                    });
+                }
+            }
+        }
+
+        Ok(Resolution {
+            rcode: r.rcode,
+            answers: chain,
+            authorities,
+        })
+    }
+
+    async fn handle(&self, req: &Message) -> Result<Option<Message>> {
+        let question = match req.questions().next() {
+            Some(question) if question.class() == Class::IN => question,
+            _ => return Ok(None),
+        };
+
+        let qname = normalize(&question.name().to_string());
+        let r = self.resolve(&qname, question.kind(), 0).await?;
+
+        let flags = Flags::builder()
+            .response()
+            .recursive_query(req.flags().is_recursive_query())
+            .recursive_available(true)
+            .rcode(r.rcode.unwrap_or(RCode::NoError))
+            .build();
+
+        let mut bu = Message::builder()
+            .id(req.id())
+            .flags(flags)
+            .raw_question(question);
+
+        let name = |it: &Record| {
+            if it.name.is_empty() {
+                ".".to_string()
+            } else {
+                Clone::clone(&it.name)
+            }
+        };
+
+        for next in &r.answers {
+            bu = bu.answer(name(next), next.kind, next.class, next.ttl, &next.data[..]);
+        }
+        for next in r.authorities.iter().filter(|it| it.kind == Kind::SOA) {
+            bu = bu.authority(name(next), next.kind, next.class, next.ttl, &next.data[..]);
+        }
+
+        Ok(Some(bu.build()?))
+    }
+}
+
+pub(crate) struct RecursiveFilter {
+    resolver: Arc<Resolver>,
+    next: Option<Box<dyn Filter>>,
+}
+
+#[async_trait]
+impl Filter for RecursiveFilter {
+    async fn handle(
+        &self,
+        ctx: &mut Context,
+        req: &mut Message,
+        res: &mut Option<Message>,
+    ) -> Result<()> {
+        if res.is_none() {
+            match self.resolver.handle(req).await {
+                Ok(Some(msg)) => {
+                    res.replace(msg);
+                }
+                Ok(None) => (),
+                Err(e) => {
+                    if let Some(question) = req.questions().next() {
+                        warn!("failed to resolve '{}' recursively: {}", question.name(), e);
+                    }
+                }
+            }
+        }
+
+        handle_next(self.next.as_deref(), ctx, req, res).await
+    }
+
+    fn set_next(&mut self, next: Box<dyn Filter>) {
+        self.next.replace(next);
+    }
+}
+
+pub(crate) struct RecursiveFilterFactory {
+    resolver: Arc<Resolver>,
+}
+
+impl TryFrom<&Options> for RecursiveFilterFactory {
+    type Error = anyhow::Error;
+
+    fn try_from(opts: &Options) -> std::result::Result<Self, Self::Error> {
+        const KEY_ROOT_HINTS: &str = "root_hints";
+        const KEY_PORT: &str = "port";
+        const KEY_TIMEOUT: &str = "timeout";
+        const KEY_QNAME_MINIMISATION: &str = "qname_minimisation";
+        const KEY_CACHE_SIZE: &str = "cache_size";
+
+        let port = match opts.get(KEY_PORT) {
+            None => crate::DEFAULT_UDP_PORT,
+            Some(v) => v
+                .as_integer()
+                .and_then(|it| u16::try_from(it).ok())
+                .ok_or_else(|| anyhow!("invalid property '{}'", KEY_PORT))?,
+        };
+
+        let roots = match opts.get(KEY_ROOT_HINTS) {
+            None => DEFAULT_ROOT_HINTS
+                .iter()
+                .map(|ip| SocketAddr::new(IpAddr::V4(*ip), port))
+                .collect::<Vec<_>>(),
+            Some(v) => {
+                let arr = v
+                    .as_array()
+                    .ok_or_else(|| anyhow!("invalid property '{}'", KEY_ROOT_HINTS))?;
+                let mut roots = vec![];
+                for next in arr {
+                    let s = next
+                        .as_str()
+                        .ok_or_else(|| anyhow!("invalid property '{}'", KEY_ROOT_HINTS))?;
+                    let addr = match SocketAddr::from_str(s) {
+                        Ok(addr) => addr,
+                        Err(_) => SocketAddr::new(s.parse::<IpAddr>()?, port),
+                    };
+                    roots.push(addr);
+                }
+                if roots.is_empty() {
+                    bail!("invalid property '{}': empty root hints", KEY_ROOT_HINTS);
+                }
+                roots
+            }
+        };
+
+        let timeout = match opts.get(KEY_TIMEOUT) {
+            None => Resolver::DEFAULT_TIMEOUT,
+            Some(v) => match v {
+                toml::Value::Integer(n) if *n > 0 => Duration::from_secs(*n as u64),
+                toml::Value::String(s) => crate::protocol::parse_duration(s)?,
+                _ => bail!("invalid property '{}'", KEY_TIMEOUT),
+            },
+        };
+
+        let minimise = match opts.get(KEY_QNAME_MINIMISATION) {
+            None => true,
+            Some(v) => v
+                .as_bool()
+                .ok_or_else(|| anyhow!("invalid property '{}'", KEY_QNAME_MINIMISATION))?,
+        };
+
+        let capacity = match opts.get(KEY_CACHE_SIZE) {
+            None => Resolver::DEFAULT_CACHE_CAPACITY,
+            Some(v) => v
+                .as_integer()
+                .and_then(|it| u64::try_from(it).ok())
+                .ok_or_else(|| anyhow!("invalid property '{}'", KEY_CACHE_SIZE))?,
+        };
+
+        Ok(Self {
+            resolver: Arc::new(Resolver {
+                roots: Arc::new(roots),
+                port,
+                timeout,
+                minimise,
+                delegations: Cache::new(capacity),
+            }),
+        })
+    }
+}
+
+impl FilterFactory for RecursiveFilterFactory {
+    type Item = RecursiveFilter;
+
+    fn get(&self) -> Result<Self::Item> {
+        Ok(RecursiveFilter {
+            resolver: Clone::clone(&self.resolver),
+            next: None,
+        })
+    }
+}
+
+/// Checks if the message is the response of the request: the same id and question.
+fn is_reply(req: &Message, res: &Message) -> bool {
+    if !res.flags().is_response() || res.id() != req.id() {
+        return false;
+    }
+    match (req.questions().next(), res.questions().next()) {
+        (Some(q), Some(r)) => {
+            q.kind() == r.kind()
+                && q.class() == r.class()
+                && q.name()
+                    .to_string()
+                    .eq_ignore_ascii_case(&r.name().to_string())
+        }
+        _ => false,
+    }
+}
+
+/// Exchanges the query over a fresh udp socket of ephemeral port. The socket is connected, so
+/// that the datagrams from other sources are discarded, and the unmatched responses are ignored.
+async fn exchange_udp(server: SocketAddr, req: &Message, timeout: Duration) -> Result<Message> {
+    let bind = match server {
+        SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+        SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(server).await?;
+    socket.send(req.as_ref()).await?;
+
+    let recv = async {
+        let mut b = BytesMut::zeroed(65536);
+        loop {
+            let n = socket.recv(&mut b[..]).await?;
+            let res = Message::from(BytesMut::from(&b[..n]));
+            if res.validate().is_err() || !is_reply(req, &res) {
+                debug!("drop unmatched response from nameserver {}", server);
+                continue;
+            }
+            return Ok(res);
+        }
+    };
+    tokio::time::timeout(timeout, recv)
+        .await
+        .map_err(|_| anyhow!("query nameserver {} timeout", server))?
+}
+
+/// Exchanges the query over a fresh tcp connection, which is used after a truncated response.
+async fn exchange_tcp(server: SocketAddr, req: &Message, timeout: Duration) -> Result<Message> {
+    let exchange = async {
+        let mut stream = crate::misc::tcp::connect(server, None).await?;
+        let (r, w) = stream.split();
+        let mut r = FramedRead::new(r, Codec);
+        let mut w = FramedWrite::new(w, Codec);
+        w.send(req).await?;
+        w.flush().await?;
+        match r.next().await {
+            Some(res) => {
+                let res = res?;
+                res.validate()?;
+                if !is_reply(req, &res) {
+                    bail!("unmatched response from nameserver {}", server);
+                }
+                Ok(res)
+            }
+            None => bail!(crate::Error::ResolveNothing),
+        }
+    };
+    tokio::time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| anyhow!("query nameserver {} timeout", server))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryLoadingCache;
+    use crate::handler::Handler;
+    use crate::server::UdpServer;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use tokio::net::UdpSocket;
+    use tokio::sync::Notify;
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    type Responder = fn(&Message, &str, Kind) -> Result<Message>;
+
+    /// An authoritative stand-in which replies by the responder.
+    #[derive(Clone)]
+    struct StandIn {
+        cnt: Arc<AtomicU64>,
+        responder: Responder,
+    }
+
+    #[async_trait]
+    impl Handler for StandIn {
+        async fn handle(&self, _ctx: &mut Context, req: &mut Message) -> Result<Option<Message>> {
+            self.cnt.fetch_add(1, Ordering::SeqCst);
+            let question = req.questions().next().unwrap();
+            let name = normalize(&question.name().to_string());
+            (self.responder)(req, &name, question.kind()).map(Some)
+        }
+    }
+
+    fn reply(
+        req: &Message,
+        rcode: RCode,
+        authoritative: bool,
+    ) -> crate::protocol::MessageBuilder<'static> {
+        let question = req.questions().next().unwrap();
+        Message::builder()
+            .id(req.id())
+            .flags(
+                Flags::builder()
+                    .response()
+                    .authoritative(authoritative)
+                    .rcode(rcode)
+                    .build(),
+            )
+            .question(
+                question.name().to_string(),
+                question.kind(),
+                question.class(),
+            )
+    }
+
+    fn name(s: &str) -> Vec<u8> {
+        let mut b = vec![];
+        for label in s.split('.').filter(|it| !it.is_empty()) {
+            b.push(label.len() as u8);
+            b.extend_from_slice(label.as_bytes());
+        }
+        b.push(0);
+        b
+    }
+
+    fn soa(zone: &str) -> Vec<u8> {
+        let mut b = name(&format!("ns.{}", zone));
+        b.extend(name(&format!("admin.{}", zone)));
+        for n in [1u32, 3600, 600, 86400, 60] {
+            b.extend_from_slice(&n.to_be_bytes());
+        }
+        b
+    }
+
+    /// root: delegates 'com' with glue and 'net' without glue.
+    fn root(req: &Message, qname: &str, _kind: Kind) -> Result<Message> {
+        if is_subdomain(qname, "com") {
+            reply(req, RCode::NoError, false)
+                .authority("com", Kind::NS, Class::IN, 3600, name("ns.com"))
+                .additional("ns.com", Kind::A, Class::IN, 3600, vec![127, 0, 0, 2])
+                .build()
+        } else if is_subdomain(qname, "net") {
+            reply(req, RCode::NoError, false)
+                .authority("net", Kind::NS, Class::IN, 3600, name("ns.example.com"))
+                .build()
+        } else {
+            reply(req, RCode::NameError, true)
+                .authority(".", Kind::SOA, Class::IN, 60, soa("root"))
+                .build()
+        }
+    }
+
+    /// com: delegates 'example.com' with glue, and 'glue.com' with a forged glue of 'net'.
+    fn com(req: &Message, qname: &str, _kind: Kind) -> Result<Message> {
+        if is_subdomain(qname, "glue.com") {
+            reply(req, RCode::NoError, false)
+                .authority(
+                    "glue.com",
+                    Kind::NS,
+                    Class::IN,
+                    3600,
+                    name("ns.example.net"),
+                )
+                .additional(
+                    "ns.example.net",
+                    Kind::A,
+                    Class::IN,
+                    3600,
+                    vec![127, 0, 0, 9],
+                )
+                .build()
+        } else if is_subdomain(qname, "example.com") {
+            reply(req, RCode::NoError, false)
+                .authority(
+                    "example.com",
+                    Kind::NS,
+                    Class::IN,
+                    3600,
+                    name("ns.example.com"),
+                )
+                .additional(
+                    "ns.example.com",
+                    Kind::A,
+                    Class::IN,
+                    3600,
+                    vec![127, 0, 0, 3],
+                )
+                .build()
+        } else {
+            reply(req, RCode::NameError, true)
+                .authority("com", Kind::SOA, Class::IN, 60, soa("com"))
+                .build()
+        }
+    }
+
+    /// example.com & net: authoritative for both zones.
+    fn example(req: &Message, qname: &str, kind: Kind) -> Result<Message> {
+        let zone = if is_subdomain(qname, "example.com") {
+            "example.com"
+        } else {
+            "net"
+        };
+        match (qname, kind) {
+            ("example.com", Kind::NS) | ("net", Kind::NS) => reply(req, RCode::NoError, true)
+                .answer(qname, Kind::NS, Class::IN, 3600, name("ns.example.com"))
+                .build(),
+            ("ns.example.com", Kind::A) | ("ns.example.net", Kind::A) => {
+                reply(req, RCode::NoError, true)
+                    .answer(qname, Kind::A, Class::IN, 3600, vec![127, 0, 0, 3])
+                    .build()
+            }
+            ("www.glue.com", Kind::A) => reply(req, RCode::NoError, true)
+                .answer(qname, Kind::A, Class::IN, 300, vec![10, 0, 0, 2])
+                .build(),
+            ("www.example.com", _) => reply(req, RCode::NoError, true)
+                .answer(qname, Kind::CNAME, Class::IN, 300, name("web.example.net"))
+                .build(),
+            // the A record of other zone is forged, it should be resolved from 'net' again
+            ("evil.example.com", _) => reply(req, RCode::NoError, true)
+                .answer(qname, Kind::CNAME, Class::IN, 300, name("web.example.net"))
+                .answer("web.example.net", Kind::A, Class::IN, 300, vec![6, 6, 6, 6])
+                .build(),
+            ("web.example.net", Kind::A) => reply(req, RCode::NoError, true)
+                .answer(qname, Kind::A, Class::IN, 300, vec![10, 0, 0, 1])
+                .build(),
+            ("ns.example.com", _) | ("example.net", _) | ("web.example.net", _) => {
+                reply(req, RCode::NoError, true)
+                    .authority(zone, Kind::SOA, Class::IN, 60, soa(zone))
+                    .build()
+            }
+            _ => reply(req, RCode::NameError, true)
+                .authority(zone, Kind::SOA, Class::IN, 60, soa(zone))
+                .build(),
+        }
+    }
+
+    async fn serve(addr: SocketAddr, responder: Responder, closer: &Arc<Notify>) -> Arc<AtomicU64> {
+        let cnt = Arc::new(AtomicU64::new(0));
+        let h = StandIn {
+            cnt: Clone::clone(&cnt),
+            responder,
+        };
+        let socket = UdpSocket::bind(addr).await.unwrap();
+        let server = UdpServer::new(
+            socket,
+            h,
+            None::<Arc<MemoryLoadingCache>>,
+            Clone::clone(closer),
+        );
+        tokio::spawn(async move {
+            server.listen().await.expect("udp server is stopped!");
+        });
+        cnt
+    }
+
+    #[tokio::test]
+    async fn test_recursive() -> anyhow::Result<()> {
+        init();
+
+        let port = {
+            let socket = std::net::UdpSocket::bind("127.0.0.1:0")?;
+            socket.local_addr()?.port()
+        };
+        let closer = Arc::new(Notify::new());
+
+        let roots = serve(SocketAddr::from(([127, 0, 0, 1], port)), root, &closer).await;
+        serve(SocketAddr::from(([127, 0, 0, 2], port)), com, &closer).await;
+        serve(SocketAddr::from(([127, 0, 0, 3], port)), example, &closer).await;
+
+        let opts: Options = toml::from_str(&format!(
+            r#"
+            root_hints = ["127.0.0.1"]
+            port = {}
+            timeout = "1s"
+            "#,
+            port
+        ))?;
+
+        let factory = RecursiveFilterFactory::try_from(&opts)?;
+        let f = factory.get()?;
+
+        let resolve = |name: &'static str| {
+            let f = &f;
+            async move {
+                let mut ctx = Context::default();
+                let mut req = Message::builder()
+                    .id(0x1234)
+                    .flags(Flags::builder().request().recursive_query(true).build())
+                    .question(name, Kind::A, Class::IN)
+                    .build()?;
+                let mut res = None;
+                f.handle(&mut ctx, &mut req, &mut res).await?;
+                res.ok_or_else(|| anyhow!("no response"))
+            }
+        };
+
+        // cname across zones, and 'net' is glueless
+        let res = resolve("www.example.com").await?;
+        assert_eq!(0x1234, res.id());
+        assert_eq!(RCode::NoError, res.flags().response_code());
+        assert!(res.flags().is_recursion_available());
+        let answers = res
+            .answers()
+            .map(|it| (it.kind(), it.rdata().unwrap().to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (Kind::CNAME, "web.example.net".to_string()),
+                (Kind::A, "10.0.0.1".to_string())
+            ],
+            answers
+        );
+
+        // delegations are cached, the root should not be asked again
+        let n = roots.load(Ordering::SeqCst);
+        let res = resolve("www.example.com").await?;
+        assert_eq!(2, res.answer_count());
+        assert_eq!(n, roots.load(Ordering::SeqCst));
+
+        // out-of-bailiwick records are ignored
+        let res = resolve("evil.example.com").await?;
+        let answers = res
+            .answers()
+            .map(|it| it.rdata().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["web.example.net", "10.0.0.1"], answers);
+
+        // out-of-bailiwick glue is ignored, the nameserver is resolved from 'net' instead
+        let res = resolve("www.glue.com").await?;
+        let answers = res
+            .answers()
+            .map(|it| it.rdata().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["10.0.0.2"], answers);
+
+        // nxdomain
+        let res = resolve("missing.example.com").await?;
+        assert_eq!(RCode::NameError, res.flags().response_code());
+        assert_eq!(0, res.answer_count());
+        assert_eq!(1, res.authority_count());
+
+        closer.notify_waiters();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!("example.com", ancestor("www.example.com", 2));
+        assert_eq!("com", ancestor("www.example.com", 1));
+        assert_eq!("www.example.com", ancestor("www.example.com", 5));
+        assert!(is_subdomain("www.example.com", "example.com"));
+        assert!(is_subdomain("www.example.com", ""));
+        assert!(!is_subdomain("www.badexample.com", "example.com"));
+        assert_eq!(0, label_count(&normalize(".")));
+    }
+
+    #[tokio::test]
+    async fn test_exchange_udp() -> anyhow::Result<()> {
+        init();
+
+        // a nameserver which sends the forged responses before the real one
+        let server = UdpSocket::bind("127.0.0.1:0").await?;
+        let addr = server.local_addr()?;
+        tokio::spawn(async move {
+            let mut b = BytesMut::zeroed(65536);
+            let (n, peer) = server.recv_from(&mut b[..]).await.unwrap();
+            let req = Message::from(BytesMut::from(&b[..n]));
+            let forged = [
+                Message::builder()
+                    .id(req.id().wrapping_add(1))
+                    .flags(Flags::builder().response().build())
+                    .question("example.com", Kind::A, Class::IN)
+                    .build()
+                    .unwrap(),
+                Message::builder()
+                    .id(req.id())
+                    .flags(Flags::builder().response().build())
+                    .question("bank.example", Kind::A, Class::IN)
+                    .build()
+                    .unwrap(),
+            ];
+            for next in forged {
+                server.send_to(next.as_ref(), peer).await.unwrap();
+            }
+            let res = reply(&req, RCode::NoError, true)
+                .answer("example.com", Kind::A, Class::IN, 60, &[1, 2, 3, 4][..])
+                .build()
+                .unwrap();
+            server.send_to(res.as_ref(), peer).await.unwrap();
+        });
+
+        let req = Message::builder()
+            .id(rand::random())
+            .flags(Flags::builder().request().build())
+            .question("Example.COM", Kind::A, Class::IN)
+            .build()?;
+        let res = exchange_udp(addr, &req, Duration::from_secs(3)).await?;
+        assert_eq!(req.id(), res.id());
+        assert_eq!(1, res.answer_count());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,954 @@
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+use hashbrown::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use toml::Value;
+
+use crate::protocol::{
+    AdditionalRR, Class, Flags, Kind, Message, MessageOwned, QuestionOwned, RCode, RData,
+    RDataOwned, RecordOwned, ZoneParser,
+};
+use crate::Result;
+
+use super::watcher::FileWatcher;
+use super::{handle_next, Context, ContextFlags, Filter, FilterFactory, Options};
+
+/// Converts a name into lowercase without the trailing dot, the root will be empty.
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Clears the bits of address which are out of the prefix.
+fn mask(addr: IpAddr, prefix: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+        }
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+        }
+    }
+}
+
+/// Parses the IP trigger like '32.1.0.0.127' or '128.1.zz.db8.2001', which is the prefix length
+/// and the labels of the address in reverse order, 'zz' means the '::' of IPv6.
+fn parse_ip_trigger(s: &str) -> Result<(IpAddr, u8)> {
+    let (prefix, rest) = s
+        .split_once('.')
+        .ok_or_else(|| anyhow!("invalid ip trigger '{}'", s))?;
+    let prefix = prefix
+        .parse::<u8>()
+        .map_err(|_| anyhow!("invalid prefix of ip trigger '{}'", s))?;
+    let mut labels = rest.split('.').collect::<Vec<_>>();
+    labels.reverse();
+
+    let is_v4 = labels.len() == 4 && labels.iter().all(|it| it.parse::<u8>().is_ok());
+    let addr = if is_v4 {
+        if prefix > 32 {
+            bail!("invalid prefix of ip trigger '{}'", s);
+        }
+        IpAddr::V4(labels.join(".").parse()?)
+    } else {
+        if prefix > 128 {
+            bail!("invalid prefix of ip trigger '{}'", s);
+        }
+        let mut addr = labels
+            .iter()
+            .map(|it| if *it == "zz" { "" } else { *it })
+            .collect::<Vec<_>>()
+            .join(":");
+        if addr.starts_with(':') && !addr.starts_with("::") {
+            addr.insert(0, ':');
+        }
+        if addr.ends_with(':') && !addr.ends_with("::") {
+            addr.push(':');
+        }
+        IpAddr::V6(
+            addr.parse::<Ipv6Addr>()
+                .map_err(|_| anyhow!("invalid ip trigger '{}'", s))?,
+        )
+    };
+
+    Ok((mask(addr, prefix), prefix))
+}
+
+/// The policy action of a trigger.
+#[derive(Debug, Clone)]
+enum Action {
+    /// 'CNAME .'
+    NXDomain,
+    /// 'CNAME *.'
+    NoData,
+    /// 'CNAME rpz-passthru.', answer it as usual, and the following zones are skipped.
+    Passthru,
+    /// 'CNAME rpz-drop.'
+    Drop,
+    /// 'CNAME rpz-tcp-only.', answer TC=1 over udp so that the client retries over tcp.
+    TcpOnly,
+    /// The local CNAME, a target like '*.example.com' means the query name under it.
+    Cname { target: String, ttl: u32 },
+    /// The local records.
+    Local(Vec<RecordOwned>),
+}
+
+impl Action {
+    fn new(owner: &str, records: Vec<RecordOwned>) -> Result<Self> {
+        let cname = match records.iter().find(|it| it.kind == Kind::CNAME) {
+            Some(cname) => cname,
+            None => return Ok(Action::Local(records)),
+        };
+        if records.len() > 1 {
+            bail!("CNAME and other data at '{}'", owner);
+        }
+        let target = match &cname.data {
+            RDataOwned::CNAME(target) => normalize(target),
+            _ => bail!("invalid CNAME at '{}'", owner),
+        };
+        Ok(match target.as_str() {
+            "" => Action::NXDomain,
+            "*" => Action::NoData,
+            "rpz-passthru" => Action::Passthru,
+            "rpz-drop" => Action::Drop,
+            "rpz-tcp-only" => Action::TcpOnly,
+            _ => Action::Cname {
+                target,
+                ttl: cname.ttl,
+            },
+        })
+    }
+}
+
+/// The triggers of names, the wildcards like '*.example.com' match the subdomains.
+#[derive(Debug, Default)]
+struct NameTriggers {
+    exact: HashMap<String, Action>,
+    wildcards: HashMap<String, Action>,
+}
+
+impl NameTriggers {
+    fn insert(&mut self, name: &str, action: Action) {
+        match name.strip_prefix("*.") {
+            Some(suffix) => self.wildcards.insert(suffix.to_string(), action),
+            None => self.exact.insert(name.to_string(), action),
+        };
+    }
+
+    fn is_empty(&self) -> bool {
+        self.exact.is_empty() && self.wildcards.is_empty()
+    }
+
+    fn find(&self, name: &str) -> Option<&Action> {
+        if let Some(action) = self.exact.get(name) {
+            return Some(action);
+        }
+        // the closest wildcard wins
+        let mut rest = name;
+        while let Some((_, parent)) = rest.split_once('.') {
+            if let Some(action) = self.wildcards.get(parent) {
+                return Some(action);
+            }
+            rest = parent;
+        }
+        None
+    }
+}
+
+/// The triggers of networks, the longest prefix wins.
+#[derive(Debug, Default)]
+struct IpTriggers {
+    networks: HashMap<(IpAddr, u8), Action>,
+    /// The prefix lengths in descending order.
+    v4_prefixes: Vec<u8>,
+    v6_prefixes: Vec<u8>,
+}
+
+impl IpTriggers {
+    fn insert(&mut self, trigger: &str, action: Action) -> Result<()> {
+        let (addr, prefix) = parse_ip_trigger(trigger)?;
+        let prefixes = match addr {
+            IpAddr::V4(_) => &mut self.v4_prefixes,
+            IpAddr::V6(_) => &mut self.v6_prefixes,
+        };
+        if let Err(i) = prefixes.binary_search_by(|it| prefix.cmp(it)) {
+            prefixes.insert(i, prefix);
+        }
+        self.networks.insert((addr, prefix), action);
+        Ok(())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+
+    fn find(&self, addr: IpAddr) -> Option<&Action> {
+        let addr = match addr {
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => IpAddr::V4(v4),
+                None => addr,
+            },
+            IpAddr::V4(_) => addr,
+        };
+        let prefixes = match addr {
+            IpAddr::V4(_) => &self.v4_prefixes,
+            IpAddr::V6(_) => &self.v6_prefixes,
+        };
+        prefixes
+            .iter()
+            .find_map(|prefix| self.networks.get(&(mask(addr, *prefix), *prefix)))
+    }
+}
+
+/// A response policy zone, see https://datatracker.ietf.org/doc/draft-vixie-dnsop-dns-rpz/.
+#[derive(Debug)]
+struct Zone {
+    origin: String,
+    soa: RecordOwned,
+    client_ip: IpTriggers,
+    qname: NameTriggers,
+    ip: IpTriggers,
+    nsdname: NameTriggers,
+    nsip: IpTriggers,
+}
+
+impl Zone {
+    fn new(records: Vec<RecordOwned>) -> Result<Self> {
+        let mut soa = None;
+        let mut owners: Vec<String> = vec![];
+        let mut rrsets: HashMap<String, Vec<RecordOwned>> = HashMap::new();
+        for next in records {
+            if next.kind == Kind::SOA {
+                if soa.is_some() {
+                    bail!("more than one SOA record in zone '{}'", next.name);
+                }
+                soa = Some(next);
+                continue;
+            }
+            let owner = normalize(&next.name);
+            match rrsets.get_mut(&owner) {
+                Some(rrset) => rrset.push(next),
+                None => {
+                    owners.push(Clone::clone(&owner));
+                    rrsets.insert(owner, vec![next]);
+                }
+            }
+        }
+
+        let soa = soa.ok_or_else(|| anyhow!("no SOA record in zone"))?;
+        let origin = normalize(&soa.name);
+        let mut zone = Zone {
+            origin,
+            soa,
+            client_ip: Default::default(),
+            qname: Default::default(),
+            ip: Default::default(),
+            nsdname: Default::default(),
+            nsip: Default::default(),
+        };
+
+        for owner in owners {
+            let records = rrsets.remove(&owner).unwrap_or_default();
+            // the NS records of apex
+            if owner == zone.origin {
+                continue;
+            }
+            let trigger = owner
+                .strip_suffix(zone.origin.as_str())
+                .and_then(|it| it.strip_suffix('.'))
+                .ok_or_else(|| anyhow!("record '{}' is out of zone '{}'", owner, zone.origin))?;
+            let action = Action::new(&owner, records)?;
+
+            match trigger.rsplit_once('.') {
+                Some((trigger, "rpz-client-ip")) => zone.client_ip.insert(trigger, action)?,
+                Some((trigger, "rpz-ip")) => zone.ip.insert(trigger, action)?,
+                Some((trigger, "rpz-nsip")) => zone.nsip.insert(trigger, action)?,
+                Some((trigger, "rpz-nsdname")) => zone.nsdname.insert(trigger, action),
+                _ => zone.qname.insert(trigger, action),
+            }
+        }
+
+        Ok(zone)
+    }
+
+    fn has_response_triggers(&self) -> bool {
+        !self.ip.is_empty() || !self.nsdname.is_empty() || !self.nsip.is_empty()
+    }
+
+    /// Matches the triggers which don't depend on the response: Client-IP and QNAME.
+    fn match_request(&self, client: Option<IpAddr>, qname: &str) -> Option<&Action> {
+        if let Some(action) = client.and_then(|it| self.client_ip.find(it)) {
+            return Some(action);
+        }
+        self.qname.find(qname)
+    }
+
+    /// Matches the triggers of the response: IP, NSDNAME and NSIP. The name servers are
+    /// taken from the NS records and their glues in the response, they are not looked up,
+    /// so NSDNAME and NSIP never match the responses of forwarders without the NS records,
+    /// which are usually stripped by the recursive resolvers.
+    fn match_response(&self, res: &Message) -> Option<&Action> {
+        let addr = |rdata: &RData<'_>| match rdata {
+            RData::A(it) => Some(IpAddr::V4(it.ipaddr())),
+            RData::AAAA(it) => Some(IpAddr::V6(it.ipaddr())),
+            _ => None,
+        };
+
+        if !self.ip.is_empty() {
+            for next in res.answers() {
+                if let Some(action) = next
+                    .rdata()
+                    .ok()
+                    .and_then(|it| addr(&it))
+                    .and_then(|it| self.ip.find(it))
+                {
+                    return Some(action);
+                }
+            }
+        }
+
+        if self.nsdname.is_empty() && self.nsip.is_empty() {
+            return None;
+        }
+
+        let mut nameservers = vec![];
+        for next in res.answers().chain(res.authorities()) {
+            if let Ok(RData::NS(it)) = next.rdata() {
+                nameservers.push(normalize(&it.nameserver().to_string()));
+            }
+        }
+        if let Some(action) = nameservers.iter().find_map(|it| self.nsdname.find(it)) {
+            return Some(action);
+        }
+
+        if !self.nsip.is_empty() {
+            for next in res.additionals() {
+                if let AdditionalRR::RR(rr) = next {
+                    if !nameservers.contains(&normalize(&rr.name().to_string())) {
+                        continue;
+                    }
+                    if let Some(action) = rr
+                        .rdata()
+                        .ok()
+                        .and_then(|it| addr(&it))
+                        .and_then(|it| self.nsip.find(it))
+                    {
+                        return Some(action);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// The sources of zones, which are in priority order.
+#[derive(Debug, Clone)]
+struct Sources {
+    files: Vec<(PathBuf, Option<String>)>,
+    inline: Option<(String, Option<String>)>,
+}
+
+impl Sources {
+    fn load(&self) -> Result<Vec<Zone>> {
+        let parser = |origin: &Option<String>| match origin {
+            Some(origin) => ZoneParser::new().origin(origin),
+            None => ZoneParser::new(),
+        };
+
+        let mut zones = vec![];
+        for (file, origin) in &self.files {
+            let zone = Zone::new(parser(origin).parse_file(file)?)
+                .map_err(|e| anyhow!("invalid rpz file '{}': {}", file.display(), e))?;
+            zones.push(zone);
+        }
+        if let Some((text, origin)) = &self.inline {
+            zones.push(Zone::new(parser(origin).parse(text)?)?);
+        }
+        Ok(zones)
+    }
+}
+
+/// Applies the Response Policy Zones in priority order, the first zone which has a matched
+/// trigger wins. In a zone, the triggers are checked in the order of Client-IP, QNAME, IP,
+/// NSDNAME and NSIP.
+pub(crate) struct RpzFilter {
+    zones: Arc<ArcSwap<Vec<Zone>>>,
+    next: Option<Box<dyn Filter>>,
+}
+
+impl RpzFilter {
+    fn response(req: &Message, rcode: RCode) -> Result<MessageOwned> {
+        let rflags = req.flags();
+        let flags = Flags::builder()
+            .response()
+            .opcode(rflags.opcode())
+            .rcode(rcode)
+            .recursive_query(rflags.is_recursive_query())
+            .recursive_available(true)
+            .build();
+        Ok(MessageOwned {
+            id: req.id(),
+            flags,
+            questions: req
+                .questions()
+                .map(|it| QuestionOwned::try_from(&it))
+                .collect::<Result<_>>()?,
+            ..Default::default()
+        })
+    }
+
+    async fn apply(
+        &self,
+        zone: &Zone,
+        action: &Action,
+        ctx: &mut Context,
+        req: &mut Message,
+        res: &mut Option<Message>,
+        resolved: bool,
+    ) -> Result<()> {
+        let (qname, qtype) = match req.questions().next() {
+            Some(question) => (question.name().to_string(), question.kind()),
+            None => return Ok(()),
+        };
+
+        debug!("rpz '{}' rewrites {} by {:?}", &zone.origin, &qname, action);
+
+        let negative = |rcode: RCode| -> Result<Message> {
+            let mut owned = Self::response(req, rcode)?;
+            owned.authorities.push(Clone::clone(&zone.soa));
+            owned.build()
+        };
+
+        match action {
+            Action::Passthru => {
+                if !resolved {
+                    handle_next(self.next.as_deref(), ctx, req, res).await?;
+                }
+            }
+            Action::Drop => {
+                ctx.flags.insert(ContextFlags::DROP);
+                res.take();
+            }
+            Action::TcpOnly => {
+                if ctx.is_tcp() {
+                    if !resolved {
+                        handle_next(self.next.as_deref(), ctx, req, res).await?;
+                    }
+                } else {
+                    let mut owned = Self::response(req, RCode::NoError)?;
+                    owned.flags = owned.flags.into_builder().truncated(true).build();
+                    res.replace(owned.build()?);
+                }
+                // the response depends on the transport
+                ctx.flags.insert(ContextFlags::NO_CACHE);
+            }
+            Action::NXDomain => {
+                res.replace(negative(RCode::NameError)?);
+            }
+            Action::NoData => {
+                res.replace(negative(RCode::NoError)?);
+            }
+            Action::Local(records) => {
+                let answers = records
+                    .iter()
+                    .filter(|it| it.kind == qtype || qtype == Kind::ANY)
+                    .map(|it| RecordOwned {
+                        name: qname.as_str().into(),
+                        ..Clone::clone(it)
+                    })
+                    .collect::<Vec<_>>();
+                if answers.is_empty() {
+                    res.replace(negative(RCode::NoError)?);
+                } else {
+                    let mut owned = Self::response(req, RCode::NoError)?;
+                    owned.answers = answers;
+                    res.replace(owned.build()?);
+                }
+            }
+            Action::Cname { target, ttl } => {
+                let target = match target.strip_prefix("*.") {
+                    Some(suffix) => format!("{}.{}", qname.trim_end_matches('.'), suffix),
+                    None => Clone::clone(target),
+                };
+                let mut owned = Self::response(req, RCode::NoError)?;
+                owned.answers.push(RecordOwned {
+                    name: qname.as_str().into(),
+                    kind: Kind::CNAME,
+                    class: Class::IN,
+                    ttl: *ttl,
+                    data: RDataOwned::CNAME(target.as_str().into()),
+                });
+
+                // resolve the target by the following filters
+                if qtype != Kind::CNAME && self.next.is_some() {
+                    let mut subreq = Message::builder()
+                        .id(req.id())
+                        .flags(req.flags())
+                        .question(&target, qtype, Class::IN)
+                        .build()?;
+                    let mut subres = None;
+                    handle_next(self.next.as_deref(), ctx, &mut subreq, &mut subres).await?;
+                    if let Some(subres) = subres {
+                        let subres = MessageOwned::try_from(&subres)?;
+                        owned.set_rcode(subres.flags.response_code());
+                        owned.answers.extend(subres.answers);
+                    }
+                }
+
+                res.replace(owned.build()?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Filter for RpzFilter {
+    async fn handle(
+        &self,
+        ctx: &mut Context,
+        req: &mut Message,
+        res: &mut Option<Message>,
+    ) -> Result<()> {
+        let zones = self.zones.load_full();
+        let qname = req
+            .questions()
+            .next()
+            .map(|it| normalize(&it.name().to_string()));
+        let qname = match qname {
+            Some(qname) => qname,
+            None => return handle_next(self.next.as_deref(), ctx, req, res).await,
+        };
+        let client = ctx.peer.map(|it| it.ip());
+
+        // the responses depend on the client address
+        if zones.iter().any(|it| !it.client_ip.is_empty()) {
+            ctx.flags.insert(ContextFlags::NO_CACHE);
+        }
+
+        let matched = zones
+            .iter()
+            .enumerate()
+            .find_map(|(i, zone)| zone.match_request(client, &qname).map(|it| (i, it)));
+
+        // the response triggers of the prior zones have higher priority, resolve it if needed
+        let prior = match matched {
+            Some((i, _)) => &zones[..i],
+            None => &zones[..],
+        };
+        if !prior.iter().any(|it| it.has_response_triggers()) {
+            return match matched {
+                Some((i, action)) => self.apply(&zones[i], action, ctx, req, res, false).await,
+                None => handle_next(self.next.as_deref(), ctx, req, res).await,
+            };
+        }
+
+        handle_next(self.next.as_deref(), ctx, req, res).await?;
+
+        let matched = match res.as_ref() {
+            Some(msg) => prior
+                .iter()
+                .enumerate()
+                .find_map(|(i, zone)| zone.match_response(msg).map(|it| (i, it))),
+            None => None,
+        }
+        .or(matched);
+
+        match matched {
+            Some((i, action)) => self.apply(&zones[i], action, ctx, req, res, true).await,
+            None => Ok(()),
+        }
+    }
+
+    fn set_next(&mut self, next: Box<dyn Filter>) {
+        self.next.replace(next);
+    }
+}
+
+pub(crate) struct RpzFilterFactory {
+    zones: Arc<ArcSwap<Vec<Zone>>>,
+}
+
+impl RpzFilterFactory {
+    /// The interval of checking the zone files.
+    const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(30);
+}
+
+impl TryFrom<&Options> for RpzFilterFactory {
+    type Error = anyhow::Error;
+
+    fn try_from(opts: &Options) -> std::result::Result<Self, Self::Error> {
+        const KEY_FILES: &str = "files";
+        const KEY_FILE: &str = "file";
+        const KEY_ORIGIN: &str = "origin";
+        const KEY_ZONE: &str = "zone";
+
+        let mut sources = Sources {
+            files: vec![],
+            inline: None,
+        };
+
+        let files = match opts.get(KEY_FILES) {
+            None => vec![],
+            Some(Value::Array(arr)) => Clone::clone(arr),
+            Some(other) => vec![Clone::clone(other)],
+        };
+        for next in files {
+            match &next {
+                Value::String(file) => sources.files.push((PathBuf::from(file), None)),
+                Value::Table(tbl) => {
+                    let file = tbl
+                        .get(KEY_FILE)
+                        .and_then(|it| it.as_str())
+                        .ok_or_else(|| anyhow!("invalid property '{}'", KEY_FILES))?;
+                    let origin = tbl
+                        .get(KEY_ORIGIN)
+                        .and_then(|it| it.as_str())
+                        .map(String::from);
+                    sources.files.push((PathBuf::from(file), origin));
+                }
+                _ => bail!("invalid property '{}'", KEY_FILES),
+            }
+        }
+
+        if let Some(v) = opts.get(KEY_ZONE) {
+            let text = v
+                .as_str()
+                .ok_or_else(|| anyhow!("invalid property '{}'", KEY_ZONE))?;
+            let origin = opts
+                .get(KEY_ORIGIN)
+                .and_then(|it| it.as_str())
+                .map(String::from);
+            sources.inline = Some((text.to_string(), origin));
+        }
+
+        if sources.files.is_empty() && sources.inline.is_none() {
+            bail!(
+                "no zone, either '{}' or '{}' is required",
+                KEY_FILES,
+                KEY_ZONE
+            );
+        }
+
+        let watcher = FileWatcher::from_options(
+            opts,
+            sources
+                .files
+                .iter()
+                .map(|(it, _)| Clone::clone(it))
+                .collect(),
+            Self::DEFAULT_WATCH_INTERVAL,
+        )?;
+
+        let zones = sources.load()?;
+        for next in &zones {
+            info!(
+                "load rpz '{}' with {} qname triggers",
+                &next.origin,
+                next.qname.exact.len() + next.qname.wildcards.len()
+            );
+        }
+        let zones = Arc::new(ArcSwap::from_pointee(zones));
+
+        // replaces the zones atomically if any of the files is changed, the old zones will be
+        // kept if the files cannot be loaded.
+        if let Some(watcher) = watcher {
+            watcher.spawn(&zones, move |zones, changed| {
+                if !changed {
+                    return;
+                }
+                match sources.load() {
+                    Ok(next) => {
+                        info!("reload rpz zones ok: {:?}", &sources.files);
+                        zones.store(Arc::new(next));
+                    }
+                    Err(e) => warn!("failed to reload rpz zones {:?}: {:?}", &sources.files, e),
+                }
+            });
+        }
+
+        Ok(Self { zones })
+    }
+}
+
+impl FilterFactory for RpzFilterFactory {
+    type Item = RpzFilter;
+
+    fn get(&self) -> Result<Self::Item> {
+        Ok(RpzFilter {
+            zones: Clone::clone(&self.zones),
+            next: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    const ZONE: &str = r#"
+$TTL 300
+@                       SOA localhost. root.localhost. 1 3600 600 86400 60
+                        NS  localhost.
+bad.com                 CNAME .
+*.bad.com               CNAME *.
+good.bad.com            CNAME rpz-passthru.
+drop.com                CNAME rpz-drop.
+tcp.com                 CNAME rpz-tcp-only.
+local.com               A   10.0.0.1
+local.com               TXT "walled"
+garden.com              CNAME *.garden.example.
+24.0.0.168.192.rpz-client-ip CNAME .
+32.4.3.2.1.rpz-ip       A   10.0.0.2
+ns.evil.rpz-nsdname     CNAME .
+32.8.8.8.8.rpz-nsip     CNAME *.
+"#;
+
+    /// Answers like an upstream, the addresses of 'hijacked.com' is 1.2.3.4.
+    struct Upstream(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Filter for Upstream {
+        async fn handle(
+            &self,
+            _ctx: &mut Context,
+            req: &mut Message,
+            res: &mut Option<Message>,
+        ) -> Result<()> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let question = req.questions().next().unwrap();
+            let name = question.name().to_string();
+            let mut bu = Message::builder()
+                .id(req.id())
+                .flags(Flags::builder().response().build())
+                .raw_question(question);
+            bu = match name.as_str() {
+                "hijacked.com" => bu.answer(&name, Kind::A, Class::IN, 60, &[1, 2, 3, 4][..]),
+                "delegated.com" => bu
+                    .answer(&name, Kind::A, Class::IN, 60, &[5, 6, 7, 8][..])
+                    .authority(
+                        &name,
+                        Kind::NS,
+                        Class::IN,
+                        60,
+                        RDataOwned::NS("ns1.delegated.com".into()).to_bytes()?,
+                    )
+                    .additional(
+                        "ns1.delegated.com",
+                        Kind::A,
+                        Class::IN,
+                        60,
+                        &[8, 8, 8, 8][..],
+                    ),
+                "evil.com" => bu.authority(
+                    &name,
+                    Kind::NS,
+                    Class::IN,
+                    60,
+                    RDataOwned::NS("ns.evil".into()).to_bytes()?,
+                ),
+                _ => bu.answer(&name, Kind::A, Class::IN, 60, &[9, 9, 9, 9][..]),
+            };
+            res.replace(bu.build()?);
+            Ok(())
+        }
+
+        fn set_next(&mut self, _next: Box<dyn Filter>) {}
+    }
+
+    async fn resolve(
+        factory: &RpzFilterFactory,
+        qname: &str,
+        qtype: Kind,
+        peer: &str,
+        tcp: bool,
+    ) -> anyhow::Result<(Option<Message>, Context, usize)> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut f = factory.get()?;
+        f.set_next(Box::new(Upstream(Clone::clone(&calls))));
+
+        let mut req = Message::builder()
+            .id(1234)
+            .question(qname, qtype, Class::IN)
+            .build()?;
+        let mut ctx = Context::default();
+        ctx.peer.replace(peer.parse()?);
+        ctx.tcp = tcp;
+        let mut res = None;
+        f.handle(&mut ctx, &mut req, &mut res).await?;
+        Ok((res, ctx, calls.load(Ordering::SeqCst)))
+    }
+
+    fn rdata(msg: &Message) -> Vec<String> {
+        msg.answers()
+            .map(|it| it.rdata().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_ip_trigger() {
+        init();
+
+        for (s, addr, prefix) in [
+            ("32.1.0.0.127", "127.0.0.1", 32),
+            ("24.0.2.0.192", "192.0.2.0", 24),
+            ("128.1.zz.db8.2001", "2001:db8::1", 128),
+            ("128.zz.1", "1::", 128),
+            ("128.1.zz", "::1", 128),
+            ("48.zz.db8.2001", "2001:db8::", 48),
+        ] {
+            let (a, p) = parse_ip_trigger(s).unwrap();
+            assert_eq!(addr.parse::<IpAddr>().unwrap(), a, "{}", s);
+            assert_eq!(prefix, p, "{}", s);
+        }
+        for bad in ["33.1.0.0.127", "1.2.3", "abc", "129.1.zz"] {
+            assert!(parse_ip_trigger(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rpz() -> anyhow::Result<()> {
+        init();
+
+        let opts: Options =
+            toml::from_str(&format!("origin = \"rpz.local\"\nzone = '''{}'''", ZONE))?;
+        let factory = RpzFilterFactory::try_from(&opts)?;
+        let peer = "10.0.0.100:5353";
+
+        // NXDOMAIN with the SOA of rpz
+        let (res, _, calls) = resolve(&factory, "bad.com", Kind::A, peer, false).await?;
+        let res = res.unwrap();
+        assert_eq!(RCode::NameError, res.flags().response_code());
+        assert_eq!(1, res.authority_count());
+        assert_eq!(1, res.question_count());
+
+        // NODATA by wildcard
+        let (res, _, _) = resolve(&factory, "www.bad.com", Kind::A, peer, false).await?;
+        let res = res.unwrap();
+        assert_eq!(RCode::NoError, res.flags().response_code());
+        assert_eq!(0, res.answer_count());
+
+        // PASSTHRU
+        let (res, _, calls) = resolve(&factory, "good.bad.com", Kind::A, peer, false).await?;
+        assert_eq!(vec!["9.9.9.9"], rdata(&res.unwrap()));
+        assert_eq!(1, calls);
+
+        // DROP
+        let (res, ctx, _) = resolve(&factory, "drop.com", Kind::A, peer, false).await?;
+        assert!(res.is_none());
+        assert!(ctx.flags.contains(ContextFlags::DROP));
+
+        // TCP-only
+        let (res, ctx, _) = resolve(&factory, "tcp.com", Kind::A, peer, false).await?;
+        assert!(res.unwrap().flags().is_message_truncated());
+        assert!(ctx.flags.contains(ContextFlags::NO_CACHE));
+        let (res, _, _) = resolve(&factory, "tcp.com", Kind::A, peer, true).await?;
+        assert_eq!(vec!["9.9.9.9"], rdata(&res.unwrap()));
+
+        // local data
+        let (res, _, _) = resolve(&factory, "local.com", Kind::A, peer, false).await?;
+        assert_eq!(vec!["10.0.0.1"], rdata(&res.unwrap()));
+        let (res, _, _) = resolve(&factory, "local.com", Kind::AAAA, peer, false).await?;
+        assert_eq!(0, res.unwrap().answer_count());
+
+        // local CNAME, which is resolved by the following filters
+        let (res, _, _) = resolve(&factory, "garden.com", Kind::A, peer, false).await?;
+        assert_eq!(
+            vec!["garden.com.garden.example", "9.9.9.9"],
+            rdata(&res.unwrap())
+        );
+
+        // Client-IP
+        let (res, _, _) =
+            resolve(&factory, "example.com", Kind::A, "192.168.0.1:5353", false).await?;
+        assert_eq!(RCode::NameError, res.unwrap().flags().response_code());
+
+        // IP
+        let (res, _, _) = resolve(&factory, "hijacked.com", Kind::A, peer, false).await?;
+        assert_eq!(vec!["10.0.0.2"], rdata(&res.unwrap()));
+
+        // NSDNAME
+        let (res, _, _) = resolve(&factory, "evil.com", Kind::A, peer, false).await?;
+        assert_eq!(RCode::NameError, res.unwrap().flags().response_code());
+
+        // NSIP
+        let (res, _, _) = resolve(&factory, "delegated.com", Kind::A, peer, false).await?;
+        assert_eq!(0, res.unwrap().answer_count());
+
+        // no trigger
+        let (res, ctx, calls) = resolve(&factory, "example.com", Kind::A, peer, false).await?;
+        assert_eq!(vec!["9.9.9.9"], rdata(&res.unwrap()));
+        assert_eq!(1, calls);
+        assert!(!ctx.flags.contains(ContextFlags::DROP));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rpz_priority_and_reload() -> anyhow::Result<()> {
+        init();
+
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("zerodns-rpz-first-{}", std::process::id()));
+        let second = dir.join(format!("zerodns-rpz-second-{}", std::process::id()));
+        std::fs::write(
+            &first,
+            "$TTL 60\n@ SOA . . 1 1 1 1 1\nexample.com CNAME rpz-passthru.\n",
+        )?;
+        std::fs::write(
+            &second,
+            "$TTL 60\n@ SOA . . 1 1 1 1 1\nexample.com CNAME .\nother.com CNAME .\n",
+        )?;
+
+        let opts: Options = toml::from_str(&format!(
+            r#"
+            files = [{{ file = "{}", origin = "first.rpz" }}, {{ file = "{}", origin = "second.rpz" }}]
+            watch = "50ms"
+            "#,
+            first.display(),
+            second.display()
+        ))?;
+        let factory = RpzFilterFactory::try_from(&opts)?;
+        let peer = "10.0.0.100:5353";
+
+        // the first zone wins
+        let (res, _, _) = resolve(&factory, "example.com", Kind::A, peer, false).await?;
+        assert_eq!(RCode::NoError, res.unwrap().flags().response_code());
+        let (res, _, _) = resolve(&factory, "other.com", Kind::A, peer, false).await?;
+        assert_eq!(RCode::NameError, res.unwrap().flags().response_code());
+
+        // reload
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        std::fs::write(&first, "$TTL 60\n@ SOA . . 2 1 1 1 1\n")?;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let (res, _, _) = resolve(&factory, "example.com", Kind::A, peer, false).await?;
+        assert_eq!(RCode::NameError, res.unwrap().flags().response_code());
+
+        std::fs::remove_file(&first)?;
+        std::fs::remove_file(&second)?;
+
+        // invalid zones
+        for bad in [
+            "",
+            "zone = 'x CNAME .'",
+            "origin = \"rpz\"\nzone = '''\n$TTL 60\n@ SOA . . 1 1 1 1 1\nx CNAME .\nx A 1.2.3.4\n'''",
+            "origin = \"rpz\"\nzone = '''\n$TTL 60\n@ SOA . . 1 1 1 1 1\n33.1.0.0.127.rpz-ip CNAME .\n'''",
+        ] {
+            let opts: Options = toml::from_str(bad)?;
+            assert!(RpzFilterFactory::try_from(&opts).is_err(), "{}", bad);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use toml::Value;
+
+use super::Options;
+use crate::Result;
+
+/// Polls the modified times of the files, so that the filters can reload them when changed.
+pub(crate) struct FileWatcher {
+    files: Vec<PathBuf>,
+    interval: Duration,
+}
+
+impl FileWatcher {
+    const KEY_WATCH: &'static str = "watch";
+
+    /// Creates a watcher by the property 'watch', which can be 'true' (default), 'false', the
+    /// seconds or an interval like '10s'. Returns None if the watching is disabled.
+    pub(crate) fn from_options(
+        opts: &Options,
+        files: Vec<PathBuf>,
+        default: Duration,
+    ) -> Result<Option<Self>> {
+        let interval = match opts.get(Self::KEY_WATCH) {
+            None | Some(Value::Boolean(true)) => default,
+            Some(Value::Boolean(false)) => return Ok(None),
+            Some(Value::Integer(n)) if *n > 0 => Duration::from_secs(*n as u64),
+            Some(Value::String(s)) => crate::protocol::parse_duration(s)?,
+            Some(_) => bail!("invalid property '{}'", Self::KEY_WATCH),
+        };
+        Ok(Some(Self { files, interval }))
+    }
+
+    /// Returns the modified times of the files, the missing files are None.
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        self.files
+            .iter()
+            .map(|it| std::fs::metadata(it).and_then(|it| it.modified()).ok())
+            .collect()
+    }
+
+    /// Polls the files in background, 'f' is called with the target and whether any of the
+    /// files is changed on every tick. The watcher stops after the target is dropped.
+    pub(crate) fn spawn<T, F>(self, target: &Arc<T>, mut f: F)
+    where
+        T: Send + Sync + 'static,
+        F: FnMut(&T, bool) + Send + 'static,
+    {
+        if self.files.is_empty() {
+            return;
+        }
+
+        let handle = match tokio::runtime::Handle::try_current() {
+            Ok(handle) => handle,
+            Err(_) => {
+                warn!("cannot watch {:?} without the tokio runtime", &self.files);
+                return;
+            }
+        };
+
+        let target = Arc::downgrade(target);
+        handle.spawn(async move {
+            let mut last = self.modified();
+            loop {
+                tokio::time::sleep(self.interval).await;
+
+                let target = match target.upgrade() {
+                    Some(target) => target,
+                    None => break,
+                };
+
+                let modified = self.modified();
+                let changed = modified != last;
+                last = modified;
+
+                f(&target, changed);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_options() {
+        let default = Duration::from_secs(5);
+        for (props, expect) in [
+            ("", Some(default)),
+            ("watch = true", Some(default)),
+            ("watch = false", None),
+            ("watch = 3", Some(Duration::from_secs(3))),
+            ("watch = \"50ms\"", Some(Duration::from_millis(50))),
+        ] {
+            let opts: Options = toml::from_str(props).unwrap();
+            let watcher = FileWatcher::from_options(&opts, vec![], default).unwrap();
+            assert_eq!(expect, watcher.map(|it| it.interval), "{}", props);
+        }
+
+        for bad in ["watch = 0", "watch = \"x\"", "watch = [1]"] {
+            let opts: Options = toml::from_str(bad).unwrap();
+            assert!(
+                FileWatcher::from_options(&opts, vec![], default).is_err(),
+                "{}",
+                bad
+            );
+        }
+    }
+}
@@ -0,0 +1,641 @@
+use crate::misc::is_valid_domain;
+use crate::protocol::{
+    AdditionalRR, Class, Flags, Kind, Message, RCode, RDataOwned, RecordOwned, ZoneParser,
+};
+use crate::Result;
+use async_trait::async_trait;
+use hashbrown::{HashMap, HashSet};
+use std::sync::Arc;
+use toml::Value;
+
+use super::{handle_next, Context, Filter, FilterFactory, Options};
+
+/// The max length of CNAME chains which are followed inside a zone.
+const MAX_CNAME_CHAIN: usize = 8;
+
+/// A record and its RDATA in wire format.
+#[derive(Debug)]
+struct Rr {
+    record: RecordOwned,
+    data: Vec<u8>,
+}
+
+impl TryFrom<RecordOwned> for Rr {
+    type Error = anyhow::Error;
+
+    fn try_from(record: RecordOwned) -> std::result::Result<Self, Self::Error> {
+        let data = record.data.to_bytes()?;
+        Ok(Self { record, data })
+    }
+}
+
+/// An authoritative zone, the names are in lowercase and without the trailing dot.
+#[derive(Debug)]
+struct Zone {
+    apex: String,
+    soa: Rr,
+    nodes: HashMap<String, Vec<Rr>>,
+    // all names in the zone, includes the empty non-terminals
+    names: HashSet<String>,
+}
+
+impl Zone {
+    fn new(records: Vec<RecordOwned>) -> Result<Self> {
+        let mut soa = None;
+        let mut rest = vec![];
+        for next in records {
+            if next.kind == Kind::SOA {
+                if soa.is_some() {
+                    bail!("more than one SOA record in zone '{}'", next.name);
+                }
+                soa = Some(next);
+            } else {
+                rest.push(next);
+            }
+        }
+
+        let soa = Rr::try_from(soa.ok_or_else(|| anyhow!("no SOA record in zone"))?)?;
+        let apex = str::to_ascii_lowercase(&soa.record.name);
+
+        let mut zone = Self {
+            apex,
+            soa,
+            nodes: Default::default(),
+            names: Default::default(),
+        };
+        zone.insert_name(Clone::clone(&zone.apex));
+
+        for next in rest {
+            let name = str::to_ascii_lowercase(&next.name);
+            if !zone.contains(&name) {
+                bail!("record '{}' is out of zone '{}'", next.name, zone.apex);
+            }
+            zone.insert_name(Clone::clone(&name));
+            zone.nodes
+                .entry(name)
+                .or_default()
+                .push(Rr::try_from(next)?);
+        }
+
+        Ok(zone)
+    }
+
+    fn insert_name(&mut self, name: String) {
+        let ancestors = self.ancestors(&name).map(String::from).collect::<Vec<_>>();
+        self.names.extend(ancestors);
+        self.names.insert(name);
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        is_subdomain(name, &self.apex)
+    }
+
+    /// Returns the ancestors of the name from the nearest one, which are below the apex.
+    fn ancestors<'a>(&self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        let depth = labels(name).saturating_sub(labels(&self.apex));
+        name.match_indices('.')
+            .map(move |(i, _)| &name[i + 1..])
+            .take(depth.saturating_sub(1))
+    }
+
+    /// Returns the NS records of the zone cut above or at the name, see RFC 1034 4.3.2.
+    fn delegation(&self, name: &str, kind: Kind) -> Option<&[Rr]> {
+        let mut cuts = self.ancestors(name).collect::<Vec<_>>();
+        // the DS records belong to the parent side of the zone cut
+        if name != self.apex && kind != Kind::DS {
+            cuts.insert(0, name);
+        }
+        cuts.into_iter().rev().find_map(|cut| {
+            let rrs = self.nodes.get(cut)?;
+            if rrs.iter().any(|it| it.record.kind == Kind::NS) {
+                Some(&rrs[..])
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Returns the records of the wildcard at the closest encloser, see RFC 4592 3.3.1.
+    fn wildcard(&self, name: &str) -> Option<&[Rr]> {
+        let encloser = self
+            .ancestors(name)
+            .find(|it| self.names.contains(*it))
+            .unwrap_or(&self.apex);
+        self.nodes
+            .get(format!("*.{}", encloser).as_str())
+            .map(|it| &it[..])
+    }
+
+    /// Returns the SOA record with the negative caching TTL, see RFC 2308 5.
+    fn negative_soa(&self) -> (&str, u32, &Rr) {
+        let ttl = match &self.soa.record.data {
+            RDataOwned::SOA { minimum_ttl, .. } => self.soa.record.ttl.min(*minimum_ttl),
+            _ => self.soa.record.ttl,
+        };
+        (&self.soa.record.name, ttl, &self.soa)
+    }
+
+    fn lookup<'a>(&'a self, qname: &'a str, kind: Kind) -> Answer<'a> {
+        let mut answer = Answer {
+            rcode: RCode::NoError,
+            authoritative: true,
+            answers: vec![],
+            authorities: vec![],
+            additionals: vec![],
+        };
+
+        let mut owner = qname;
+        for _ in 0..MAX_CNAME_CHAIN {
+            let name = owner.to_ascii_lowercase();
+
+            // refer to the delegated zone with glues
+            if let Some(rrs) = self.delegation(&name, kind) {
+                answer.authoritative = !answer.answers.is_empty();
+                for next in rrs.iter().filter(|it| it.record.kind == Kind::NS) {
+                    answer.authorities.push(Self::item(next));
+                    if let RDataOwned::NS(ns) = &next.record.data {
+                        let ns = str::to_ascii_lowercase(ns);
+                        if let Some(glues) = self.nodes.get(&ns) {
+                            answer.additionals.extend(
+                                glues
+                                    .iter()
+                                    .filter(|it| matches!(it.record.kind, Kind::A | Kind::AAAA))
+                                    .map(Self::item),
+                            );
+                        }
+                    }
+                }
+                return answer;
+            }
+
+            let rrs = match self.nodes.get(&name) {
+                Some(rrs) => &rrs[..],
+                // the empty non-terminal has no data
+                None if self.names.contains(&name) => &[],
+                None => match self.wildcard(&name) {
+                    Some(rrs) => rrs,
+                    None => {
+                        answer.rcode = RCode::NameError;
+                        answer.authorities.push(self.negative_soa());
+                        return answer;
+                    }
+                },
+            };
+
+            let len = answer.answers.len();
+            for next in rrs {
+                if kind == Kind::ANY || next.record.kind == kind {
+                    answer.answers.push((owner, next.record.ttl, next));
+                }
+            }
+            if answer.answers.len() > len {
+                return answer;
+            }
+
+            match rrs.iter().find(|it| it.record.kind == Kind::CNAME) {
+                Some(next) => {
+                    answer.answers.push((owner, next.record.ttl, next));
+                    match &next.record.data {
+                        RDataOwned::CNAME(target)
+                            if self.contains(&str::to_ascii_lowercase(target)) =>
+                        {
+                            owner = target;
+                        }
+                        // the target out of zone is left to the client
+                        _ => return answer,
+                    }
+                }
+                None => {
+                    answer.authorities.push(self.negative_soa());
+                    return answer;
+                }
+            }
+        }
+
+        answer
+    }
+
+    #[inline]
+    fn item(rr: &Rr) -> (&str, u32, &Rr) {
+        (&rr.record.name, rr.record.ttl, rr)
+    }
+}
+
+/// The records are in the form of (owner, ttl, record).
+struct Answer<'a> {
+    rcode: RCode,
+    authoritative: bool,
+    answers: Vec<(&'a str, u32, &'a Rr)>,
+    authorities: Vec<(&'a str, u32, &'a Rr)>,
+    additionals: Vec<(&'a str, u32, &'a Rr)>,
+}
+
+/// Returns the amount of labels of the name, the root has no label.
+fn labels(name: &str) -> usize {
+    if name.is_empty() || name == "." {
+        0
+    } else {
+        name.split('.').count()
+    }
+}
+
+fn is_subdomain(name: &str, parent: &str) -> bool {
+    parent == "."
+        || name == parent
+        || (name.len() > parent.len()
+            && name.ends_with(parent)
+            && name.as_bytes()[name.len() - parent.len() - 1] == b'.')
+}
+
+pub(crate) struct ZoneFilter {
+    zones: Arc<Vec<Zone>>,
+    next: Option<Box<dyn Filter>>,
+}
+
+impl ZoneFilter {
+    fn answer(&self, req: &Message) -> Result<Option<Message>> {
+        if req.question_count() != 1 {
+            return Ok(None);
+        }
+        let question = match req.questions().next() {
+            Some(question) => question,
+            None => return Ok(None),
+        };
+        if question.class() != Class::IN {
+            return Ok(None);
+        }
+
+        let qname = question.name().to_string();
+        if !is_valid_domain(&qname) {
+            return Ok(None);
+        }
+        let lower = qname.to_ascii_lowercase();
+
+        // the most specific zone wins
+        let zone = match self
+            .zones
+            .iter()
+            .filter(|it| it.contains(&lower))
+            .max_by_key(|it| labels(&it.apex))
+        {
+            Some(zone) => zone,
+            None => return Ok(None),
+        };
+
+        let answer = zone.lookup(&qname, question.kind());
+
+        let flags = Flags::builder()
+            .response()
+            .opcode(req.flags().opcode())
+            .authoritative(answer.authoritative)
+            .recursive_query(req.flags().is_recursive_query())
+            .recursive_available(true)
+            .rcode(answer.rcode)
+            .build();
+
+        let mut bu = Message::builder()
+            .id(req.id())
+            .flags(flags)
+            .raw_question(question);
+
+        for (name, ttl, rr) in &answer.answers {
+            bu = bu.answer(*name, rr.record.kind, rr.record.class, *ttl, &rr.data[..]);
+        }
+        for (name, ttl, rr) in &answer.authorities {
+            bu = bu.authority(*name, rr.record.kind, rr.record.class, *ttl, &rr.data[..]);
+        }
+        for (name, ttl, rr) in &answer.additionals {
+            bu = bu.additional(*name, rr.record.kind, rr.record.class, *ttl, &rr.data[..]);
+        }
+        if req
+            .additionals()
+            .any(|it| matches!(it, AdditionalRR::PseudoRR(_)))
+        {
+            bu = bu.additional_pseudo(Message::DEFAULT_UDP_PAYLOAD_SIZE, 0, 0, 0, None::<&[u8]>);
+        }
+
+        Ok(Some(bu.build()?))
+    }
+}
+
+#[async_trait]
+impl Filter for ZoneFilter {
+    async fn handle(
+        &self,
+        ctx: &mut Context,
+        req: &mut Message,
+        res: &mut Option<Message>,
+    ) -> Result<()> {
+        if res.is_none() {
+            if let Some(msg) = self.answer(req)? {
+                res.replace(msg);
+                return Ok(());
+            }
+        }
+        handle_next(self.next.as_deref(), ctx, req, res).await
+    }
+
+    fn set_next(&mut self, next: Box<dyn Filter>) {
+        self.next.replace(next);
+    }
+}
+
+pub(crate) struct ZoneFilterFactory {
+    zones: Arc<Vec<Zone>>,
+}
+
+impl TryFrom<&Options> for ZoneFilterFactory {
+    type Error = anyhow::Error;
+
+    fn try_from(opts: &Options) -> std::result::Result<Self, Self::Error> {
+        const KEY_FILES: &str = "files";
+        const KEY_ZONE: &str = "zone";
+        const KEY_ORIGIN: &str = "origin";
+        const KEY_FILE: &str = "file";
+
+        let mut zones = vec![];
+
+        // the files are in the form of 'path' or '{ file = "path", origin = "example.com" }'
+        let files = match opts.get(KEY_FILES) {
+            None => vec![],
+            Some(Value::String(file)) => vec![Value::String(Clone::clone(file))],
+            Some(Value::Array(files)) => Clone::clone(files),
+            Some(_) => bail!("invalid property '{}'", KEY_FILES),
+        };
+        for next in files {
+            let (file, parser) = match &next {
+                Value::String(file) => (file.as_str(), ZoneParser::new()),
+                Value::Table(tbl) => {
+                    let file = tbl
+                        .get(KEY_FILE)
+                        .and_then(|it| it.as_str())
+                        .ok_or_else(|| anyhow!("invalid property '{}'", KEY_FILES))?;
+                    let parser = match tbl.get(KEY_ORIGIN).and_then(|it| it.as_str()) {
+                        Some(origin) => ZoneParser::new().origin(origin),
+                        None => ZoneParser::new(),
+                    };
+                    (file, parser)
+                }
+                _ => bail!("invalid property '{}'", KEY_FILES),
+            };
+            let zone = Zone::new(parser.parse_file(file)?)
+                .map_err(|e| anyhow!("invalid zone file '{}': {}", file, e))?;
+            zones.push(zone);
+        }
+
+        // the inline zone text
+        if let Some(v) = opts.get(KEY_ZONE) {
+            let text = v
+                .as_str()
+                .ok_or_else(|| anyhow!("invalid property '{}'", KEY_ZONE))?;
+            let parser = match opts.get(KEY_ORIGIN).and_then(|it| it.as_str()) {
+                Some(origin) => ZoneParser::new().origin(origin),
+                None => ZoneParser::new(),
+            };
+            zones.push(Zone::new(parser.parse(text)?)?);
+        }
+
+        if zones.is_empty() {
+            bail!(
+                "no zone, either '{}' or '{}' is required",
+                KEY_FILES,
+                KEY_ZONE
+            );
+        }
+        for (i, zone) in zones.iter().enumerate() {
+            if zones[..i].iter().any(|it| it.apex == zone.apex) {
+                bail!("duplicated zone '{}'", zone.apex);
+            }
+            info!(
+                "load zone '{}' with {} names",
+                zone.apex,
+                zone.nodes.len() + 1
+            );
+        }
+
+        Ok(Self {
+            zones: Arc::new(zones),
+        })
+    }
+}
+
+impl FilterFactory for ZoneFilterFactory {
+    type Item = ZoneFilter;
+
+    fn get(&self) -> Result<Self::Item> {
+        Ok(ZoneFilter {
+            zones: Clone::clone(&self.zones),
+            next: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::RR;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    /// The next filter which only counts the requests.
+    struct Counter(Arc<AtomicU64>);
+
+    #[async_trait]
+    impl Filter for Counter {
+        async fn handle(
+            &self,
+            _ctx: &mut Context,
+            _req: &mut Message,
+            _res: &mut Option<Message>,
+        ) -> Result<()> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn set_next(&mut self, _next: Box<dyn Filter>) {}
+    }
+
+    const ZONE: &str = r#"
+$ORIGIN home.lan.
+$TTL 3600
+@           SOA ns hostmaster 1 7200 900 604800 300
+            NS  ns
+ns          A   192.168.1.1
+router      A   192.168.1.1
+            AAAA fd00::1
+nas         CNAME storage
+storage     A   192.168.1.2
+external    CNAME www.example.com.
+loop1       CNAME loop2
+loop2       CNAME loop1
+*.dev       A   192.168.1.100
+www.a.b     A   192.168.1.3
+lab         NS  ns.lab
+ns.lab      A   192.168.2.1
+"#;
+
+    fn filter() -> (ZoneFilter, Arc<AtomicU64>) {
+        let opts: Options = toml::from_str(&format!("zone = '''{}'''", ZONE)).unwrap();
+        let mut f = ZoneFilterFactory::try_from(&opts).unwrap().get().unwrap();
+        let cnt = Arc::new(AtomicU64::new(0));
+        f.set_next(Box::new(Counter(Clone::clone(&cnt))));
+        (f, cnt)
+    }
+
+    async fn query(f: &ZoneFilter, name: &str, kind: Kind) -> Option<Message> {
+        let mut req = Message::builder()
+            .id(0x1234)
+            .flags(Flags::builder().request().recursive_query(true).build())
+            .question(name, kind, Class::IN)
+            .build()
+            .unwrap();
+        let mut ctx = Context::default();
+        let mut res = None;
+        f.handle(&mut ctx, &mut req, &mut res).await.unwrap();
+        res
+    }
+
+    fn names<'a>(rrs: impl Iterator<Item = RR<'a>>) -> Vec<String> {
+        rrs.map(|it| format!("{} {}", it.name(), it.kind()))
+            .collect()
+    }
+
+    fn summary(msg: &Message) -> (RCode, bool, Vec<String>, Vec<String>, Vec<String>) {
+        (
+            msg.flags().response_code(),
+            msg.flags().is_authoritative(),
+            names(msg.answers()),
+            names(msg.authorities()),
+            names(msg.additionals().filter_map(|it| match it {
+                AdditionalRR::RR(rr) => Some(rr),
+                AdditionalRR::PseudoRR(_) => None,
+            })),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_zone_answers() {
+        init();
+
+        let (f, cnt) = filter();
+        let strs = |v: &[&str]| v.iter().map(|it| it.to_string()).collect::<Vec<_>>();
+
+        // positive answers, the name is case insensitive
+        let res = query(&f, "Router.Home.Lan", Kind::AAAA).await.unwrap();
+        assert_eq!(0x1234, res.id());
+        assert!(res.flags().is_recursion_available());
+        assert_eq!(
+            (
+                RCode::NoError,
+                true,
+                strs(&["Router.Home.Lan AAAA"]),
+                vec![],
+                vec![]
+            ),
+            summary(&res)
+        );
+
+        // cname inside the zone
+        let res = query(&f, "nas.home.lan", Kind::A).await.unwrap();
+        assert_eq!(
+            strs(&["nas.home.lan CNAME", "storage.home.lan A"]),
+            summary(&res).2
+        );
+
+        // cname out of zone
+        let res = query(&f, "external.home.lan", Kind::A).await.unwrap();
+        assert_eq!(strs(&["external.home.lan CNAME"]), summary(&res).2);
+
+        // cname loop
+        let res = query(&f, "loop1.home.lan", Kind::A).await.unwrap();
+        assert_eq!(MAX_CNAME_CHAIN, res.answer_count() as usize);
+
+        // wildcard
+        let res = query(&f, "foo.bar.dev.home.lan", Kind::A).await.unwrap();
+        assert_eq!(
+            (
+                RCode::NoError,
+                true,
+                strs(&["foo.bar.dev.home.lan A"]),
+                vec![],
+                vec![]
+            ),
+            summary(&res)
+        );
+
+        // nodata
+        let res = query(&f, "storage.home.lan", Kind::AAAA).await.unwrap();
+        assert_eq!(
+            (
+                RCode::NoError,
+                true,
+                vec![],
+                strs(&["home.lan SOA"]),
+                vec![]
+            ),
+            summary(&res)
+        );
+        match res.authorities().next().unwrap().time_to_live() {
+            300 => (),
+            other => panic!("the negative ttl should be 300, got {}", other),
+        }
+
+        // empty non-terminal
+        let res = query(&f, "a.b.home.lan", Kind::A).await.unwrap();
+        assert_eq!(RCode::NoError, res.flags().response_code());
+        assert_eq!(0, res.answer_count());
+
+        // nxdomain
+        let res = query(&f, "nothing.home.lan", Kind::A).await.unwrap();
+        assert_eq!(
+            (
+                RCode::NameError,
+                true,
+                vec![],
+                strs(&["home.lan SOA"]),
+                vec![]
+            ),
+            summary(&res)
+        );
+
+        // delegation with glue
+        let res = query(&f, "www.lab.home.lan", Kind::A).await.unwrap();
+        assert_eq!(
+            (
+                RCode::NoError,
+                false,
+                vec![],
+                strs(&["lab.home.lan NS"]),
+                strs(&["ns.lab.home.lan A"])
+            ),
+            summary(&res)
+        );
+
+        // the names out of zones are passed to the next filter
+        assert!(query(&f, "www.example.com", Kind::A).await.is_none());
+        assert!(query(&f, "lan", Kind::A).await.is_none());
+        assert_eq!(2, cnt.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_invalid_zone() {
+        init();
+
+        for (zone, reason) in [
+            ("$ORIGIN a.\n$TTL 60\nwww A 1.1.1.1", "no SOA"),
+            (
+                "$ORIGIN a.\n$TTL 60\n@ SOA ns hm 1 2 3 4 5\nwww.b. A 1.1.1.1",
+                "out of zone",
+            ),
+        ] {
+            let opts: Options = toml::from_str(&format!("zone = '''{}'''", zone)).unwrap();
+            let err = ZoneFilterFactory::try_from(&opts)
+                .err()
+                .expect("should be invalid");
+            assert!(err.to_string().contains(reason), "{}", err);
+        }
+    }
+}
@@ -6,4 +6,10 @@ use async_trait::async_trait;
 #[async_trait]
 pub trait Handler: Send + Sync + 'static {
     async fn handle(&self, ctx: &mut Context, request: &mut Message) -> Result<Option<Message>>;
+
+    /// Returns the name of the view which serves the client, the responses of different views
+    /// are cached separately.
+    fn view(&self, _ctx: &Context) -> Option<&str> {
+        None
+    }
 }
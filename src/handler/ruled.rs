@@ -7,11 +7,12 @@ use async_trait::async_trait;
 use glob::Pattern;
 use smallvec::SmallVec;
 
-use config::{Filter as FilterConf, Rule as RuleConf};
+use config::{Filter as FilterConf, Rule as RuleConf, View as ViewConf};
 
 use super::{FilteredHandler, Handler};
-use crate::filter::{load as load_filter, Context, Filter, FilterFactoryExt};
+use crate::filter::{load as load_filter, Context, Filter, FilterFactoryExt, Options};
 use crate::handler::filtered::FilteredHandlerBuilder;
+use crate::misc::ipset::IpSet;
 use crate::protocol::Message;
 use crate::{config, Result};
 
@@ -63,6 +64,14 @@ impl Rule {
     }
 }
 
+/// A split-horizon view, the clients in it are served by its own rules.
+#[derive(Debug, Clone)]
+struct View {
+    name: String,
+    clients: IpSet,
+    rules: Vec<Rule>,
+}
+
 enum FilterKind {
     Factory(Box<dyn FilterFactoryExt>),
     Chain(Vec<String>),
@@ -71,9 +80,14 @@ enum FilterKind {
 pub(crate) struct RuledHandlerBuilder {
     filters: HashMap<String, FilterKind, ahash::RandomState>,
     rules: Vec<Rule>,
+    views: Vec<View>,
+    preludes: Vec<String>,
 }
 
 impl RuledHandlerBuilder {
+    /// The name of the implicit hosts filter of 'global.hosts_file'.
+    const GLOBAL_HOSTS: &'static str = "@global.hosts_file";
+
     pub(crate) fn filter<K>(mut self, key: K, value: &FilterConf) -> Result<Self>
     where
         K: Into<String>,
@@ -105,17 +119,66 @@ impl RuledHandlerBuilder {
         Ok(self)
     }
 
+    /// Puts an implicit hosts filter of the file ahead of every request, even if no rule matches.
+    pub(crate) fn hosts_file(mut self, path: &str) -> Result<Self> {
+        let mut props = Options::new();
+        props.insert("include".to_string(), toml::Value::String(path.to_string()));
+        let factory = load_filter("hosts", &props)?;
+        self.filters
+            .insert(Self::GLOBAL_HOSTS.to_string(), FilterKind::Factory(factory));
+        self.preludes.push(Self::GLOBAL_HOSTS.to_string());
+        Ok(self)
+    }
+
     pub(crate) fn rule(mut self, rule: &RuleConf) -> Result<Self> {
         let r = Rule::new(&rule.domain, Clone::clone(&rule.filters))?;
         self.rules.push(r);
         Ok(self)
     }
 
+    pub(crate) fn view(mut self, view: &ViewConf) -> Result<Self> {
+        if view.name.is_empty() {
+            bail!("invalid view: name is empty!");
+        }
+        if self.views.iter().any(|it| it.name == view.name) {
+            bail!("invalid view '{}': duplicated name!", &view.name);
+        }
+        if view.clients.is_empty() {
+            bail!("invalid view '{}': clients is empty!", &view.name);
+        }
+
+        let mut clients = IpSet::builder();
+        for next in &view.clients {
+            clients.insert(next).map_err(|e| {
+                anyhow!("invalid client '{}' of view '{}': {}", next, &view.name, e)
+            })?;
+        }
+
+        let mut rules = vec![];
+        for next in &view.rules {
+            rules.push(Rule::new(&next.domain, Clone::clone(&next.filters))?);
+        }
+
+        self.views.push(View {
+            name: Clone::clone(&view.name),
+            clients: clients.build(),
+            rules,
+        });
+        Ok(self)
+    }
+
     pub(crate) fn build(self) -> RuledHandler {
-        let Self { rules, filters } = self;
+        let Self {
+            rules,
+            views,
+            filters,
+            preludes,
+        } = self;
         RuledHandler {
             rules: Arc::new(rules),
+            views: Arc::new(views),
             filters: Arc::new(filters),
+            preludes: Arc::new(preludes),
         }
     }
 }
@@ -124,6 +187,9 @@ impl RuledHandlerBuilder {
 pub(crate) struct RuledHandler {
     filters: Arc<HashMap<String, FilterKind, ahash::RandomState>>,
     rules: Arc<Vec<Rule>>,
+    views: Arc<Vec<View>>,
+    /// The filters ahead of every request, even if no rule matches.
+    preludes: Arc<Vec<String>>,
 }
 
 impl RuledHandler {
@@ -131,10 +197,19 @@ impl RuledHandler {
         RuledHandlerBuilder {
             filters: Default::default(),
             rules: Default::default(),
+            views: Default::default(),
+            preludes: Default::default(),
         }
     }
 
-    fn get_rule(&self, req: &Message) -> Option<&Rule> {
+    /// Selects the first view which contains the client, the top-level rules are used if no view
+    /// matches.
+    fn get_view(&self, ctx: &Context) -> Option<&View> {
+        let peer = ctx.peer?;
+        self.views.iter().find(|it| it.clients.contains(peer.ip()))
+    }
+
+    fn get_rule<'a>(rules: &'a [Rule], req: &Message) -> Option<&'a Rule> {
         if let Some(first) = req.questions().next() {
             let mut v = SmallVec::<[u8; 64]>::new();
             for (i, next) in first.name().enumerate() {
@@ -146,7 +221,7 @@ impl RuledHandler {
 
             let domain = unsafe { std::str::from_utf8_unchecked(&v[..]) };
 
-            return self.rules.iter().find(|r| r.is_match(domain));
+            return rules.iter().find(|r| r.is_match(domain));
         }
 
         None
@@ -176,20 +251,33 @@ impl RuledHandler {
 #[async_trait]
 impl Handler for RuledHandler {
     async fn handle(&self, ctx: &mut Context, req: &mut Message) -> Result<Option<Message>> {
-        if let Some(rule) = self.get_rule(req) {
-            let mut b = FilteredHandler::builder();
+        let rules = match self.get_view(ctx) {
+            Some(view) => &view.rules[..],
+            None => &self.rules[..],
+        };
 
-            for filter in &rule.filters {
-                self.add_next_filter(&mut b, filter)?;
-            }
+        let rule = Self::get_rule(rules, req);
+        if rule.is_none() && self.preludes.is_empty() {
+            return Ok(None);
+        }
 
-            if let Some(h) = b.build() {
-                return h.handle(ctx, req).await;
-            }
+        let mut b = FilteredHandler::builder();
+
+        let filters = rule.into_iter().flat_map(|it| it.filters.iter());
+        for filter in self.preludes.iter().chain(filters) {
+            self.add_next_filter(&mut b, filter)?;
+        }
+
+        if let Some(h) = b.build() {
+            return h.handle(ctx, req).await;
         }
 
         Ok(None)
     }
+
+    fn view(&self, ctx: &Context) -> Option<&str> {
+        self.get_view(ctx).map(|it| it.name.as_str())
+    }
 }
 
 #[cfg(test)]
@@ -264,4 +352,129 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_global_hosts_file() -> anyhow::Result<()> {
+        init();
+
+        let path = std::env::temp_dir().join(format!("zerodns-ruled-hosts-{}", std::process::id()));
+        std::fs::write(&path, "192.168.1.1 router.lan\n")?;
+
+        let c: Config = toml::from_str(
+            r#"
+            [server]
+            listen = "127.0.0.1:5454"
+
+            [filters.a]
+            kind = "hosts"
+
+            [[rules]]
+            domain = "*.com"
+            filters = ["a"]
+            "#,
+        )?;
+
+        let mut b = RuledHandler::builder().hosts_file(path.to_str().unwrap())?;
+        for next in &c.rules {
+            b = b.rule(next)?;
+        }
+        for (k, v) in &c.filters {
+            b = b.filter(k, v)?;
+        }
+        let h = b.build();
+        std::fs::remove_file(&path)?;
+
+        let mut req = Message::builder()
+            .id(1234)
+            .question(
+                "router.lan",
+                crate::protocol::Kind::A,
+                crate::protocol::Class::IN,
+            )
+            .build()?;
+        // the hosts file is consulted even if no rule matches
+        let mut ctx = Context::default();
+        let res = h.handle(&mut ctx, &mut req).await?;
+        assert_eq!(Some(1), res.map(|it| it.answer_count()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_views() -> anyhow::Result<()> {
+        init();
+
+        let c: Config = toml::from_str(
+            r#"
+            [server]
+            listen = "127.0.0.1:5454"
+
+            [filters.office]
+            kind = "hosts"
+            props.hosts = { "192.168.1.10" = "git.corp" }
+
+            [filters.vpn]
+            kind = "hosts"
+            props.hosts = { "10.8.0.10" = "git.corp" }
+
+            [[rules]]
+            domain = "*"
+            filters = []
+
+            [[views]]
+            name = "office"
+            clients = ["192.168.1.0/24"]
+            rules = [{ domain = "*.corp", filters = ["office"] }]
+
+            [[views]]
+            name = "vpn"
+            clients = ["10.8.0.0/16", "fd00::/8"]
+            rules = [{ domain = "*.corp", filters = ["vpn"] }]
+            "#,
+        )?;
+
+        let mut b = RuledHandler::builder();
+        for next in &c.rules {
+            b = b.rule(next)?;
+        }
+        for next in &c.views {
+            b = b.view(next)?;
+        }
+        for (k, v) in &c.filters {
+            b = b.filter(k, v)?;
+        }
+        let h = b.build();
+
+        for (peer, view, answer) in [
+            ("192.168.1.100:5353", Some("office"), Some("192.168.1.10")),
+            ("10.8.3.4:5353", Some("vpn"), Some("10.8.0.10")),
+            ("[fd00::2]:5353", Some("vpn"), Some("10.8.0.10")),
+            ("172.16.0.1:5353", None, None),
+        ] {
+            let mut req = Message::builder()
+                .id(1234)
+                .question(
+                    "git.corp",
+                    crate::protocol::Kind::A,
+                    crate::protocol::Class::IN,
+                )
+                .build()?;
+            let mut ctx = Context::default();
+            ctx.peer.replace(peer.parse()?);
+            assert_eq!(view, h.view(&ctx), "{}", peer);
+
+            let res = h.handle(&mut ctx, &mut req).await?;
+            let rdata = res
+                .as_ref()
+                .and_then(|it| it.answers().next())
+                .map(|it| it.rdata().unwrap().to_string());
+            assert_eq!(answer.map(String::from), rdata, "{}", peer);
+        }
+
+        // the view without clients is invalid
+        let view: ViewConf = toml::from_str("name = \"empty\"\nrules = []")?;
+        assert!(RuledHandler::builder().view(&view).is_err());
+
+        Ok(())
+    }
 }
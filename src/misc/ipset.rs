@@ -0,0 +1,238 @@
+use std::io::{BufRead, BufReader};
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::Result;
+
+/// A set of IP networks, the ranges are sorted and merged so that the lookups are binary searches.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct IpSet {
+    v4: Vec<(u32, u32)>,
+    v6: Vec<(u128, u128)>,
+}
+
+impl IpSet {
+    pub(crate) fn builder() -> IpSetBuilder {
+        Default::default()
+    }
+
+    /// Checks if the address is in the set, the IPv4-mapped IPv6 addresses are checked as IPv4.
+    pub(crate) fn contains(&self, addr: IpAddr) -> bool {
+        #[inline]
+        fn search<T: Ord + Copy>(ranges: &[(T, T)], n: T) -> bool {
+            match ranges.binary_search_by(|(start, _)| start.cmp(&n)) {
+                Ok(_) => true,
+                Err(0) => false,
+                Err(i) => ranges[i - 1].1 >= n,
+            }
+        }
+
+        match addr {
+            IpAddr::V4(v4) => search(&self.v4, u32::from(v4)),
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => search(&self.v4, u32::from(v4)),
+                None => search(&self.v6, u128::from(v6)),
+            },
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.v4.is_empty() && self.v6.is_empty()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.v4.len() + self.v6.len()
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct IpSetBuilder {
+    v4: Vec<(u32, u32)>,
+    v6: Vec<(u128, u128)>,
+}
+
+impl IpSetBuilder {
+    /// Inserts a network like '10.0.0.0/8', '2001:db8::/32' or a single address.
+    pub(crate) fn insert(&mut self, s: &str) -> Result<()> {
+        let s = s.trim();
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>()?, Some(prefix.parse::<u8>()?)),
+            None => (s.parse::<IpAddr>()?, None),
+        };
+        match addr {
+            IpAddr::V4(v4) => {
+                let prefix = prefix.unwrap_or(32);
+                if prefix > 32 {
+                    bail!("invalid network '{}'", s);
+                }
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                let start = u32::from(v4) & mask;
+                self.v4.push((start, start | !mask));
+            }
+            IpAddr::V6(v6) => {
+                let prefix = prefix.unwrap_or(128);
+                if prefix > 128 {
+                    bail!("invalid network '{}'", s);
+                }
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                let start = u128::from(v6) & mask;
+                self.v6.push((start, start | !mask));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the networks from a list file like china_ip_list, one network per line, the blank
+    /// lines and the comments which start with '#' are ignored.
+    pub(crate) fn read_file<P>(&mut self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        Self::read_networks(path, |network| self.insert(network))
+    }
+
+    /// Reads the list file like 'read_file', but passes every network to 'f' instead.
+    pub(crate) fn read_networks<P, F>(path: P, mut f: F) -> Result<()>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&str) -> Result<()>,
+    {
+        let path = path.as_ref();
+        let r = BufReader::new(std::fs::File::open(path)?);
+        for (i, line) in r.lines().enumerate() {
+            let line = line?;
+            let line = match line.split_once('#') {
+                Some((line, _)) => line.trim(),
+                None => line.trim(),
+            };
+            if line.is_empty() {
+                continue;
+            }
+            f(line).map_err(|e| anyhow!("invalid line {} of {:?}: {}", i + 1, path, e))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn build(self) -> IpSet {
+        fn merge<T: Ord + Copy>(
+            mut ranges: Vec<(T, T)>,
+            succ: impl Fn(T) -> Option<T>,
+        ) -> Vec<(T, T)> {
+            ranges.sort_unstable();
+            let mut merged: Vec<(T, T)> = Vec::with_capacity(ranges.len());
+            for (start, end) in ranges {
+                if let Some(last) = merged.last_mut() {
+                    // the overlapped or adjacent ranges
+                    let joined = match succ(last.1) {
+                        Some(next) => next >= start,
+                        None => true,
+                    };
+                    if joined {
+                        if end > last.1 {
+                            last.1 = end;
+                        }
+                        continue;
+                    }
+                }
+                merged.push((start, end));
+            }
+            merged.shrink_to_fit();
+            merged
+        }
+
+        IpSet {
+            v4: merge(self.v4, |it| it.checked_add(1)),
+            v6: merge(self.v6, |it| it.checked_add(1)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    #[test]
+    fn test_ipset() {
+        init();
+
+        let mut bu = IpSet::builder();
+        for next in [
+            "10.0.0.0/8",
+            "10.1.0.0/16",
+            "192.168.0.0/24",
+            "192.168.1.0/24",
+            "1.2.3.4",
+            "0.0.0.0/0",
+            "2001:db8::/32",
+            "fd00::1",
+        ] {
+            bu.insert(next).unwrap();
+        }
+        for bad in ["10.0.0.0/33", "fd00::/129", "10.0.0", "abc"] {
+            assert!(bu.insert(bad).is_err(), "{}", bad);
+        }
+        let all = bu.build();
+        assert_eq!(1 + 2, all.len());
+
+        let mut bu = IpSet::builder();
+        for next in [
+            "10.0.0.0/8",
+            "192.168.0.0/24",
+            "192.168.1.0/24",
+            "1.2.3.4",
+            "2001:db8::/32",
+            "fd00::1",
+        ] {
+            bu.insert(next).unwrap();
+        }
+        let set = bu.build();
+        assert_eq!(5, set.len());
+        assert!(!set.is_empty());
+
+        for (addr, ok) in [
+            ("10.255.255.255", true),
+            ("11.0.0.0", false),
+            ("9.255.255.255", false),
+            ("192.168.1.255", true),
+            ("192.168.2.0", false),
+            ("1.2.3.4", true),
+            ("1.2.3.5", false),
+            ("2001:db8:ffff::1", true),
+            ("2001:db9::", false),
+            ("fd00::1", true),
+            ("fd00::2", false),
+            ("::ffff:10.1.2.3", true),
+        ] {
+            assert_eq!(ok, set.contains(addr.parse().unwrap()), "{}", addr);
+        }
+        assert!(all.contains("8.8.8.8".parse().unwrap()));
+        assert!(!IpSet::default().contains("8.8.8.8".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_read_file() {
+        init();
+
+        let path = std::env::temp_dir().join(format!("zerodns-ipset-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "# china ip list\n1.0.1.0/24\n\n1.0.8.0/23 # comments\n",
+        )
+        .unwrap();
+        let mut bu = IpSet::builder();
+        bu.read_file(&path).unwrap();
+        let set = bu.build();
+        assert_eq!(2, set.len());
+        assert!(set.contains("1.0.9.1".parse().unwrap()));
+
+        std::fs::write(&path, "1.0.1.0/24\nbad\n").unwrap();
+        let err = IpSet::builder().read_file(&path).unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,6 +1,8 @@
 use once_cell::sync::Lazy;
 
 pub(crate) mod http;
+pub(crate) mod ipset;
+pub(crate) mod proxy;
 pub(crate) mod tcp;
 pub(crate) mod tls;
 
@@ -0,0 +1,587 @@
+use crate::protocol::Proxy;
+use crate::Result;
+use bytes::{Buf, BufMut, BytesMut};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+// https://www.rfc-editor.org/rfc/rfc1928
+const SOCKS5_VERSION: u8 = 0x05;
+const SOCKS5_AUTH_NONE: u8 = 0x00;
+const SOCKS5_AUTH_PASSWORD: u8 = 0x02;
+const SOCKS5_AUTH_UNACCEPTABLE: u8 = 0xff;
+const SOCKS5_CMD_CONNECT: u8 = 0x01;
+const SOCKS5_CMD_UDP_ASSOCIATE: u8 = 0x03;
+const SOCKS5_ATYP_IPV4: u8 = 0x01;
+const SOCKS5_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS5_ATYP_IPV6: u8 = 0x04;
+
+/// Connects to the destination through the proxy.
+pub(crate) async fn connect(
+    proxy: &Proxy,
+    dst: SocketAddr,
+    source: Option<SocketAddr>,
+) -> Result<TcpStream> {
+    let mut stream = super::tcp::connect(proxy.addr(), source).await?;
+    match proxy {
+        Proxy::Socks5 { .. } => {
+            socks5_handshake(&mut stream, proxy, SOCKS5_CMD_CONNECT, dst).await?;
+        }
+        Proxy::Http { .. } => {
+            http_connect(&mut stream, proxy, dst).await?;
+        }
+    }
+    Ok(stream)
+}
+
+async fn http_connect(stream: &mut TcpStream, proxy: &Proxy, dst: SocketAddr) -> Result<()> {
+    use crate::misc::http::CRLF;
+    use std::fmt::Write;
+
+    let mut req = String::with_capacity(128);
+    write!(&mut req, "CONNECT {} HTTP/1.1{}", dst, CRLF)?;
+    write!(&mut req, "Host: {}{}", dst, CRLF)?;
+    if let Some((username, password)) = proxy.auth() {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        let token = STANDARD.encode(format!("{}:{}", username, password));
+        write!(&mut req, "Proxy-Authorization: Basic {}{}", token, CRLF)?;
+    }
+    write!(&mut req, "{}", CRLF)?;
+
+    stream.write_all(req.as_bytes()).await?;
+    stream.flush().await?;
+
+    // read byte by byte, the bytes after headers belong to the tunnel
+    let mut b = BytesMut::with_capacity(256);
+    loop {
+        if b.len() > 8192 {
+            bail!("too large response from http proxy {}", proxy.addr());
+        }
+        let c = stream.read_u8().await?;
+        b.put_u8(c);
+        if b.ends_with(b"\r\n\r\n") {
+            break;
+        }
+    }
+
+    let mut headers = [httparse::EMPTY_HEADER; 32];
+    let mut res = httparse::Response::new(&mut headers);
+    res.parse(&b[..])?;
+
+    match res.code {
+        Some(code) if (200..300).contains(&code) => Ok(()),
+        code => bail!(
+            "http proxy {} refused to connect {}: {:?}",
+            proxy.addr(),
+            dst,
+            code
+        ),
+    }
+}
+
+/// Negotiates with the SOCKS5 proxy, returns the bound address of the reply.
+async fn socks5_handshake(
+    stream: &mut TcpStream,
+    proxy: &Proxy,
+    cmd: u8,
+    dst: SocketAddr,
+) -> Result<SocketAddr> {
+    let auth = proxy.auth();
+
+    // greeting
+    {
+        let method = match auth {
+            Some(_) => SOCKS5_AUTH_PASSWORD,
+            None => SOCKS5_AUTH_NONE,
+        };
+        stream.write_all(&[SOCKS5_VERSION, 1, method]).await?;
+
+        let mut b = [0u8; 2];
+        stream.read_exact(&mut b).await?;
+        if b[0] != SOCKS5_VERSION {
+            bail!("invalid socks5 version {}", b[0]);
+        }
+        match b[1] {
+            SOCKS5_AUTH_NONE => (),
+            SOCKS5_AUTH_PASSWORD => {
+                // https://www.rfc-editor.org/rfc/rfc1929
+                let (username, password) =
+                    auth.ok_or_else(|| anyhow!("socks5 proxy requires authentication"))?;
+                if username.len() > 255 || password.len() > 255 {
+                    bail!("too long username or password of socks5 proxy");
+                }
+                let mut b = BytesMut::with_capacity(3 + username.len() + password.len());
+                b.put_u8(0x01);
+                b.put_u8(username.len() as u8);
+                b.put_slice(username.as_bytes());
+                b.put_u8(password.len() as u8);
+                b.put_slice(password.as_bytes());
+                stream.write_all(&b[..]).await?;
+
+                let mut b = [0u8; 2];
+                stream.read_exact(&mut b).await?;
+                if b[1] != 0x00 {
+                    bail!("socks5 authentication failed: status={}", b[1]);
+                }
+            }
+            SOCKS5_AUTH_UNACCEPTABLE => bail!("no acceptable socks5 authentication method"),
+            other => bail!("unsupported socks5 authentication method {}", other),
+        }
+    }
+
+    // request
+    {
+        let mut b = BytesMut::with_capacity(22);
+        b.put_slice(&[SOCKS5_VERSION, cmd, 0x00]);
+        write_socks5_addr(&mut b, dst);
+        stream.write_all(&b[..]).await?;
+    }
+
+    // reply
+    let mut b = [0u8; 4];
+    stream.read_exact(&mut b).await?;
+    if b[1] != 0x00 {
+        bail!("socks5 proxy replies failure: code={}", b[1]);
+    }
+    let ip = match b[3] {
+        SOCKS5_ATYP_IPV4 => {
+            let mut b = [0u8; 4];
+            stream.read_exact(&mut b).await?;
+            IpAddr::V4(Ipv4Addr::from(b))
+        }
+        SOCKS5_ATYP_IPV6 => {
+            let mut b = [0u8; 16];
+            stream.read_exact(&mut b).await?;
+            IpAddr::V6(Ipv6Addr::from(b))
+        }
+        SOCKS5_ATYP_DOMAIN => {
+            // the bound domain is useless for us, use the address of proxy instead
+            let n = stream.read_u8().await? as usize;
+            let mut b = vec![0u8; n];
+            stream.read_exact(&mut b[..]).await?;
+            proxy.addr().ip()
+        }
+        other => bail!("invalid socks5 address type {}", other),
+    };
+    let port = stream.read_u16().await?;
+
+    Ok(SocketAddr::new(ip, port))
+}
+
+#[inline]
+fn write_socks5_addr(b: &mut BytesMut, addr: SocketAddr) {
+    match addr.ip() {
+        IpAddr::V4(v4) => {
+            b.put_u8(SOCKS5_ATYP_IPV4);
+            b.put_slice(&v4.octets());
+        }
+        IpAddr::V6(v6) => {
+            b.put_u8(SOCKS5_ATYP_IPV6);
+            b.put_slice(&v6.octets());
+        }
+    }
+    b.put_u16(addr.port());
+}
+
+/// The UDP relay of SOCKS5 proxy, the association will be terminated once it's dropped.
+pub(crate) struct Socks5UdpRelay {
+    // the association is alive only when the control connection is alive
+    _control: TcpStream,
+    socket: UdpSocket,
+    relay: SocketAddr,
+}
+
+impl Socks5UdpRelay {
+    /// Creates an UDP association by the SOCKS5 proxy.
+    pub(crate) async fn associate(proxy: &Proxy, source: Option<SocketAddr>) -> Result<Self> {
+        if !matches!(proxy, Proxy::Socks5 { .. }) {
+            bail!("udp is not supported by proxy {}", proxy);
+        }
+
+        let mut control = super::tcp::connect(proxy.addr(), source).await?;
+
+        let bind = match source {
+            Some(source) => source,
+            None => match proxy.addr() {
+                SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+                SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+            },
+        };
+        let socket = UdpSocket::bind(bind).await?;
+
+        // tell the proxy where the datagrams come from
+        let local = SocketAddr::new(control.local_addr()?.ip(), socket.local_addr()?.port());
+        let mut relay =
+            socks5_handshake(&mut control, proxy, SOCKS5_CMD_UDP_ASSOCIATE, local).await?;
+
+        // some proxies reply an unspecified address, which means the same host with proxy
+        if relay.ip().is_unspecified() {
+            relay.set_ip(proxy.addr().ip());
+        }
+
+        Ok(Self {
+            _control: control,
+            socket,
+            relay,
+        })
+    }
+
+    pub(crate) async fn send_to(&self, data: &[u8], dst: SocketAddr) -> Result<()> {
+        let mut b = BytesMut::with_capacity(22 + data.len());
+        // RSV + FRAG
+        b.put_slice(&[0x00, 0x00, 0x00]);
+        write_socks5_addr(&mut b, dst);
+        b.put_slice(data);
+        self.socket.send_to(&b[..], self.relay).await?;
+        Ok(())
+    }
+
+    /// Receives a datagram, returns the payload and where it comes from.
+    pub(crate) async fn recv_from(&self) -> Result<(BytesMut, SocketAddr)> {
+        let mut b = BytesMut::zeroed(65536);
+        loop {
+            let (n, from) = self.socket.recv_from(&mut b[..]).await?;
+            if from != self.relay {
+                continue;
+            }
+            let mut b = BytesMut::from(&b[..n]);
+            if b.len() < 4 || b[2] != 0x00 {
+                // fragmentation is not supported
+                continue;
+            }
+            let atyp = b[3];
+            b.advance(4);
+            let ip = match atyp {
+                SOCKS5_ATYP_IPV4 if b.len() >= 6 => {
+                    let ip = Ipv4Addr::new(b[0], b[1], b[2], b[3]);
+                    b.advance(4);
+                    IpAddr::V4(ip)
+                }
+                SOCKS5_ATYP_IPV6 if b.len() >= 18 => {
+                    let mut octets = [0u8; 16];
+                    octets.copy_from_slice(&b[..16]);
+                    b.advance(16);
+                    IpAddr::V6(Ipv6Addr::from(octets))
+                }
+                _ => continue,
+            };
+            let port = b.get_u16();
+            return Ok((b, SocketAddr::new(ip, port)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryLoadingCache;
+    use crate::client::{Client, DoHClient, TcpClient, UdpClient};
+    use crate::filter::Context;
+    use crate::handler::Handler;
+    use crate::protocol::{Class, Flags, Kind, Message};
+    use crate::server::{TcpServer, UdpServer};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio::sync::Notify;
+
+    #[derive(Clone)]
+    struct MockHandler {
+        resp: Message,
+    }
+
+    #[async_trait::async_trait]
+    impl Handler for MockHandler {
+        async fn handle(&self, _ctx: &mut Context, req: &mut Message) -> Result<Option<Message>> {
+            let mut res = Clone::clone(&self.resp);
+            res.set_id(req.id());
+            Ok(Some(res))
+        }
+    }
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    /// A minimal SOCKS5 proxy, which supports CONNECT and UDP ASSOCIATE.
+    async fn serve_socks5(
+        listener: TcpListener,
+        auth: Option<(&'static str, &'static str)>,
+        cnt: Arc<AtomicU64>,
+    ) -> Result<()> {
+        loop {
+            let (mut stream, _) = listener.accept().await?;
+            let cnt = Clone::clone(&cnt);
+            tokio::spawn(async move {
+                let mut b = [0u8; 2];
+                stream.read_exact(&mut b).await?;
+                let mut methods = vec![0u8; b[1] as usize];
+                stream.read_exact(&mut methods[..]).await?;
+
+                match auth {
+                    None => {
+                        stream
+                            .write_all(&[SOCKS5_VERSION, SOCKS5_AUTH_NONE])
+                            .await?
+                    }
+                    Some((username, password)) => {
+                        if !methods.contains(&SOCKS5_AUTH_PASSWORD) {
+                            stream
+                                .write_all(&[SOCKS5_VERSION, SOCKS5_AUTH_UNACCEPTABLE])
+                                .await?;
+                            return Ok(());
+                        }
+                        stream
+                            .write_all(&[SOCKS5_VERSION, SOCKS5_AUTH_PASSWORD])
+                            .await?;
+                        let _ver = stream.read_u8().await?;
+                        let mut u = vec![0u8; stream.read_u8().await? as usize];
+                        stream.read_exact(&mut u[..]).await?;
+                        let mut p = vec![0u8; stream.read_u8().await? as usize];
+                        stream.read_exact(&mut p[..]).await?;
+                        if u != username.as_bytes() || p != password.as_bytes() {
+                            stream.write_all(&[0x01, 0x01]).await?;
+                            return Ok(());
+                        }
+                        stream.write_all(&[0x01, 0x00]).await?;
+                    }
+                }
+
+                let mut b = [0u8; 4];
+                stream.read_exact(&mut b).await?;
+                let mut ip = [0u8; 4];
+                stream.read_exact(&mut ip).await?;
+                let port = stream.read_u16().await?;
+                let dst = SocketAddr::new(IpAddr::V4(Ipv4Addr::from(ip)), port);
+
+                cnt.fetch_add(1, Ordering::SeqCst);
+
+                match b[1] {
+                    SOCKS5_CMD_CONNECT => {
+                        let mut upstream = TcpStream::connect(dst).await?;
+                        let mut reply = BytesMut::new();
+                        reply.put_slice(&[SOCKS5_VERSION, 0x00, 0x00]);
+                        write_socks5_addr(&mut reply, upstream.local_addr()?);
+                        stream.write_all(&reply[..]).await?;
+                        tokio::io::copy_bidirectional(&mut stream, &mut upstream).await?;
+                    }
+                    SOCKS5_CMD_UDP_ASSOCIATE => {
+                        let relay = UdpSocket::bind("127.0.0.1:0").await?;
+                        let mut reply = BytesMut::new();
+                        reply.put_slice(&[SOCKS5_VERSION, 0x00, 0x00]);
+                        write_socks5_addr(&mut reply, relay.local_addr()?);
+                        stream.write_all(&reply[..]).await?;
+
+                        // relay one request and its response
+                        let mut b = vec![0u8; 4096];
+                        let (n, client) = relay.recv_from(&mut b[..]).await?;
+                        let ip = Ipv4Addr::new(b[4], b[5], b[6], b[7]);
+                        let port = u16::from_be_bytes([b[8], b[9]]);
+                        let upstream = SocketAddr::new(IpAddr::V4(ip), port);
+                        relay.send_to(&b[10..n], upstream).await?;
+
+                        let (n, from) = relay.recv_from(&mut b[..]).await?;
+                        let mut res = BytesMut::new();
+                        res.put_slice(&[0x00, 0x00, 0x00]);
+                        write_socks5_addr(&mut res, from);
+                        res.put_slice(&b[..n]);
+                        relay.send_to(&res[..], client).await?;
+                    }
+                    _ => (),
+                }
+
+                Ok::<(), anyhow::Error>(())
+            });
+        }
+    }
+
+    /// A minimal HTTP proxy, which supports the CONNECT method only.
+    async fn serve_http(listener: TcpListener, cnt: Arc<AtomicU64>) -> Result<()> {
+        loop {
+            let (mut stream, _) = listener.accept().await?;
+            let cnt = Clone::clone(&cnt);
+            tokio::spawn(async move {
+                let mut b = BytesMut::new();
+                while !b.ends_with(b"\r\n\r\n") {
+                    b.put_u8(stream.read_u8().await?);
+                }
+                let mut headers = [httparse::EMPTY_HEADER; 16];
+                let mut req = httparse::Request::new(&mut headers);
+                req.parse(&b[..])?;
+                assert_eq!(Some("CONNECT"), req.method);
+                let dst = req.path.unwrap().parse::<SocketAddr>()?;
+
+                cnt.fetch_add(1, Ordering::SeqCst);
+
+                let mut upstream = TcpStream::connect(dst).await?;
+                stream
+                    .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+                    .await?;
+                tokio::io::copy_bidirectional(&mut stream, &mut upstream).await?;
+                Ok::<(), anyhow::Error>(())
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_proxy() -> anyhow::Result<()> {
+        init();
+
+        let req = Message::builder()
+            .id(0x1234)
+            .flags(Flags::builder().request().recursive_query(true).build())
+            .question("www.example.com", Kind::A, Class::IN)
+            .build()?;
+        let res = Message::builder()
+            .id(0x1234)
+            .flags(Flags::builder().response().build())
+            .question("www.example.com", Kind::A, Class::IN)
+            .answer(
+                "www.example.com",
+                Kind::A,
+                Class::IN,
+                300,
+                &[1u8, 2, 3, 4][..],
+            )
+            .build()?;
+
+        let closer = Arc::new(Notify::new());
+        let h = MockHandler {
+            resp: Clone::clone(&res),
+        };
+
+        // the upstream dns servers
+        let dns_addr = {
+            let udp = UdpSocket::bind("127.0.0.1:0").await?;
+            let addr = udp.local_addr()?;
+            let tcp = TcpListener::bind(addr).await?;
+
+            let server = UdpServer::new(
+                udp,
+                Clone::clone(&h),
+                None::<Arc<MemoryLoadingCache>>,
+                Clone::clone(&closer),
+            );
+            tokio::spawn(server.listen());
+
+            let server = TcpServer::new(
+                addr,
+                tcp,
+                Clone::clone(&h),
+                None::<Arc<MemoryLoadingCache>>,
+                Clone::clone(&closer),
+            );
+            tokio::spawn(server.listen());
+            addr
+        };
+
+        let socks5_cnt = Arc::new(AtomicU64::new(0));
+        let socks5: Proxy = {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            tokio::spawn(serve_socks5(listener, None, Clone::clone(&socks5_cnt)));
+            format!("socks5://{}", addr).parse()?
+        };
+
+        let socks5_auth_cnt = Arc::new(AtomicU64::new(0));
+        let (socks5_auth, socks5_bad_auth): (Proxy, Proxy) = {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            tokio::spawn(serve_socks5(
+                listener,
+                Some(("foo", "p@ss")),
+                Clone::clone(&socks5_auth_cnt),
+            ));
+            (
+                format!("socks5://foo:p%40ss@{}", addr).parse()?,
+                format!("socks5://foo:bar@{}", addr).parse()?,
+            )
+        };
+
+        let http_cnt = Arc::new(AtomicU64::new(0));
+        let http: Proxy = {
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let addr = listener.local_addr()?;
+            tokio::spawn(serve_http(listener, Clone::clone(&http_cnt)));
+            format!("http://{}", addr).parse()?
+        };
+
+        let timeout = Duration::from_secs(3);
+
+        // tcp
+        for (proxy, cnt) in [
+            (&socks5, &socks5_cnt),
+            (&socks5_auth, &socks5_auth_cnt),
+            (&http, &http_cnt),
+        ] {
+            let c = TcpClient::builder(dns_addr)
+                .timeout(timeout)
+                .proxy(Clone::clone(proxy))
+                .build()?;
+            let before = cnt.load(Ordering::SeqCst);
+            assert!(c.request(&req).await.is_ok_and(|msg| msg == res));
+            assert_eq!(before + 1, cnt.load(Ordering::SeqCst));
+        }
+
+        let c = TcpClient::builder(dns_addr)
+            .timeout(timeout)
+            .proxy(socks5_bad_auth)
+            .build()?;
+        assert!(c.request(&req).await.is_err());
+
+        // udp
+        let c = UdpClient::builder(dns_addr)
+            .timeout(timeout)
+            .proxy(Clone::clone(&socks5))
+            .build();
+        let before = socks5_cnt.load(Ordering::SeqCst);
+        assert!(c.request(&req).await.is_ok_and(|msg| msg == res));
+        assert_eq!(before + 1, socks5_cnt.load(Ordering::SeqCst));
+
+        let c = UdpClient::builder(dns_addr)
+            .timeout(timeout)
+            .proxy(Clone::clone(&http))
+            .build();
+        assert!(c.request(&req).await.is_err());
+
+        // doh over http, the mock server cannot speak http, so only check the proxy is dialed
+        let c = DoHClient::builder(dns_addr)
+            .https(false)
+            .timeout(Duration::from_millis(300))
+            .proxy(Clone::clone(&http))
+            .build();
+        let before = http_cnt.load(Ordering::SeqCst);
+        let _ = c.request(&req).await;
+        assert_eq!(before + 1, http_cnt.load(Ordering::SeqCst));
+
+        closer.notify_waiters();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stalled_proxy() -> anyhow::Result<()> {
+        init();
+
+        // a proxy which accepts connections but never answers the handshake
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            let mut conns = vec![];
+            while let Ok((conn, _)) = listener.accept().await {
+                conns.push(conn);
+            }
+        });
+
+        let dst = "127.0.0.1:53".parse()?;
+        for proxy in [format!("socks5://{}", addr), format!("http://{}", addr)] {
+            let proxy = proxy.parse::<Proxy>()?;
+            let res =
+                crate::misc::tcp::dial(dst, None, Some(&proxy), Duration::from_millis(200)).await;
+            assert!(res.is_err());
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
+use crate::protocol::Proxy;
 use crate::Result;
 use deadpool::managed::{self, Metrics, RecycleError, RecycleResult};
-use futures::future;
+use futures::{future, FutureExt};
 use hashbrown::HashMap;
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
@@ -9,13 +10,35 @@ use std::future::Future;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::net::TcpStream;
+use tokio::net::{TcpSocket, TcpStream};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Key {
+    pub(crate) addr: SocketAddr,
+    pub(crate) source: Option<SocketAddr>,
+    pub(crate) proxy: Option<Proxy>,
+    pub(crate) pool_size: usize,
+    /// the deadline of connecting, includes the handshake of proxy
+    pub(crate) timeout: Duration,
+}
 
-pub(crate) type Key = (SocketAddr, Option<SocketAddr>);
+impl Key {
+    pub(crate) fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            source: None,
+            proxy: None,
+            pool_size: DEFAULT_POOL_SIZE,
+            timeout: DEFAULT_CONNECT_TIMEOUT,
+        }
+    }
+}
+
+pub(crate) const DEFAULT_POOL_SIZE: usize = 8;
+pub(crate) const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub(crate) fn get(key: Key) -> Result<Pool> {
-    static POOLS: Lazy<Arc<RwLock<HashMap<(SocketAddr, Option<SocketAddr>), Pool>>>> =
-        Lazy::new(Default::default);
+    static POOLS: Lazy<Arc<RwLock<HashMap<Key, Pool>>>> = Lazy::new(Default::default);
 
     let pools = POOLS.clone();
 
@@ -32,15 +55,65 @@ pub(crate) fn get(key: Key) -> Result<Pool> {
     }
 
     let mgr = Manager {
-        key,
+        key: Clone::clone(&key),
         lifetime: Duration::from_secs(60),
     };
-    let pool = Pool::builder(mgr).max_size(8).build()?;
+    let pool = Pool::builder(mgr).max_size(key.pool_size).build()?;
     w.insert(key, Clone::clone(&pool));
 
     Ok(pool)
 }
 
+/// Connects to the destination directly, or through the proxy if it's present. Both the
+/// connecting and the handshake of proxy should be finished before the timeout.
+pub(crate) async fn dial(
+    dst: SocketAddr,
+    source: Option<SocketAddr>,
+    proxy: Option<&Proxy>,
+    timeout: Duration,
+) -> Result<TcpStream> {
+    let dial = async {
+        match proxy {
+            None => connect(dst, source).await,
+            Some(proxy) => super::proxy::connect(proxy, dst, source).await,
+        }
+    };
+    tokio::time::timeout(timeout, dial)
+        .await
+        .map_err(|_| anyhow!("connect to {} timeout", dst))?
+}
+
+/// Connects to the destination, the source address will be bound if it's present.
+pub(crate) async fn connect(dst: SocketAddr, source: Option<SocketAddr>) -> Result<TcpStream> {
+    let socket: std::net::TcpStream = {
+        let domain = match &dst {
+            SocketAddr::V4(_) => Domain::IPV4,
+            SocketAddr::V6(_) => Domain::IPV6,
+        };
+        let socket = socket2::Socket::new(domain, Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_nodelay(true)?;
+        socket.set_keepalive(true)?;
+
+        if let Some(source) = source {
+            socket.set_reuse_address(true)?;
+            socket.set_reuse_port(true)?;
+            let src = SockAddr::from(source);
+            socket
+                .bind(&src)
+                .map_err(|e| crate::Error::NetworkBindFailure(source, e))?;
+        }
+
+        // connect without blocking the runtime
+        socket.set_nonblocking(true)?;
+
+        socket.into()
+    };
+
+    let stream = TcpSocket::from_std_stream(socket).connect(dst).await?;
+
+    Ok(stream)
+}
+
 pub(crate) type Pool = managed::Pool<Manager>;
 
 pub(crate) struct Manager {
@@ -49,37 +122,18 @@ pub(crate) struct Manager {
 }
 
 impl Manager {
-    pub fn key(&self) -> Key {
-        self.key
+    pub fn key(&self) -> &Key {
+        &self.key
     }
 
-    fn connect(&self) -> Result<TcpStream> {
-        let stream: std::net::TcpStream = {
-            let dst = SockAddr::from(self.key.0);
-
-            let socket = socket2::Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP))?;
-            socket.set_nodelay(true)?;
-            socket.set_keepalive(true)?;
-
-            if let Some(source) = self.key.1 {
-                socket.set_reuse_address(true)?;
-                socket.set_reuse_port(true)?;
-                let src = SockAddr::from(source);
-                socket
-                    .bind(&src)
-                    .map_err(|e| crate::Error::NetworkBindFailure(source, e))?;
-            }
-
-            socket.connect(&dst)?;
-
-            socket.set_nonblocking(true)?;
-
-            socket.into()
-        };
-
-        let socket = TcpStream::from_std(stream)?;
-
-        Ok(socket)
+    async fn connect(&self) -> Result<TcpStream> {
+        dial(
+            self.key.addr,
+            self.key.source,
+            self.key.proxy.as_ref(),
+            self.key.timeout,
+        )
+        .await
     }
 }
 
@@ -89,10 +143,7 @@ impl managed::Manager for Manager {
     type Error = anyhow::Error;
 
     fn create(&self) -> impl Future<Output = std::result::Result<Self::Type, Self::Error>> + Send {
-        match self.connect() {
-            Ok(socket) => future::ok((0, socket)),
-            Err(e) => future::err(e),
-        }
+        self.connect().map(|it| it.map(|it| (0, it)))
     }
 
     fn recycle(
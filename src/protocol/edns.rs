@@ -0,0 +1,316 @@
+use super::ClientSubnet;
+use byteorder::{BigEndian, ByteOrder};
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+/// The typed options of EDNS, see RFC 6891 6.1.2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EdnsOption {
+    /// Name Server Identifier, see RFC 5001.
+    Nsid(Vec<u8>),
+    /// Client Subnet, the scope prefix should be zero in requests, see RFC 7871.
+    ClientSubnet {
+        subnet: ClientSubnet,
+        scope_prefix: u8,
+    },
+    /// DNS Cookies, the server cookie is absent in the first request, see RFC 7873.
+    Cookie { client: [u8; 8], server: Vec<u8> },
+    /// TCP keepalive, the timeout is in units of 100 milliseconds and absent in requests,
+    /// see RFC 7828.
+    TcpKeepalive(Option<u16>),
+    /// Padding with the given size of zeros, see RFC 7830.
+    Padding(u16),
+    /// Extended DNS Errors, see RFC 8914.
+    ExtendedError { info_code: u16, extra_text: String },
+    /// The options which are unknown or malformed, they are kept as is.
+    Unknown(u16, Vec<u8>),
+}
+
+impl EdnsOption {
+    pub const NSID: u16 = 3;
+    pub const CLIENT_SUBNET: u16 = ClientSubnet::OPTION_CODE;
+    pub const COOKIE: u16 = 10;
+    pub const TCP_KEEPALIVE: u16 = 11;
+    pub const PADDING: u16 = 12;
+    pub const EXTENDED_ERROR: u16 = 15;
+
+    pub fn code(&self) -> u16 {
+        match self {
+            EdnsOption::Nsid(_) => Self::NSID,
+            EdnsOption::ClientSubnet { .. } => Self::CLIENT_SUBNET,
+            EdnsOption::Cookie { .. } => Self::COOKIE,
+            EdnsOption::TcpKeepalive(_) => Self::TCP_KEEPALIVE,
+            EdnsOption::Padding(_) => Self::PADDING,
+            EdnsOption::ExtendedError { .. } => Self::EXTENDED_ERROR,
+            EdnsOption::Unknown(code, _) => *code,
+        }
+    }
+
+    /// Parses the option, the malformed ones will be kept as 'Unknown'.
+    pub fn parse(code: u16, data: &[u8]) -> Self {
+        Self::try_parse(code, data).unwrap_or_else(|| EdnsOption::Unknown(code, data.to_vec()))
+    }
+
+    fn try_parse(code: u16, data: &[u8]) -> Option<Self> {
+        Some(match code {
+            Self::NSID => EdnsOption::Nsid(data.to_vec()),
+            Self::CLIENT_SUBNET => {
+                if data.len() < 4 {
+                    return None;
+                }
+                let (prefix, scope_prefix) = (data[2], data[3]);
+                let raw = &data[4..];
+                if raw.len() != (prefix as usize + 7) / 8 {
+                    return None;
+                }
+                let addr = match BigEndian::read_u16(data) {
+                    1 if prefix <= 32 && scope_prefix <= 32 => {
+                        let mut octets = [0u8; 4];
+                        octets[..raw.len()].copy_from_slice(raw);
+                        IpAddr::V4(Ipv4Addr::from(octets))
+                    }
+                    2 if prefix <= 128 && scope_prefix <= 128 => {
+                        let mut octets = [0u8; 16];
+                        octets[..raw.len()].copy_from_slice(raw);
+                        IpAddr::V6(Ipv6Addr::from(octets))
+                    }
+                    _ => return None,
+                };
+                let subnet = ClientSubnet { addr, prefix };
+                // the bits out of prefix must be zero, see RFC 7871 6.
+                if subnet.encode()[4..] != *raw {
+                    return None;
+                }
+                EdnsOption::ClientSubnet {
+                    subnet,
+                    scope_prefix,
+                }
+            }
+            Self::COOKIE => {
+                let n = data.len();
+                if n != 8 && !(16..=40).contains(&n) {
+                    return None;
+                }
+                let mut client = [0u8; 8];
+                client.copy_from_slice(&data[..8]);
+                EdnsOption::Cookie {
+                    client,
+                    server: data[8..].to_vec(),
+                }
+            }
+            Self::TCP_KEEPALIVE => match data.len() {
+                0 => EdnsOption::TcpKeepalive(None),
+                2 => EdnsOption::TcpKeepalive(Some(BigEndian::read_u16(data))),
+                _ => return None,
+            },
+            Self::PADDING => {
+                if data.iter().any(|it| *it != 0) {
+                    return None;
+                }
+                EdnsOption::Padding(data.len() as u16)
+            }
+            Self::EXTENDED_ERROR => {
+                if data.len() < 2 {
+                    return None;
+                }
+                EdnsOption::ExtendedError {
+                    info_code: BigEndian::read_u16(data),
+                    extra_text: String::from_utf8(data[2..].to_vec()).ok()?,
+                }
+            }
+            _ => return None,
+        })
+    }
+
+    /// Encodes the payload of the option, which excludes the code and length.
+    pub fn data(&self) -> Vec<u8> {
+        match self {
+            EdnsOption::Nsid(it) => it.clone(),
+            EdnsOption::ClientSubnet {
+                subnet,
+                scope_prefix,
+            } => {
+                let mut b = subnet.encode();
+                b[3] = *scope_prefix;
+                b
+            }
+            EdnsOption::Cookie { client, server } => {
+                let mut b = Vec::with_capacity(8 + server.len());
+                b.extend_from_slice(client);
+                b.extend_from_slice(server);
+                b
+            }
+            EdnsOption::TcpKeepalive(timeout) => match timeout {
+                Some(it) => it.to_be_bytes().to_vec(),
+                None => vec![],
+            },
+            EdnsOption::Padding(n) => vec![0; *n as usize],
+            EdnsOption::ExtendedError {
+                info_code,
+                extra_text,
+            } => {
+                let mut b = Vec::with_capacity(2 + extra_text.len());
+                b.extend_from_slice(&info_code.to_be_bytes());
+                b.extend_from_slice(extra_text.as_bytes());
+                b
+            }
+            EdnsOption::Unknown(_, it) => it.clone(),
+        }
+    }
+
+    /// Encodes the option with code and length into wire format.
+    pub fn to_bytes(&self) -> crate::Result<Vec<u8>> {
+        let data = self.data();
+        if data.len() > u16::MAX as usize {
+            bail!(
+                "too large EDNS option {}: {} bytes",
+                self.code(),
+                data.len()
+            );
+        }
+        let mut b = Vec::with_capacity(4 + data.len());
+        b.extend_from_slice(&self.code().to_be_bytes());
+        b.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        b.extend_from_slice(&data);
+        Ok(b)
+    }
+
+    /// Returns the timeout of TCP keepalive.
+    pub fn keepalive_timeout(&self) -> Option<Duration> {
+        match self {
+            EdnsOption::TcpKeepalive(Some(it)) => Some(Duration::from_millis(*it as u64 * 100)),
+            _ => None,
+        }
+    }
+}
+
+impl Display for EdnsOption {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EdnsOption::Nsid(it) => {
+                write!(f, "NSID: {}", hex::encode(it))?;
+                if let Ok(s) = std::str::from_utf8(it) {
+                    write!(f, " (\"{}\")", s)?;
+                }
+                Ok(())
+            }
+            EdnsOption::ClientSubnet {
+                subnet,
+                scope_prefix,
+            } => write!(f, "CLIENT-SUBNET: {}/{}", subnet, scope_prefix),
+            EdnsOption::Cookie { client, server } => {
+                write!(f, "COOKIE: {}{}", hex::encode(client), hex::encode(server))
+            }
+            EdnsOption::TcpKeepalive(timeout) => match timeout {
+                Some(it) => write!(f, "TCP-KEEPALIVE: {}.{} secs", it / 10, it % 10),
+                None => write!(f, "TCP-KEEPALIVE"),
+            },
+            EdnsOption::Padding(n) => write!(f, "PADDING: {} bytes", n),
+            EdnsOption::ExtendedError {
+                info_code,
+                extra_text,
+            } => {
+                write!(f, "EDE: {}", info_code)?;
+                if !extra_text.is_empty() {
+                    write!(f, ": ({})", extra_text)?;
+                }
+                Ok(())
+            }
+            EdnsOption::Unknown(code, it) => write!(f, "OPT={}: {}", code, hex::encode(it)),
+        }
+    }
+}
+
+/// Iterator over the options of OPT pseudo-RR.
+pub(crate) struct EdnsOptionIter<'a>(pub(crate) &'a [u8]);
+
+impl Iterator for EdnsOptionIter<'_> {
+    type Item = EdnsOption;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.len() < 4 {
+            return None;
+        }
+        let code = BigEndian::read_u16(self.0);
+        let size = BigEndian::read_u16(&self.0[2..]) as usize;
+        let end = usize::min(self.0.len(), 4 + size);
+        let next = EdnsOption::parse(code, &self.0[4..end]);
+        self.0 = &self.0[end..];
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    #[test]
+    fn test_edns_option() {
+        init();
+
+        let options = [
+            EdnsOption::Nsid(b"ns1".to_vec()),
+            EdnsOption::ClientSubnet {
+                subnet: "1.2.3.0/24".parse().unwrap(),
+                scope_prefix: 16,
+            },
+            EdnsOption::ClientSubnet {
+                subnet: "2001:db8::/56".parse().unwrap(),
+                scope_prefix: 0,
+            },
+            EdnsOption::Cookie {
+                client: [1, 2, 3, 4, 5, 6, 7, 8],
+                server: vec![],
+            },
+            EdnsOption::Cookie {
+                client: [1, 2, 3, 4, 5, 6, 7, 8],
+                server: vec![9; 16],
+            },
+            EdnsOption::TcpKeepalive(None),
+            EdnsOption::TcpKeepalive(Some(300)),
+            EdnsOption::Padding(12),
+            EdnsOption::ExtendedError {
+                info_code: 6,
+                extra_text: "bad signature".to_string(),
+            },
+            EdnsOption::Unknown(65001, vec![1, 2, 3]),
+        ];
+
+        let mut b = vec![];
+        for next in &options {
+            info!("{}", next);
+            b.extend(next.to_bytes().unwrap());
+        }
+
+        let parsed = EdnsOptionIter(&b).collect::<Vec<_>>();
+        assert_eq!(&options[..], &parsed[..]);
+
+        assert_eq!(
+            "CLIENT-SUBNET: 1.2.3.0/24/16",
+            options[1].to_string().as_str()
+        );
+        assert_eq!(
+            Some(Duration::from_secs(30)),
+            options[6].keepalive_timeout()
+        );
+
+        // malformed options are kept as is
+        for (code, data) in [
+            (EdnsOption::CLIENT_SUBNET, &[0, 1, 24, 0, 1, 2][..]),
+            (EdnsOption::CLIENT_SUBNET, &[0, 1, 20, 0, 1, 2, 0xff][..]),
+            (EdnsOption::COOKIE, &[1, 2, 3][..]),
+            (EdnsOption::TCP_KEEPALIVE, &[1][..]),
+            (EdnsOption::PADDING, &[0, 1][..]),
+        ] {
+            assert_eq!(
+                EdnsOption::Unknown(code, data.to_vec()),
+                EdnsOption::parse(code, data)
+            );
+        }
+    }
+}
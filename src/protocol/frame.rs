@@ -1,3 +1,4 @@
+use super::{Edns, EdnsOption, EdnsOptionIter};
 use crate::cachestr::Cachestr;
 use crate::misc::is_valid_domain;
 use byteorder::{BigEndian, ByteOrder};
@@ -5,6 +6,7 @@ use bytes::{BufMut, Bytes, BytesMut};
 use clap::{builder::PossibleValue, ValueEnum};
 use hashbrown::HashMap;
 use once_cell::sync::Lazy;
+use smallvec::SmallVec;
 use std::borrow::Cow;
 use std::fmt::{Debug, Display, Formatter};
 use std::net::{Ipv4Addr, Ipv6Addr};
@@ -199,6 +201,8 @@ pub enum Kind {
     IXFR = 251,
     /// RFC 6891, Option, This is a pseudo-record type needed to support EDNS.
     OPT = 41,
+    /// The types which are not supported yet, they are passed through as RFC 3597 describes.
+    UNKNOWN = 0,
 }
 
 impl ValueEnum for Kind {
@@ -311,6 +315,7 @@ impl ValueEnum for Kind {
             Self::AXFR => PossibleValue::new("axfr").help("Type AXFR"),
             Self::IXFR => PossibleValue::new("ixfr").help("Type IXFR"),
             Self::OPT => PossibleValue::new("opt").help("Type OPT"),
+            Self::UNKNOWN => return None,
         })
     }
 }
@@ -369,13 +374,14 @@ impl Display for Kind {
             Kind::AXFR => f.write_str("AXFR"),
             Kind::IXFR => f.write_str("IXFR"),
             Kind::OPT => f.write_str("OPT"),
+            Kind::UNKNOWN => f.write_str("UNKNOWN"),
         }
     }
 }
 
 static KINDS: Lazy<HashMap<String, Kind>> = Lazy::new(|| {
     let mut m = HashMap::<String, Kind>::new();
-    Kind::iter().for_each(|k| {
+    Kind::iter().filter(|k| *k != Kind::UNKNOWN).for_each(|k| {
         m.insert(k.to_string(), k);
     });
     m
@@ -402,11 +408,24 @@ pub enum Class {
     CH = 3,
     /// Hesiod, see https://en.wikipedia.org/wiki/Hesiod_(name_service)
     HS = 4,
+    /// RFC 2136, used by the prerequisites and updates of DNS UPDATE
+    NONE = 254,
+    /// RFC 1035, any class, also used by TSIG records
+    ANY = 255,
+    /// The classes which are not supported yet.
+    UNKNOWN = 0,
 }
 
 impl ValueEnum for Class {
     fn value_variants<'a>() -> &'a [Self] {
-        &[Self::IN, Self::CS, Self::CH, Self::HS]
+        &[
+            Self::IN,
+            Self::CS,
+            Self::CH,
+            Self::HS,
+            Self::NONE,
+            Self::ANY,
+        ]
     }
 
     fn to_possible_value(&self) -> Option<PossibleValue> {
@@ -415,6 +434,9 @@ impl ValueEnum for Class {
             Class::CS => PossibleValue::new("cs").help("Class CS"),
             Class::CH => PossibleValue::new("ch").help("Class CH"),
             Class::HS => PossibleValue::new("hs").help("Class HS"),
+            Class::NONE => PossibleValue::new("none").help("Class NONE"),
+            Class::ANY => PossibleValue::new("any").help("Class ANY"),
+            Class::UNKNOWN => return None,
         })
     }
 }
@@ -426,6 +448,9 @@ impl Display for Class {
             Class::CS => f.write_str("CS"),
             Class::CH => f.write_str("CH"),
             Class::HS => f.write_str("HS"),
+            Class::NONE => f.write_str("NONE"),
+            Class::ANY => f.write_str("ANY"),
+            Class::UNKNOWN => f.write_str("UNKNOWN"),
         }
     }
 }
@@ -439,6 +464,8 @@ impl FromStr for Class {
             "CS" => Ok(Class::CS),
             "CH" => Ok(Class::CH),
             "HS" => Ok(Class::HS),
+            "NONE" => Ok(Class::NONE),
+            "ANY" => Ok(Class::ANY),
             other => bail!("invalid message class '{}'", other),
         }
     }
@@ -522,6 +549,28 @@ impl FlagsBuilder {
         self
     }
 
+    /// Sets the AD bit, see RFC 4035 3.2.3.
+    pub fn authentic_data(mut self, enabled: bool) -> Self {
+        const MASK: u16 = 1 << 5;
+        if enabled {
+            self.0 |= MASK;
+        } else {
+            self.0 &= !MASK;
+        }
+        self
+    }
+
+    /// Sets the CD bit, see RFC 4035 3.2.2.
+    pub fn checking_disabled(mut self, enabled: bool) -> Self {
+        const MASK: u16 = 1 << 4;
+        if enabled {
+            self.0 |= MASK;
+        } else {
+            self.0 &= !MASK;
+        }
+        self
+    }
+
     pub fn recursive_query(mut self, enabled: bool) -> Self {
         const MASK: u16 = 1 << 8;
         if enabled {
@@ -560,6 +609,11 @@ impl Flags {
     pub fn builder() -> FlagsBuilder {
         FlagsBuilder(0)
     }
+
+    /// Returns a builder which starts from the current flags.
+    pub fn into_builder(self) -> FlagsBuilder {
+        FlagsBuilder(self.0)
+    }
 }
 
 impl Flags {
@@ -587,6 +641,14 @@ impl Flags {
         (self.0 >> 7) & 0x01 != 0
     }
 
+    pub fn is_authentic_data(&self) -> bool {
+        (self.0 >> 5) & 0x01 != 0
+    }
+
+    pub fn is_checking_disabled(&self) -> bool {
+        (self.0 >> 4) & 0x01 != 0
+    }
+
     pub fn reserved(&self) -> u16 {
         // 3 bits
         (self.0 >> 4) & 0x0007
@@ -614,19 +676,6 @@ impl Flags {
     }
 }
 
-struct Authority<'a> {
-    name: Cow<'a, str>,
-    kind: Kind,
-    class: Class,
-    ttl: u32,
-    primary_name_server: Cow<'a, str>,
-    responsible_authority_mailbox: Cow<'a, str>,
-    refresh_interval: u32,
-    retry_interval: u32,
-    expire_limit: u32,
-    minimum_ttl: u32,
-}
-
 struct RRBuilder<'a> {
     name: Cow<'a, str>,
     kind: Kind,
@@ -650,8 +699,8 @@ enum AdditionalBuilder<'a> {
 
 struct Query<'a> {
     name: Cow<'a, str>,
-    kind: Kind,
-    class: Class,
+    kind: u16,
+    class: u16,
 }
 
 #[derive(Default)]
@@ -660,7 +709,7 @@ pub struct MessageBuilder<'a> {
     flags: Flags,
     queries: Vec<Query<'a>>,
     answers: Vec<RRBuilder<'a>>,
-    authorities: Vec<Authority<'a>>,
+    authorities: Vec<RRBuilder<'a>>,
     additionals: Vec<AdditionalBuilder<'a>>,
 }
 
@@ -675,9 +724,17 @@ impl<'a> MessageBuilder<'a> {
         self
     }
 
-    pub fn raw_question(self, question: Question<'a>) -> Self {
-        let name = question.name().to_string();
-        self.question(name, question.kind(), question.class())
+    pub fn raw_question(mut self, question: Question<'a>) -> Self {
+        let mut name = question.name().to_string();
+        if name.is_empty() {
+            name.push('.');
+        }
+        self.queries.push(Query {
+            name: name.into(),
+            kind: question.kind_code(),
+            class: question.class_code(),
+        });
+        self
     }
 
     pub fn question<N>(mut self, name: N, kind: Kind, class: Class) -> Self
@@ -686,8 +743,8 @@ impl<'a> MessageBuilder<'a> {
     {
         self.queries.push(Query {
             name: name.into(),
-            kind,
-            class,
+            kind: kind as u16,
+            class: class as u16,
         });
         self
     }
@@ -707,6 +764,21 @@ impl<'a> MessageBuilder<'a> {
         self
     }
 
+    pub fn authority<N, D>(mut self, name: N, kind: Kind, class: Class, ttl: u32, data: D) -> Self
+    where
+        N: Into<Cow<'a, str>>,
+        D: Into<Cow<'a, [u8]>>,
+    {
+        self.authorities.push(RRBuilder {
+            name: name.into(),
+            kind,
+            class,
+            ttl,
+            data: data.into(),
+        });
+        self
+    }
+
     pub fn additional<N, D>(mut self, name: N, kind: Kind, class: Class, ttl: u32, data: D) -> Self
     where
         N: Into<Cow<'a, str>>,
@@ -724,12 +796,27 @@ impl<'a> MessageBuilder<'a> {
         self
     }
 
+    /// Appends the OPT pseudo-RR with typed options.
+    pub fn edns(self, edns: &Edns) -> crate::Result<Self> {
+        let mut data = vec![];
+        for next in &edns.options {
+            data.extend(next.to_bytes()?);
+        }
+        Ok(self.additional_pseudo(
+            edns.udp_payload_size,
+            edns.extended_rcode,
+            edns.version,
+            edns.z,
+            if data.is_empty() { None } else { Some(data) },
+        ))
+    }
+
     pub fn additional_pseudo<D>(
         mut self,
         udp_payload_size: u16,
         extended_rcode: u8,
         version: u8,
-        z: u8,
+        z: u16,
         data: Option<D>,
     ) -> Self
     where
@@ -738,8 +825,8 @@ impl<'a> MessageBuilder<'a> {
         let rr = PseudoRRBuilder {
             udp_payload_size,
             extended_rcode,
-            version: 0,
-            z: 0,
+            version,
+            z,
             data: data.map(|it| it.into()),
         };
         self.additionals.push(AdditionalBuilder::PseudoRR(rr));
@@ -757,6 +844,7 @@ impl<'a> MessageBuilder<'a> {
         } = self;
 
         let mut b = BytesMut::with_capacity(1536);
+        let mut names = NameCompressor::default();
         b.put_u16(id);
         b.put_u16(flags.0);
 
@@ -767,92 +855,27 @@ impl<'a> MessageBuilder<'a> {
 
         for next in queries {
             let name = next.name;
-            if next.kind != Kind::NS && !is_valid_domain(&name) {
+            if next.kind != Kind::NS as u16 && !is_valid_domain(&name) {
                 bail!("invalid question name '{}'", &name);
             }
-            for label in name
-                .split('.')
-                .filter(|it| !it.is_empty())
-                .map(|it| it.as_bytes())
-            {
-                b.put_u8(label.len() as u8);
-                b.put_slice(label);
-            }
-            b.put_u8(0);
-            b.put_u16(next.kind as u16);
-            b.put_u16(next.class as u16);
+            names.write_name(&mut b, &name);
+            b.put_u16(next.kind);
+            b.put_u16(next.class);
         }
 
         // http://www.tcpipguide.com/free/t_DNSMessageResourceRecordFieldFormats-2.htm
         for next in answers {
-            let name = next.name;
-            if next.kind != Kind::NS && !is_valid_domain(&name) {
-                bail!("invalid answer name '{}'", &name);
-            }
-            // name
-            {
-                for label in name
-                    .split('.')
-                    .filter(|it| !it.is_empty())
-                    .map(|it| it.as_bytes())
-                {
-                    b.put_u8(label.len() as u8);
-                    b.put_slice(label);
-                }
-                b.put_u8(0);
-            }
-
-            // type
-            b.put_u16(next.kind as u16);
-
-            // class
-            b.put_u16(next.class as u16);
-
-            // ttl
-            b.put_u32(next.ttl);
-
-            // rdata
-            b.put_u16(next.data.len() as u16);
-            b.put_slice(&next.data);
+            Self::write_rr(&mut b, next, "answer", &mut names)?;
         }
 
         for next in authorities {
-            // TODO: write authority
+            Self::write_rr(&mut b, next, "authority", &mut names)?;
         }
 
         for next in additionals {
-            // TODO: write additional
             match next {
                 AdditionalBuilder::RR(next) => {
-                    let name = next.name;
-                    if next.kind != Kind::NS && !is_valid_domain(&name) {
-                        bail!("invalid answer name '{}'", &name);
-                    }
-                    // name
-                    {
-                        for label in name
-                            .split('.')
-                            .filter(|it| !it.is_empty())
-                            .map(|it| it.as_bytes())
-                        {
-                            b.put_u8(label.len() as u8);
-                            b.put_slice(label);
-                        }
-                        b.put_u8(0);
-                    }
-
-                    // type
-                    b.put_u16(next.kind as u16);
-
-                    // class
-                    b.put_u16(next.class as u16);
-
-                    // ttl
-                    b.put_u32(next.ttl);
-
-                    // rdata
-                    b.put_u16(next.data.len() as u16);
-                    b.put_slice(&next.data);
+                    Self::write_rr(&mut b, next, "additional", &mut names)?;
                 }
                 AdditionalBuilder::PseudoRR(next) => {
                     // empty name
@@ -876,6 +899,131 @@ impl<'a> MessageBuilder<'a> {
 
         Ok(Message(b))
     }
+
+    fn write_rr(
+        b: &mut BytesMut,
+        rr: RRBuilder<'_>,
+        section: &str,
+        names: &mut NameCompressor,
+    ) -> crate::Result<()> {
+        let name = rr.name;
+        // the owner name can be a wildcard, eg: '*.example.com'
+        let check = name.strip_prefix("*.").unwrap_or(&name);
+        if rr.kind != Kind::NS && !is_valid_domain(check) {
+            bail!("invalid {} name '{}'", section, &name);
+        }
+        // name
+        names.write_name(b, &name);
+
+        // type
+        b.put_u16(rr.kind as u16);
+
+        // class
+        b.put_u16(rr.class as u16);
+
+        // ttl
+        b.put_u32(rr.ttl);
+
+        // rdata
+        let pos = b.len();
+        b.put_u16(0);
+        names.write_rdata(b, rr.kind, &rr.data);
+        let size = b.len() - pos - 2;
+        if size > u16::MAX as usize {
+            bail!("too large rdata of {} '{}': {} bytes", section, &name, size);
+        }
+        BigEndian::write_u16(&mut b[pos..], size as u16);
+
+        Ok(())
+    }
+}
+
+/// Compresses domain names with pointers to their previous occurrences, see RFC 1035 4.1.4.
+#[derive(Default)]
+struct NameCompressor(HashMap<Vec<u8>, u16>);
+
+impl NameCompressor {
+    /// The max offset which can be addressed by a compression pointer.
+    const MAX_POINTER: usize = 0x3fff;
+
+    fn write_name(&mut self, b: &mut BytesMut, name: &str) {
+        let labels = name
+            .split('.')
+            .filter(|it| !it.is_empty())
+            .map(|it| it.as_bytes())
+            .collect::<SmallVec<[&[u8]; 8]>>();
+        self.write_labels(b, &labels);
+    }
+
+    fn write_labels(&mut self, b: &mut BytesMut, labels: &[&[u8]]) {
+        for i in 0..labels.len() {
+            let mut suffix = Vec::with_capacity(64);
+            for label in &labels[i..] {
+                suffix.push(label.len() as u8);
+                suffix.extend_from_slice(label);
+            }
+
+            if let Some(pos) = self.0.get(&suffix) {
+                b.put_u16(0xc000 | *pos);
+                return;
+            }
+            if b.len() <= Self::MAX_POINTER {
+                self.0.insert(suffix, b.len() as u16);
+            }
+
+            b.put_u8(labels[i].len() as u8);
+            b.put_slice(labels[i]);
+        }
+        b.put_u8(0);
+    }
+
+    /// Writes the RDATA, the names of well-known types will be compressed, see RFC 3597 4.
+    fn write_rdata(&mut self, b: &mut BytesMut, kind: Kind, data: &[u8]) {
+        let (prefix, count) = match kind {
+            Kind::CNAME | Kind::NS | Kind::PTR => (0, 1),
+            Kind::MX => (2, 1),
+            Kind::SOA => (0, 2),
+            _ => (0, 0),
+        };
+
+        match Self::read_names(data, prefix, count) {
+            Some((names, end)) if count > 0 => {
+                b.put_slice(&data[..prefix]);
+                for labels in names {
+                    self.write_labels(b, &labels);
+                }
+                b.put_slice(&data[end..]);
+            }
+            _ => b.put_slice(data),
+        }
+    }
+
+    /// Reads the uncompressed names, returns None if the RDATA is malformed or compressed already.
+    #[allow(clippy::type_complexity)]
+    fn read_names(
+        data: &[u8],
+        mut offset: usize,
+        count: usize,
+    ) -> Option<(SmallVec<[SmallVec<[&[u8]; 8]>; 2]>, usize)> {
+        let mut names = SmallVec::new();
+        for _ in 0..count {
+            let mut labels = SmallVec::new();
+            loop {
+                let size = *data.get(offset)? as usize;
+                if size == 0 {
+                    offset += 1;
+                    break;
+                }
+                if size > 63 || offset + 1 + size > data.len() {
+                    return None;
+                }
+                labels.push(&data[offset + 1..offset + 1 + size]);
+                offset += 1 + size;
+            }
+            names.push(labels);
+        }
+        Some((names, offset))
+    }
 }
 
 /// DNS message, see links below:
@@ -905,6 +1053,10 @@ impl Message {
         BigEndian::write_u16(&mut self.0[..], id);
     }
 
+    pub fn set_flags(&mut self, flags: Flags) {
+        BigEndian::write_u16(&mut self.0[2..], flags.0);
+    }
+
     pub fn flags(&self) -> Flags {
         Flags(BigEndian::read_u16(&self.0[2..]))
     }
@@ -928,14 +1080,9 @@ impl Message {
     }
 
     pub fn answers(&self) -> impl Iterator<Item = RR<'_>> {
-        let mut offset = 12;
-        for next in self.questions() {
-            offset += next.len();
-        }
-
         RRIter {
             raw: &self.0[..],
-            offset,
+            offset: self.section_offset(1),
             lefts: self.answer_count(),
         }
     }
@@ -945,17 +1092,9 @@ impl Message {
     }
 
     pub fn authorities(&self) -> impl Iterator<Item = RR<'_>> {
-        let mut offset = 12;
-        for next in self.questions() {
-            offset += next.len();
-        }
-        for next in self.answers() {
-            offset += next.len();
-        }
-
         RRIter {
             raw: &self.0[..],
-            offset,
+            offset: self.section_offset(2),
             lefts: self.authority_count(),
         }
     }
@@ -965,22 +1104,242 @@ impl Message {
     }
 
     pub fn additionals(&self) -> impl Iterator<Item = AdditionalRR<'_>> {
+        AdditionalRRIter {
+            raw: &self.0[..],
+            offset: self.section_offset(3),
+            lefts: self.additional_count(),
+        }
+    }
+
+    /// Returns an index of the message, which should be preferred when the sections are accessed
+    /// more than once, see [`MessageIndex`].
+    pub fn index(&self) -> MessageIndex<'_> {
+        MessageIndex::new(&self.0[..])
+    }
+
+    /// Returns the offset where the section begins, the sections before it are walked only once.
+    /// The sections are numbered as: 0=questions, 1=answers, 2=authorities, 3=additionals.
+    fn section_offset(&self, section: usize) -> usize {
+        let raw = &self.0[..];
+        let mut offset = 12;
+        if section > 0 {
+            for _ in 0..self.question_count() {
+                offset += Question { raw, offset }.len();
+            }
+        }
+        for count in [self.answer_count(), self.authority_count()]
+            .into_iter()
+            .take(section.saturating_sub(1))
+        {
+            for _ in 0..count {
+                offset += RR { raw, offset }.len();
+            }
+        }
+        offset
+    }
+
+    /// Checks every section, compression pointer and length of the message once, so that the
+    /// accessors never panic on it. The messages received from network must be validated before
+    /// accessing, and the malformed ones should be answered with FORMERR.
+    pub fn validate(&self) -> crate::Result<()> {
+        let raw = &self.0[..];
+        let malformed = |offset: usize, reason: Cow<'static, str>| {
+            crate::Error::MalformedMessage(offset, reason)
+        };
+
+        if raw.len() < 12 {
+            bail!(malformed(raw.len(), "truncated header".into()));
+        }
+        let flags = self.flags();
+        if OpCode::try_from((flags.0 >> 11) & 0x000f).is_err() {
+            bail!(malformed(2, "unknown opcode".into()));
+        }
+        if flags.0 & 0x000f > RCode::NotZone as u16 {
+            bail!(malformed(3, "unknown rcode".into()));
+        }
+
         let mut offset = 12;
-        for next in self.questions() {
-            offset += next.len();
+        for _ in 0..self.question_count() {
+            let n = check_name(raw, offset, raw.len()).map_err(|e| malformed(offset, e.into()))?;
+            if raw.len() < offset + n + 4 {
+                bail!(malformed(offset, "truncated question".into()));
+            }
+            offset += n + 4;
+        }
+
+        let mut opt = false;
+        let sections = [
+            self.answer_count(),
+            self.authority_count(),
+            self.additional_count(),
+        ];
+        for (i, count) in sections.into_iter().enumerate() {
+            for _ in 0..count {
+                offset += check_rr(raw, offset, i == 2, &mut opt)
+                    .map_err(|(offset, e)| malformed(offset, e))?;
+            }
         }
-        for next in self.answers() {
-            offset += next.len();
+
+        Ok(())
+    }
+}
+
+impl Message {
+    /// The default udp payload size of the OPT pseudo-RR which is created by us.
+    pub const DEFAULT_UDP_PAYLOAD_SIZE: u16 = 1232;
+
+    /// Returns the offset of the OPT pseudo-RR.
+    fn pseudo_rr_offset(&self) -> Option<usize> {
+        let mut offset = self.section_offset(3);
+        for next in self.additionals() {
+            match next {
+                AdditionalRR::PseudoRR(_) => return Some(offset),
+                AdditionalRR::RR(rr) => offset += rr.len(),
+            }
         }
-        for next in self.authorities() {
-            offset += next.len();
+        None
+    }
+
+    /// Returns the offset of the OPT pseudo-RR, an empty one will be appended if it's absent.
+    fn ensure_pseudo_rr(&mut self) -> usize {
+        if let Some(offset) = self.pseudo_rr_offset() {
+            return offset;
         }
 
-        AdditionalRRIter {
-            raw: &self.0[..],
-            offset,
-            lefts: self.additional_count(),
+        let offset = self.len();
+        self.0.put_u8(0);
+        self.0.put_u16(Kind::OPT as u16);
+        self.0.put_u16(Self::DEFAULT_UDP_PAYLOAD_SIZE);
+        self.0.put_u32(0);
+        self.0.put_u16(0);
+
+        let n = self.additional_count() + 1;
+        BigEndian::write_u16(&mut self.0[10..], n);
+
+        offset
+    }
+
+    /// Sets the udp payload size of EDNS, the OPT pseudo-RR will be appended if it's absent.
+    pub fn set_udp_payload_size(&mut self, size: u16) {
+        let offset = self.ensure_pseudo_rr();
+        let offset = offset + Notation::new(&self.0[..], offset).len() + 2;
+        BigEndian::write_u16(&mut self.0[offset..], size);
+    }
+
+    /// Sets the DO bit of EDNS, the OPT pseudo-RR will be appended if it's absent.
+    pub fn set_dnssec_ok(&mut self, enabled: bool) {
+        let offset = self.ensure_pseudo_rr();
+        // the DO bit is the most significant bit of z
+        let offset = offset + Notation::new(&self.0[..], offset).len() + 6;
+        if enabled {
+            self.0[offset] |= 0x80;
+        } else {
+            self.0[offset] &= 0x7f;
+        }
+    }
+
+    /// Checks if the DO bit of EDNS is set.
+    pub fn is_dnssec_ok(&self) -> bool {
+        self.additionals().any(|it| match it {
+            AdditionalRR::PseudoRR(rr) => rr.z() & 0x8000 != 0,
+            _ => false,
+        })
+    }
+
+    /// Rewrites the time-to-live of all records except the OPT pseudo-RR.
+    pub fn update_time_to_live<F>(&mut self, mut f: F)
+    where
+        F: FnMut(u32) -> u32,
+    {
+        let positions = {
+            let index = self.index();
+            let mut positions = SmallVec::<[usize; 8]>::new();
+            positions.extend(index.answers().map(|it| it.time_to_live_pos()));
+            positions.extend(index.authorities().map(|it| it.time_to_live_pos()));
+            positions.extend(index.additionals().filter_map(|it| match it {
+                AdditionalRR::RR(rr) => Some(rr.time_to_live_pos()),
+                AdditionalRR::PseudoRR(_) => None,
+            }));
+            positions
+        };
+
+        for pos in positions {
+            let ttl = BigEndian::read_u32(&self.0[pos..]);
+            BigEndian::write_u32(&mut self.0[pos..], f(ttl));
+        }
+    }
+
+    /// Returns an iterator over the typed EDNS options.
+    pub fn edns_options(&self) -> impl Iterator<Item = EdnsOption> + '_ {
+        self.additionals()
+            .find_map(|it| match it {
+                AdditionalRR::PseudoRR(rr) => rr.data(),
+                AdditionalRR::RR(_) => None,
+            })
+            .map(EdnsOptionIter)
+            .into_iter()
+            .flatten()
+    }
+
+    /// Returns the first EDNS option with the code.
+    pub fn edns_option(&self, code: u16) -> Option<EdnsOption> {
+        self.edns_options().find(|it| it.code() == code)
+    }
+
+    /// Sets a typed EDNS option, the existing options with the same code will be replaced.
+    pub fn put_edns_option(&mut self, option: &EdnsOption) {
+        self.set_edns_option(option.code(), &option.data());
+    }
+
+    /// Sets an EDNS option, the existing options with the same code will be replaced.
+    pub fn set_edns_option(&mut self, code: u16, data: &[u8]) {
+        self.rewrite_edns_options(code, Some(data));
+    }
+
+    /// Removes the EDNS option with the given code, returns false if nothing removed.
+    pub fn remove_edns_option(&mut self, code: u16) -> bool {
+        let exists = self.edns_options().any(|it| it.code() == code);
+        if exists {
+            self.rewrite_edns_options(code, None);
+        }
+        exists
+    }
+
+    /// Rewrites the options of OPT pseudo-RR: drops all options of the code, then appends the data if present.
+    fn rewrite_edns_options(&mut self, code: u16, data: Option<&[u8]>) {
+        let offset = self.ensure_pseudo_rr();
+        let rdlen_pos = offset + Notation::new(&self.0[..], offset).len() + 8;
+        let rdlen = BigEndian::read_u16(&self.0[rdlen_pos..]) as usize;
+        let begin = rdlen_pos + 2;
+        let end = begin + rdlen;
+
+        let extra = data.map(|it| 4 + it.len()).unwrap_or_default();
+        let mut b = BytesMut::with_capacity(self.len() + extra);
+        b.extend_from_slice(&self.0[..begin]);
+
+        // copy other options
+        let mut cur = begin;
+        while cur + 4 <= end {
+            let size = BigEndian::read_u16(&self.0[cur + 2..]) as usize;
+            let next = usize::min(end, cur + 4 + size);
+            if BigEndian::read_u16(&self.0[cur..]) != code {
+                b.extend_from_slice(&self.0[cur..next]);
+            }
+            cur = next;
         }
+
+        if let Some(data) = data {
+            b.put_u16(code);
+            b.put_u16(data.len() as u16);
+            b.put_slice(data);
+        }
+
+        let size = b.len() - begin;
+        BigEndian::write_u16(&mut b[rdlen_pos..], size as u16);
+
+        b.extend_from_slice(&self.0[end..]);
+
+        self.0 = b;
     }
 }
 
@@ -1014,6 +1373,131 @@ impl Into<Bytes> for Message {
     }
 }
 
+/// An index of a message, the offsets of questions and records are computed once when it's
+/// created, so that the sections can be iterated many times and accessed randomly without walking
+/// the sections before them again.
+#[derive(Debug, Clone)]
+pub struct MessageIndex<'a> {
+    raw: &'a [u8],
+    // the offsets of questions, answers, authorities and additionals in order
+    offsets: SmallVec<[usize; 16]>,
+    // the boundaries of sections in offsets
+    bounds: [usize; 5],
+    // the position of the OPT pseudo-RR in offsets
+    pseudo_rr: Option<usize>,
+}
+
+impl<'a> MessageIndex<'a> {
+    fn new(raw: &'a [u8]) -> Self {
+        let mut offsets = SmallVec::new();
+        let mut bounds = [0; 5];
+        let mut pseudo_rr = None;
+
+        let mut offset = 12;
+        for _ in 0..BigEndian::read_u16(&raw[4..]) {
+            offsets.push(offset);
+            offset += Question { raw, offset }.len();
+        }
+        bounds[1] = offsets.len();
+
+        for section in 1..4 {
+            for _ in 0..BigEndian::read_u16(&raw[4 + section * 2..]) {
+                if section == 3 && pseudo_rr.is_none() && is_pseudo_rr(raw, offset) {
+                    pseudo_rr = Some(offsets.len());
+                }
+                offsets.push(offset);
+                offset += RR { raw, offset }.len();
+            }
+            bounds[section + 1] = offsets.len();
+        }
+
+        Self {
+            raw,
+            offsets,
+            bounds,
+            pseudo_rr,
+        }
+    }
+
+    #[inline]
+    fn section(&self, section: usize) -> &[usize] {
+        &self.offsets[self.bounds[section]..self.bounds[section + 1]]
+    }
+
+    pub fn question(&self, i: usize) -> Option<Question<'a>> {
+        let raw = self.raw;
+        self.section(0)
+            .get(i)
+            .map(|&offset| Question { raw, offset })
+    }
+
+    pub fn questions(&self) -> impl ExactSizeIterator<Item = Question<'a>> + '_ {
+        let raw = self.raw;
+        self.section(0)
+            .iter()
+            .map(move |&offset| Question { raw, offset })
+    }
+
+    pub fn answer(&self, i: usize) -> Option<RR<'a>> {
+        let raw = self.raw;
+        self.section(1).get(i).map(|&offset| RR { raw, offset })
+    }
+
+    pub fn answers(&self) -> impl ExactSizeIterator<Item = RR<'a>> + '_ {
+        let raw = self.raw;
+        self.section(1)
+            .iter()
+            .map(move |&offset| RR { raw, offset })
+    }
+
+    pub fn authority(&self, i: usize) -> Option<RR<'a>> {
+        let raw = self.raw;
+        self.section(2).get(i).map(|&offset| RR { raw, offset })
+    }
+
+    pub fn authorities(&self) -> impl ExactSizeIterator<Item = RR<'a>> + '_ {
+        let raw = self.raw;
+        self.section(2)
+            .iter()
+            .map(move |&offset| RR { raw, offset })
+    }
+
+    pub fn additional(&self, i: usize) -> Option<AdditionalRR<'a>> {
+        self.section(3)
+            .get(i)
+            .map(|&offset| self.additional_at(offset))
+    }
+
+    pub fn additionals(&self) -> impl ExactSizeIterator<Item = AdditionalRR<'a>> + '_ {
+        self.section(3)
+            .iter()
+            .map(move |&offset| self.additional_at(offset))
+    }
+
+    /// Returns the OPT pseudo-RR of EDNS.
+    pub fn pseudo_rr(&self) -> Option<PseudoRR<'a>> {
+        self.pseudo_rr.map(|i| PseudoRR {
+            raw: self.raw,
+            offset: self.offsets[i],
+        })
+    }
+
+    fn additional_at(&self, offset: usize) -> AdditionalRR<'a> {
+        let raw = self.raw;
+        if is_pseudo_rr(raw, offset) {
+            AdditionalRR::PseudoRR(PseudoRR { raw, offset })
+        } else {
+            AdditionalRR::RR(RR { raw, offset })
+        }
+    }
+}
+
+#[inline]
+fn is_pseudo_rr(raw: &[u8], offset: usize) -> bool {
+    let offset = offset + Notation::new(raw, offset).len();
+    BigEndian::read_u16(&raw[offset..]) == Kind::OPT as u16
+}
+
 struct QuestionIter<'a> {
     raw: &'a [u8],
     offset: usize,
@@ -1059,21 +1543,31 @@ impl Question<'_> {
         Notation::new(self.raw, self.offset)
     }
 
+    /// Returns the type of the question, the unsupported types are UNKNOWN.
     pub fn kind(&self) -> Kind {
-        let n = self.offset + self.name().len();
-        Kind::try_from(BigEndian::read_u16(&self.raw[n..])).expect("Invalid question type!")
+        Kind::try_from(self.kind_code()).unwrap_or(Kind::UNKNOWN)
     }
 
+    /// Returns the class of the question, the unsupported classes are UNKNOWN.
     pub fn class(&self) -> Class {
+        Class::try_from(self.class_code()).unwrap_or(Class::UNKNOWN)
+    }
+
+    pub(crate) fn kind_code(&self) -> u16 {
+        let n = self.offset + self.name().len();
+        BigEndian::read_u16(&self.raw[n..])
+    }
+
+    pub(crate) fn class_code(&self) -> u16 {
         let n = self.offset + self.name().len() + 2;
-        Class::try_from(BigEndian::read_u16(&self.raw[n..])).expect("Invalid question class!")
+        BigEndian::read_u16(&self.raw[n..])
     }
 }
 
 impl Display for Question<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         for next in self.name() {
-            write!(f, "{}.", unsafe { std::str::from_utf8_unchecked(next) })?;
+            write!(f, "{}.", String::from_utf8_lossy(next))?;
         }
         write!(f, "\t{}", self.class())?;
         write!(f, "\t{}", self.kind())?;
@@ -1158,7 +1652,7 @@ pub struct PseudoRR<'a> {
     offset: usize,
 }
 
-impl PseudoRR<'_> {
+impl<'a> PseudoRR<'a> {
     pub fn name(&self) -> Notation<'_> {
         Notation::new(self.raw, self.offset)
     }
@@ -1166,7 +1660,7 @@ impl PseudoRR<'_> {
     pub fn kind(&self) -> Kind {
         let offset = self.offset + self.name().len();
         let code = BigEndian::read_u16(&self.raw[offset..]);
-        Kind::try_from(code).expect("Invalid RR type!")
+        Kind::try_from(code).unwrap_or(Kind::UNKNOWN)
     }
 
     pub fn udp_payload_size(&self) -> u16 {
@@ -1194,7 +1688,7 @@ impl PseudoRR<'_> {
         BigEndian::read_u16(&self.raw[offset..]) as usize
     }
 
-    pub fn data(&self) -> Option<&'_ [u8]> {
+    pub fn data(&self) -> Option<&'a [u8]> {
         let offset = self.offset + self.name().len() + 10;
         let size = self.data_len();
         if size == 0 {
@@ -1204,6 +1698,11 @@ impl PseudoRR<'_> {
         }
     }
 
+    /// Returns an iterator over the typed EDNS options.
+    pub fn options(&self) -> impl Iterator<Item = EdnsOption> + 'a {
+        EdnsOptionIter(self.data().unwrap_or_default())
+    }
+
     pub fn len(&self) -> usize {
         self.name().len() + 10 + self.data_len()
     }
@@ -1235,8 +1734,8 @@ impl Display for PseudoRR<'_> {
         write!(f, "\tz={:#x}", self.z())?;
         write!(f, "\tdata_len={}", self.data_len())?;
 
-        if let Some(data) = self.data() {
-            write!(f, "\tdata={:?}", data)?;
+        for next in self.options() {
+            write!(f, "\t{}", next)?;
         }
 
         Ok(())
@@ -1254,19 +1753,27 @@ impl RR<'_> {
         Notation::new(self.raw, self.offset)
     }
 
+    /// Returns the type of the record, the unsupported types are UNKNOWN.
     pub fn kind(&self) -> Kind {
-        let offset = self.offset + self.name().len();
-        let code = BigEndian::read_u16(&self.raw[offset..]);
-        Kind::try_from(code).expect("Invalid RR type!")
+        Kind::try_from(self.kind_code()).unwrap_or(Kind::UNKNOWN)
     }
 
+    /// Returns the class of the record, the unsupported classes are UNKNOWN.
     pub fn class(&self) -> Class {
-        let offset = self.offset + self.name().len() + 2;
-        let n = BigEndian::read_u16(&self.raw[offset..]);
-        Class::try_from(n).expect("Invalid RR class!")
+        Class::try_from(self.class_code()).unwrap_or(Class::UNKNOWN)
     }
 
-    #[inline(always)]
+    pub(crate) fn kind_code(&self) -> u16 {
+        let offset = self.offset + self.name().len();
+        BigEndian::read_u16(&self.raw[offset..])
+    }
+
+    pub(crate) fn class_code(&self) -> u16 {
+        let offset = self.offset + self.name().len() + 2;
+        BigEndian::read_u16(&self.raw[offset..])
+    }
+
+    #[inline(always)]
     pub(crate) fn time_to_live_pos(&self) -> usize {
         self.offset + self.name().len() + 4
     }
@@ -1335,13 +1842,187 @@ impl RR<'_> {
                 size,
             }),
             Kind::TXT => {
-                let cs = read_character_string(&self.raw[offset..offset + size]);
-                RData::TXT(CharacterString(cs))
+                let b = &self.raw[offset..offset + size];
+                let mut n = 0;
+                while n < b.len() {
+                    n += 1 + b[n] as usize;
+                }
+                if n != b.len() {
+                    bail!("invalid RR format: malformed type(TXT)");
+                }
+                RData::TXT(TXT(b))
+            }
+            Kind::DNSKEY => {
+                if size < 4 {
+                    bail!("invalid RR format: size of type(DNSKEY) is too small");
+                }
+                RData::DNSKEY(DNSKEY(&self.raw[offset..offset + size]))
+            }
+            Kind::DS => {
+                if size < 4 {
+                    bail!("invalid RR format: size of type(DS) is too small");
+                }
+                RData::DS(DS(&self.raw[offset..offset + size]))
+            }
+            Kind::RRSIG => {
+                if size < 19 {
+                    bail!("invalid RR format: size of type(RRSIG) is too small");
+                }
+                RData::RRSIG(RRSIG {
+                    raw: &self.raw[..offset + size],
+                    offset,
+                    size,
+                })
+            }
+            Kind::NSEC => RData::NSEC(NSEC {
+                raw: &self.raw[..offset + size],
+                offset,
+                size,
+            }),
+            Kind::NSEC3 => {
+                let b = &self.raw[offset..offset + size];
+                let valid = b.len() > 5 && {
+                    let n = 5 + b[4] as usize;
+                    b.len() > n && b.len() >= n + 1 + b[n] as usize
+                };
+                if !valid {
+                    bail!("invalid RR format: malformed type(NSEC3)");
+                }
+                RData::NSEC3(NSEC3(b))
+            }
+            Kind::SRV => {
+                if size < 7 {
+                    bail!("invalid RR format: size of type(SRV) is too small");
+                }
+                RData::SRV(SRV {
+                    raw: &self.raw[..offset + size],
+                    offset,
+                    size,
+                })
+            }
+            Kind::CAA => {
+                let b = &self.raw[offset..offset + size];
+                // the tag must be alphanumeric, see RFC 8659 4.1.
+                let valid = b.len() >= 2
+                    && b.len() >= 2 + b[1] as usize
+                    && b[2..2 + b[1] as usize]
+                        .iter()
+                        .all(|c| c.is_ascii_alphanumeric());
+                if !valid {
+                    bail!("invalid RR format: malformed type(CAA)");
+                }
+                RData::CAA(CAA(b))
+            }
+            Kind::NAPTR => {
+                let b = &self.raw[offset..offset + size];
+                let mut n = 4;
+                for _ in 0..3 {
+                    if b.len() <= n {
+                        bail!("invalid RR format: malformed type(NAPTR)");
+                    }
+                    n += 1 + b[n] as usize;
+                }
+                if b.len() <= n {
+                    bail!("invalid RR format: malformed type(NAPTR)");
+                }
+                RData::NAPTR(NAPTR {
+                    raw: &self.raw[..offset + size],
+                    offset,
+                    size,
+                })
+            }
+            Kind::TLSA => {
+                if size < 3 {
+                    bail!("invalid RR format: size of type(TLSA) is too small");
+                }
+                RData::TLSA(TLSA(&self.raw[offset..offset + size]))
+            }
+            Kind::SSHFP => {
+                if size < 2 {
+                    bail!("invalid RR format: size of type(SSHFP) is too small");
+                }
+                RData::SSHFP(SSHFP(&self.raw[offset..offset + size]))
+            }
+            Kind::SVCB => RData::SVCB(SVCB {
+                raw: &self.raw[..offset + size],
+                offset,
+                size,
+            }),
+            Kind::LOC => {
+                if size != 16 {
+                    bail!(
+                        "invalid RR format: size of type(LOC) should be 16, actual is {}",
+                        size
+                    );
+                }
+                let b = &self.raw[offset..offset + size];
+                if b[0] != 0 {
+                    bail!(
+                        "invalid RR format: unsupported version {} of type(LOC)",
+                        b[0]
+                    );
+                }
+                RData::LOC(LOC(b))
             }
             _ => RData::UNKNOWN(&self.raw[offset..offset + size]),
         })
     }
 
+    /// Returns the RDATA whose domain names are decompressed, the names will be converted to
+    /// lowercase if 'canonical' is true, see RFC 4034 6.2.
+    pub fn expanded_data(&self, canonical: bool) -> crate::Result<Cow<'_, [u8]>> {
+        let put_name = |dst: &mut Vec<u8>, name: Notation<'_>| {
+            for label in name {
+                dst.push(label.len() as u8);
+                if canonical {
+                    dst.extend(label.iter().map(|b| b.to_ascii_lowercase()));
+                } else {
+                    dst.extend_from_slice(label);
+                }
+            }
+            dst.push(0);
+        };
+
+        let mut b = vec![];
+        match self.rdata()? {
+            RData::CNAME(it) => put_name(&mut b, it.cname()),
+            RData::NS(it) => put_name(&mut b, it.nameserver()),
+            RData::PTR(it) => put_name(&mut b, it.domain_name()),
+            RData::MX(it) => {
+                b.extend_from_slice(&it.preference().to_be_bytes());
+                put_name(&mut b, it.mail_exchange());
+            }
+            RData::SOA(it) => {
+                put_name(&mut b, it.primary_nameserver());
+                put_name(&mut b, it.responsible_authority_mailbox());
+                let n = it.primary_nameserver().len() + it.responsible_authority_mailbox().len();
+                b.extend_from_slice(&it.raw[it.offset + n..it.offset + n + 20]);
+            }
+            RData::RRSIG(it) => {
+                b.extend_from_slice(&it.raw[it.offset..it.offset + 18]);
+                put_name(&mut b, it.signer_name());
+                b.extend_from_slice(it.signature());
+            }
+            RData::SRV(it) => {
+                b.extend_from_slice(&it.raw[it.offset..it.offset + 6]);
+                put_name(&mut b, it.target());
+            }
+            RData::NSEC(it) => {
+                // the next domain name of NSEC keeps its case, see RFC 6840 5.1. It should never
+                // be compressed, but the pointers are meaningless out of the message anyway.
+                for label in it.next_domain_name() {
+                    b.push(label.len() as u8);
+                    b.extend_from_slice(label);
+                }
+                b.push(0);
+                b.extend_from_slice(it.type_bit_maps().0);
+            }
+            _ => return Ok(Cow::Borrowed(self.data())),
+        }
+
+        Ok(Cow::Owned(b))
+    }
+
     pub fn data(&self) -> &[u8] {
         let (offset, size) = self.data_offset_and_size();
         &self.raw[offset..offset + size]
@@ -1358,16 +2039,10 @@ impl RR<'_> {
     }
 }
 
-#[inline]
-fn read_character_string(b: &[u8]) -> &[u8] {
-    let n = b[0] as usize;
-    &b[1..n]
-}
-
 impl Display for RR<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "name={}", self.name())?;
-        write!(f, "\tkind={}", self.kind())?;
+        write!(f, "\tkind={}", kind_name(self.kind_code()))?;
         write!(f, "\tclass={}", self.class())?;
         write!(f, "\ttime_to_live={}", self.time_to_live())?;
         match self.rdata() {
@@ -1378,11 +2053,159 @@ impl Display for RR<'_> {
     }
 }
 
+/// Checks the domain name at the offset, the labels and pointers must be in front of the limit.
+/// Returns the length of name at the offset.
+fn check_name(raw: &[u8], offset: usize, limit: usize) -> Result<usize, &'static str> {
+    let mut cur = offset;
+    let mut limit = limit;
+    let mut len = None;
+    let mut total = 0usize;
+
+    loop {
+        if cur >= limit || cur >= raw.len() {
+            if len.is_some() && cur < raw.len() {
+                return Err("compression loop");
+            }
+            return Err("domain name out of bounds");
+        }
+        let first = raw[cur];
+        match first & 0xc0 {
+            0xc0 => {
+                if cur + 2 > limit || cur + 2 > raw.len() {
+                    return Err("truncated compression pointer");
+                }
+                let pos = (BigEndian::read_u16(&raw[cur..]) & 0x3fff) as usize;
+                // the pointers must point backward, and the following labels must be in front
+                // of the pointer, so the compression loops are impossible.
+                if pos < 12 || pos >= cur {
+                    return Err("invalid compression pointer");
+                }
+                if len.is_none() {
+                    len = Some(cur + 2 - offset);
+                }
+                limit = cur;
+                cur = pos;
+            }
+            0x00 => {
+                let size = first as usize;
+                if cur + 1 + size > limit || cur + 1 + size > raw.len() {
+                    return Err("label out of bounds");
+                }
+                total += 1 + size;
+                if total > 255 {
+                    return Err("domain name is too long");
+                }
+                cur += 1 + size;
+                if size == 0 {
+                    return Ok(len.unwrap_or_else(|| cur - offset));
+                }
+            }
+            _ => return Err("unsupported label type"),
+        }
+    }
+}
+
+/// Checks the resource record at the offset, returns the length of it.
+fn check_rr(
+    raw: &[u8],
+    offset: usize,
+    additional: bool,
+    opt: &mut bool,
+) -> Result<usize, (usize, Cow<'static, str>)> {
+    let n = check_name(raw, offset, raw.len()).map_err(|e| (offset, e.into()))?;
+    let header = raw
+        .get(offset + n..offset + n + 10)
+        .ok_or((offset, "truncated RR".into()))?;
+    let begin = offset + n + 10;
+    let end = begin + BigEndian::read_u16(&header[8..]) as usize;
+    if end > raw.len() {
+        return Err((begin, "RDATA out of bounds".into()));
+    }
+
+    // the unknown types and classes are passed through, see RFC 3597
+    let kind = Kind::try_from(BigEndian::read_u16(header)).unwrap_or(Kind::UNKNOWN);
+
+    if kind == Kind::OPT {
+        // see RFC 6891 6.1.1
+        if !additional || *opt || n != 1 {
+            return Err((offset, "misplaced OPT pseudo-RR".into()));
+        }
+        *opt = true;
+        let mut cur = begin;
+        while cur < end {
+            if cur + 4 > end {
+                return Err((cur, "truncated EDNS option".into()));
+            }
+            cur += 4 + BigEndian::read_u16(&raw[cur + 2..]) as usize;
+        }
+        if cur != end {
+            return Err((begin, "EDNS option out of bounds".into()));
+        }
+        return Ok(end - offset);
+    }
+
+    // the domain names in RDATA
+    let name = |at: usize| check_name(raw, at, end).map_err(|e| (at, Cow::from(e)));
+    let exact = |at: usize| {
+        if at == end {
+            Ok(())
+        } else {
+            Err((begin, Cow::from("malformed RDATA")))
+        }
+    };
+    match kind {
+        Kind::CNAME | Kind::NS | Kind::PTR => exact(begin + name(begin)?)?,
+        Kind::MX => exact(begin + 2 + name(begin + 2)?)?,
+        Kind::SRV => exact(begin + 6 + name(begin + 6)?)?,
+        Kind::SOA => {
+            let mut cur = begin + name(begin)?;
+            cur += name(cur)?;
+            exact(cur + 20)?;
+        }
+        Kind::NAPTR => {
+            let mut cur = begin + 4;
+            for _ in 0..3 {
+                if cur >= end {
+                    return Err((begin, "malformed RDATA".into()));
+                }
+                cur += 1 + raw[cur] as usize;
+            }
+            exact(cur + name(cur)?)?;
+        }
+        Kind::RRSIG => {
+            name(begin + 18)?;
+        }
+        Kind::NSEC => {
+            name(begin)?;
+        }
+        Kind::HTTPS | Kind::SVCB => {
+            let mut cur = begin + 2 + name(begin + 2)?;
+            while cur < end {
+                if cur + 4 > end {
+                    return Err((cur, "truncated SvcParam".into()));
+                }
+                cur += 4 + BigEndian::read_u16(&raw[cur + 2..]) as usize;
+            }
+            exact(cur)?;
+        }
+        _ => (),
+    }
+
+    let rr = RR { raw, offset };
+    if let Err(e) = rr.rdata() {
+        return Err((begin, e.to_string().into()));
+    }
+
+    Ok(end - offset)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Notation<'a> {
     raw: &'a [u8],
     offset: usize,
     cur: usize,
+    // the position of the latest followed pointer, the labels must be in front of it
+    limit: usize,
 }
 
 impl<'a> Notation<'a> {
@@ -1391,6 +2214,7 @@ impl<'a> Notation<'a> {
             raw,
             offset,
             cur: offset,
+            limit: raw.len(),
         }
     }
 }
@@ -1405,15 +2229,18 @@ impl Notation<'_> {
         let mut n = 0usize;
 
         loop {
-            if offset >= self.raw.len() {
-                error!(
-                    "overflow: raw={}, offset={}, current={}",
-                    hex::encode(self.raw),
-                    self.offset,
-                    offset
-                );
-            }
-            let first = self.raw[offset];
+            let first = match self.raw.get(offset) {
+                Some(first) => *first,
+                None => {
+                    error!(
+                        "overflow: raw={}, offset={}, current={}",
+                        hex::encode(self.raw),
+                        self.offset,
+                        offset
+                    );
+                    break;
+                }
+            };
             if first & 0xc0 == 0xc0 {
                 n += 2;
                 break;
@@ -1432,17 +2259,13 @@ impl Notation<'_> {
 
 impl Display for Notation<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut notation = Self {
-            raw: self.raw,
-            offset: self.offset,
-            cur: self.offset,
-        };
+        let mut notation = Notation::new(self.raw, self.offset);
         match notation.next() {
             None => Ok(()),
             Some(first) => {
-                write!(f, "{}", unsafe { std::str::from_utf8_unchecked(first) })?;
+                write!(f, "{}", String::from_utf8_lossy(first))?;
                 for next in notation {
-                    write!(f, ".{}", unsafe { std::str::from_utf8_unchecked(next) })?;
+                    write!(f, ".{}", String::from_utf8_lossy(next))?;
                 }
                 Ok(())
             }
@@ -1454,34 +2277,41 @@ impl<'a> Iterator for Notation<'a> {
     type Item = &'a [u8];
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.cur == usize::MAX {
-            return None;
-        }
-        let first = self.raw[self.cur];
-
-        if first & 0xc0 == 0xc0 {
-            // 1. compression pointer
-            let pos = BigEndian::read_u16(&self.raw[self.cur..]) & !0xc000;
-            self.cur = pos as usize;
-            self.next()
-        } else {
-            // 2. length-based
-            let size = first as usize;
-
-            if size == 0 {
-                self.cur = usize::MAX;
+        loop {
+            if self.cur >= self.limit {
                 return None;
             }
-            let offset = self.cur + 1;
-            self.cur = offset + size;
-            let b = &self.raw[offset..self.cur];
-            Some(b)
+            let first = *self.raw.get(self.cur)?;
+
+            if first & 0xc0 == 0xc0 {
+                // 1. compression pointer, only the backward ones are followed
+                let b = self.raw.get(self.cur..self.cur + 2)?;
+                let pos = (BigEndian::read_u16(b) & !0xc000) as usize;
+                if pos >= self.cur {
+                    self.cur = usize::MAX;
+                    return None;
+                }
+                self.limit = self.cur;
+                self.cur = pos;
+            } else {
+                // 2. length-based
+                let size = first as usize;
+
+                if size == 0 {
+                    self.cur = usize::MAX;
+                    return None;
+                }
+                let offset = self.cur + 1;
+                let b = self.raw.get(offset..offset + size)?;
+                self.cur = offset + size;
+                return Some(b);
+            }
         }
     }
 }
 
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RDataOwned {
     A(Ipv4Addr),
     AAAA(Ipv6Addr),
@@ -1506,10 +2336,504 @@ pub enum RDataOwned {
         target_name: Cachestr,
         params: Vec<(SvcParamKey, Vec<u8>)>,
     },
-    TXT(Cachestr),
+    TXT(Vec<Cachestr>),
+    SRV {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: Cachestr,
+    },
+    CAA {
+        flags: u8,
+        tag: Cachestr,
+        value: Vec<u8>,
+    },
+    NAPTR {
+        order: u16,
+        preference: u16,
+        flags: Cachestr,
+        services: Cachestr,
+        regexp: Cachestr,
+        replacement: Cachestr,
+    },
+    TLSA {
+        usage: u8,
+        selector: u8,
+        matching_type: u8,
+        data: Vec<u8>,
+    },
+    SSHFP {
+        algorithm: u8,
+        fingerprint_type: u8,
+        fingerprint: Vec<u8>,
+    },
+    SVCB {
+        priority: u16,
+        target_name: Cachestr,
+        params: Vec<(SvcParamKey, Vec<u8>)>,
+    },
+    LOC {
+        size: u8,
+        horiz_pre: u8,
+        vert_pre: u8,
+        latitude: u32,
+        longitude: u32,
+        altitude: u32,
+    },
     UNKNOWN(Vec<u8>),
 }
 
+impl RDataOwned {
+    /// Creates a TXT record from a long text, which will be split into strings of 255 bytes at most.
+    pub fn txt(text: &str) -> Self {
+        let mut strings = vec![];
+        let mut rest = text;
+        while rest.len() > u8::MAX as usize {
+            let mut n = u8::MAX as usize;
+            while !rest.is_char_boundary(n) {
+                n -= 1;
+            }
+            strings.push(Cachestr::from(&rest[..n]));
+            rest = &rest[n..];
+        }
+        if !rest.is_empty() || strings.is_empty() {
+            strings.push(Cachestr::from(rest));
+        }
+        RDataOwned::TXT(strings)
+    }
+
+    /// Encodes the RDATA in wire format, which can be used by [MessageBuilder::answer].
+    pub fn to_bytes(&self) -> crate::Result<Vec<u8>> {
+        let mut b = vec![];
+        match self {
+            RDataOwned::A(it) => b.extend_from_slice(&it.octets()),
+            RDataOwned::AAAA(it) => b.extend_from_slice(&it.octets()),
+            RDataOwned::CNAME(it) | RDataOwned::PTR(it) | RDataOwned::NS(it) => {
+                put_name(&mut b, it)?
+            }
+            RDataOwned::MX {
+                preference,
+                mail_exchange,
+            } => {
+                b.extend_from_slice(&preference.to_be_bytes());
+                put_name(&mut b, mail_exchange)?;
+            }
+            RDataOwned::SOA {
+                primary_nameserver,
+                responsible_authority_mailbox,
+                serial_number,
+                refresh_interval,
+                retry_interval,
+                expire_limit,
+                minimum_ttl,
+            } => {
+                put_name(&mut b, primary_nameserver)?;
+                put_name(&mut b, responsible_authority_mailbox)?;
+                for next in [
+                    serial_number,
+                    refresh_interval,
+                    retry_interval,
+                    expire_limit,
+                    minimum_ttl,
+                ] {
+                    b.extend_from_slice(&next.to_be_bytes());
+                }
+            }
+            RDataOwned::HTTPS {
+                priority,
+                target_name,
+                params,
+            }
+            | RDataOwned::SVCB {
+                priority,
+                target_name,
+                params,
+            } => {
+                b.extend_from_slice(&priority.to_be_bytes());
+                put_name(&mut b, target_name)?;
+                for (key, value) in params {
+                    if value.len() > u16::MAX as usize {
+                        bail!("too large value of svc param '{}'", key);
+                    }
+                    let key: u16 = (*key).into();
+                    b.extend_from_slice(&key.to_be_bytes());
+                    b.extend_from_slice(&(value.len() as u16).to_be_bytes());
+                    b.extend_from_slice(value);
+                }
+            }
+            RDataOwned::TXT(strings) => {
+                for next in strings {
+                    put_character_string(&mut b, next.as_bytes())?;
+                }
+            }
+            RDataOwned::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                for next in [priority, weight, port] {
+                    b.extend_from_slice(&next.to_be_bytes());
+                }
+                put_name(&mut b, target)?;
+            }
+            RDataOwned::CAA { flags, tag, value } => {
+                if tag.is_empty() || !tag.bytes().all(|c| c.is_ascii_alphanumeric()) {
+                    bail!("invalid CAA tag '{}'", tag);
+                }
+                b.push(*flags);
+                put_character_string(&mut b, tag.as_bytes())?;
+                b.extend_from_slice(value);
+            }
+            RDataOwned::NAPTR {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement,
+            } => {
+                b.extend_from_slice(&order.to_be_bytes());
+                b.extend_from_slice(&preference.to_be_bytes());
+                put_character_string(&mut b, flags.as_bytes())?;
+                put_character_string(&mut b, services.as_bytes())?;
+                put_character_string(&mut b, regexp.as_bytes())?;
+                put_name(&mut b, replacement)?;
+            }
+            RDataOwned::TLSA {
+                usage,
+                selector,
+                matching_type,
+                data,
+            } => {
+                b.extend_from_slice(&[*usage, *selector, *matching_type]);
+                b.extend_from_slice(data);
+            }
+            RDataOwned::SSHFP {
+                algorithm,
+                fingerprint_type,
+                fingerprint,
+            } => {
+                b.extend_from_slice(&[*algorithm, *fingerprint_type]);
+                b.extend_from_slice(fingerprint);
+            }
+            RDataOwned::LOC {
+                size,
+                horiz_pre,
+                vert_pre,
+                latitude,
+                longitude,
+                altitude,
+            } => {
+                b.extend_from_slice(&[0, *size, *horiz_pre, *vert_pre]);
+                for next in [latitude, longitude, altitude] {
+                    b.extend_from_slice(&next.to_be_bytes());
+                }
+            }
+            RDataOwned::UNKNOWN(it) => b.extend_from_slice(it),
+        }
+
+        if b.len() > u16::MAX as usize {
+            bail!("too large rdata: {} bytes", b.len());
+        }
+
+        Ok(b)
+    }
+}
+
+/// Formats the RDATA in the presentation format of zone files, the names are absolute and the
+/// unsupported ones are written in the generic form of RFC 3597 5.
+impl Display for RDataOwned {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RDataOwned::A(it) => write!(f, "{}", it),
+            RDataOwned::AAAA(it) => write!(f, "{}", it),
+            RDataOwned::CNAME(it) | RDataOwned::PTR(it) | RDataOwned::NS(it) => {
+                write!(f, "{}", Fqdn(it))
+            }
+            RDataOwned::MX {
+                preference,
+                mail_exchange,
+            } => {
+                write!(f, "{} {}", preference, Fqdn(mail_exchange))
+            }
+            RDataOwned::SOA {
+                primary_nameserver,
+                responsible_authority_mailbox,
+                serial_number,
+                refresh_interval,
+                retry_interval,
+                expire_limit,
+                minimum_ttl,
+            } => {
+                write!(
+                    f,
+                    "{} {} {} {} {} {} {}",
+                    Fqdn(primary_nameserver),
+                    Fqdn(responsible_authority_mailbox),
+                    serial_number,
+                    refresh_interval,
+                    retry_interval,
+                    expire_limit,
+                    minimum_ttl
+                )
+            }
+            RDataOwned::HTTPS {
+                priority,
+                target_name,
+                params,
+            }
+            | RDataOwned::SVCB {
+                priority,
+                target_name,
+                params,
+            } => {
+                write!(f, "{} {}", priority, Fqdn(target_name))?;
+                for (key, value) in params {
+                    let mut b = Vec::with_capacity(4 + value.len());
+                    b.extend_from_slice(&Into::<u16>::into(*key).to_be_bytes());
+                    b.extend_from_slice(&(value.len() as u16).to_be_bytes());
+                    b.extend_from_slice(value);
+                    write!(f, " {}", HttpsSvcParam(&b))?;
+                }
+                Ok(())
+            }
+            RDataOwned::TXT(strings) => {
+                for (i, next) in strings.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "\"")?;
+                    write_escaped(f, next.as_bytes())?;
+                    write!(f, "\"")?;
+                }
+                Ok(())
+            }
+            RDataOwned::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                write!(f, "{} {} {} {}", priority, weight, port, Fqdn(target))
+            }
+            RDataOwned::CAA { flags, tag, value } => {
+                write!(f, "{} {} \"", flags, tag)?;
+                write_escaped(f, value)?;
+                write!(f, "\"")
+            }
+            RDataOwned::NAPTR {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement,
+            } => {
+                write!(f, "{} {}", order, preference)?;
+                for next in [flags, services, regexp] {
+                    write!(f, " \"")?;
+                    write_escaped(f, next.as_bytes())?;
+                    write!(f, "\"")?;
+                }
+                write!(f, " {}", Fqdn(replacement))
+            }
+            RDataOwned::TLSA {
+                usage,
+                selector,
+                matching_type,
+                data,
+            } => write!(
+                f,
+                "{} {} {} {}",
+                usage,
+                selector,
+                matching_type,
+                hex::encode_upper(data)
+            ),
+            RDataOwned::SSHFP {
+                algorithm,
+                fingerprint_type,
+                fingerprint,
+            } => write!(
+                f,
+                "{} {} {}",
+                algorithm,
+                fingerprint_type,
+                hex::encode_upper(fingerprint)
+            ),
+            RDataOwned::LOC { .. } => match self.to_bytes() {
+                Ok(b) => write!(f, "{}", LOC(&b)),
+                Err(_) => Err(std::fmt::Error),
+            },
+            RDataOwned::UNKNOWN(it) if it.is_empty() => write!(f, "\\# 0"),
+            RDataOwned::UNKNOWN(it) => write!(f, "\\# {} {}", it.len(), hex::encode_upper(it)),
+        }
+    }
+}
+
+/// Displays the name with a trailing dot, the root is displayed as '.'.
+pub(crate) struct Fqdn<'a>(pub(crate) &'a str);
+
+impl Display for Fqdn<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            "" => f.write_str("."),
+            name if name.ends_with('.') => f.write_str(name),
+            name => write!(f, "{}.", name),
+        }
+    }
+}
+
+/// Writes an uncompressed domain name in wire format.
+fn put_name(b: &mut Vec<u8>, name: &str) -> crate::Result<()> {
+    if !name.is_empty() && !is_valid_domain(name) {
+        bail!("invalid domain name '{}'", name);
+    }
+    for label in name.split('.').filter(|it| !it.is_empty()) {
+        b.push(label.len() as u8);
+        b.extend_from_slice(label.as_bytes());
+    }
+    b.push(0);
+    Ok(())
+}
+
+/// Writes a <character-string> which is prefixed by its length, see RFC 1035 3.3.
+fn put_character_string(b: &mut Vec<u8>, s: &[u8]) -> crate::Result<()> {
+    if s.len() > u8::MAX as usize {
+        bail!("too long character-string: {} bytes", s.len());
+    }
+    b.push(s.len() as u8);
+    b.extend_from_slice(s);
+    Ok(())
+}
+
+/// Converts the name into an owned string, the root will be '.'.
+///
+/// Fails if the name cannot be represented in text, eg: a label is not in UTF-8 or contains dots.
+pub(crate) fn notation_to_cachestr(name: Notation<'_>) -> crate::Result<Cachestr> {
+    let mut s = String::new();
+    for label in Clone::clone(&name) {
+        match std::str::from_utf8(label) {
+            Ok(label) if !label.contains('.') => {
+                if !s.is_empty() {
+                    s.push('.');
+                }
+                s.push_str(label);
+            }
+            _ => bail!("unsupported domain name '{}'", name),
+        }
+    }
+    if s.is_empty() {
+        s.push('.');
+    }
+    Ok(Cachestr::from(s))
+}
+
+impl TryFrom<&RR<'_>> for RDataOwned {
+    type Error = anyhow::Error;
+
+    fn try_from(rr: &RR<'_>) -> Result<Self, Self::Error> {
+        fn to_str(b: &[u8]) -> Option<Cachestr> {
+            std::str::from_utf8(b).ok().map(Cachestr::from)
+        }
+
+        let params = |it: &HTTPS<'_>| -> Vec<(SvcParamKey, Vec<u8>)> {
+            it.params().map(|p| (p.key(), p.data().to_vec())).collect()
+        };
+
+        let owned = match rr.rdata()? {
+            RData::A(it) => RDataOwned::A(it.ipaddr()),
+            RData::AAAA(it) => RDataOwned::AAAA(it.ipaddr()),
+            RData::CNAME(it) => RDataOwned::CNAME(notation_to_cachestr(it.cname())?),
+            RData::NS(it) => RDataOwned::NS(notation_to_cachestr(it.nameserver())?),
+            RData::PTR(it) => RDataOwned::PTR(notation_to_cachestr(it.domain_name())?),
+            RData::MX(it) => RDataOwned::MX {
+                preference: it.preference(),
+                mail_exchange: notation_to_cachestr(it.mail_exchange())?,
+            },
+            RData::SOA(it) => RDataOwned::SOA {
+                primary_nameserver: notation_to_cachestr(it.primary_nameserver())?,
+                responsible_authority_mailbox: notation_to_cachestr(
+                    it.responsible_authority_mailbox(),
+                )?,
+                serial_number: it.serial_number(),
+                refresh_interval: it.refresh_interval(),
+                retry_interval: it.retry_interval(),
+                expire_limit: it.expire_limit(),
+                minimum_ttl: it.minimum_ttl(),
+            },
+            RData::HTTPS(it) => RDataOwned::HTTPS {
+                priority: it.priority(),
+                target_name: notation_to_cachestr(it.target_name())?,
+                params: params(&it),
+            },
+            RData::SVCB(it) => RDataOwned::SVCB {
+                priority: it.priority(),
+                target_name: notation_to_cachestr(it.target_name())?,
+                params: params(&it),
+            },
+            RData::SRV(it) => RDataOwned::SRV {
+                priority: it.priority(),
+                weight: it.weight(),
+                port: it.port(),
+                target: notation_to_cachestr(it.target())?,
+            },
+            RData::CAA(it) => RDataOwned::CAA {
+                flags: it.flags(),
+                tag: Cachestr::from(it.tag()),
+                value: it.value().to_vec(),
+            },
+            RData::TLSA(it) => RDataOwned::TLSA {
+                usage: it.usage(),
+                selector: it.selector(),
+                matching_type: it.matching_type(),
+                data: it.data().to_vec(),
+            },
+            RData::SSHFP(it) => RDataOwned::SSHFP {
+                algorithm: it.algorithm(),
+                fingerprint_type: it.fingerprint_type(),
+                fingerprint: it.fingerprint().to_vec(),
+            },
+            RData::LOC(it) => {
+                let b = it.0;
+                RDataOwned::LOC {
+                    size: b[1],
+                    horiz_pre: b[2],
+                    vert_pre: b[3],
+                    latitude: BigEndian::read_u32(&b[4..]),
+                    longitude: BigEndian::read_u32(&b[8..]),
+                    altitude: BigEndian::read_u32(&b[12..]),
+                }
+            }
+            // the strings which are not in UTF-8 are kept as raw bytes
+            RData::TXT(it) => match it.strings().map(|s| to_str(s.as_bytes())).collect() {
+                Some(strings) => RDataOwned::TXT(strings),
+                None => RDataOwned::UNKNOWN(it.0.to_vec()),
+            },
+            RData::NAPTR(it) => match (
+                to_str(it.flags().as_bytes()),
+                to_str(it.services().as_bytes()),
+                to_str(it.regexp().as_bytes()),
+            ) {
+                (Some(flags), Some(services), Some(regexp)) => RDataOwned::NAPTR {
+                    order: it.order(),
+                    preference: it.preference(),
+                    flags,
+                    services,
+                    regexp,
+                    replacement: notation_to_cachestr(it.replacement())?,
+                },
+                _ => RDataOwned::UNKNOWN(rr.data().to_vec()),
+            },
+            // the names in RDATA of DNSSEC records will be decompressed
+            _ => RDataOwned::UNKNOWN(rr.expanded_data(false)?.into_owned()),
+        };
+
+        Ok(owned)
+    }
+}
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug)]
 pub enum RData<'a> {
@@ -1521,7 +2845,19 @@ pub enum RData<'a> {
     PTR(PTR<'a>),
     NS(NS<'a>),
     HTTPS(HTTPS<'a>),
-    TXT(CharacterString<'a>),
+    TXT(TXT<'a>),
+    DNSKEY(DNSKEY<'a>),
+    DS(DS<'a>),
+    RRSIG(RRSIG<'a>),
+    NSEC(NSEC<'a>),
+    NSEC3(NSEC3<'a>),
+    SRV(SRV<'a>),
+    CAA(CAA<'a>),
+    NAPTR(NAPTR<'a>),
+    TLSA(TLSA<'a>),
+    SSHFP(SSHFP<'a>),
+    SVCB(SVCB<'a>),
+    LOC(LOC<'a>),
     UNKNOWN(&'a [u8]),
 }
 
@@ -1537,6 +2873,18 @@ impl Display for RData<'_> {
             RData::NS(it) => write!(f, "{}", it),
             RData::HTTPS(it) => write!(f, "{}", it),
             RData::TXT(it) => write!(f, "{}", it),
+            RData::DNSKEY(it) => write!(f, "{}", it),
+            RData::DS(it) => write!(f, "{}", it),
+            RData::RRSIG(it) => write!(f, "{}", it),
+            RData::NSEC(it) => write!(f, "{}", it),
+            RData::NSEC3(it) => write!(f, "{}", it),
+            RData::SRV(it) => write!(f, "{}", it),
+            RData::CAA(it) => write!(f, "{}", it),
+            RData::NAPTR(it) => write!(f, "{}", it),
+            RData::TLSA(it) => write!(f, "{}", it),
+            RData::SSHFP(it) => write!(f, "{}", it),
+            RData::SVCB(it) => write!(f, "{}", it),
+            RData::LOC(it) => write!(f, "{}", it),
             RData::UNKNOWN(it) => write!(f, "UNKNOWN({:?})", it),
         }
     }
@@ -1582,22 +2930,60 @@ impl HTTPS<'_> {
 
 impl Display for HTTPS<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}\t{}.\t", self.priority(), self.target_name())?;
+        write!(f, "{} {}.", self.priority(), self.target_name())?;
+        for next in self.params() {
+            write!(f, " {}", next)?;
+        }
+        Ok(())
+    }
+}
 
-        for (i, next) in self.params().enumerate() {
-            if i != 0 {
-                write!(f, " ")?;
-            }
+/// SVCB shares the same wire format with HTTPS, see RFC 9460 2.2.
+pub type SVCB<'a> = HTTPS<'a>;
 
-            write!(f, "{}=", next.key())?;
+/// TXT record which consists of one or more <character-string>s, see RFC 1035 3.3.14.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub struct TXT<'a>(&'a [u8]);
+
+impl TXT<'_> {
+    pub fn strings(&self) -> impl Iterator<Item = CharacterString<'_>> {
+        let mut rest = self.0;
+        std::iter::from_fn(move || {
+            let n = *rest.first()? as usize;
+            let next = CharacterString(&rest[1..1 + n]);
+            rest = &rest[1 + n..];
+            Some(next)
+        })
+    }
 
-            // write values
-            for (j, val) in next.values().enumerate() {
-                if j != 0 {
-                    write!(f, ",")?;
+    /// Returns the concatenation of all strings, eg: the SPF and DKIM records which are longer
+    /// than 255 bytes, see RFC 7208 3.3.
+    pub fn joined(&self) -> Cow<'_, [u8]> {
+        let mut strings = self.strings();
+        match (strings.next(), strings.next()) {
+            (None, _) => Cow::Borrowed(&[]),
+            (Some(first), None) => Cow::Borrowed(first.0),
+            (Some(first), Some(second)) => {
+                let mut b = Vec::with_capacity(self.0.len());
+                for next in [first, second].into_iter().chain(strings) {
+                    b.extend_from_slice(next.as_bytes());
                 }
-                write!(f, "{}", unsafe { std::str::from_utf8_unchecked(val) })?;
+                Cow::Owned(b)
+            }
+        }
+    }
+}
+
+impl Display for TXT<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, next) in self.strings().enumerate() {
+            if i != 0 {
+                write!(f, " ")?;
             }
+            write!(f, "\"")?;
+            write_escaped(f, next.as_bytes())?;
+            write!(f, "\"")?;
         }
         Ok(())
     }
@@ -1608,8 +2994,8 @@ impl Display for HTTPS<'_> {
 pub struct CharacterString<'a>(&'a [u8]);
 
 impl CharacterString<'_> {
-    pub fn as_str(&self) -> &str {
-        unsafe { std::str::from_utf8_unchecked(self.0) }
+    pub fn as_str(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.0)
     }
 
     pub fn as_bytes(&self) -> &[u8] {
@@ -1625,15 +3011,9 @@ impl CharacterString<'_> {
     }
 }
 
-impl AsRef<str> for CharacterString<'_> {
-    fn as_ref(&self) -> &str {
-        self.as_str()
-    }
-}
-
 impl Display for CharacterString<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.as_str())?;
+        f.write_str(&self.as_str())?;
         Ok(())
     }
 }
@@ -1729,16 +3109,86 @@ impl HttpsSvcParam<'_> {
     }
 
     pub fn values(&self) -> impl Iterator<Item = &'_ [u8]> {
+        HttpsSvcParamValues(self.data())
+    }
+
+    /// Returns the raw value of the param.
+    pub fn data(&self) -> &[u8] {
         let size = BigEndian::read_u16(&self.0[2..]) as usize;
-        HttpsSvcParamValues(&self.0[4..4 + size])
+        &self.0[4..4 + size]
     }
 }
 
-struct HttpsSvcParamValues<'a>(&'a [u8]);
-
-impl<'a> Iterator for HttpsSvcParamValues<'a> {
-    type Item = &'a [u8];
-
+impl Display for HttpsSvcParam<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let key = self.key();
+        let data = self.data();
+        match key {
+            SvcParamKey::NODEFAULTALPN => write!(f, "{}", key),
+            SvcParamKey::ALPN => {
+                write!(f, "{}=\"", key)?;
+                for (i, next) in self.values().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write_escaped(f, next)?;
+                }
+                write!(f, "\"")
+            }
+            SvcParamKey::PORT if data.len() == 2 => {
+                write!(f, "{}={}", key, BigEndian::read_u16(data))
+            }
+            SvcParamKey::IPV4HINT if data.len() % 4 == 0 => {
+                write!(f, "{}=", key)?;
+                for (i, next) in data.chunks(4).enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", A(next))?;
+                }
+                Ok(())
+            }
+            SvcParamKey::IPV6HINT if data.len() % 16 == 0 => {
+                write!(f, "{}=", key)?;
+                for (i, next) in data.chunks(16).enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", AAAA(next))?;
+                }
+                Ok(())
+            }
+            SvcParamKey::ECHCONFIG => {
+                use base64::{engine::general_purpose::STANDARD, Engine as _};
+                write!(f, "{}={}", key, STANDARD.encode(data))
+            }
+            _ => {
+                write!(f, "{}=\"", key)?;
+                write_escaped(f, data)?;
+                write!(f, "\"")
+            }
+        }
+    }
+}
+
+/// Writes bytes of a quoted string, the special and non-printable characters will be escaped
+/// in '\X' or '\DDD' form, see RFC 1035 5.1.
+fn write_escaped(f: &mut Formatter<'_>, b: &[u8]) -> std::fmt::Result {
+    for &c in b {
+        match c {
+            b'"' | b'\\' => write!(f, "\\{}", c as char)?,
+            0x20..=0x7e => write!(f, "{}", c as char)?,
+            _ => write!(f, "\\{:03}", c)?,
+        }
+    }
+    Ok(())
+}
+
+struct HttpsSvcParamValues<'a>(&'a [u8]);
+
+impl<'a> Iterator for HttpsSvcParamValues<'a> {
+    type Item = &'a [u8];
+
     fn next(&mut self) -> Option<Self::Item> {
         if self.0.is_empty() {
             return None;
@@ -1820,153 +3270,796 @@ pub struct PTR<'a> {
     size: usize,
 }
 
-impl PTR<'_> {
-    pub fn domain_name(&self) -> Notation<'_> {
-        Notation::new(self.raw, self.offset)
+impl PTR<'_> {
+    pub fn domain_name(&self) -> Notation<'_> {
+        Notation::new(self.raw, self.offset)
+    }
+}
+
+impl Display for PTR<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.domain_name())
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub struct NS<'a> {
+    raw: &'a [u8],
+    offset: usize,
+    size: usize,
+}
+
+impl NS<'_> {
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn nameserver(&self) -> Notation<'_> {
+        Notation::new(self.raw, self.offset)
+    }
+}
+
+impl Display for NS<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.nameserver())
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub struct CNAME<'a> {
+    raw: &'a [u8],
+    offset: usize,
+    size: usize,
+}
+
+impl CNAME<'_> {
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn cname(&self) -> Notation<'_> {
+        Notation::new(self.raw, self.offset)
+    }
+}
+
+impl Display for CNAME<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.cname())
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub struct SOA<'a> {
+    raw: &'a [u8],
+    offset: usize,
+    size: usize,
+}
+
+impl SOA<'_> {
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn primary_nameserver(&self) -> Notation<'_> {
+        Notation::new(self.raw, self.offset)
+    }
+
+    pub fn responsible_authority_mailbox(&self) -> Notation<'_> {
+        Notation::new(self.raw, self.offset + self.primary_nameserver().len())
+    }
+
+    pub fn serial_number(&self) -> u32 {
+        let offset = self.offset
+            + self.primary_nameserver().len()
+            + self.responsible_authority_mailbox().len();
+        BigEndian::read_u32(&self.raw[offset..])
+    }
+
+    pub fn refresh_interval(&self) -> u32 {
+        let offset = self.offset
+            + self.primary_nameserver().len()
+            + self.responsible_authority_mailbox().len()
+            + 4;
+        BigEndian::read_u32(&self.raw[offset..])
+    }
+
+    pub fn retry_interval(&self) -> u32 {
+        let offset = self.offset
+            + self.primary_nameserver().len()
+            + self.responsible_authority_mailbox().len()
+            + 8;
+        BigEndian::read_u32(&self.raw[offset..])
+    }
+
+    pub fn expire_limit(&self) -> u32 {
+        let offset = self.offset
+            + self.primary_nameserver().len()
+            + self.responsible_authority_mailbox().len()
+            + 12;
+        BigEndian::read_u32(&self.raw[offset..])
+    }
+
+    pub fn minimum_ttl(&self) -> u32 {
+        let offset = self.offset
+            + self.primary_nameserver().len()
+            + self.responsible_authority_mailbox().len()
+            + 16;
+        BigEndian::read_u32(&self.raw[offset..])
+    }
+}
+
+impl Display for SOA<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {}",
+            self.primary_nameserver(),
+            self.responsible_authority_mailbox(),
+            self.serial_number(),
+            self.refresh_interval(),
+            self.retry_interval(),
+            self.expire_limit(),
+            self.minimum_ttl(),
+        )
+    }
+}
+
+/// Returns the mnemonic of the type code, or 'TYPE<N>' for unknown types, see RFC 3597 5.
+fn kind_name(code: u16) -> String {
+    match Kind::try_from(code) {
+        Ok(kind) => kind.to_string(),
+        Err(_) => format!("TYPE{}", code),
+    }
+}
+
+/// Encodes bytes in Base32 with extended hex alphabet and without padding, see RFC 4648 7.
+pub(crate) fn base32hex(b: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHIJKLMNOPQRSTUV";
+
+    let mut s = String::with_capacity((b.len() * 8 + 4) / 5);
+    let mut buf = 0u16;
+    let mut bits = 0u8;
+    for &next in b {
+        buf = (buf << 8) | next as u16;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            s.push(ALPHABET[((buf >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        s.push(ALPHABET[((buf << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    s
+}
+
+/// Formats seconds since epoch in 'YYYYMMDDHHmmSS', see RFC 4034 3.2.
+fn format_timestamp(secs: u32) -> String {
+    match chrono::DateTime::from_timestamp(secs as i64, 0) {
+        Some(t) => t.format("%Y%m%d%H%M%S").to_string(),
+        None => secs.to_string(),
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub struct DNSKEY<'a>(&'a [u8]);
+
+impl<'a> DNSKEY<'a> {
+    /// Wraps the RDATA of DNSKEY, which should have 4 bytes at least.
+    pub(crate) fn new(rdata: &'a [u8]) -> Self {
+        debug_assert!(rdata.len() >= 4);
+        Self(rdata)
+    }
+}
+
+impl DNSKEY<'_> {
+    pub fn flags(&self) -> u16 {
+        BigEndian::read_u16(self.0)
+    }
+
+    /// Checks if the Zone Key flag is set.
+    pub fn is_zone_key(&self) -> bool {
+        self.flags() & 0x0100 != 0
+    }
+
+    /// Checks if the Secure Entry Point flag is set, which means it's a KSK usually.
+    pub fn is_secure_entry_point(&self) -> bool {
+        self.flags() & 0x0001 != 0
+    }
+
+    pub fn protocol(&self) -> u8 {
+        self.0[2]
+    }
+
+    pub fn algorithm(&self) -> u8 {
+        self.0[3]
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.0[4..]
+    }
+
+    /// Computes the key tag, see RFC 4034 Appendix B.
+    pub fn key_tag(&self) -> u16 {
+        let mut ac = 0u32;
+        for (i, b) in self.0.iter().enumerate() {
+            if i & 1 == 0 {
+                ac += (*b as u32) << 8;
+            } else {
+                ac += *b as u32;
+            }
+        }
+        ac += (ac >> 16) & 0xffff;
+        (ac & 0xffff) as u16
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.0
+    }
+}
+
+impl Display for DNSKEY<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        write!(
+            f,
+            "{} {} {} {}",
+            self.flags(),
+            self.protocol(),
+            self.algorithm(),
+            STANDARD.encode(self.public_key())
+        )
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub struct DS<'a>(&'a [u8]);
+
+impl DS<'_> {
+    pub fn key_tag(&self) -> u16 {
+        BigEndian::read_u16(self.0)
+    }
+
+    pub fn algorithm(&self) -> u8 {
+        self.0[2]
+    }
+
+    pub fn digest_type(&self) -> u8 {
+        self.0[3]
+    }
+
+    pub fn digest(&self) -> &[u8] {
+        &self.0[4..]
+    }
+}
+
+impl Display for DS<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.key_tag(),
+            self.algorithm(),
+            self.digest_type(),
+            hex::encode_upper(self.digest())
+        )
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub struct RRSIG<'a> {
+    raw: &'a [u8],
+    offset: usize,
+    size: usize,
+}
+
+impl RRSIG<'_> {
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the code of covered type, which may be unknown.
+    pub fn type_covered(&self) -> u16 {
+        BigEndian::read_u16(&self.raw[self.offset..])
+    }
+
+    pub fn algorithm(&self) -> u8 {
+        self.raw[self.offset + 2]
+    }
+
+    pub fn labels(&self) -> u8 {
+        self.raw[self.offset + 3]
+    }
+
+    pub fn original_ttl(&self) -> u32 {
+        BigEndian::read_u32(&self.raw[self.offset + 4..])
+    }
+
+    pub fn expiration(&self) -> u32 {
+        BigEndian::read_u32(&self.raw[self.offset + 8..])
+    }
+
+    pub fn inception(&self) -> u32 {
+        BigEndian::read_u32(&self.raw[self.offset + 12..])
+    }
+
+    pub fn key_tag(&self) -> u16 {
+        BigEndian::read_u16(&self.raw[self.offset + 16..])
+    }
+
+    pub fn signer_name(&self) -> Notation<'_> {
+        Notation::new(self.raw, self.offset + 18)
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        let n = self.signer_name().len();
+        &self.raw[usize::min(self.offset + 18 + n, self.raw.len())..]
+    }
+}
+
+impl Display for RRSIG<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {}. {}",
+            kind_name(self.type_covered()),
+            self.algorithm(),
+            self.labels(),
+            self.original_ttl(),
+            format_timestamp(self.expiration()),
+            format_timestamp(self.inception()),
+            self.key_tag(),
+            self.signer_name(),
+            STANDARD.encode(self.signature())
+        )
+    }
+}
+
+/// The type bit maps of NSEC and NSEC3, see RFC 4034 4.1.2.
+#[derive(Debug, Clone)]
+pub struct TypeBitMaps<'a>(&'a [u8]);
+
+impl TypeBitMaps<'_> {
+    /// Returns the type codes in the bit maps.
+    pub fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        let mut windows = vec![];
+        let mut b = self.0;
+        while b.len() >= 2 {
+            let (window, n) = (b[0] as u16, usize::min(b[1] as usize, b.len() - 2));
+            windows.push((window, &b[2..2 + n]));
+            b = &b[2 + n..];
+        }
+        windows.into_iter().flat_map(|(window, bitmap)| {
+            bitmap.iter().enumerate().flat_map(move |(i, octet)| {
+                (0..8u16)
+                    .filter(move |bit| octet & (0x80 >> bit) != 0)
+                    .map(move |bit| (window << 8) | ((i as u16) << 3) | bit)
+            })
+        })
+    }
+
+    pub fn contains(&self, kind: Kind) -> bool {
+        let code = kind as u16;
+        self.iter().any(|it| it == code)
+    }
+}
+
+impl Display for TypeBitMaps<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, next) in self.iter().enumerate() {
+            if i != 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", kind_name(next))?;
+        }
+        Ok(())
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub struct NSEC<'a> {
+    raw: &'a [u8],
+    offset: usize,
+    size: usize,
+}
+
+impl NSEC<'_> {
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn next_domain_name(&self) -> Notation<'_> {
+        Notation::new(self.raw, self.offset)
+    }
+
+    pub fn type_bit_maps(&self) -> TypeBitMaps<'_> {
+        let n = self.next_domain_name().len();
+        TypeBitMaps(&self.raw[usize::min(self.offset + n, self.raw.len())..])
+    }
+}
+
+impl Display for NSEC<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}. {}", self.next_domain_name(), self.type_bit_maps())
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub struct NSEC3<'a>(&'a [u8]);
+
+impl NSEC3<'_> {
+    pub fn hash_algorithm(&self) -> u8 {
+        self.0[0]
+    }
+
+    pub fn flags(&self) -> u8 {
+        self.0[1]
+    }
+
+    pub fn is_opt_out(&self) -> bool {
+        self.flags() & 0x01 != 0
+    }
+
+    pub fn iterations(&self) -> u16 {
+        BigEndian::read_u16(&self.0[2..])
+    }
+
+    pub fn salt(&self) -> &[u8] {
+        let n = self.0[4] as usize;
+        &self.0[5..5 + n]
+    }
+
+    pub fn next_hashed_owner_name(&self) -> &[u8] {
+        let offset = 5 + self.salt().len();
+        let n = self.0[offset] as usize;
+        &self.0[offset + 1..offset + 1 + n]
+    }
+
+    pub fn type_bit_maps(&self) -> TypeBitMaps<'_> {
+        let offset = 5 + self.salt().len() + 1 + self.next_hashed_owner_name().len();
+        TypeBitMaps(&self.0[offset..])
+    }
+}
+
+impl Display for NSEC3<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let salt = if self.salt().is_empty() {
+            "-".to_string()
+        } else {
+            hex::encode_upper(self.salt())
+        };
+        write!(
+            f,
+            "{} {} {} {} {} {}",
+            self.hash_algorithm(),
+            self.flags(),
+            self.iterations(),
+            salt,
+            base32hex(self.next_hashed_owner_name()),
+            self.type_bit_maps()
+        )
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub struct SRV<'a> {
+    raw: &'a [u8],
+    offset: usize,
+    size: usize,
+}
+
+impl SRV<'_> {
+    pub fn priority(&self) -> u16 {
+        BigEndian::read_u16(&self.raw[self.offset..])
+    }
+
+    pub fn weight(&self) -> u16 {
+        BigEndian::read_u16(&self.raw[self.offset + 2..])
+    }
+
+    pub fn port(&self) -> u16 {
+        BigEndian::read_u16(&self.raw[self.offset + 4..])
+    }
+
+    pub fn target(&self) -> Notation<'_> {
+        Notation::new(self.raw, self.offset + 6)
+    }
+}
+
+impl Display for SRV<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}.",
+            self.priority(),
+            self.weight(),
+            self.port(),
+            self.target()
+        )
+    }
+}
+
+/// Certification Authority Authorization, see RFC 8659.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub struct CAA<'a>(&'a [u8]);
+
+impl CAA<'_> {
+    pub fn flags(&self) -> u8 {
+        self.0[0]
+    }
+
+    pub fn is_critical(&self) -> bool {
+        self.flags() & 0x80 != 0
+    }
+
+    pub fn tag(&self) -> &str {
+        let n = self.0[1] as usize;
+        std::str::from_utf8(&self.0[2..2 + n]).unwrap_or_default()
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.0[2 + self.0[1] as usize..]
+    }
+}
+
+impl Display for CAA<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} \"", self.flags(), self.tag())?;
+        write_escaped(f, self.value())?;
+        write!(f, "\"")
+    }
+}
+
+/// Naming Authority Pointer, see RFC 3403 4.1.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+pub struct NAPTR<'a> {
+    raw: &'a [u8],
+    offset: usize,
+    size: usize,
+}
+
+impl NAPTR<'_> {
+    pub fn order(&self) -> u16 {
+        BigEndian::read_u16(&self.raw[self.offset..])
+    }
+
+    pub fn preference(&self) -> u16 {
+        BigEndian::read_u16(&self.raw[self.offset + 2..])
+    }
+
+    pub fn flags(&self) -> CharacterString<'_> {
+        self.character_string(0)
+    }
+
+    pub fn services(&self) -> CharacterString<'_> {
+        self.character_string(1)
+    }
+
+    pub fn regexp(&self) -> CharacterString<'_> {
+        self.character_string(2)
+    }
+
+    pub fn replacement(&self) -> Notation<'_> {
+        let mut offset = self.offset + 4;
+        for _ in 0..3 {
+            offset += 1 + self.raw[offset] as usize;
+        }
+        Notation::new(self.raw, offset)
+    }
+
+    fn character_string(&self, index: usize) -> CharacterString<'_> {
+        let mut offset = self.offset + 4;
+        for _ in 0..index {
+            offset += 1 + self.raw[offset] as usize;
+        }
+        let n = self.raw[offset] as usize;
+        CharacterString(&self.raw[offset + 1..offset + 1 + n])
     }
 }
 
-impl Display for PTR<'_> {
+impl Display for NAPTR<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.domain_name())
+        write!(f, "{} {}", self.order(), self.preference())?;
+        for next in [self.flags(), self.services(), self.regexp()] {
+            write!(f, " \"")?;
+            write_escaped(f, next.as_bytes())?;
+            write!(f, "\"")?;
+        }
+        write!(f, " {}.", self.replacement())
     }
 }
 
+/// TLSA certificate association, see RFC 6698 2.1.
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug)]
-pub struct NS<'a> {
-    raw: &'a [u8],
-    offset: usize,
-    size: usize,
-}
+pub struct TLSA<'a>(&'a [u8]);
 
-impl NS<'_> {
-    pub fn len(&self) -> usize {
-        self.size
+impl TLSA<'_> {
+    pub fn usage(&self) -> u8 {
+        self.0[0]
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
+    pub fn selector(&self) -> u8 {
+        self.0[1]
     }
 
-    pub fn nameserver(&self) -> Notation<'_> {
-        Notation::new(self.raw, self.offset)
+    pub fn matching_type(&self) -> u8 {
+        self.0[2]
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.0[3..]
     }
 }
 
-impl Display for NS<'_> {
+impl Display for TLSA<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.nameserver())
+        write!(
+            f,
+            "{} {} {} {}",
+            self.usage(),
+            self.selector(),
+            self.matching_type(),
+            hex::encode_upper(self.data())
+        )
     }
 }
 
+/// SSH key fingerprint, see RFC 4255 3.1.
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug)]
-pub struct CNAME<'a> {
-    raw: &'a [u8],
-    offset: usize,
-    size: usize,
-}
+pub struct SSHFP<'a>(&'a [u8]);
 
-impl CNAME<'_> {
-    pub fn len(&self) -> usize {
-        self.size
+impl SSHFP<'_> {
+    pub fn algorithm(&self) -> u8 {
+        self.0[0]
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
+    pub fn fingerprint_type(&self) -> u8 {
+        self.0[1]
     }
 
-    pub fn cname(&self) -> Notation<'_> {
-        Notation::new(self.raw, self.offset)
+    pub fn fingerprint(&self) -> &[u8] {
+        &self.0[2..]
     }
 }
 
-impl Display for CNAME<'_> {
+impl Display for SSHFP<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.cname())
+        write!(
+            f,
+            "{} {} {}",
+            self.algorithm(),
+            self.fingerprint_type(),
+            hex::encode_upper(self.fingerprint())
+        )
     }
 }
 
+/// Location information, see RFC 1876 2.
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Debug)]
-pub struct SOA<'a> {
-    raw: &'a [u8],
-    offset: usize,
-    size: usize,
-}
+pub struct LOC<'a>(&'a [u8]);
 
-impl SOA<'_> {
-    pub fn len(&self) -> usize {
-        self.size
-    }
+impl LOC<'_> {
+    const EQUATOR: u32 = 1 << 31;
+    const REFERENCE_ALTITUDE: i64 = 10000000;
 
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
+    pub fn version(&self) -> u8 {
+        self.0[0]
     }
 
-    pub fn primary_nameserver(&self) -> Notation<'_> {
-        Notation::new(self.raw, self.offset)
+    /// Returns the diameter of the sphere enclosing the described entity in centimeters.
+    pub fn size(&self) -> u64 {
+        Self::precision(self.0[1])
     }
 
-    pub fn responsible_authority_mailbox(&self) -> Notation<'_> {
-        Notation::new(self.raw, self.offset + self.primary_nameserver().len())
+    /// Returns the horizontal precision in centimeters.
+    pub fn horiz_pre(&self) -> u64 {
+        Self::precision(self.0[2])
     }
 
-    pub fn serial_number(&self) -> u32 {
-        let offset = self.offset
-            + self.primary_nameserver().len()
-            + self.responsible_authority_mailbox().len();
-        BigEndian::read_u32(&self.raw[offset..])
+    /// Returns the vertical precision in centimeters.
+    pub fn vert_pre(&self) -> u64 {
+        Self::precision(self.0[3])
     }
 
-    pub fn refresh_interval(&self) -> u32 {
-        let offset = self.offset
-            + self.primary_nameserver().len()
-            + self.responsible_authority_mailbox().len()
-            + 4;
-        BigEndian::read_u32(&self.raw[offset..])
+    /// Returns the latitude in thousandths of a second of arc, the north is positive.
+    pub fn latitude(&self) -> i64 {
+        BigEndian::read_u32(&self.0[4..]) as i64 - Self::EQUATOR as i64
     }
 
-    pub fn retry_interval(&self) -> u32 {
-        let offset = self.offset
-            + self.primary_nameserver().len()
-            + self.responsible_authority_mailbox().len()
-            + 8;
-        BigEndian::read_u32(&self.raw[offset..])
+    /// Returns the longitude in thousandths of a second of arc, the east is positive.
+    pub fn longitude(&self) -> i64 {
+        BigEndian::read_u32(&self.0[8..]) as i64 - Self::EQUATOR as i64
     }
 
-    pub fn expire_limit(&self) -> u32 {
-        let offset = self.offset
-            + self.primary_nameserver().len()
-            + self.responsible_authority_mailbox().len()
-            + 12;
-        BigEndian::read_u32(&self.raw[offset..])
+    /// Returns the altitude from the WGS 84 reference spheroid in centimeters.
+    pub fn altitude(&self) -> i64 {
+        BigEndian::read_u32(&self.0[12..]) as i64 - Self::REFERENCE_ALTITUDE
     }
 
-    pub fn minimum_ttl(&self) -> u32 {
-        let offset = self.offset
-            + self.primary_nameserver().len()
-            + self.responsible_authority_mailbox().len()
-            + 16;
-        BigEndian::read_u32(&self.raw[offset..])
+    fn precision(b: u8) -> u64 {
+        (b >> 4) as u64 * 10u64.pow((b & 0x0f).min(9) as u32)
     }
-}
 
-impl Display for SOA<'_> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn write_coordinate(f: &mut Formatter<'_>, v: i64, hemispheres: [char; 2]) -> std::fmt::Result {
+        let hemisphere = if v < 0 {
+            hemispheres[1]
+        } else {
+            hemispheres[0]
+        };
+        let v = v.unsigned_abs();
         write!(
             f,
-            "{} {} {} {} {} {} {}",
-            self.primary_nameserver(),
-            self.responsible_authority_mailbox(),
-            self.serial_number(),
-            self.refresh_interval(),
-            self.retry_interval(),
-            self.expire_limit(),
-            self.minimum_ttl(),
+            "{} {} {}.{:03} {}",
+            v / 3600000,
+            v / 60000 % 60,
+            v / 1000 % 60,
+            v % 1000,
+            hemisphere
         )
     }
+
+    fn write_meters(f: &mut Formatter<'_>, cm: i64) -> std::fmt::Result {
+        let sign = if cm < 0 { "-" } else { "" };
+        let cm = cm.unsigned_abs();
+        if cm % 100 == 0 {
+            write!(f, "{}{}m", sign, cm / 100)
+        } else {
+            write!(f, "{}{}.{:02}m", sign, cm / 100, cm % 100)
+        }
+    }
+}
+
+impl Display for LOC<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Self::write_coordinate(f, self.latitude(), ['N', 'S'])?;
+        write!(f, " ")?;
+        Self::write_coordinate(f, self.longitude(), ['E', 'W'])?;
+        for next in [
+            self.altitude(),
+            self.size() as i64,
+            self.horiz_pre() as i64,
+            self.vert_pre() as i64,
+        ] {
+            write!(f, " ")?;
+            Self::write_meters(f, next)?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -2188,7 +4281,7 @@ mod tests {
             let domain = "google.com";
             let msg = Message::builder()
                 .id(1234)
-                .question(&format!("{}.", domain), Kind::A, Class::IN)
+                .question(format!("{}.", domain), Kind::A, Class::IN)
                 .build();
 
             assert!(msg.is_ok_and(|msg| {
@@ -2279,6 +4372,51 @@ mod tests {
         assert_eq!(4, msg.authority_count(), "invalid authority count");
     }
 
+    #[test]
+    fn test_message_index() {
+        init();
+
+        let s = "a4e5808000010000000400120a68747470733a2f2f696d0864696e6774616c6b03636f6d0000010001c017000200010000001e000d036e73360674616f62616fc020c017000200010000001e0006036e7335c039c017000200010000001e0006036e7337c039c017000200010000001e0006036e7334c039c072000100010000001e0004aa211849c072000100010000001e0004aa21184bc072000100010000001e00042f584a21c072000100010000001e00042f584a23c072000100010000001e00042ff1cf0dc072000100010000001e00042ff1cf0fc04e000100010000001e00048ccd7a21c04e000100010000001e00048ccd7a22c035000100010000001e00048ccd7a24c035000100010000001e00048ccd7a23c060000100010000001e00046a0b2996c060000100010000001e00046a0b2319c060000100010000001e00046a0b231ac060000100010000001e00046a0b2995c072001c00010000001e00102401b180410000000000000000000004c04e001c00010000001e00102401b180410000000000000000000005c035001c00010000001e00102401b180410000000000000000000006c060001c00010000001e00102401b180410000000000000000000007";
+        let msg = Message::from(hex::decode(s).unwrap());
+        let index = msg.index();
+
+        assert_eq!(1, index.questions().len());
+        assert_eq!(0, index.answers().len());
+        assert_eq!(4, index.authorities().len());
+        assert_eq!(18, index.additionals().len());
+        assert!(index.pseudo_rr().is_none());
+
+        assert_eq!(
+            "https://im.dingtalk.com",
+            index.question(0).unwrap().name().to_string()
+        );
+        assert!(index.answer(0).is_none());
+        assert_eq!(
+            msg.authorities().nth(3).unwrap().to_string(),
+            index.authority(3).unwrap().to_string()
+        );
+        assert!(index.authority(4).is_none());
+        match index.additional(17) {
+            Some(AdditionalRR::RR(rr)) => assert_eq!(Kind::AAAA, rr.kind()),
+            _ => panic!("the last additional should be an AAAA record"),
+        }
+        assert!(index.additional(18).is_none());
+
+        // the OPT pseudo-RR
+        let msg = Message::from(hex::decode("f2508180000100020000000105626169647503636f6d0000010001c00c00010001000000b70004279c420ac00c00010001000000b700046ef244420000290580000000000000").unwrap());
+        let index = msg.index();
+        assert_eq!(2, index.answers().len());
+        assert_eq!(
+            Some(1408),
+            index.pseudo_rr().map(|it| it.udp_payload_size())
+        );
+        assert!(matches!(
+            index.additional(0),
+            Some(AdditionalRR::PseudoRR(_))
+        ));
+        walk(&msg);
+    }
+
     #[test]
     fn test_broken() {
         init();
@@ -2359,4 +4497,649 @@ mod tests {
         assert_eq!(13, cnt.0, "the num of rr should be 13");
         assert_eq!(1, cnt.1, "the num of pseude-rr should be 11");
     }
+
+    #[test]
+    fn test_set_edns() {
+        init();
+
+        let pseudo = |msg: &Message| -> Option<(u16, Vec<u8>)> {
+            msg.additionals().find_map(|it| match it {
+                AdditionalRR::PseudoRR(rr) => Some((
+                    rr.udp_payload_size(),
+                    rr.data().map(|it| it.to_vec()).unwrap_or_default(),
+                )),
+                AdditionalRR::RR(_) => None,
+            })
+        };
+
+        // without OPT
+        let mut msg = Message::builder()
+            .id(1234)
+            .flags(Flags::request())
+            .question("www.google.com", Kind::A, Class::IN)
+            .build()
+            .unwrap();
+        assert!(pseudo(&msg).is_none());
+
+        msg.set_udp_payload_size(4096);
+        assert_eq!(1, msg.additional_count());
+        assert_eq!(Some((4096, vec![])), pseudo(&msg));
+
+        msg.set_edns_option(8, &[0, 1, 24, 0, 1, 2, 3]);
+        assert_eq!(
+            Some((4096, vec![0, 8, 0, 7, 0, 1, 24, 0, 1, 2, 3])),
+            pseudo(&msg)
+        );
+
+        // replace the existing option
+        msg.set_edns_option(10, &[0xff; 8]);
+        msg.set_edns_option(8, &[0, 1, 16, 0, 1, 2]);
+        let (_, data) = pseudo(&msg).unwrap();
+        assert_eq!(&data[..4], &[0, 10, 0, 8]);
+        assert_eq!(&data[12..], &[0, 8, 0, 6, 0, 1, 16, 0, 1, 2]);
+        assert_eq!(1, msg.additional_count());
+
+        // with OPT
+        let mut msg = {
+            let raw = hex::decode(
+                "1afb0120000100000000000105626169647503636f6d00000100010000291000000000000000",
+            )
+            .unwrap();
+            Message::from(raw)
+        };
+        msg.set_udp_payload_size(1232);
+        msg.set_edns_option(8, &[0, 1, 0, 0]);
+        assert_eq!(1, msg.additional_count());
+        assert_eq!(Some((1232, vec![0, 8, 0, 4, 0, 1, 0, 0])), pseudo(&msg));
+        assert_eq!(
+            "baidu.com",
+            msg.questions().next().unwrap().name().to_string()
+        );
+    }
+
+    #[test]
+    fn test_rdata_round_trip() {
+        init();
+
+        let tlsa = hex::decode("d2abde240d7cd3ee6b4b28c54df034b97983a1d16e8a410e4561cb106618e971")
+            .unwrap();
+        let sshfp = hex::decode("123456789abcdef67890123456789abcdef67890").unwrap();
+
+        let cases = [
+            (
+                Kind::SRV,
+                RDataOwned::SRV {
+                    priority: 10,
+                    weight: 60,
+                    port: 5060,
+                    target: Cachestr::from("sip.example.com"),
+                },
+                "10 60 5060 sip.example.com.",
+            ),
+            (
+                Kind::CAA,
+                RDataOwned::CAA {
+                    flags: 128,
+                    tag: Cachestr::from("issue"),
+                    value: b"letsencrypt.org; \"x\"".to_vec(),
+                },
+                r#"128 issue "letsencrypt.org; \"x\"""#,
+            ),
+            (
+                Kind::NAPTR,
+                RDataOwned::NAPTR {
+                    order: 100,
+                    preference: 10,
+                    flags: Cachestr::from("S"),
+                    services: Cachestr::from("SIP+D2U"),
+                    regexp: Cachestr::from(""),
+                    replacement: Cachestr::from("_sip._udp.example.com"),
+                },
+                r#"100 10 "S" "SIP+D2U" "" _sip._udp.example.com."#,
+            ),
+            (
+                Kind::TLSA,
+                RDataOwned::TLSA {
+                    usage: 3,
+                    selector: 1,
+                    matching_type: 1,
+                    data: tlsa.clone(),
+                },
+                "3 1 1 D2ABDE240D7CD3EE6B4B28C54DF034B97983A1D16E8A410E4561CB106618E971",
+            ),
+            (
+                Kind::SSHFP,
+                RDataOwned::SSHFP {
+                    algorithm: 2,
+                    fingerprint_type: 1,
+                    fingerprint: sshfp.clone(),
+                },
+                "2 1 123456789ABCDEF67890123456789ABCDEF67890",
+            ),
+            (
+                Kind::SVCB,
+                RDataOwned::SVCB {
+                    priority: 1,
+                    target_name: Cachestr::from("svc.example.com"),
+                    params: vec![
+                        (SvcParamKey::ALPN, b"\x02h2\x02h3".to_vec()),
+                        (SvcParamKey::PORT, 8443u16.to_be_bytes().to_vec()),
+                        (SvcParamKey::IPV4HINT, vec![192, 0, 2, 1, 192, 0, 2, 2]),
+                    ],
+                },
+                r#"1 svc.example.com. alpn="h2,h3" port=8443 ipv4hint=192.0.2.1,192.0.2.2"#,
+            ),
+            (
+                Kind::HTTPS,
+                RDataOwned::HTTPS {
+                    priority: 1,
+                    target_name: Cachestr::from("."),
+                    params: vec![
+                        (SvcParamKey::NODEFAULTALPN, vec![]),
+                        (
+                            SvcParamKey::IPV6HINT,
+                            "2001:db8::1".parse::<Ipv6Addr>().unwrap().octets().to_vec(),
+                        ),
+                    ],
+                },
+                "1 . no-default-alpn ipv6hint=2001:db8::1",
+            ),
+            (
+                Kind::LOC,
+                RDataOwned::LOC {
+                    size: 0x33,
+                    horiz_pre: 0x16,
+                    vert_pre: 0x13,
+                    latitude: 2299997648,
+                    longitude: 1891505648,
+                    altitude: 9997600,
+                },
+                "42 21 54.000 N 71 6 18.000 W -24m 30m 10000m 10m",
+            ),
+        ];
+
+        for (kind, data, expect) in cases {
+            let b = data.to_bytes().unwrap();
+            let msg = Message::builder()
+                .id(1234)
+                .flags(Flags::builder().response().build())
+                .question("example.com", kind, Class::IN)
+                .answer("example.com", kind, Class::IN, 300, b.clone())
+                .build()
+                .unwrap();
+
+            let answer = msg.answers().next().unwrap();
+            assert_eq!(kind, answer.kind());
+            assert_eq!(&b[..], answer.data());
+
+            let rdata = answer.rdata().unwrap();
+            assert_eq!(expect, rdata.to_string());
+
+            match rdata {
+                RData::SRV(it) => {
+                    assert_eq!(10, it.priority());
+                    assert_eq!(60, it.weight());
+                    assert_eq!(5060, it.port());
+                    assert_eq!("sip.example.com", it.target().to_string());
+                }
+                RData::CAA(it) => {
+                    assert!(it.is_critical());
+                    assert_eq!("issue", it.tag());
+                }
+                RData::NAPTR(it) => {
+                    assert_eq!(100, it.order());
+                    assert_eq!(10, it.preference());
+                    assert_eq!("S", it.flags().as_str());
+                    assert_eq!("SIP+D2U", it.services().as_str());
+                    assert!(it.regexp().is_empty());
+                    assert_eq!("_sip._udp.example.com", it.replacement().to_string());
+                }
+                RData::TLSA(it) => assert_eq!(&tlsa[..], it.data()),
+                RData::SSHFP(it) => assert_eq!(&sshfp[..], it.fingerprint()),
+                RData::SVCB(it) => {
+                    let ports = it
+                        .params()
+                        .filter(|it| it.key() == SvcParamKey::PORT)
+                        .map(|it| BigEndian::read_u16(it.data()))
+                        .collect::<Vec<_>>();
+                    assert_eq!(vec![8443], ports);
+                }
+                RData::LOC(it) => {
+                    assert_eq!(0, it.version());
+                    assert_eq!(3000, it.size());
+                    assert_eq!(-2400, it.altitude());
+                    assert!(it.longitude() < 0);
+                }
+                _ => (),
+            }
+        }
+
+        // malformed
+        {
+            let msg = Message::builder()
+                .answer("example.com", Kind::CAA, Class::IN, 300, &[0, 5, b'i'][..])
+                .build()
+                .unwrap();
+            assert!(msg.answers().next().unwrap().rdata().is_err());
+
+            let data = RDataOwned::CAA {
+                flags: 0,
+                tag: Cachestr::from("bad tag"),
+                value: vec![],
+            };
+            assert!(data.to_bytes().is_err());
+        }
+    }
+
+    #[test]
+    fn test_rdata_txt() {
+        init();
+
+        let dkim = format!("v=DKIM1; k=rsa; p={}", "A".repeat(400));
+        let cases = [
+            RDataOwned::TXT(vec![Cachestr::from("v=spf1 -all")]),
+            RDataOwned::TXT(vec![
+                Cachestr::from("a \"quoted\" \\ text"),
+                Cachestr::from(""),
+            ]),
+            RDataOwned::txt(&dkim),
+        ];
+
+        let msg = cases
+            .iter()
+            .fold(Message::builder(), |bu, next| {
+                bu.answer(
+                    "example.com",
+                    Kind::TXT,
+                    Class::IN,
+                    300,
+                    next.to_bytes().unwrap(),
+                )
+            })
+            .build()
+            .unwrap();
+
+        let txts = msg
+            .answers()
+            .map(|it| match it.rdata().unwrap() {
+                RData::TXT(txt) => (
+                    txt.to_string(),
+                    txt.strings().map(|it| it.len()).collect::<Vec<_>>(),
+                    txt.joined().to_vec(),
+                ),
+                other => panic!("unexpected rdata: {}", other),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!("\"v=spf1 -all\"", txts[0].0);
+        assert_eq!(b"v=spf1 -all", &txts[0].2[..]);
+
+        assert_eq!(r#""a \"quoted\" \\ text" """#, txts[1].0);
+        assert_eq!(vec![17, 0], txts[1].1);
+
+        assert_eq!(vec![255, dkim.len() - 255], txts[2].1);
+        assert_eq!(dkim.as_bytes(), &txts[2].2[..]);
+
+        // non-printable
+        {
+            let msg = Message::builder()
+                .answer(
+                    "example.com",
+                    Kind::TXT,
+                    Class::IN,
+                    300,
+                    &[2, 0x09, 0xff][..],
+                )
+                .build()
+                .unwrap();
+            let answer = msg.answers().next().unwrap();
+            assert_eq!("\"\\009\\255\"", answer.rdata().unwrap().to_string());
+        }
+
+        // malformed
+        for data in [&[5, b'a'][..], &[1, b'a', 2, b'b'][..]] {
+            let msg = Message::builder()
+                .answer("example.com", Kind::TXT, Class::IN, 300, data)
+                .build()
+                .unwrap();
+            assert!(msg.answers().next().unwrap().rdata().is_err());
+        }
+
+        // empty rdata
+        {
+            let msg = Message::builder()
+                .answer("example.com", Kind::TXT, Class::IN, 300, &[][..])
+                .build()
+                .unwrap();
+            let answer = msg.answers().next().unwrap();
+            assert!(answer
+                .rdata()
+                .is_ok_and(|it| matches!(it, RData::TXT(txt) if txt.strings().count() == 0)));
+        }
+
+        assert!(RDataOwned::TXT(vec![Cachestr::from("x".repeat(256))])
+            .to_bytes()
+            .is_err());
+    }
+
+    #[test]
+    fn test_message_builder_compression() {
+        init();
+
+        let records = [
+            (
+                Kind::CNAME,
+                RDataOwned::CNAME(Cachestr::from("cdn.example.com")),
+            ),
+            (Kind::A, RDataOwned::A(Ipv4Addr::new(1, 1, 1, 1))),
+            (Kind::A, RDataOwned::A(Ipv4Addr::new(2, 2, 2, 2))),
+            (
+                Kind::MX,
+                RDataOwned::MX {
+                    preference: 10,
+                    mail_exchange: Cachestr::from("mail.example.com"),
+                },
+            ),
+            (Kind::NS, RDataOwned::NS(Cachestr::from("ns1.example.com"))),
+            (
+                Kind::PTR,
+                RDataOwned::PTR(Cachestr::from("www.example.com")),
+            ),
+            (
+                Kind::SOA,
+                RDataOwned::SOA {
+                    primary_nameserver: Cachestr::from("ns1.example.com"),
+                    responsible_authority_mailbox: Cachestr::from("hostmaster.example.com"),
+                    serial_number: 2024010101,
+                    refresh_interval: 7200,
+                    retry_interval: 3600,
+                    expire_limit: 1209600,
+                    minimum_ttl: 300,
+                },
+            ),
+            (
+                Kind::SRV,
+                RDataOwned::SRV {
+                    priority: 0,
+                    weight: 5,
+                    port: 443,
+                    target: Cachestr::from("www.example.com"),
+                },
+            ),
+        ];
+
+        let mut bu = Message::builder()
+            .id(1234)
+            .flags(Flags::builder().response().build())
+            .question("www.example.com", Kind::A, Class::IN);
+        let mut uncompressed = 12 + 17 + 4;
+        for (kind, data) in &records {
+            let b = data.to_bytes().unwrap();
+            uncompressed += 17 + 10 + b.len();
+            bu = bu.answer("www.example.com", *kind, Class::IN, 300, b);
+        }
+        let msg = bu.build().unwrap();
+
+        info!("compressed: {} -> {} bytes", uncompressed, msg.len());
+        assert!(msg.len() < uncompressed);
+
+        assert_eq!(records.len(), msg.answers().count());
+        for (answer, (kind, data)) in msg.answers().zip(records.iter()) {
+            // owner names are pointers to the question
+            assert_eq!(2, answer.name().len());
+            assert_eq!("www.example.com", answer.name().to_string());
+            assert_eq!(*kind, answer.kind());
+            assert_eq!(*data, RDataOwned::try_from(&answer).unwrap());
+            assert_eq!(
+                &data.to_bytes().unwrap()[..],
+                &answer.expanded_data(false).unwrap()[..]
+            );
+        }
+
+        // the names of unknown types and the malformed rdata are written as is
+        let rdata = [0xc0, 0x0c];
+        let msg = Message::builder()
+            .answer("example.com", Kind::CNAME, Class::IN, 300, &rdata[..])
+            .answer(
+                "example.com",
+                Kind::SRV,
+                Class::IN,
+                300,
+                &b"\0\0\0\0\0\0\x07example\x03com\0"[..],
+            )
+            .build()
+            .unwrap();
+        let answers = msg
+            .answers()
+            .map(|it| it.data().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(&rdata[..], &answers[0][..]);
+        assert_eq!(19, answers[1].len());
+    }
+
+    /// Accesses everything of the message, which should never panic after validation.
+    fn walk(msg: &Message) {
+        let _ = msg.flags().opcode();
+        let _ = msg.flags().response_code();
+        for next in msg.questions() {
+            let _ = next.to_string();
+        }
+        for next in msg.answers().chain(msg.authorities()) {
+            let _ = next.to_string();
+            let _ = next.expanded_data(true);
+            let _ = RDataOwned::try_from(&next);
+        }
+        for next in msg.additionals() {
+            match next {
+                AdditionalRR::PseudoRR(it) => {
+                    let _ = it.to_string();
+                }
+                AdditionalRR::RR(it) => {
+                    let _ = it.to_string();
+                }
+            }
+        }
+        let _ = msg.edns_options().count();
+
+        let index = msg.index();
+        assert!(msg
+            .questions()
+            .map(|it| it.offset)
+            .eq(index.questions().map(|it| it.offset)));
+        assert!(msg
+            .answers()
+            .chain(msg.authorities())
+            .map(|it| it.offset)
+            .eq(index
+                .answers()
+                .chain(index.authorities())
+                .map(|it| it.offset)));
+        let offset = |it: AdditionalRR<'_>| match it {
+            AdditionalRR::PseudoRR(it) => (true, it.offset),
+            AdditionalRR::RR(it) => (false, it.offset),
+        };
+        assert!(msg
+            .additionals()
+            .map(offset)
+            .eq(index.additionals().map(offset)));
+
+        if let Ok(owned) = crate::protocol::MessageOwned::try_from(msg) {
+            if let Ok(rebuilt) = Message::try_from(&owned) {
+                assert!(rebuilt.validate().is_ok(), "{}", hex::encode(&msg.0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        init();
+
+        let samples = [
+            "0001818000010003000100000770616e63616b65056170706c6503636f6d0000410001c00c00050001000050bd00220770616e63616b650963646e2d6170706c6503636f6d06616b61646e73036e657400c02f000500010000012c00170d6170706c65646f776e6c6f61640671746c63646ec01ac05d000500010000000500210d6170706c65646f776e6c6f61640671746c63646e03636f6d0563646e6d67c01ac099000600010000003c003004646e73310563646e3230036f726700097765626d6173746572c09954cace5700002a3000000e1000093a800000003c",
+            "f2508180000100020000000105626169647503636f6d0000010001c00c00010001000000b70004279c420ac00c00010001000000b700046ef244420000290580000000000000",
+            // found by fuzzing: the next domain name of NSEC is compressed
+            "0001818000010003000100000770616e63616b65056170706c6503636f6d0000410001c00c00050001000050bd00220770616e63616b650963646e2d6170706c6503636f6d06616b61646e73036e657400c027000500010002012c00170d6170706c65646f776e6c6f6164066e7471646c63c01ac057000500010000000500210d6170706c65646f776e6c6f61640671746c63646e06616b61646e73036e657400c02f002f00010000012c00170d6170706c65646f776e6c6f61640671746c63646ec05d05c01a00646f766e6c6f61640671746c63646e03636f6d0563646e6d67c01ac099000600010000003c003003636f6d0563646e6d67c09954cace5700002a3000000e100009d0800000003c",
+        ]
+        .iter()
+        .map(|it| hex::decode(it).unwrap())
+        .collect::<Vec<_>>();
+
+        for next in &samples {
+            let msg = Message::from(next.clone());
+            assert!(msg.validate().is_ok());
+            walk(&msg);
+        }
+
+        // found by fuzzing: the names which cannot be represented in text are not owned
+        for raw in [
+            "abcd0100000100000000000003ff61620000010001",
+            "abcd0100000100000000000003612e620000010001",
+        ] {
+            let msg = Message::from(hex::decode(raw).unwrap());
+            assert!(msg.validate().is_ok());
+            assert!(crate::protocol::MessageOwned::try_from(&msg).is_err());
+            walk(&msg);
+        }
+
+        for (raw, reason) in [
+            ("abcd0100000100000000", "truncated header"),
+            (
+                "abcd01000001000000000000c00c00010001",
+                "compression pointer",
+            ),
+            (
+                "abcd0100000100000000000003777777c00c00010001",
+                "compression loop",
+            ),
+            (
+                "abcd010000010000000000000377777700000100",
+                "truncated question",
+            ),
+            (
+                "abcd0100000000010000000000ff0000010000000000050102",
+                "RDATA out of bounds",
+            ),
+            ("abcd01000001000000000000407777770000010001", "label type"),
+            (
+                "abcd01000000000100000000000001000100000000000501020304",
+                "RDATA out of bounds",
+            ),
+            (
+                "abcd0100000000010000000000000100010000000000050102030405",
+                "type(A)",
+            ),
+            ("abcd010000000001000000000000290200000000000000", "OPT"),
+            (
+                "abcd010000000000000000020000290200000000000000000029020000000000000000",
+                "OPT",
+            ),
+            (
+                "abcd0100000000000000000100002902000000000000050001000401",
+                "EDNS option",
+            ),
+            (
+                "abcd01000000000100000000000005000100000000000303777777",
+                "out of bounds",
+            ),
+            ("abcd7900000000000000000000", "opcode"),
+            ("abcd810f000000000000000000", "rcode"),
+        ] {
+            let msg = Message::from(hex::decode(raw).unwrap());
+            let err = msg.validate().unwrap_err();
+            info!("{}: {}", raw, err);
+            assert!(
+                matches!(
+                    err.downcast_ref::<crate::Error>(),
+                    Some(crate::Error::MalformedMessage(_, _))
+                ),
+                "{}",
+                raw
+            );
+            assert!(err.to_string().contains(reason), "{}: {}", raw, err);
+        }
+
+        // the unknown types and the classes ANY/NONE are passed through, see RFC 3597
+        let msg = Message::from(
+            hex::decode(concat!(
+                "abcd8180000100020000000103777777",
+                "00ff000001c00cff00000100000e1000",
+                "03010203c00c000100fe00000e100004",
+                "7f000001036b657900",
+                "00fa00ff000000000006000102030405"
+            ))
+            .unwrap(),
+        );
+        assert!(msg.validate().is_ok());
+        walk(&msg);
+        let question = msg.questions().next().unwrap();
+        assert_eq!(Kind::UNKNOWN, question.kind());
+        assert_eq!(0xff00, question.kind_code());
+        let answers = msg.answers().collect::<Vec<_>>();
+        assert_eq!(Kind::UNKNOWN, answers[0].kind());
+        assert_eq!(Class::NONE, answers[1].class());
+        // the owned records cannot encode the unknown types again
+        assert!(crate::protocol::MessageOwned::try_from(&msg).is_err());
+        let tsig = msg.additionals().next().unwrap();
+        assert!(
+            matches!(tsig, AdditionalRR::RR(ref rr) if rr.kind() == Kind::TSIG && rr.class() == Class::ANY)
+        );
+
+        // the unknown question type is echoed
+        let res = Message::builder().raw_question(question).build().unwrap();
+        assert_eq!(0xff00, res.questions().next().unwrap().kind_code());
+
+        // the walker is bounded even if the message is not validated
+        let msg =
+            Message::from(hex::decode("abcd0100000100000000000003777777c00c00010001").unwrap());
+        assert!(msg.validate().is_err());
+        assert_eq!(2, msg.questions().next().unwrap().name().count());
+    }
+
+    #[test]
+    fn test_validate_mutations() {
+        init();
+
+        let samples = [
+            "0001818000010003000100000770616e63616b65056170706c6503636f6d0000410001c00c00050001000050bd00220770616e63616b650963646e2d6170706c6503636f6d06616b61646e73036e657400c02f000500010000012c00170d6170706c65646f776e6c6f61640671746c63646ec01ac05d000500010000000500210d6170706c65646f776e6c6f61640671746c63646e03636f6d0563646e6d67c01ac099000600010000003c003004646e73310563646e3230036f726700097765626d6173746572c09954cace5700002a3000000e1000093a800000003c",
+            "f2508180000100020000000105626169647503636f6d0000010001c00c00010001000000b70004279c420ac00c00010001000000b700046ef244420000290580000000000000",
+        ]
+        .iter()
+        .map(|it| hex::decode(it).unwrap())
+        .collect::<Vec<_>>();
+
+        // xorshift, the mutations are reproducible
+        let mut seed = 0x2545f4914f6cdd1du64;
+        let mut rand = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        let mut valid = 0;
+        for i in 0..20000 {
+            let mut b = samples[i % samples.len()].clone();
+            for _ in 0..1 + rand() % 4 {
+                let pos = rand() as usize % b.len();
+                match rand() % 4 {
+                    0 => b[pos] = rand() as u8,
+                    1 => b[pos] ^= 1 << (rand() % 8),
+                    2 => b[pos] = 0xc0 | (rand() as u8 & 0x3f),
+                    _ => b.truncate(pos),
+                }
+                if b.is_empty() {
+                    break;
+                }
+            }
+
+            let msg = Message::from(b);
+            if msg.validate().is_ok() {
+                valid += 1;
+                walk(&msg);
+            }
+        }
+
+        info!("{} mutations passed validation", valid);
+        assert!(valid > 0);
+    }
 }
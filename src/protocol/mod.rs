@@ -1,7 +1,14 @@
 mod dns;
+mod edns;
 mod frame;
+mod owned;
 mod tcp;
+mod zone;
 
 pub use dns::*;
+pub use edns::EdnsOption;
+pub(crate) use edns::EdnsOptionIter;
 pub use frame::*;
+pub use owned::*;
 pub(crate) use tcp::Codec;
+pub use zone::ZoneParser;
@@ -0,0 +1,443 @@
+use super::frame::{notation_to_cachestr, Fqdn};
+use super::{
+    AdditionalRR, Class, EdnsOption, Flags, Kind, Message, Question, RCode, RDataOwned, RR,
+};
+use crate::cachestr::Cachestr;
+use std::fmt::{Display, Formatter};
+
+/// The sections of resource records in a DNS message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Section {
+    Answer,
+    Authority,
+    Additional,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuestionOwned {
+    pub name: Cachestr,
+    pub kind: Kind,
+    pub class: Class,
+}
+
+impl TryFrom<&Question<'_>> for QuestionOwned {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &Question<'_>) -> Result<Self, Self::Error> {
+        if value.kind() == Kind::UNKNOWN || value.class() == Class::UNKNOWN {
+            bail!("unsupported question type or class: {}", value);
+        }
+        Ok(Self {
+            name: notation_to_cachestr(value.name())?,
+            kind: value.kind(),
+            class: value.class(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordOwned {
+    pub name: Cachestr,
+    pub kind: Kind,
+    pub class: Class,
+    pub ttl: u32,
+    pub data: RDataOwned,
+}
+
+impl TryFrom<&RR<'_>> for RecordOwned {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &RR<'_>) -> Result<Self, Self::Error> {
+        if value.kind() == Kind::UNKNOWN || value.class() == Class::UNKNOWN {
+            bail!("unsupported RR type or class: {}", value);
+        }
+        Ok(Self {
+            name: notation_to_cachestr(value.name())?,
+            kind: value.kind(),
+            class: value.class(),
+            ttl: value.time_to_live(),
+            data: RDataOwned::try_from(value)?,
+        })
+    }
+}
+
+/// Formats the record as a line of zone files, see RFC 1035 5.1.
+impl Display for RecordOwned {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            Fqdn(&self.name),
+            self.ttl,
+            self.class,
+            self.kind,
+            self.data
+        )
+    }
+}
+
+/// The OPT pseudo-RR of EDNS, see RFC 6891 6.1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub z: u16,
+    pub options: Vec<EdnsOption>,
+}
+
+impl Default for Edns {
+    fn default() -> Self {
+        Self {
+            udp_payload_size: Message::DEFAULT_UDP_PAYLOAD_SIZE,
+            extended_rcode: 0,
+            version: 0,
+            z: 0,
+            options: vec![],
+        }
+    }
+}
+
+impl Edns {
+    pub fn option(&self, code: u16) -> Option<&EdnsOption> {
+        self.options.iter().find(|it| it.code() == code)
+    }
+
+    /// Sets an option, the existing options with the same code will be replaced.
+    pub fn set_option(&mut self, option: EdnsOption) {
+        self.remove_option(option.code());
+        self.options.push(option);
+    }
+
+    pub fn remove_option(&mut self, code: u16) -> bool {
+        let n = self.options.len();
+        self.options.retain(|it| it.code() != code);
+        n != self.options.len()
+    }
+
+    pub fn is_dnssec_ok(&self) -> bool {
+        self.z & 0x8000 != 0
+    }
+
+    pub fn set_dnssec_ok(&mut self, enabled: bool) {
+        if enabled {
+            self.z |= 0x8000;
+        } else {
+            self.z &= 0x7fff;
+        }
+    }
+}
+
+/// An owned and editable DNS message, which can be converted from and into the wire format.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MessageOwned {
+    pub id: u16,
+    pub flags: Flags,
+    pub questions: Vec<QuestionOwned>,
+    pub answers: Vec<RecordOwned>,
+    pub authorities: Vec<RecordOwned>,
+    /// The additional records, excludes the OPT pseudo-RR which is kept in 'edns'.
+    pub additionals: Vec<RecordOwned>,
+    pub edns: Option<Edns>,
+}
+
+impl MessageOwned {
+    pub fn records(&self, section: Section) -> &[RecordOwned] {
+        match section {
+            Section::Answer => &self.answers,
+            Section::Authority => &self.authorities,
+            Section::Additional => &self.additionals,
+        }
+    }
+
+    pub fn records_mut(&mut self, section: Section) -> &mut Vec<RecordOwned> {
+        match section {
+            Section::Answer => &mut self.answers,
+            Section::Authority => &mut self.authorities,
+            Section::Additional => &mut self.additionals,
+        }
+    }
+
+    pub fn push(&mut self, section: Section, record: RecordOwned) {
+        self.records_mut(section).push(record);
+    }
+
+    /// Removes all records of the kind, returns the amount of removed records.
+    pub fn remove(&mut self, section: Section, kind: Kind) -> usize {
+        let records = self.records_mut(section);
+        let n = records.len();
+        records.retain(|it| it.kind != kind);
+        n - records.len()
+    }
+
+    pub fn retain<F>(&mut self, section: Section, f: F)
+    where
+        F: FnMut(&RecordOwned) -> bool,
+    {
+        self.records_mut(section).retain(f);
+    }
+
+    /// Returns an iterator over the records of all sections.
+    pub fn records_all_mut(&mut self) -> impl Iterator<Item = &mut RecordOwned> {
+        self.answers
+            .iter_mut()
+            .chain(self.authorities.iter_mut())
+            .chain(self.additionals.iter_mut())
+    }
+
+    pub fn set_rcode(&mut self, rcode: RCode) {
+        self.flags = self.flags.into_builder().rcode(rcode).build();
+    }
+
+    /// Returns the EDNS, an empty one will be created if it's absent.
+    pub fn edns_mut(&mut self) -> &mut Edns {
+        self.edns.get_or_insert_with(Default::default)
+    }
+
+    /// Formats the records of all sections in zone file format, the questions are written as
+    /// comments.
+    pub fn to_zone(&self) -> String {
+        use std::fmt::Write;
+
+        let mut s = String::new();
+        for next in &self.questions {
+            writeln!(s, ";{}\t{}\t{}", Fqdn(&next.name), next.class, next.kind).ok();
+        }
+        for next in self
+            .answers
+            .iter()
+            .chain(self.authorities.iter())
+            .chain(self.additionals.iter())
+        {
+            writeln!(s, "{}", next).ok();
+        }
+        s
+    }
+
+    /// Encodes the message into wire format.
+    pub fn build(&self) -> crate::Result<Message> {
+        let mut bu = Message::builder().id(self.id).flags(self.flags);
+
+        for next in &self.questions {
+            bu = bu.question(next.name.as_ref(), next.kind, next.class);
+        }
+        for next in &self.answers {
+            bu = bu.answer(
+                next.name.as_ref(),
+                next.kind,
+                next.class,
+                next.ttl,
+                next.data.to_bytes()?,
+            );
+        }
+        for next in &self.authorities {
+            bu = bu.authority(
+                next.name.as_ref(),
+                next.kind,
+                next.class,
+                next.ttl,
+                next.data.to_bytes()?,
+            );
+        }
+        for next in &self.additionals {
+            bu = bu.additional(
+                next.name.as_ref(),
+                next.kind,
+                next.class,
+                next.ttl,
+                next.data.to_bytes()?,
+            );
+        }
+        if let Some(edns) = &self.edns {
+            bu = bu.edns(edns)?;
+        }
+
+        bu.build()
+    }
+}
+
+impl TryFrom<&Message> for MessageOwned {
+    type Error = anyhow::Error;
+
+    fn try_from(msg: &Message) -> Result<Self, Self::Error> {
+        let index = msg.index();
+        let mut owned = MessageOwned {
+            id: msg.id(),
+            flags: msg.flags(),
+            questions: index
+                .questions()
+                .map(|it| QuestionOwned::try_from(&it))
+                .collect::<crate::Result<_>>()?,
+            answers: index
+                .answers()
+                .map(|it| RecordOwned::try_from(&it))
+                .collect::<crate::Result<_>>()?,
+            authorities: index
+                .authorities()
+                .map(|it| RecordOwned::try_from(&it))
+                .collect::<crate::Result<_>>()?,
+            ..Default::default()
+        };
+
+        for next in index.additionals() {
+            match next {
+                AdditionalRR::RR(rr) => owned.additionals.push(RecordOwned::try_from(&rr)?),
+                AdditionalRR::PseudoRR(rr) => {
+                    if owned.edns.is_some() {
+                        bail!("invalid message: more than one OPT pseudo-RR");
+                    }
+                    owned.edns.replace(Edns {
+                        udp_payload_size: rr.udp_payload_size(),
+                        extended_rcode: rr.extended_rcode(),
+                        version: rr.version(),
+                        z: rr.z(),
+                        options: rr.options().collect(),
+                    });
+                }
+            }
+        }
+
+        Ok(owned)
+    }
+}
+
+impl TryFrom<&MessageOwned> for Message {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &MessageOwned) -> Result<Self, Self::Error> {
+        value.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        init();
+
+        // a compressed response of www.youtube.com
+        let raw = hex::decode("e7ad81800001000e000000010377777707796f757475626503636f6d0000010001c00c00050001000000ab00160a796f75747562652d7569016c06676f6f676c65c018c02d00010001000000ad00048efa442ec02d00010001000000ad00048efa48eec02d00010001000000ad00048efabceec02d00010001000000ad00048efa488ec02d00010001000000ad00048efa48aec02d00010001000000ad00048efab00ec02d00010001000000ad00048efabd0ec02d00010001000000ad00048efad98ec02d00010001000000ad00048efb282ec02d00010001000000ad00048efa440ec02d00010001000000ad0004acd90c8ec02d00010001000000ad0004acd90e4ec02d00010001000000ad00048efa446e0000290200000000000000").unwrap();
+        let msg = Message::from(raw);
+
+        let owned = MessageOwned::try_from(&msg).unwrap();
+        assert_eq!(msg.id(), owned.id);
+        assert_eq!(msg.flags(), owned.flags);
+        assert_eq!(1, owned.questions.len());
+        assert_eq!(14, owned.answers.len());
+        assert_eq!(
+            RDataOwned::CNAME(Cachestr::from("youtube-ui.l.google.com")),
+            owned.answers[0].data
+        );
+        assert!(owned
+            .edns
+            .as_ref()
+            .is_some_and(|it| it.udp_payload_size == 512 && it.options.is_empty()));
+
+        let built = owned.build().unwrap();
+        assert_eq!(owned, MessageOwned::try_from(&built).unwrap());
+        assert_eq!(
+            msg.answers().map(|it| it.to_string()).collect::<Vec<_>>(),
+            built.answers().map(|it| it.to_string()).collect::<Vec<_>>()
+        );
+
+        // an uncompressed message keeps the same bytes
+        let again = MessageOwned::try_from(&built).unwrap().build().unwrap();
+        assert_eq!(built, again);
+    }
+
+    #[test]
+    fn test_edit() {
+        init();
+
+        let msg = Message::builder()
+            .id(1234)
+            .flags(Flags::builder().response().build())
+            .question("example.com", Kind::A, Class::IN)
+            .answer(
+                "example.com",
+                Kind::CNAME,
+                Class::IN,
+                300,
+                &b"\x03foo\x03com\x00"[..],
+            )
+            .answer("foo.com", Kind::A, Class::IN, 300, &[1, 2, 3, 4][..])
+            .answer("foo.com", Kind::AAAA, Class::IN, 300, &[0u8; 16][..])
+            .additional_pseudo(1232, 0, 0, 0x8000, None::<&[u8]>)
+            .build()
+            .unwrap();
+
+        let mut owned = MessageOwned::try_from(&msg).unwrap();
+
+        assert_eq!(1, owned.remove(Section::Answer, Kind::AAAA));
+        assert_eq!(0, owned.remove(Section::Authority, Kind::AAAA));
+
+        // rewrite the cname target
+        for next in owned.records_mut(Section::Answer) {
+            match &mut next.data {
+                RDataOwned::CNAME(target) => *target = Cachestr::from("bar.com"),
+                _ => next.name = Cachestr::from("bar.com"),
+            }
+        }
+        owned.retain(Section::Answer, |it| it.kind != Kind::MX);
+        owned.push(
+            Section::Answer,
+            RecordOwned {
+                name: Cachestr::from("bar.com"),
+                kind: Kind::A,
+                class: Class::IN,
+                ttl: 300,
+                data: RDataOwned::A(Ipv4Addr::new(5, 6, 7, 8)),
+            },
+        );
+        owned.records_all_mut().for_each(|it| it.ttl = 60);
+        owned.set_rcode(RCode::NameError);
+
+        let edns = owned.edns_mut();
+        assert!(edns.is_dnssec_ok());
+        edns.set_dnssec_ok(false);
+        edns.set_option(EdnsOption::Cookie {
+            client: [1, 2, 3, 4, 5, 6, 7, 8],
+            server: vec![],
+        });
+        edns.set_option(EdnsOption::Cookie {
+            client: [8, 7, 6, 5, 4, 3, 2, 1],
+            server: vec![],
+        });
+
+        let msg = owned.build().unwrap();
+        assert_eq!(1234, msg.id());
+        assert_eq!(RCode::NameError, msg.flags().response_code());
+        assert!(!msg.is_dnssec_ok());
+        assert_eq!(
+            vec![
+                "bar.com".to_string(),
+                "1.2.3.4".to_string(),
+                "5.6.7.8".to_string()
+            ],
+            msg.answers()
+                .map(|it| {
+                    assert_eq!(60, it.time_to_live());
+                    it.rdata().unwrap().to_string()
+                })
+                .collect::<Vec<_>>()
+        );
+
+        let owned = MessageOwned::try_from(&msg).unwrap();
+        let edns = owned.edns.unwrap();
+        assert_eq!(1, edns.options.len());
+        assert_eq!(
+            Some(&EdnsOption::Cookie {
+                client: [8, 7, 6, 5, 4, 3, 2, 1],
+                server: vec![],
+            }),
+            edns.option(EdnsOption::COOKIE)
+        );
+    }
+}
@@ -0,0 +1,1126 @@
+use super::{Class, Kind, RDataOwned, RecordOwned, SvcParamKey};
+use crate::cachestr::Cachestr;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The max depth of nested '$INCLUDE' directives.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// A parser of zone files in the master file format, see RFC 1035 5.
+///
+/// The directives '$ORIGIN', '$TTL' and '$INCLUDE', the relative names, '@', parentheses and
+/// comments are supported. The records of unsupported types can be written in the generic form
+/// of RFC 3597 5, eg: 'example.com. 3600 IN DS \# 4 0A0B0C0D'.
+#[derive(Debug, Clone, Default)]
+pub struct ZoneParser {
+    origin: Option<Cachestr>,
+    ttl: Option<u32>,
+}
+
+impl ZoneParser {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the initial origin, which can be changed by '$ORIGIN'. The origin is always absolute,
+    /// with or without the trailing dot.
+    pub fn origin(mut self, origin: &str) -> Self {
+        let origin = match origin.trim_end_matches('.') {
+            "" => ".",
+            it => it,
+        };
+        self.origin = Some(Cachestr::from(origin));
+        self
+    }
+
+    /// Sets the default TTL of records without explicit TTLs, which can be changed by '$TTL'.
+    pub fn ttl(mut self, ttl: u32) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Parses the text of a zone file, the relative paths of '$INCLUDE' are resolved from the
+    /// current directory.
+    pub fn parse(&self, text: &str) -> crate::Result<Vec<RecordOwned>> {
+        let mut records = vec![];
+        let mut state = State {
+            origin: self.origin.clone(),
+            ttl: self.ttl,
+            ..Default::default()
+        };
+        state.parse(text, "<text>", None, 0, &mut records)?;
+        Ok(records)
+    }
+
+    /// Reads and parses a zone file, the relative paths of '$INCLUDE' are resolved from the
+    /// directory of the file.
+    pub fn parse_file<P>(&self, path: P) -> crate::Result<Vec<RecordOwned>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut records = vec![];
+        let mut state = State {
+            origin: self.origin.clone(),
+            ttl: self.ttl,
+            ..Default::default()
+        };
+        state.parse_file(path.as_ref(), 0, &mut records)?;
+        Ok(records)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    origin: Option<Cachestr>,
+    ttl: Option<u32>,
+    last_owner: Option<Cachestr>,
+    last_ttl: Option<u32>,
+    last_class: Option<Class>,
+}
+
+impl State {
+    fn parse_file(
+        &mut self,
+        path: &Path,
+        depth: usize,
+        records: &mut Vec<RecordOwned>,
+    ) -> crate::Result<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            bail!("too deep $INCLUDE of zone file '{}'", path.display());
+        }
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("cannot read zone file '{}': {}", path.display(), e))?;
+        let source = path.display().to_string();
+        self.parse(&text, &source, path.parent(), depth, records)
+    }
+
+    fn parse(
+        &mut self,
+        text: &str,
+        source: &str,
+        dir: Option<&Path>,
+        depth: usize,
+        records: &mut Vec<RecordOwned>,
+    ) -> crate::Result<()> {
+        for entry in tokenize(text).map_err(|e| anyhow!("invalid zone '{}': {}", source, e))? {
+            let line = entry.line;
+            self.parse_entry(entry, dir, depth, records)
+                .map_err(|e| anyhow!("invalid zone '{}' at line {}: {}", source, line, e))?;
+        }
+        Ok(())
+    }
+
+    fn parse_entry(
+        &mut self,
+        entry: Entry<'_>,
+        dir: Option<&Path>,
+        depth: usize,
+        records: &mut Vec<RecordOwned>,
+    ) -> crate::Result<()> {
+        let mut fields = Fields::new(&entry.tokens);
+
+        if !entry.blank_owner {
+            if let Some(directive) = entry.tokens[0].text.strip_prefix('$') {
+                fields.next("directive")?;
+                return match directive.to_ascii_uppercase().as_str() {
+                    "ORIGIN" => {
+                        let origin = self.name(fields.next("origin")?.text)?;
+                        fields.end()?;
+                        self.origin = Some(origin);
+                        Ok(())
+                    }
+                    "TTL" => {
+                        let ttl = parse_ttl(fields.next("ttl")?.text)?;
+                        fields.end()?;
+                        self.ttl = Some(ttl);
+                        Ok(())
+                    }
+                    "INCLUDE" => {
+                        let file = fields.next("file")?.text;
+                        let path = match dir {
+                            Some(dir) => dir.join(file),
+                            None => PathBuf::from(file),
+                        };
+                        // the origin and TTL of the included file don't affect the current one
+                        let mut included = self.clone();
+                        if let Some(origin) = fields.next_if_any() {
+                            included.origin = Some(self.name(origin.text)?);
+                        }
+                        fields.end()?;
+                        included.parse_file(&path, depth + 1, records)
+                    }
+                    _ => bail!("unsupported directive '${}'", directive),
+                };
+            }
+        }
+
+        let name = if entry.blank_owner {
+            self.last_owner
+                .clone()
+                .ok_or_else(|| anyhow!("no owner name of the first record"))?
+        } else {
+            self.name(fields.next("owner")?.text)?
+        };
+
+        // the TTL and class are optional and can be in any order
+        let mut ttl = None;
+        let mut class = None;
+        let kind = loop {
+            let next = fields.next("type")?.text;
+            if ttl.is_none() && next.starts_with(|c: char| c.is_ascii_digit()) {
+                ttl = Some(parse_ttl(next)?);
+            } else if let (None, Ok(c)) = (class, Class::from_str(&next.to_ascii_uppercase())) {
+                class = Some(c);
+            } else {
+                break parse_kind(next)?;
+            }
+        };
+
+        let ttl = ttl
+            .or(self.ttl)
+            .or(self.last_ttl)
+            .ok_or_else(|| anyhow!("no TTL of record '{}', use '$TTL' to set a default", name))?;
+        let class = class.or(self.last_class).unwrap_or(Class::IN);
+        let data = self.rdata(kind, &mut fields)?;
+
+        self.last_owner = Some(Clone::clone(&name));
+        self.last_ttl = Some(ttl);
+        self.last_class = Some(class);
+
+        records.push(RecordOwned {
+            name,
+            kind,
+            class,
+            ttl,
+            data,
+        });
+
+        Ok(())
+    }
+
+    /// Converts a name in zone files into an absolute name without the trailing dot.
+    fn name(&self, s: &str) -> crate::Result<Cachestr> {
+        let name = if s == "@" {
+            self.origin
+                .clone()
+                .ok_or_else(|| anyhow!("no origin of '@', use '$ORIGIN' to set it"))?
+        } else if s.ends_with('.') {
+            Cachestr::from(absolute(s))
+        } else {
+            match &self.origin {
+                None => bail!("no origin of relative name '{}'", s),
+                Some(origin) if origin.as_ref() == "." => Cachestr::from(s),
+                Some(origin) => Cachestr::from(format!("{}.{}", s, origin)),
+            }
+        };
+
+        if name.as_ref() != "." {
+            if name.contains('\\') {
+                bail!("unsupported escaped name '{}'", s);
+            }
+            if name.len() > 253
+                || name
+                    .split('.')
+                    .any(|label| label.is_empty() || label.len() > 63)
+            {
+                bail!("invalid name '{}'", s);
+            }
+        }
+
+        Ok(name)
+    }
+
+    fn rdata(&self, kind: Kind, fields: &mut Fields<'_>) -> crate::Result<RDataOwned> {
+        // the generic form, see RFC 3597 5
+        if fields
+            .peek()
+            .map_or(false, |it| !it.quoted && it.text == "\\#")
+        {
+            fields.next("\\#")?;
+            let size = fields.parse::<usize>("rdata length")?;
+            let data =
+                hex::decode(fields.rest().concat()).map_err(|_| anyhow!("invalid hex of rdata"))?;
+            if data.len() != size {
+                bail!(
+                    "mismatched rdata length: expect {}, got {}",
+                    size,
+                    data.len()
+                );
+            }
+            return Ok(RDataOwned::UNKNOWN(data));
+        }
+
+        let data = match kind {
+            Kind::A => RDataOwned::A(fields.parse::<Ipv4Addr>("address")?),
+            Kind::AAAA => RDataOwned::AAAA(fields.parse::<Ipv6Addr>("address")?),
+            Kind::CNAME => RDataOwned::CNAME(self.name(fields.next("name")?.text)?),
+            Kind::NS => RDataOwned::NS(self.name(fields.next("name")?.text)?),
+            Kind::PTR => RDataOwned::PTR(self.name(fields.next("name")?.text)?),
+            Kind::MX => RDataOwned::MX {
+                preference: fields.parse("preference")?,
+                mail_exchange: self.name(fields.next("exchange")?.text)?,
+            },
+            Kind::SOA => RDataOwned::SOA {
+                primary_nameserver: self.name(fields.next("mname")?.text)?,
+                responsible_authority_mailbox: self.name(fields.next("rname")?.text)?,
+                serial_number: fields.parse("serial")?,
+                refresh_interval: parse_ttl(fields.next("refresh")?.text)?,
+                retry_interval: parse_ttl(fields.next("retry")?.text)?,
+                expire_limit: parse_ttl(fields.next("expire")?.text)?,
+                minimum_ttl: parse_ttl(fields.next("minimum")?.text)?,
+            },
+            Kind::TXT => {
+                let mut strings = vec![];
+                for next in fields.rest() {
+                    let b = unescape(next)?;
+                    if b.len() > u8::MAX as usize {
+                        bail!("too long character-string: {} bytes", b.len());
+                    }
+                    strings.push(b);
+                }
+                if strings.is_empty() {
+                    bail!("missing text");
+                }
+                match strings
+                    .iter()
+                    .map(|it| std::str::from_utf8(it).ok().map(Cachestr::from))
+                    .collect::<Option<Vec<_>>>()
+                {
+                    Some(strings) => RDataOwned::TXT(strings),
+                    // the strings which are not in UTF-8 are kept as raw bytes
+                    None => {
+                        let mut b = vec![];
+                        for next in strings {
+                            b.push(next.len() as u8);
+                            b.extend_from_slice(&next);
+                        }
+                        RDataOwned::UNKNOWN(b)
+                    }
+                }
+            }
+            Kind::SRV => RDataOwned::SRV {
+                priority: fields.parse("priority")?,
+                weight: fields.parse("weight")?,
+                port: fields.parse("port")?,
+                target: self.name(fields.next("target")?.text)?,
+            },
+            Kind::CAA => RDataOwned::CAA {
+                flags: fields.parse("flags")?,
+                tag: Cachestr::from(fields.next("tag")?.text),
+                value: unescape(fields.next("value")?.text)?,
+            },
+            Kind::NAPTR => RDataOwned::NAPTR {
+                order: fields.parse("order")?,
+                preference: fields.parse("preference")?,
+                flags: fields.string("flags")?,
+                services: fields.string("services")?,
+                regexp: fields.string("regexp")?,
+                replacement: self.name(fields.next("replacement")?.text)?,
+            },
+            Kind::TLSA => RDataOwned::TLSA {
+                usage: fields.parse("usage")?,
+                selector: fields.parse("selector")?,
+                matching_type: fields.parse("matching type")?,
+                data: fields.hex("certificate association data")?,
+            },
+            Kind::SSHFP => RDataOwned::SSHFP {
+                algorithm: fields.parse("algorithm")?,
+                fingerprint_type: fields.parse("fingerprint type")?,
+                fingerprint: fields.hex("fingerprint")?,
+            },
+            Kind::HTTPS | Kind::SVCB => {
+                let priority = fields.parse("priority")?;
+                let target_name = self.name(fields.next("target")?.text)?;
+                let mut params = vec![];
+                for next in fields.rest() {
+                    params.push(parse_svc_param(next)?);
+                }
+                params.sort_by_key(|(key, _)| Into::<u16>::into(*key));
+                if kind == Kind::HTTPS {
+                    RDataOwned::HTTPS {
+                        priority,
+                        target_name,
+                        params,
+                    }
+                } else {
+                    RDataOwned::SVCB {
+                        priority,
+                        target_name,
+                        params,
+                    }
+                }
+            }
+            Kind::LOC => parse_loc(fields.rest())?,
+            other => bail!(
+                "unsupported rdata of type {}, use the generic form '\\# <length> <hex>'",
+                other
+            ),
+        };
+
+        fields.end()?;
+
+        Ok(data)
+    }
+}
+
+/// Removes the trailing dot of an absolute name, the root will be kept as '.'.
+fn absolute(s: &str) -> &str {
+    match s.strip_suffix('.') {
+        Some(name) if !name.is_empty() => name,
+        _ => ".",
+    }
+}
+
+fn parse_kind(s: &str) -> crate::Result<Kind> {
+    let s = s.to_ascii_uppercase();
+    // the generic type, see RFC 3597 5
+    if let Some(code) = s.strip_prefix("TYPE").and_then(|it| it.parse::<u16>().ok()) {
+        return Kind::try_from(code).map_err(|_| anyhow!("unsupported type '{}'", s));
+    }
+    Kind::from_str(&s)
+}
+
+/// Parses a TTL in seconds or in the units of BIND, eg: '3600', '1h', '1w2d'.
+fn parse_ttl(s: &str) -> crate::Result<u32> {
+    if let Ok(n) = s.parse::<u32>() {
+        return Ok(n);
+    }
+
+    let mut total = 0u32;
+    let mut n = None::<u32>;
+    for c in s.chars() {
+        match c.to_ascii_lowercase() {
+            '0'..='9' => {
+                let digit = c.to_digit(10).unwrap_or_default();
+                n = n
+                    .unwrap_or_default()
+                    .checked_mul(10)
+                    .and_then(|it| it.checked_add(digit));
+                if n.is_none() {
+                    bail!("invalid ttl '{}'", s);
+                }
+            }
+            unit => {
+                let secs = match unit {
+                    's' => 1,
+                    'm' => 60,
+                    'h' => 3600,
+                    'd' => 86400,
+                    'w' => 604800,
+                    _ => bail!("invalid ttl '{}'", s),
+                };
+                total = n
+                    .take()
+                    .and_then(|it| it.checked_mul(secs))
+                    .and_then(|it| it.checked_add(total))
+                    .ok_or_else(|| anyhow!("invalid ttl '{}'", s))?;
+            }
+        }
+    }
+    if s.is_empty() || n.is_some() {
+        bail!("invalid ttl '{}'", s);
+    }
+    Ok(total)
+}
+
+/// Unescapes the '\X' and '\DDD' sequences of a character-string, see RFC 1035 5.1.
+fn unescape(s: &str) -> crate::Result<Vec<u8>> {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] != b'\\' {
+            out.push(b[i]);
+            i += 1;
+            continue;
+        }
+        match b.get(i + 1..i + 4) {
+            Some(d) if d.iter().all(u8::is_ascii_digit) => {
+                let n = d.iter().fold(0u32, |n, c| n * 10 + (c - b'0') as u32);
+                if n > u8::MAX as u32 {
+                    bail!("invalid escaped character in '{}'", s);
+                }
+                out.push(n as u8);
+                i += 4;
+            }
+            _ => match b.get(i + 1) {
+                Some(c) => {
+                    out.push(*c);
+                    i += 2;
+                }
+                None => bail!("dangling escape in '{}'", s),
+            },
+        }
+    }
+    Ok(out)
+}
+
+/// Parses a SvcParam in the form of 'key=value' or 'key', see RFC 9460 2.1.
+fn parse_svc_param(s: &str) -> crate::Result<(SvcParamKey, Vec<u8>)> {
+    let (key, value) = match s.split_once('=') {
+        Some((key, value)) => {
+            let value = value
+                .strip_prefix('"')
+                .and_then(|it| it.strip_suffix('"'))
+                .unwrap_or(value);
+            (key, Some(unescape(value)?))
+        }
+        None => (s, None),
+    };
+
+    let key = parse_svc_param_key(key)?;
+    let value = value.unwrap_or_default();
+    let list = || {
+        std::str::from_utf8(&value)
+            .map(|it| it.split(',').filter(|it| !it.is_empty()))
+            .map_err(|_| anyhow!("invalid value of svc param '{}'", key))
+    };
+
+    let mut b = vec![];
+    match key {
+        SvcParamKey::PRIVATE(0) => {
+            for next in list()? {
+                let key = parse_svc_param_key(next)?;
+                b.extend_from_slice(&Into::<u16>::into(key).to_be_bytes());
+            }
+        }
+        SvcParamKey::ALPN => {
+            for next in list()? {
+                if next.len() > u8::MAX as usize {
+                    bail!("too long alpn '{}'", next);
+                }
+                b.push(next.len() as u8);
+                b.extend_from_slice(next.as_bytes());
+            }
+        }
+        SvcParamKey::NODEFAULTALPN => {
+            if !value.is_empty() {
+                bail!("no value is allowed for svc param '{}'", key);
+            }
+        }
+        SvcParamKey::PORT => {
+            let port = std::str::from_utf8(&value)
+                .ok()
+                .and_then(|it| it.parse::<u16>().ok())
+                .ok_or_else(|| anyhow!("invalid port of svc param"))?;
+            b.extend_from_slice(&port.to_be_bytes());
+        }
+        SvcParamKey::IPV4HINT => {
+            for next in list()? {
+                b.extend_from_slice(&next.parse::<Ipv4Addr>()?.octets());
+            }
+        }
+        SvcParamKey::IPV6HINT => {
+            for next in list()? {
+                b.extend_from_slice(&next.parse::<Ipv6Addr>()?.octets());
+            }
+        }
+        SvcParamKey::ECHCONFIG => {
+            use base64::{engine::general_purpose::STANDARD, Engine as _};
+            b = STANDARD
+                .decode(&value)
+                .map_err(|_| anyhow!("invalid base64 of svc param '{}'", key))?;
+        }
+        _ => b = value,
+    }
+
+    if b.len() > u16::MAX as usize {
+        bail!("too large value of svc param '{}'", key);
+    }
+
+    Ok((key, b))
+}
+
+fn parse_svc_param_key(key: &str) -> crate::Result<SvcParamKey> {
+    Ok(match key.to_ascii_lowercase().as_str() {
+        "mandatory" => SvcParamKey::PRIVATE(0),
+        "alpn" => SvcParamKey::ALPN,
+        "no-default-alpn" => SvcParamKey::NODEFAULTALPN,
+        "port" => SvcParamKey::PORT,
+        "ipv4hint" => SvcParamKey::IPV4HINT,
+        "ech" | "echconfig" => SvcParamKey::ECHCONFIG,
+        "ipv6hint" => SvcParamKey::IPV6HINT,
+        other => match other
+            .strip_prefix("key")
+            .and_then(|it| it.parse::<u16>().ok())
+        {
+            Some(n) => SvcParamKey::from(n),
+            None => bail!("invalid svc param key '{}'", key),
+        },
+    })
+}
+
+/// Parses the presentation format of LOC, see RFC 1876 3.
+fn parse_loc(tokens: Vec<&str>) -> crate::Result<RDataOwned> {
+    const EQUATOR: i64 = 1 << 31;
+    const REFERENCE_ALTITUDE: i64 = 10000000;
+
+    let mut tokens = tokens.into_iter().peekable();
+
+    // d1 [m1 [s1]] {"N"|"S"}, returns thousandths of a second of arc
+    let mut coordinate = |hemispheres: [&str; 2], max: i64| -> crate::Result<u32> {
+        let mut parts = vec![];
+        let sign = loop {
+            let next = tokens
+                .next()
+                .ok_or_else(|| anyhow!("missing coordinate of LOC"))?;
+            if next.eq_ignore_ascii_case(hemispheres[0]) {
+                break 1;
+            }
+            if next.eq_ignore_ascii_case(hemispheres[1]) {
+                break -1;
+            }
+            if parts.len() == 3 {
+                bail!("invalid coordinate of LOC");
+            }
+            parts.push(next);
+        };
+        let mut v = 0i64;
+        for (i, next) in parts.iter().enumerate() {
+            let n = match i {
+                2 => parse_decimal(next, 3)?,
+                _ => next.parse::<i64>()? * 1000,
+            };
+            v += n * [3600, 60, 1][i];
+        }
+        if parts.is_empty() || v > max * 3600000 {
+            bail!("invalid coordinate of LOC");
+        }
+        Ok((EQUATOR + sign * v) as u32)
+    };
+
+    let latitude = coordinate(["N", "S"], 90)?;
+    let longitude = coordinate(["E", "W"], 180)?;
+
+    let mut meters = |default: Option<i64>| -> crate::Result<i64> {
+        match tokens.next() {
+            Some(next) => parse_decimal(next.strip_suffix(['m', 'M']).unwrap_or(next), 2),
+            None => default.ok_or_else(|| anyhow!("missing altitude of LOC")),
+        }
+    };
+
+    let altitude = meters(None)? + REFERENCE_ALTITUDE;
+    if !(0..=u32::MAX as i64).contains(&altitude) {
+        bail!("invalid altitude of LOC");
+    }
+    let size = precision(meters(Some(100))?)?;
+    let horiz_pre = precision(meters(Some(1000000))?)?;
+    let vert_pre = precision(meters(Some(1000))?)?;
+    if tokens.next().is_some() {
+        bail!("too many fields of LOC");
+    }
+
+    Ok(RDataOwned::LOC {
+        size,
+        horiz_pre,
+        vert_pre,
+        latitude,
+        longitude,
+        altitude: altitude as u32,
+    })
+}
+
+/// Parses a decimal into an integer of the given scale, eg: '1.5' in scale 2 is 150.
+fn parse_decimal(s: &str, scale: u32) -> crate::Result<i64> {
+    let (sign, s) = match s.strip_prefix('-') {
+        Some(s) => (-1, s),
+        None => (1, s),
+    };
+    let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+    if integer.is_empty()
+        || fraction.len() > scale as usize
+        || !integer
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|c| c.is_ascii_digit())
+    {
+        bail!("invalid decimal '{}'", s);
+    }
+    let mut n = integer.parse::<i64>()?;
+    for i in 0..scale as usize {
+        let digit = fraction.as_bytes().get(i).map_or(0, |c| (c - b'0') as i64);
+        n = n
+            .checked_mul(10)
+            .and_then(|n| n.checked_add(digit))
+            .ok_or_else(|| anyhow!("invalid decimal '{}'", s))?;
+    }
+    Ok(sign * n)
+}
+
+/// Encodes the centimeters in the form of mantissa and exponent, see RFC 1876 2.
+fn precision(cm: i64) -> crate::Result<u8> {
+    if !(0..=9000000000).contains(&cm) {
+        bail!("invalid precision of LOC");
+    }
+    let mut mantissa = cm;
+    let mut exponent = 0u8;
+    while mantissa > 9 {
+        mantissa /= 10;
+        exponent += 1;
+    }
+    Ok(((mantissa as u8) << 4) | exponent)
+}
+
+/// A logical line of zone files, which may span multiple lines in parentheses.
+struct Entry<'a> {
+    line: usize,
+    // the line begins with blanks, the owner is the same as the previous record
+    blank_owner: bool,
+    tokens: Vec<Token<'a>>,
+}
+
+struct Token<'a> {
+    text: &'a str,
+    quoted: bool,
+}
+
+/// Splits the text into entries, the comments and parentheses are removed.
+fn tokenize(text: &str) -> crate::Result<Vec<Entry<'_>>> {
+    let b = text.as_bytes();
+    let mut entries = vec![];
+    let mut current = None::<Entry<'_>>;
+    let mut line = 1;
+    let mut depth = 0;
+    let mut blank_owner = false;
+    let mut line_start = true;
+
+    let mut i = 0;
+    while i < b.len() {
+        let c = b[i];
+        if line_start && depth == 0 {
+            blank_owner = c == b' ' || c == b'\t';
+        }
+        line_start = false;
+
+        match c {
+            b'\n' => {
+                line += 1;
+                line_start = true;
+                if depth == 0 {
+                    entries.extend(current.take());
+                }
+                i += 1;
+            }
+            b' ' | b'\t' | b'\r' => i += 1,
+            b';' => {
+                while i < b.len() && b[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'(' => {
+                depth += 1;
+                i += 1;
+            }
+            b')' => {
+                if depth == 0 {
+                    bail!("unbalanced parentheses at line {}", line);
+                }
+                depth -= 1;
+                i += 1;
+            }
+            _ => {
+                let start_line = line;
+                let quoted = c == b'"';
+                let start = if quoted { i + 1 } else { i };
+                let mut in_quotes = quoted;
+                i = start;
+                while i < b.len() {
+                    match b[i] {
+                        b'\\' => i += 1,
+                        b'"' if in_quotes && quoted => break,
+                        b'"' => in_quotes = !in_quotes,
+                        b'\n' if in_quotes => line += 1,
+                        b' ' | b'\t' | b'\r' | b'\n' | b';' | b'(' | b')' if !in_quotes => break,
+                        _ => (),
+                    }
+                    i += 1;
+                }
+                if i > b.len() || (in_quotes && i == b.len()) {
+                    bail!("unterminated string at line {}", start_line);
+                }
+                let token = Token {
+                    text: &text[start..i],
+                    quoted,
+                };
+                if quoted {
+                    i += 1;
+                }
+                current
+                    .get_or_insert_with(|| Entry {
+                        line: start_line,
+                        blank_owner,
+                        tokens: vec![],
+                    })
+                    .tokens
+                    .push(token);
+            }
+        }
+    }
+
+    if depth != 0 {
+        bail!("unbalanced parentheses at line {}", line);
+    }
+    entries.extend(current.take());
+
+    Ok(entries)
+}
+
+/// The cursor of tokens in an entry.
+struct Fields<'a> {
+    tokens: &'a [Token<'a>],
+    pos: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(tokens: &'a [Token<'a>]) -> Self {
+        Self { tokens, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<&'a Token<'a>> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next_if_any(&mut self) -> Option<&'a Token<'a>> {
+        let next = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(next)
+    }
+
+    fn next(&mut self, what: &str) -> crate::Result<&'a Token<'a>> {
+        self.next_if_any()
+            .ok_or_else(|| anyhow!("missing {}", what))
+    }
+
+    fn parse<T>(&mut self, what: &str) -> crate::Result<T>
+    where
+        T: FromStr,
+    {
+        let next = self.next(what)?.text;
+        next.parse::<T>()
+            .map_err(|_| anyhow!("invalid {} '{}'", what, next))
+    }
+
+    /// Returns the next <character-string> which must be in UTF-8.
+    fn string(&mut self, what: &str) -> crate::Result<Cachestr> {
+        let b = unescape(self.next(what)?.text)?;
+        if b.len() > u8::MAX as usize {
+            bail!("too long {}: {} bytes", what, b.len());
+        }
+        String::from_utf8(b)
+            .map(Cachestr::from)
+            .map_err(|_| anyhow!("invalid {} which is not in UTF-8", what))
+    }
+
+    /// Returns the hex of the rest tokens, which may be separated by blanks.
+    fn hex(&mut self, what: &str) -> crate::Result<Vec<u8>> {
+        let s = self.rest().concat();
+        if s.is_empty() {
+            bail!("missing {}", what);
+        }
+        hex::decode(&s).map_err(|_| anyhow!("invalid hex of {}", what))
+    }
+
+    fn rest(&mut self) -> Vec<&'a str> {
+        let rest = self.tokens[self.pos..].iter().map(|it| it.text).collect();
+        self.pos = self.tokens.len();
+        rest
+    }
+
+    fn end(&self) -> crate::Result<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(next) => bail!("unexpected '{}'", next.text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Message, MessageOwned};
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    const ZONE: &str = r#"
+$ORIGIN example.com.
+$TTL 1h ; the default ttl
+@       IN  SOA ns1 hostmaster.example.com. (
+                2024010101 ; serial
+                2h         ; refresh
+                15m        ; retry
+                1w         ; expire
+                300 )      ; minimum
+        IN  NS  ns1
+        IN  NS  ns2.example.net.
+        IN  MX  10 mail
+ns1     300 IN  A   192.0.2.1
+        IN  300 AAAA 2001:db8::1
+mail        A   192.0.2.2
+www         CNAME @
+*.dev       A   192.0.2.3
+txt         TXT "v=spf1 -all" "a \"quoted\" \059 text" plain
+_sip._tcp   SRV 10 60 5060 sip
+caa         CAA 0 issue "letsencrypt.org"
+naptr       NAPTR 100 10 "S" "SIP+D2U" "" _sip._udp
+_443._tcp   TLSA 3 1 1 ( 0D6FCE1F0C6FC4C4
+                         A0AD8F5C6E6C9E44 )
+ssh         SSHFP 4 2 123456789abcdef67890123456789abcdef67890123456789abcdef123456789
+svc         HTTPS 1 . alpn="h2,h3" port=8443 ipv4hint=192.0.2.1,192.0.2.2
+loc         LOC 52 22 23.000 N 4 53 32.000 E -2.00m 0.00m 10000m 10m
+ds          DS  \# 4 0A0B0C0D
+$ORIGIN sub.example.com.
+host        A   192.0.2.4
+"#;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        init();
+
+        let records = ZoneParser::new().parse(ZONE)?;
+        for next in &records {
+            info!("{}", next);
+        }
+        assert_eq!(19, records.len());
+
+        let soa = &records[0];
+        assert_eq!("example.com", soa.name.as_ref());
+        assert_eq!(3600, soa.ttl);
+        assert_eq!(
+            RDataOwned::SOA {
+                primary_nameserver: Cachestr::from("ns1.example.com"),
+                responsible_authority_mailbox: Cachestr::from("hostmaster.example.com"),
+                serial_number: 2024010101,
+                refresh_interval: 7200,
+                retry_interval: 900,
+                expire_limit: 604800,
+                minimum_ttl: 300,
+            },
+            soa.data
+        );
+
+        let find = |name: &str, kind: Kind| {
+            records
+                .iter()
+                .find(|it| it.name.as_ref() == name && it.kind == kind)
+                .unwrap_or_else(|| panic!("no {} record of {}", kind, name))
+        };
+
+        assert_eq!(
+            RDataOwned::NS(Cachestr::from("ns2.example.net")),
+            records[2].data
+        );
+        assert_eq!("example.com", records[2].name.as_ref());
+
+        let aaaa = find("ns1.example.com", Kind::AAAA);
+        assert_eq!(300, aaaa.ttl);
+        assert_eq!(Class::IN, aaaa.class);
+
+        assert_eq!(3600, find("mail.example.com", Kind::A).ttl);
+        assert_eq!(
+            RDataOwned::CNAME(Cachestr::from("example.com")),
+            find("www.example.com", Kind::CNAME).data
+        );
+        assert_eq!(
+            RDataOwned::A(Ipv4Addr::new(192, 0, 2, 3)),
+            find("*.dev.example.com", Kind::A).data
+        );
+        assert_eq!(
+            RDataOwned::TXT(vec![
+                Cachestr::from("v=spf1 -all"),
+                Cachestr::from("a \"quoted\" ; text"),
+                Cachestr::from("plain"),
+            ]),
+            find("txt.example.com", Kind::TXT).data
+        );
+        assert_eq!(
+            RDataOwned::SRV {
+                priority: 10,
+                weight: 60,
+                port: 5060,
+                target: Cachestr::from("sip.example.com"),
+            },
+            find("_sip._tcp.example.com", Kind::SRV).data
+        );
+        assert_eq!(
+            RDataOwned::TLSA {
+                usage: 3,
+                selector: 1,
+                matching_type: 1,
+                data: hex::decode("0D6FCE1F0C6FC4C4A0AD8F5C6E6C9E44")?,
+            },
+            find("_443._tcp.example.com", Kind::TLSA).data
+        );
+        assert_eq!(
+            RDataOwned::HTTPS {
+                priority: 1,
+                target_name: Cachestr::from("."),
+                params: vec![
+                    (SvcParamKey::ALPN, b"\x02h2\x02h3".to_vec()),
+                    (SvcParamKey::PORT, vec![0x20, 0xfb]),
+                    (SvcParamKey::IPV4HINT, vec![192, 0, 2, 1, 192, 0, 2, 2]),
+                ],
+            },
+            find("svc.example.com", Kind::HTTPS).data
+        );
+        assert_eq!(
+            RDataOwned::UNKNOWN(vec![0x0a, 0x0b, 0x0c, 0x0d]),
+            find("ds.example.com", Kind::DS).data
+        );
+        find("host.sub.example.com", Kind::A);
+
+        // the initial origin with or without the trailing dot
+        for origin in ["example.org", "example.org."] {
+            let records = ZoneParser::new().origin(origin).parse("@ 60 A 192.0.2.1")?;
+            assert_eq!("example.org", &records[0].name[..]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        init();
+
+        let records = ZoneParser::new().parse(ZONE)?;
+
+        // zone text -> records -> zone text -> records
+        let text = records
+            .iter()
+            .map(|it| it.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(records, ZoneParser::new().parse(&text)?);
+
+        // records -> wire -> records
+        let msg = MessageOwned {
+            answers: records,
+            ..Default::default()
+        };
+        let wire = msg.build()?;
+        assert!(wire.validate().is_ok());
+        let decoded = MessageOwned::try_from(&wire)?;
+        let zone = decoded.to_zone();
+        info!("{}", zone);
+        assert_eq!(msg.answers, ZoneParser::new().parse(&zone)?);
+
+        // the LOC is written in the same form as the wire one
+        let loc = msg.answers.iter().find(|it| it.kind == Kind::LOC).unwrap();
+        assert_eq!(
+            "52 22 23.000 N 4 53 32.000 E -2m 0m 10000m 10m",
+            loc.data.to_string()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_to_zone() -> anyhow::Result<()> {
+        init();
+
+        let msg = Message::from(hex::decode("f2508180000100020000000105626169647503636f6d0000010001c00c00010001000000b70004279c420ac00c00010001000000b700046ef244420000290580000000000000")?);
+        let zone = MessageOwned::try_from(&msg)?.to_zone();
+        assert_eq!(
+            ";baidu.com.\tIN\tA\nbaidu.com.\t183\tIN\tA\t39.156.66.10\nbaidu.com.\t183\tIN\tA\t110.242.68.66\n",
+            zone
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_include() -> anyhow::Result<()> {
+        init();
+
+        let dir = std::env::temp_dir().join(format!("zerodns-zone-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(
+            dir.join("hosts.zone"),
+            "$TTL 60\nhost1 A 192.0.2.1\n$ORIGIN other.com.\nhost2 A 192.0.2.2\n",
+        )?;
+        std::fs::write(
+            dir.join("main.zone"),
+            "$ORIGIN example.com.\n$TTL 300\n$INCLUDE hosts.zone lan.example.com.\nwww A 192.0.2.3\n",
+        )?;
+
+        let records = ZoneParser::new().parse_file(dir.join("main.zone"));
+        std::fs::remove_dir_all(&dir).ok();
+        let records = records?;
+
+        assert_eq!(
+            vec![
+                ("host1.lan.example.com", 60),
+                ("host2.other.com", 60),
+                ("www.example.com", 300),
+            ],
+            records
+                .iter()
+                .map(|it| (it.name.as_ref(), it.ttl))
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        init();
+
+        for (text, reason) in [
+            ("www A 192.0.2.1", "no origin of relative name"),
+            ("www.example.com. A 192.0.2.1", "no TTL"),
+            ("$TTL 60\nwww.example.com. A 192.0.2.256", "invalid address"),
+            (
+                "$TTL 60\nwww.example.com. A 192.0.2.1 extra",
+                "unexpected 'extra'",
+            ),
+            (
+                "$TTL 60\nwww.example.com. DS 1 2 3 abcd",
+                "unsupported rdata",
+            ),
+            (
+                "$TTL 60\nwww.example.com. DS \\# 3 0a0b",
+                "mismatched rdata length",
+            ),
+            (
+                "$TTL 60\nwww.example.com. TXT \"unterminated",
+                "unterminated string",
+            ),
+            (
+                "$TTL 60\nwww.example.com. MX ( 10 mail.example.com.",
+                "unbalanced",
+            ),
+            ("$TTL 1x\n", "invalid ttl"),
+            ("$UNKNOWN\n", "unsupported directive"),
+            ("  A 192.0.2.1", "no owner name"),
+        ] {
+            let err = ZoneParser::new().parse(text).expect_err(text);
+            assert!(
+                err.to_string().contains(reason),
+                "{}: expect '{}', got '{}'",
+                text,
+                reason,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_ttl() {
+        init();
+
+        for (s, ttl) in [
+            ("0", 0),
+            ("3600", 3600),
+            ("1h", 3600),
+            ("1H30m", 5400),
+            ("1w2d", 777600),
+        ] {
+            assert_eq!(ttl, parse_ttl(s).unwrap(), "{}", s);
+        }
+        for s in ["", "h", "1h30", "99999999999", "1y"] {
+            assert!(parse_ttl(s).is_err(), "{}", s);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::config::AclConfig;
+use crate::misc::ipset::IpSet;
+use crate::Result;
+
+/// The action of the rejected clients.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub(crate) enum RejectAction {
+    /// Answers REFUSED.
+    #[default]
+    Refused,
+    /// Drops the request silently.
+    Drop,
+}
+
+impl FromStr for RejectAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "refused" => Ok(Self::Refused),
+            "drop" => Ok(Self::Drop),
+            other => bail!("invalid reject action '{}'", other),
+        }
+    }
+}
+
+/// The access control list of clients, the denied networks take precedence over the allowed
+/// networks, and all clients are allowed if no allowed network is given.
+#[derive(Debug, Default)]
+pub(crate) struct Acl {
+    allow: Option<IpSet>,
+    deny: IpSet,
+    action: RejectAction,
+    rejections: AtomicU64,
+}
+
+impl Acl {
+    pub(crate) fn is_allowed(&self, addr: IpAddr) -> bool {
+        if self.deny.contains(addr) {
+            return false;
+        }
+        match &self.allow {
+            Some(allow) => allow.contains(addr),
+            None => true,
+        }
+    }
+
+    /// Counts a rejected request, and returns how to reject it.
+    pub(crate) fn reject(&self, peer: SocketAddr) -> RejectAction {
+        let n = self.rejections.fetch_add(1, Ordering::Relaxed) + 1;
+        debug!("reject the request from {}", peer);
+        // avoid flooding the log when under abuse
+        if n.is_power_of_two() {
+            warn!(
+                "{} requests have been rejected by acl, the latest one is from {}",
+                n, peer
+            );
+        }
+        self.action
+    }
+
+    /// Returns how to reject the clients.
+    pub(crate) fn action(&self) -> RejectAction {
+        self.action
+    }
+
+    /// Returns the count of rejected requests.
+    pub(crate) fn rejections(&self) -> u64 {
+        self.rejections.load(Ordering::Relaxed)
+    }
+}
+
+impl TryFrom<&AclConfig> for Acl {
+    type Error = anyhow::Error;
+
+    fn try_from(c: &AclConfig) -> Result<Self> {
+        fn ipset(networks: &[String]) -> Result<IpSet> {
+            let mut bu = IpSet::builder();
+            for next in networks {
+                bu.insert(next)
+                    .map_err(|e| anyhow!("invalid acl network '{}': {}", next, e))?;
+            }
+            Ok(bu.build())
+        }
+
+        let allow = match c.allow.as_deref() {
+            Some(allow) if !allow.is_empty() => Some(ipset(allow)?),
+            _ => None,
+        };
+        let deny = ipset(c.deny.as_deref().unwrap_or_default())?;
+        let action = match &c.reject {
+            Some(s) => s.parse::<RejectAction>()?,
+            None => RejectAction::default(),
+        };
+
+        Ok(Self {
+            allow,
+            deny,
+            action,
+            rejections: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    #[test]
+    fn test_acl() {
+        init();
+
+        let c: AclConfig = toml::from_str(
+            r#"
+            allow = ["192.168.0.0/16", "127.0.0.1"]
+            deny = ["192.168.100.0/24"]
+            reject = "drop"
+            "#,
+        )
+        .unwrap();
+        let acl = Acl::try_from(&c).unwrap();
+
+        for (addr, ok) in [
+            ("127.0.0.1", true),
+            ("192.168.1.1", true),
+            ("192.168.100.1", false),
+            ("10.0.0.1", false),
+        ] {
+            assert_eq!(ok, acl.is_allowed(addr.parse().unwrap()), "{}", addr);
+        }
+
+        let peer = "10.0.0.1:5353".parse().unwrap();
+        assert_eq!(RejectAction::Drop, acl.reject(peer));
+        assert_eq!(RejectAction::Drop, acl.reject(peer));
+        assert_eq!(2, acl.rejections());
+
+        // allow all by default
+        let acl = Acl::try_from(&AclConfig::default()).unwrap();
+        assert!(acl.is_allowed("8.8.8.8".parse().unwrap()));
+        assert_eq!(RejectAction::Refused, acl.reject(peer));
+
+        // the overrides of listener
+        let overrides: AclConfig = toml::from_str("allow = []").unwrap();
+        let acl = Acl::try_from(&c.merge(Some(&overrides))).unwrap();
+        assert!(acl.is_allowed("10.0.0.1".parse().unwrap()));
+        assert!(!acl.is_allowed("192.168.100.1".parse().unwrap()));
+
+        for bad in [r#"allow = ["10.0.0.0/33"]"#, r#"reject = "ignore""#] {
+            let c: AclConfig = toml::from_str(bad).unwrap();
+            assert!(Acl::try_from(&c).is_err(), "{}", bad);
+        }
+    }
+}
@@ -1,9 +1,10 @@
-use crate::cache::{LoadingCache, LoadingCacheExt};
+use crate::cache::{Loaded, LoadingCache, LoadingCacheExt};
 use crate::error::Error;
-use crate::filter::Context;
+use crate::filter::{Context, ContextFlags};
 use crate::handler::Handler;
-use crate::protocol::{Flags, Message, RCode};
+use crate::protocol::{Flags, Message, OpCode, RCode};
 use crate::{Error as ZError, Result};
+use parking_lot::Mutex;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -85,55 +86,322 @@ fn convert_error_to_message(
     bu.build().unwrap()
 }
 
+/// Builds the FORMERR response of a malformed request, returns None if it shouldn't be answered,
+/// eg: the header is broken, or it's a response.
+pub(super) fn format_error(req: &Message, err: &anyhow::Error) -> Option<Message> {
+    if req.len() < 12 || req.flags().is_response() {
+        return None;
+    }
+
+    debug!("malformed dns request 0x{:04x}: {}", req.id(), err);
+
+    // the header is not validated, so read the opcode leniently
+    let rflags = req.flags();
+    let opcode =
+        OpCode::try_from((rflags.as_u16() >> 11) & 0x000f).unwrap_or(OpCode::StandardQuery);
+    let flags = {
+        let mut bu = Flags::builder()
+            .response()
+            .opcode(opcode)
+            .rcode(RCode::FormatError);
+        if rflags.is_recursive_query() {
+            bu = bu.recursive_query(true);
+            bu = bu.recursive_available(true);
+        }
+        bu.build()
+    };
+
+    Message::builder().id(req.id()).flags(flags).build().ok()
+}
+
+/// Builds the REFUSED response of a rejected request, returns None if it shouldn't be answered.
+pub(super) fn refused(req: &Message) -> Option<Message> {
+    empty_response(req, RCode::Refused, false)
+}
+
+/// Builds the empty truncated response of a rate limited request, so that the client will retry
+/// over tcp.
+pub(super) fn truncated(req: &Message) -> Option<Message> {
+    empty_response(req, RCode::NoError, true)
+}
+
+fn empty_response(req: &Message, rcode: RCode, truncated: bool) -> Option<Message> {
+    if req.len() < 12 || req.flags().is_response() {
+        return None;
+    }
+
+    let rflags = req.flags();
+    let opcode =
+        OpCode::try_from((rflags.as_u16() >> 11) & 0x000f).unwrap_or(OpCode::StandardQuery);
+    let flags = Flags::builder()
+        .response()
+        .opcode(opcode)
+        .rcode(rcode)
+        .truncated(truncated)
+        .recursive_query(rflags.is_recursive_query())
+        .build();
+
+    let mut bu = Message::builder().id(req.id()).flags(flags);
+    if req.validate().is_ok() {
+        for next in req.questions() {
+            bu = bu.raw_question(next);
+        }
+    }
+    bu.build().ok()
+}
+
+#[inline]
+fn new_context(peer: SocketAddr, tcp: bool) -> Context {
+    let mut ctx = Context::default();
+    ctx.peer.replace(peer);
+    ctx.tcp = tcp;
+    ctx
+}
+
+fn is_dropped(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<ZError>(), Some(ZError::Dropped))
+}
+
 #[inline]
-async fn handle_<H>(peer: SocketAddr, req: &Message, h: Arc<H>) -> Result<Message>
+async fn handle_<H>(
+    peer: SocketAddr,
+    tcp: bool,
+    req: &Message,
+    h: Arc<H>,
+) -> Result<(Message, Context)>
 where
     H: Handler,
 {
     let mut req = Clone::clone(req);
-    let mut ctx = Context::default();
-    ctx.peer.replace(peer);
+    let mut ctx = new_context(peer, tcp);
+
+    let res = h.handle(&mut ctx, &mut req).await?;
+
+    if ctx.flags.contains(ContextFlags::DROP) {
+        bail!(ZError::Dropped);
+    }
+
+    let res = res.ok_or_else(|| anyhow!(ZError::ResolveNothing))?;
 
-    h.handle(&mut ctx, &mut req)
-        .await?
-        .ok_or_else(|| anyhow!(ZError::ResolveNothing))
+    Ok((res, ctx))
+}
+
+/// The response of a request, and how it is answered.
+pub(super) struct Handled {
+    pub(super) res: Message,
+    pub(super) cached: bool,
+    pub(super) upstream: Option<String>,
+    /// The filters decide to drop the request, so the response shouldn't be sent.
+    pub(super) dropped: bool,
+}
+
+impl Handled {
+    /// Writes the answers into the access log, which are tagged by the cache or the upstream.
+    pub(super) fn log(&self) {
+        if self.dropped {
+            info!("0x{:04x} <- <DROP>", self.res.id());
+            return;
+        }
+        let tag = if self.cached {
+            Some("\t<CACHE>".to_string())
+        } else {
+            self.upstream.as_ref().map(|it| format!("\t<{}>", it))
+        };
+        for next in self.res.answers() {
+            if let Ok(rdata) = next.rdata() {
+                info!(
+                    "0x{:04x} <- {}.\t{}\t{:?}\t{:?}\t{}{}",
+                    self.res.id(),
+                    next.name(),
+                    next.time_to_live(),
+                    next.class(),
+                    next.kind(),
+                    rdata,
+                    tag.as_deref().unwrap_or_default(),
+                );
+            }
+        }
+    }
 }
 
 pub(super) async fn handle<H, C>(
     peer: SocketAddr,
+    tcp: bool,
     req: Message,
     h: Arc<H>,
     cache: Option<Arc<C>>,
-) -> (Message, bool)
+) -> Handled
 where
     H: Handler,
     C: LoadingCache,
 {
     if let Err(e) = validate_request(&req) {
-        return (convert_error_to_message(&req, e, false), false);
+        return Handled {
+            res: convert_error_to_message(&req, e, false),
+            cached: false,
+            upstream: None,
+            dropped: false,
+        };
     }
 
-    let (res, cached) = match cache.as_deref() {
-        None => (handle_(peer, &req, h).await, false),
+    let (res, cached, upstream) = match cache.as_deref() {
+        None => match handle_(peer, tcp, &req, h).await {
+            Ok((res, ctx)) => (Ok(res), false, ctx.upstream),
+            Err(e) => (Err(e), false, None),
+        },
         Some(lc) => {
+            let ns = h
+                .view(&new_context(peer, tcp))
+                .unwrap_or_default()
+                .to_string();
             let cached = Arc::new(AtomicBool::new(true));
+            let dropped = Arc::new(AtomicBool::new(false));
+            let upstream = Arc::new(Mutex::new(None));
 
             let res = {
                 let req = Clone::clone(&req);
+                let h = Clone::clone(&h);
                 let cached = Clone::clone(&cached);
-                lc.try_get_with_fixed(req, move |req| {
+                let dropped = Clone::clone(&dropped);
+                let upstream = Clone::clone(&upstream);
+                lc.try_get_with_fixed(&ns, req, move |req| {
                     cached.store(false, Ordering::SeqCst);
-                    async move { handle_(peer, &req, h).await }
+                    async move {
+                        let (res, ctx) = match handle_(peer, tcp, &req, h).await {
+                            Ok(it) => it,
+                            Err(e) => {
+                                if is_dropped(&e) {
+                                    dropped.store(true, Ordering::SeqCst);
+                                }
+                                return Err(e);
+                            }
+                        };
+                        *upstream.lock() = ctx.upstream;
+                        // the filters refuse to cache the response, eg: it depends on the client address
+                        if ctx.flags.contains(ContextFlags::NO_CACHE) {
+                            Ok(Loaded::Private(res))
+                        } else {
+                            Ok(Loaded::Shared(res))
+                        }
+                    }
                 })
                 .await
             };
 
-            (res, cached.load(Ordering::Relaxed))
+            // the response is private to the client which loaded it, resolve it by ourselves
+            let res = match res {
+                Ok(Some(res)) => Ok(res),
+                Ok(None) => {
+                    cached.store(false, Ordering::SeqCst);
+                    match handle_(peer, tcp, &req, h).await {
+                        Ok((res, ctx)) => {
+                            *upstream.lock() = ctx.upstream;
+                            Ok(res)
+                        }
+                        Err(e) => {
+                            if is_dropped(&e) {
+                                dropped.store(true, Ordering::SeqCst);
+                            }
+                            Err(e)
+                        }
+                    }
+                }
+                Err(e) => Err(e),
+            };
+
+            // the error of cache loader is wrapped, so check the flag instead
+            let res = if dropped.load(Ordering::SeqCst) {
+                Err(anyhow!(ZError::Dropped))
+            } else {
+                res
+            };
+
+            let upstream = upstream.lock().take();
+            (res, cached.load(Ordering::Relaxed), upstream)
         }
     };
 
     match res {
-        Ok(msg) => (msg, cached),
-        Err(e) => (convert_error_to_message(&req, e, true), cached),
+        Ok(res) => Handled {
+            res,
+            cached,
+            upstream,
+            dropped: false,
+        },
+        Err(e) if is_dropped(&e) => Handled {
+            res: req,
+            cached,
+            upstream: None,
+            dropped: true,
+        },
+        Err(e) => Handled {
+            res: convert_error_to_message(&req, e, true),
+            cached,
+            upstream: None,
+            dropped: false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::MemoryLoadingCache;
+    use crate::protocol::{Class, Kind};
+    use std::sync::atomic::AtomicU64;
+
+    /// Answers the TCP requests only like the TCP-only action of RPZ.
+    struct TcpOnlyHandler(Arc<AtomicU64>);
+
+    #[async_trait::async_trait]
+    impl Handler for TcpOnlyHandler {
+        async fn handle(&self, ctx: &mut Context, req: &mut Message) -> Result<Option<Message>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            ctx.flags.insert(ContextFlags::NO_CACHE);
+            let flags = Flags::builder().response().truncated(!ctx.is_tcp()).build();
+            let res = Message::builder()
+                .id(req.id())
+                .flags(flags)
+                .question("example.com", Kind::A, Class::IN)
+                .build()?;
+            Ok(Some(res))
+        }
+    }
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    #[tokio::test]
+    async fn test_handle_nocache() -> anyhow::Result<()> {
+        init();
+
+        let cnt = Arc::new(AtomicU64::new(0));
+        let h = Arc::new(TcpOnlyHandler(Clone::clone(&cnt)));
+        let cache = Arc::new(MemoryLoadingCache::builder().build());
+        let peer = "127.0.0.1:5353".parse::<SocketAddr>()?;
+
+        let req = Message::builder()
+            .id(0x1234)
+            .flags(Flags::builder().request().recursive_query(true).build())
+            .question("example.com", Kind::A, Class::IN)
+            .build()?;
+
+        // the truncated response of udp never leaks to tcp, and vice versa
+        for tcp in [false, true, false, true] {
+            let handled = handle(
+                peer,
+                tcp,
+                Clone::clone(&req),
+                Clone::clone(&h),
+                Some(Clone::clone(&cache)),
+            )
+            .await;
+            assert!(!handled.cached);
+            assert_eq!(!tcp, handled.res.flags().is_message_truncated());
+        }
+        assert_eq!(4, cnt.load(Ordering::SeqCst));
+
+        Ok(())
     }
 }
@@ -0,0 +1,281 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use moka::sync::Cache;
+use parking_lot::Mutex;
+
+use crate::config::RateLimitConfig;
+use crate::Result;
+
+/// The max count of buckets of each limit, the idle buckets are evicted since they are full.
+const MAX_BUCKETS: u64 = 65536;
+
+const DEFAULT_IPV4_PREFIX: u8 = 24;
+const DEFAULT_IPV6_PREFIX: u8 = 56;
+const DEFAULT_SLIP: u32 = 2;
+
+/// The verdict of a request.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Verdict {
+    Pass,
+    /// Over the limit, answers an empty truncated response so that the client retries over tcp.
+    Slip,
+    /// Over the limit, drops the request silently.
+    Drop,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    /// The count of limited requests, which decides the slipped ones.
+    limited: u64,
+}
+
+/// A token bucket limit of the keys.
+struct Limit {
+    rate: f64,
+    burst: f64,
+    buckets: Cache<IpAddr, Arc<Mutex<Bucket>>>,
+}
+
+impl Limit {
+    fn new(rate: u32, burst: Option<u32>) -> Result<Self> {
+        if rate == 0 {
+            bail!("invalid rate limit: rate must be positive");
+        }
+        let rate = rate as f64;
+        let burst = match burst {
+            Some(0) => bail!("invalid rate limit: burst must be positive"),
+            Some(burst) => burst as f64,
+            None => rate,
+        };
+
+        // a bucket is full again after idle for a while, so it's safe to be evicted.
+        let idle = Duration::from_secs_f64(burst / rate) + Duration::from_secs(1);
+        let buckets = Cache::builder()
+            .max_capacity(MAX_BUCKETS)
+            .time_to_idle(idle)
+            .build();
+
+        Ok(Self {
+            rate,
+            burst,
+            buckets,
+        })
+    }
+
+    fn bucket(&self, key: IpAddr, now: Instant) -> Arc<Mutex<Bucket>> {
+        self.buckets.get_with(key, || {
+            Arc::new(Mutex::new(Bucket {
+                tokens: self.burst,
+                updated_at: now,
+                limited: 0,
+            }))
+        })
+    }
+
+    /// Refills the bucket, and returns whether it has a token.
+    fn refill(&self, bucket: &mut Bucket, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(bucket.updated_at)
+            .as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated_at = now;
+        bucket.tokens >= 1.0
+    }
+}
+
+/// The rate limiter of clients, which limits both the client address and its network prefix.
+/// The over-limit requests are dropped, but every 'slip' one is answered with TC=1 like the
+/// Response Rate Limiting of BIND, 0 means dropping all of them.
+pub(crate) struct RateLimiter {
+    client: Option<Limit>,
+    prefix: Option<Limit>,
+    ipv4_prefix: u8,
+    ipv6_prefix: u8,
+    slip: u32,
+    limited: AtomicU64,
+}
+
+impl RateLimiter {
+    pub(crate) fn check(&self, peer: SocketAddr) -> Verdict {
+        let now = Instant::now();
+        let addr = peer.ip();
+
+        let client = self.client.as_ref().map(|it| (it, it.bucket(addr, now)));
+        let prefix = self
+            .prefix
+            .as_ref()
+            .map(|it| (it, it.bucket(self.network(addr), now)));
+
+        // the tokens are taken only if both of the buckets pass
+        let mut buckets = client
+            .iter()
+            .chain(prefix.iter())
+            .map(|(limit, bucket)| (limit, bucket.lock()))
+            .collect::<Vec<_>>();
+        let rejected = buckets
+            .iter_mut()
+            .position(|(limit, bucket)| !limit.refill(bucket, now));
+        let rejected = match rejected {
+            Some(i) => &mut buckets[i].1,
+            None => {
+                for (_, bucket) in buckets.iter_mut() {
+                    bucket.tokens -= 1.0;
+                }
+                return Verdict::Pass;
+            }
+        };
+
+        // the requests slip in turn of each bucket like RRL
+        rejected.limited += 1;
+        let slipped = self.slip != 0 && rejected.limited % self.slip as u64 == 0;
+
+        let n = self.limited.fetch_add(1, Ordering::Relaxed) + 1;
+        debug!("rate limit the request from {}", peer);
+        // avoid flooding the log when under attack
+        if n.is_power_of_two() {
+            warn!(
+                "{} requests have been rate limited, the latest one is from {}",
+                n, peer
+            );
+        }
+
+        if slipped {
+            Verdict::Slip
+        } else {
+            Verdict::Drop
+        }
+    }
+
+    /// Returns the count of rate limited requests.
+    pub(crate) fn limited(&self) -> u64 {
+        self.limited.load(Ordering::Relaxed)
+    }
+
+    fn network(&self, addr: IpAddr) -> IpAddr {
+        match addr {
+            IpAddr::V4(v4) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.ipv4_prefix as u32)
+                    .unwrap_or(0);
+                IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask))
+            }
+            IpAddr::V6(v6) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.ipv6_prefix as u32)
+                    .unwrap_or(0);
+                IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask))
+            }
+        }
+    }
+}
+
+impl TryFrom<&RateLimitConfig> for RateLimiter {
+    type Error = anyhow::Error;
+
+    fn try_from(c: &RateLimitConfig) -> Result<Self> {
+        let client = match c.client_rate {
+            Some(rate) => Some(Limit::new(rate, c.client_burst)?),
+            None => None,
+        };
+        let prefix = match c.prefix_rate {
+            Some(rate) => Some(Limit::new(rate, c.prefix_burst)?),
+            None => None,
+        };
+
+        let ipv4_prefix = c.ipv4_prefix.unwrap_or(DEFAULT_IPV4_PREFIX);
+        if ipv4_prefix > 32 {
+            bail!("invalid ipv4 prefix {}", ipv4_prefix);
+        }
+        let ipv6_prefix = c.ipv6_prefix.unwrap_or(DEFAULT_IPV6_PREFIX);
+        if ipv6_prefix > 128 {
+            bail!("invalid ipv6 prefix {}", ipv6_prefix);
+        }
+
+        Ok(Self {
+            client,
+            prefix,
+            ipv4_prefix,
+            ipv6_prefix,
+            slip: c.slip.unwrap_or(DEFAULT_SLIP),
+            limited: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init() {
+        pretty_env_logger::try_init_timed().ok();
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        init();
+
+        let c: RateLimitConfig = toml::from_str(
+            r#"
+            client_rate = 1
+            client_burst = 5
+            prefix_rate = 1
+            prefix_burst = 8
+            slip = 2
+            "#,
+        )
+        .unwrap();
+        let limiter = RateLimiter::try_from(&c).unwrap();
+
+        let first = "192.168.1.1:5353".parse().unwrap();
+        for _ in 0..5 {
+            assert_eq!(Verdict::Pass, limiter.check(first));
+        }
+        // every 2nd over-limit request slips
+        assert_eq!(Verdict::Drop, limiter.check(first));
+        assert_eq!(Verdict::Slip, limiter.check(first));
+
+        // the other client of the same prefix is limited by the rest tokens of prefix
+        let second = "192.168.1.2:5353".parse().unwrap();
+        for _ in 0..3 {
+            assert_eq!(Verdict::Pass, limiter.check(second));
+        }
+        // the slips are counted by the bucket which limits the request
+        assert_eq!(Verdict::Drop, limiter.check(second));
+        assert_eq!(Verdict::Slip, limiter.check(second));
+
+        // the client tokens are kept when the prefix bucket rejects
+        let tokens = {
+            let client = limiter.client.as_ref().unwrap();
+            let bucket = client.buckets.get(&second.ip()).unwrap();
+            let tokens = bucket.lock().tokens;
+            tokens
+        };
+        assert!((2.0..3.0).contains(&tokens), "{}", tokens);
+
+        // the other prefix is not affected
+        assert_eq!(
+            Verdict::Pass,
+            limiter.check("192.168.2.1:5353".parse().unwrap())
+        );
+        assert_eq!(4, limiter.limited());
+
+        assert_eq!(
+            "2001:db8:0:ff00::".parse::<IpAddr>().unwrap(),
+            limiter.network("2001:db8:0:ffff::1".parse().unwrap())
+        );
+
+        for bad in [
+            "client_rate = 0",
+            "client_rate = 1\nclient_burst = 0",
+            "ipv4_prefix = 33",
+        ] {
+            let c: RateLimitConfig = toml::from_str(bad).unwrap();
+            assert!(RateLimiter::try_from(&c).is_err(), "{}", bad);
+        }
+    }
+}
@@ -1,6 +1,10 @@
+mod acl;
 mod helper;
+mod limit;
 mod tcp;
 mod udp;
 
+pub(crate) use acl::Acl;
+pub(crate) use limit::RateLimiter;
 pub use tcp::TcpServer;
 pub use udp::UdpServer;
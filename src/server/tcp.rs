@@ -1,22 +1,30 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use futures::{SinkExt, StreamExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::Notify;
+use tokio::sync::{Notify, Semaphore};
 use tokio_util::codec::{FramedRead, FramedWrite};
 
+use super::acl::{Acl, RejectAction};
 use crate::cache::LoadingCache;
 use crate::handler::Handler;
-use crate::protocol::Codec;
+use crate::protocol::{Codec, EdnsOption};
 use crate::Result;
 
+/// The idle timeout of tcp connections, which is advertised by the EDNS TCP keepalive option,
+/// see RFC 7828.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub struct TcpServer<H, C> {
     h: H,
     listener: TcpListener,
     cache: Option<Arc<C>>,
     closer: Arc<Notify>,
     addr: SocketAddr,
+    acl: Option<Arc<Acl>>,
+    inflight: Option<Arc<Semaphore>>,
 }
 
 impl<H, C> TcpServer<H, C> {
@@ -33,8 +41,22 @@ impl<H, C> TcpServer<H, C> {
             listener,
             cache,
             closer,
+            acl: None,
+            inflight: None,
         }
     }
+
+    /// Sets the access control list of clients, which is checked before handling requests.
+    pub(crate) fn acl(mut self, acl: Acl) -> Self {
+        self.acl.replace(Arc::new(acl));
+        self
+    }
+
+    /// Sets the permits of in-flight requests, the requests wait until a permit is available.
+    pub(crate) fn inflight(mut self, inflight: Arc<Semaphore>) -> Self {
+        self.inflight.replace(inflight);
+        self
+    }
 }
 
 impl<H, C> TcpServer<H, C>
@@ -49,6 +71,8 @@ where
             listener,
             cache,
             closer,
+            acl,
+            inflight,
         } = self;
         let h = Arc::new(h);
 
@@ -58,16 +82,33 @@ where
             tokio::select! {
                 accept = listener.accept() => {
                     let (stream, addr) = accept?;
+
+                    // the rejected clients are closed at once, or refused on every request
+                    let rejected = match &acl {
+                        Some(acl) if !acl.is_allowed(addr.ip()) => {
+                            if let RejectAction::Drop = acl.action() {
+                                acl.reject(addr);
+                                continue;
+                            }
+                            Some(Clone::clone(acl))
+                        }
+                        _ => None,
+                    };
+
                     let h = Clone::clone(&h);
                     let cache = Clone::clone(&cache);
+                    let inflight = Clone::clone(&inflight);
                     tokio::spawn(async move {
-                        if let Err(e) = Self::handle(stream, addr, h, cache).await {
+                        if let Err(e) = Self::handle(stream, addr, h, cache, rejected, inflight).await {
                             error!("failed to handle tcp stream: {:?}", e);
                         }
                     });
                 }
                 () = closer.notified() => {
                     info!("close signal is received, tcp dns server is stopping...");
+                    if let Some(acl) = &acl {
+                        info!("{} requests have been rejected by tcp dns server", acl.rejections());
+                    }
                     break;
                 }
             }
@@ -81,46 +122,68 @@ where
         addr: SocketAddr,
         handler: Arc<H>,
         cache: Option<Arc<C>>,
+        rejected: Option<Arc<Acl>>,
+        inflight: Option<Arc<Semaphore>>,
     ) -> Result<()> {
         let (r, w) = stream.split();
         let mut r = FramedRead::with_capacity(r, Codec, 4096);
         let mut w = FramedWrite::new(w, Codec);
 
-        while let Some(next) = r.next().await {
-            let req = next?;
-            let handler = Clone::clone(&handler);
-            let cache = Clone::clone(&cache);
-            let (res, cached) = super::helper::handle(addr, req, handler, cache).await;
-
-            if res.answer_count() > 0 {
-                for next in res.answers() {
-                    if let Ok(rdata) = next.rdata() {
-                        if cached {
-                            info!(
-                                "0x{:04x} <- {}.\t{}\t{:?}\t{:?}\t{}\t<CACHE>",
-                                res.id(),
-                                next.name(),
-                                next.time_to_live(),
-                                next.class(),
-                                next.kind(),
-                                rdata,
-                            );
-                        } else {
-                            info!(
-                                "0x{:04x} <- {}.\t{}\t{:?}\t{:?}\t{}",
-                                res.id(),
-                                next.name(),
-                                next.time_to_live(),
-                                next.class(),
-                                next.kind(),
-                                rdata,
-                            );
-                        }
+        loop {
+            let req = match tokio::time::timeout(IDLE_TIMEOUT, r.next()).await {
+                Ok(Some(next)) => next?,
+                Ok(None) => break,
+                Err(_) => {
+                    debug!("close idle tcp connection from {}", addr);
+                    break;
+                }
+            };
+
+            if let Some(acl) = &rejected {
+                acl.reject(addr);
+                match super::helper::refused(&req) {
+                    Some(res) => {
+                        w.send(&res).await?;
+                        continue;
                     }
+                    None => break,
                 }
             }
 
-            w.send(&res).await?;
+            if let Err(e) = req.validate() {
+                match super::helper::format_error(&req, &e) {
+                    Some(res) => {
+                        w.send(&res).await?;
+                        continue;
+                    }
+                    None => break,
+                }
+            }
+
+            let keepalive = req.edns_option(EdnsOption::TCP_KEEPALIVE).is_some();
+            let handler = Clone::clone(&handler);
+            let cache = Clone::clone(&cache);
+            let permit = match &inflight {
+                Some(inflight) => Some(inflight.acquire().await?),
+                None => None,
+            };
+            let mut handled = super::helper::handle(addr, true, req, handler, cache).await;
+            drop(permit);
+
+            if keepalive {
+                let timeout = (IDLE_TIMEOUT.as_millis() / 100) as u16;
+                handled
+                    .res
+                    .put_edns_option(&EdnsOption::TcpKeepalive(Some(timeout)));
+            }
+
+            handled.log();
+
+            if handled.dropped {
+                continue;
+            }
+
+            w.send(&handled.res).await?;
         }
 
         Ok(())
@@ -197,12 +260,12 @@ mod tests {
         // no cache
         assert!(request(&dns, &req, Duration::from_secs(3))
             .await
-            .is_ok_and(|msg| &msg == &res));
+            .is_ok_and(|msg| msg == res));
 
         // use cache
         assert!(request(&dns, &req, Duration::from_secs(3))
             .await
-            .is_ok_and(|msg| &msg == &res));
+            .is_ok_and(|msg| msg == res));
 
         assert_eq!(
             1,
@@ -210,8 +273,93 @@ mod tests {
             "should only call handler once!"
         );
 
+        // advertise the idle timeout
+        {
+            let mut req = Clone::clone(&req);
+            req.put_edns_option(&EdnsOption::TcpKeepalive(None));
+            let res = request(&dns, &req, Duration::from_secs(3)).await?;
+            assert_eq!(
+                Some(EdnsOption::TcpKeepalive(Some(300))),
+                res.edns_option(EdnsOption::TCP_KEEPALIVE)
+            );
+        }
+
         closer.notify_waiters();
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_tcp_acl() -> anyhow::Result<()> {
+        use crate::protocol::{Class, Flags, Kind, RCode};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        init();
+
+        let cnts = Arc::new(AtomicU64::new(0));
+        let h = MockHandler {
+            cnt: Clone::clone(&cnts),
+            resp: Message::builder()
+                .flags(Flags::builder().response().build())
+                .build()?,
+        };
+
+        let req = Message::builder()
+            .id(0x1234)
+            .flags(Flags::builder().request().recursive_query(true).build())
+            .question("example.com", Kind::A, Class::IN)
+            .build()?;
+
+        for reject in ["refused", "drop"] {
+            let acl = {
+                let c: crate::config::AclConfig = toml::from_str(&format!(
+                    "allow = [\"10.0.0.0/8\"]\nreject = \"{}\"",
+                    reject
+                ))?;
+                Acl::try_from(&c)?
+            };
+
+            let addr = "127.0.0.1:0".parse::<SocketAddr>()?;
+            let listener = TcpListener::bind(addr).await?;
+            let port = listener.local_addr()?.port();
+            let closer = Arc::new(Notify::new());
+            let server = TcpServer::new(
+                addr,
+                listener,
+                Clone::clone(&h),
+                None::<Arc<MemoryLoadingCache>>,
+                Clone::clone(&closer),
+            )
+            .acl(acl);
+
+            tokio::spawn(async move {
+                server.listen().await.expect("server stopped");
+            });
+
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+            let mut b = vec![];
+
+            if reject == "drop" {
+                // closed at once without any request
+                let n = tokio::time::timeout(Duration::from_secs(1), stream.read_to_end(&mut b))
+                    .await??;
+                assert_eq!(0, n);
+            } else {
+                stream.write_u16(req.len() as u16).await?;
+                stream.write_all(req.as_ref()).await?;
+                let n = tokio::time::timeout(Duration::from_secs(1), stream.read_u16()).await??;
+                b.resize(n as usize, 0);
+                stream.read_exact(&mut b).await?;
+                let res = Message::from(b);
+                assert_eq!(0x1234, res.id());
+                assert_eq!(RCode::Refused, res.flags().response_code());
+            }
+
+            closer.notify_waiters();
+        }
+
+        assert_eq!(0, cnts.load(Ordering::SeqCst));
+
+        Ok(())
+    }
 }
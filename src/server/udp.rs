@@ -2,11 +2,13 @@ use futures::StreamExt;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::net::UdpSocket;
-use tokio::sync::Notify;
+use tokio::sync::{Notify, Semaphore};
 use tokio_util::codec::BytesCodec;
 use tokio_util::udp::UdpFramed;
 
+use super::acl::{Acl, RejectAction};
 use super::helper;
+use super::limit::{RateLimiter, Verdict};
 use crate::cache::LoadingCache;
 use crate::handler::Handler;
 use crate::protocol::Message;
@@ -17,6 +19,9 @@ pub struct UdpServer<H, C> {
     socket: UdpSocket,
     cache: Option<Arc<C>>,
     closer: Arc<Notify>,
+    acl: Option<Acl>,
+    limiter: Option<RateLimiter>,
+    inflight: Option<Arc<Semaphore>>,
 }
 
 impl<H, C> UdpServer<H, C> {
@@ -26,8 +31,30 @@ impl<H, C> UdpServer<H, C> {
             socket,
             cache,
             closer,
+            acl: None,
+            limiter: None,
+            inflight: None,
         }
     }
+
+    /// Sets the access control list of clients, which is checked before handling requests.
+    pub(crate) fn acl(mut self, acl: Acl) -> Self {
+        self.acl.replace(acl);
+        self
+    }
+
+    /// Sets the rate limiter of clients.
+    pub(crate) fn limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter.replace(limiter);
+        self
+    }
+
+    /// Sets the permits of in-flight requests, the datagrams won't be received until a permit is
+    /// available.
+    pub(crate) fn inflight(mut self, inflight: Arc<Semaphore>) -> Self {
+        self.inflight.replace(inflight);
+        self
+    }
 }
 impl<H, C> UdpServer<H, C>
 where
@@ -41,47 +68,52 @@ where
         h: Arc<H>,
         cache: Option<Arc<C>>,
     ) {
-        let (res, cached) = helper::handle(peer, req, h, cache).await;
-
-        if res.answer_count() > 0 {
-            for next in res.answers() {
-                if let Ok(rdata) = next.rdata() {
-                    if cached {
-                        info!(
-                            "0x{:04x} <- {}.\t{}\t{:?}\t{:?}\t{}\t<CACHE>",
-                            res.id(),
-                            next.name(),
-                            next.time_to_live(),
-                            next.class(),
-                            next.kind(),
-                            rdata,
-                        );
-                    } else {
-                        info!(
-                            "0x{:04x} <- {}.\t{}\t{:?}\t{:?}\t{}",
-                            res.id(),
-                            next.name(),
-                            next.time_to_live(),
-                            next.class(),
-                            next.kind(),
-                            rdata,
-                        );
-                    }
-                }
-            }
+        let handled = helper::handle(peer, false, req, h, cache).await;
+
+        handled.log();
+
+        if handled.dropped {
+            return;
         }
 
-        if let Err(e) = socket.send_to(res.as_ref(), peer).await {
+        if let Err(e) = socket.send_to(handled.res.as_ref(), peer).await {
             error!("failed to reply dns response: {:?}", e);
         }
     }
 
+    fn reply(socket: Arc<UdpSocket>, peer: SocketAddr, res: Message) {
+        tokio::spawn(async move {
+            if let Err(e) = socket.send_to(res.as_ref(), peer).await {
+                error!("failed to reply dns response: {:?}", e);
+            }
+        });
+    }
+
+    fn stopping(acl: &Option<Acl>, limiter: &Option<RateLimiter>) {
+        info!("close signal is received, udp dns server is stopping...");
+        if let Some(acl) = acl {
+            info!(
+                "{} requests have been rejected by udp dns server",
+                acl.rejections()
+            );
+        }
+        if let Some(limiter) = limiter {
+            info!(
+                "{} requests have been rate limited by udp dns server",
+                limiter.limited()
+            );
+        }
+    }
+
     pub async fn listen(self) -> Result<()> {
         let Self {
             h,
             socket,
             cache,
             closer,
+            acl,
+            limiter,
+            mut inflight,
         } = self;
 
         info!("udp dns server is listening on {}", socket.local_addr()?);
@@ -92,14 +124,70 @@ where
         let mut framed = UdpFramed::new(Clone::clone(&socket), BytesCodec::new());
 
         loop {
+            // backpressure: stop receiving until a request is finished
+            let permit = match &inflight {
+                Some(inflight) => {
+                    let inflight = Clone::clone(inflight);
+                    tokio::select! {
+                        permit = inflight.acquire_owned() => Some(permit),
+                        () = closer.notified() => {
+                            Self::stopping(&acl, &limiter);
+                            break;
+                        }
+                    }
+                }
+                None => None,
+            };
+            let permit = match permit {
+                Some(Ok(permit)) => Some(permit),
+                Some(Err(_)) => {
+                    warn!("the in-flight permits of udp dns server are closed, stop limiting them");
+                    inflight.take();
+                    None
+                }
+                None => None,
+            };
+
             tokio::select! {
                 recv = framed.next() => {
                     match recv {
                         Some(Ok((b, peer))) => {
                             let req = Message::from(b);
+                            let socket = Clone::clone(&socket);
+
+                            if let Some(acl) = &acl {
+                                if !acl.is_allowed(peer.ip()) {
+                                    if let RejectAction::Refused = acl.reject(peer) {
+                                        if let Some(res) = helper::refused(&req) {
+                                            Self::reply(socket, peer, res);
+                                        }
+                                    }
+                                    continue;
+                                }
+                            }
+
+                            if let Some(limiter) = &limiter {
+                                match limiter.check(peer) {
+                                    Verdict::Pass => (),
+                                    Verdict::Slip => {
+                                        if let Some(res) = helper::truncated(&req) {
+                                            Self::reply(socket, peer, res);
+                                        }
+                                        continue;
+                                    }
+                                    Verdict::Drop => continue,
+                                }
+                            }
+
+                            if let Err(e) = req.validate() {
+                                if let Some(res) = helper::format_error(&req, &e) {
+                                    Self::reply(socket, peer, res);
+                                }
+                                continue;
+                            }
+
                             let h = Clone::clone(&h);
                             let cache = Clone::clone(&cache);
-                            let socket = Clone::clone(&socket);
 
                             if req.question_count() > 0 {
                                 for next in req.questions() {
@@ -109,6 +197,7 @@ where
 
                             tokio::spawn(async move {
                                 Self::handle_request(socket, peer, req, h, cache).await;
+                                drop(permit);
                             });
                         }
                         _ => {
@@ -117,7 +206,7 @@ where
                     }
                 }
                 () = closer.notified() => {
-                    info!("close signal is received, udp dns server is stopping...");
+                    Self::stopping(&acl, &limiter);
                     break;
                 }
             }
@@ -133,7 +222,7 @@ mod tests {
     use crate::cache::MemoryLoadingCache;
     use crate::client::request;
     use crate::filter::Context;
-    use crate::protocol::{Class, Flags, Kind, Message, DNS};
+    use crate::protocol::{Class, Flags, Kind, Message, RCode, DNS};
     use std::str::FromStr;
     use std::sync::atomic::{AtomicU64, Ordering};
     use std::time::Duration;
@@ -197,12 +286,12 @@ mod tests {
         // no cache
         assert!(request(&dns, &req, Duration::from_secs(3))
             .await
-            .is_ok_and(|msg| &msg == &res));
+            .is_ok_and(|msg| msg == res));
 
         // use cache
         assert!(request(&dns, &req, Duration::from_secs(3))
             .await
-            .is_ok_and(|msg| &msg == &res));
+            .is_ok_and(|msg| msg == res));
 
         assert_eq!(
             1,
@@ -210,7 +299,136 @@ mod tests {
             "should only call handler once!"
         );
 
+        // malformed request: a compression loop in question
+        {
+            let raw = hex::decode("abcd01000001000000000000c00c00010001")?;
+            let client = UdpSocket::bind("127.0.0.1:0").await?;
+            client.send_to(&raw, ("127.0.0.1", port)).await?;
+            let mut b = [0u8; 512];
+            let (n, _) =
+                tokio::time::timeout(Duration::from_secs(3), client.recv_from(&mut b)).await??;
+            let res = Message::from(b[..n].to_vec());
+            assert!(res.validate().is_ok());
+            assert_eq!(0xabcd, res.id());
+            assert_eq!(RCode::FormatError, res.flags().response_code());
+            assert_eq!(0, res.question_count());
+        }
+
+        closer.notify_waiters();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_udp_acl() -> anyhow::Result<()> {
+        init();
+
+        let cnts = Arc::new(AtomicU64::new(0));
+        let h = MockHandler {
+            cnt: Clone::clone(&cnts),
+            resp: Message::builder()
+                .flags(Flags::builder().response().build())
+                .build()?,
+        };
+
+        let req = Message::builder()
+            .id(0x1234)
+            .flags(Flags::builder().request().recursive_query(true).build())
+            .question("example.com", Kind::A, Class::IN)
+            .build()?;
+
+        for (reject, refused) in [("refused", true), ("drop", false)] {
+            let acl = {
+                let c: crate::config::AclConfig = toml::from_str(&format!(
+                    "allow = [\"10.0.0.0/8\"]\nreject = \"{}\"",
+                    reject
+                ))?;
+                Acl::try_from(&c)?
+            };
+
+            let socket = UdpSocket::bind("127.0.0.1:0").await?;
+            let port = socket.local_addr()?.port();
+            let closer = Arc::new(Notify::new());
+            let server = UdpServer::new(
+                socket,
+                Clone::clone(&h),
+                None::<Arc<MemoryLoadingCache>>,
+                Clone::clone(&closer),
+            )
+            .acl(acl);
+
+            tokio::spawn(async move {
+                server.listen().await.expect("udp server is stopped!");
+            });
+
+            let client = UdpSocket::bind("127.0.0.1:0").await?;
+            client.send_to(req.as_ref(), ("127.0.0.1", port)).await?;
+            let mut b = [0u8; 512];
+            let recv =
+                tokio::time::timeout(Duration::from_millis(500), client.recv_from(&mut b)).await;
+            assert_eq!(refused, recv.is_ok(), "{}", reject);
+            if refused {
+                let (n, _) = recv??;
+                let res = Message::from(b[..n].to_vec());
+                assert_eq!(0x1234, res.id());
+                assert_eq!(RCode::Refused, res.flags().response_code());
+                assert_eq!(1, res.question_count());
+            }
+
+            closer.notify_waiters();
+        }
+
+        assert_eq!(0, cnts.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_udp_saturated_shutdown() -> anyhow::Result<()> {
+        init();
+
+        /// Never answers, so the in-flight permit is held forever.
+        struct PendingHandler;
+
+        #[async_trait::async_trait]
+        impl Handler for PendingHandler {
+            async fn handle(
+                &self,
+                _ctx: &mut Context,
+                _req: &mut Message,
+            ) -> Result<Option<Message>> {
+                futures::future::pending().await
+            }
+        }
+
+        let req = Message::builder()
+            .id(0x1234)
+            .flags(Flags::builder().request().recursive_query(true).build())
+            .question("example.com", Kind::A, Class::IN)
+            .build()?;
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await?;
+        let port = socket.local_addr()?.port();
+        let closer = Arc::new(Notify::new());
+        let server = UdpServer::new(
+            socket,
+            PendingHandler,
+            None::<Arc<MemoryLoadingCache>>,
+            Clone::clone(&closer),
+        )
+        .inflight(Arc::new(Semaphore::new(1)));
+
+        let listening = tokio::spawn(server.listen());
+
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        for _ in 0..2 {
+            client.send_to(req.as_ref(), ("127.0.0.1", port)).await?;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // the server is waiting for a permit, but it should stop anyway
         closer.notify_waiters();
+        tokio::time::timeout(Duration::from_secs(3), listening).await???;
 
         Ok(())
     }